@@ -0,0 +1,156 @@
+//! MPIDR_EL1 affinity decoding.
+//!
+//! The affinity fields identify a core in the system topology, Aff0 being the most
+//! tightly coupled level (thread or core) and Aff3 the least. Firmware interfaces and the
+//! GIC use different packings of the same four fields.
+
+/// Affinity of a core as reported by MPIDR_EL1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Affinity {
+    pub aff0: u8,
+    pub aff1: u8,
+    pub aff2: u8,
+    pub aff3: u8,
+    /// Aff0 enumerates threads of a multithreaded core (MT)
+    pub multithreaded: bool,
+    /// The core is part of a uniprocessor system (U)
+    pub uniprocessor: bool,
+}
+
+impl Affinity {
+    /// Create from the affinity levels, MT and U cleared
+    pub const fn new(aff3: u8, aff2: u8, aff1: u8, aff0: u8) -> Self {
+        Self {
+            aff0,
+            aff1,
+            aff2,
+            aff3,
+            multithreaded: false,
+            uniprocessor: false,
+        }
+    }
+
+    /// Decode a MPIDR_EL1 value
+    pub const fn from_mpidr(mpidr: u64) -> Self {
+        Self {
+            aff0: mpidr as u8,
+            aff1: (mpidr >> 8) as u8,
+            aff2: (mpidr >> 16) as u8,
+            aff3: (mpidr >> 32) as u8,
+            multithreaded: mpidr & (1 << 24) != 0,
+            uniprocessor: mpidr & (1 << 30) != 0,
+        }
+    }
+
+    /// Encode as MPIDR_EL1 value, bit 31 (RES1) set
+    pub const fn to_mpidr(self) -> u64 {
+        (1 << 31)
+            | ((self.uniprocessor as u64) << 30)
+            | ((self.multithreaded as u64) << 24)
+            | self.packed()
+    }
+
+    /// Affinity fields in their MPIDR_EL1 positions, Aff3 at bits [39:32]
+    ///
+    /// This is the target_cpu argument of PSCI CPU_ON and AFFINITY_INFO and the layout
+    /// of GICD_IROUTER<n> and GICR_TYPER.Affinity_Value.
+    pub const fn packed(self) -> u64 {
+        ((self.aff3 as u64) << 32)
+            | ((self.aff2 as u64) << 16)
+            | ((self.aff1 as u64) << 8)
+            | self.aff0 as u64
+    }
+
+    /// Decode a value packed as by [`packed`](Self::packed)
+    pub const fn from_packed(value: u64) -> Self {
+        Self::new(
+            (value >> 32) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        )
+    }
+
+    /// Affinity fields of ICC_SGI1R_EL1 / ICC_SGI0R_EL1 / ICC_ASGI1R_EL1 addressing this core
+    ///
+    /// Sets Aff3 [55:48], Aff2 [39:32], Aff1 [23:16], the range selector RS [47:44] and
+    /// the bit of Aff0 in TargetList [15:0]. INTID and IRM are left to the caller.
+    pub const fn sgi_target(self) -> u64 {
+        ((self.aff3 as u64) << 48)
+            | ((self.aff2 as u64) << 32)
+            | (((self.aff0 >> 4) as u64) << 44)
+            | ((self.aff1 as u64) << 16)
+            | (1 << (self.aff0 & 0xF))
+    }
+
+    /// Check if both cores share Aff1-Aff3, i.e. are in the same cluster
+    pub const fn same_cluster(self, other: Self) -> bool {
+        self.aff1 == other.aff1 && self.aff2 == other.aff2 && self.aff3 == other.aff3
+    }
+
+    /// Check if both cores are the same, ignoring MT and U
+    pub const fn same_core(self, other: Self) -> bool {
+        self.packed() == other.packed()
+    }
+}
+
+impl PartialOrd for Affinity {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders by topology, Aff3 first
+impl Ord for Affinity {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.packed()
+            .cmp(&other.packed())
+            .then(self.multithreaded.cmp(&other.multithreaded))
+            .then(self.uniprocessor.cmp(&other.uniprocessor))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Affinity {
+    /// Affinity of the executing core
+    pub fn current() -> Self {
+        use aarch64_cpu::registers::{MPIDR_EL1, Readable};
+
+        Self::from_mpidr(MPIDR_EL1.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affinity() {
+        let aff = Affinity::from_mpidr(0x8100_0000 | (2 << 32) | (1 << 16) | (3 << 8) | 0x12);
+        assert_eq!((aff.aff3, aff.aff2, aff.aff1, aff.aff0), (2, 1, 3, 0x12));
+        assert!(aff.multithreaded);
+        assert!(!aff.uniprocessor);
+        assert_eq!(
+            aff.to_mpidr(),
+            0x8100_0000 | (2 << 32) | (1 << 16) | (3 << 8) | 0x12
+        );
+        assert_eq!(aff.packed(), 0x02_0001_0312);
+        assert_eq!(
+            Affinity::from_packed(aff.packed()),
+            Affinity::new(2, 1, 3, 0x12)
+        );
+
+        // RS = 1, TargetList bit 2
+        let sgi = aff.sgi_target();
+        assert_eq!(
+            sgi,
+            (2 << 48) | (1 << 32) | (1 << 44) | (3 << 16) | (1 << 2)
+        );
+
+        let sibling = Affinity::new(2, 1, 3, 0);
+        assert!(aff.same_cluster(sibling));
+        assert!(!aff.same_core(sibling));
+        assert!(sibling < Affinity::new(2, 1, 3, 0x12));
+        assert!(Affinity::new(0, 0, 4, 0) > Affinity::new(0, 0, 3, 7));
+    }
+}
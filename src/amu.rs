@@ -0,0 +1,120 @@
+//! Activity Monitors Extension (FEAT_AMU).
+//!
+//! The activity monitors are free-running per-core counters meant for power management
+//! rather than profiling: unlike the [PMU](crate::pmu) they are not reprogrammed by
+//! users, so a governor can sample them at any time. The four architected counters of
+//! group 0 are read into an [`AmuCounters`] snapshot, the difference of two snapshots gives
+//! the activity over an interval, e.g. the utilization as core cycles over constant cycles.
+//!
+//! The counters are usually enabled by the firmware, and access from EL1 can be trapped by
+//! EL2 (CPTR_EL2.TAM) or EL3 (CPTR_EL3.TAM).
+
+/// Snapshot of the architected activity monitor counters (AMEVCNTR0<n>_EL0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AmuCounters {
+    /// Processor frequency cycles (counter 0, CPU_CYCLES)
+    pub core_cycles: u64,
+    /// Constant frequency cycles, at the system counter frequency (counter 1, CNT_CYCLES)
+    pub constant_cycles: u64,
+    /// Instructions retired (counter 2, INST_RETIRED)
+    pub instructions: u64,
+    /// Memory stall cycles (counter 3, STALL_BACKEND_MEM)
+    pub memory_stall_cycles: u64,
+}
+
+impl AmuCounters {
+    /// Counts since `earlier`, the counters wrap around
+    pub const fn delta(self, earlier: AmuCounters) -> AmuCounters {
+        AmuCounters {
+            core_cycles: self.core_cycles.wrapping_sub(earlier.core_cycles),
+            constant_cycles: self.constant_cycles.wrapping_sub(earlier.constant_cycles),
+            instructions: self.instructions.wrapping_sub(earlier.instructions),
+            memory_stall_cycles: self
+                .memory_stall_cycles
+                .wrapping_sub(earlier.memory_stall_cycles),
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::asm::barrier::{SY, isb};
+
+    use super::AmuCounters;
+    use crate::features::{Feature, is_supported as has_feature};
+
+    /// Architected counters of group 0
+    const GROUP0_MASK: u64 = 0b1111;
+
+    /// Check if the core implements the activity monitors
+    #[inline]
+    pub fn is_supported() -> bool {
+        has_feature(Feature::Amu)
+    }
+
+    /// Enable the architected counters (AMCNTENSET0_EL0)
+    ///
+    /// Needs write access to the enable registers, which EL3 may not grant.
+    pub fn enable() {
+        unsafe { crate::sysreg!(write "S3_3_C13_C2_5", GROUP0_MASK) };
+        isb(SY);
+    }
+
+    /// Architected counters that are enabled (AMCNTENSET0_EL0), bit n for counter n
+    #[inline]
+    pub fn enabled() -> u64 {
+        crate::sysreg!(read "S3_3_C13_C2_5") & GROUP0_MASK
+    }
+
+    /// Read architected counter `n` (AMEVCNTR0<n>_EL0), 0-3
+    #[inline]
+    pub fn read_counter(n: usize) -> u64 {
+        match n {
+            0 => crate::sysreg!(read "S3_3_C13_C4_0"),
+            1 => crate::sysreg!(read "S3_3_C13_C4_1"),
+            2 => crate::sysreg!(read "S3_3_C13_C4_2"),
+            3 => crate::sysreg!(read "S3_3_C13_C4_3"),
+            _ => panic!("invalid architected AMU counter"),
+        }
+    }
+
+    /// Snapshot the architected counters
+    pub fn read() -> AmuCounters {
+        AmuCounters {
+            core_cycles: read_counter(0),
+            constant_cycles: read_counter(1),
+            instructions: read_counter(2),
+            memory_stall_cycles: read_counter(3),
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use access::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta() {
+        let earlier = AmuCounters {
+            core_cycles: 1000,
+            constant_cycles: 500,
+            instructions: u64::MAX - 9,
+            memory_stall_cycles: 20,
+        };
+        let later = AmuCounters {
+            core_cycles: 3000,
+            constant_cycles: 1500,
+            instructions: 10,
+            memory_stall_cycles: 120,
+        };
+        let delta = later.delta(earlier);
+        assert_eq!(delta.core_cycles, 2000);
+        assert_eq!(delta.constant_cycles, 1000);
+        // Wrapped around
+        assert_eq!(delta.instructions, 20);
+        assert_eq!(delta.memory_stall_cycles, 100);
+    }
+}
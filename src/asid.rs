@@ -0,0 +1,107 @@
+use aarch64_cpu::registers::*;
+
+use crate::{
+    structures::id_allocator::{GenerationalId, IdAllocator},
+    tlb::TlbFlush,
+};
+
+/// ASID allocator with generation based rollover
+///
+/// ASID 0 is never handed out and can be used for kernel-only address spaces. When all
+/// ASIDs of a generation are in use, a new generation starts and every non-global EL1&0
+/// TLB entry is invalidated with TLBI VMALLE1IS before the new ASID is returned.
+///
+/// The allocator is not synchronized, SMP users must wrap it in a lock. A typical
+/// context switch looks like:
+///
+/// ```ignore
+/// let asid = ALLOCATOR.lock().allocate(task.asid, &active_asids_of_all_cores);
+/// task.asid = Some(asid);
+/// TTBR0_EL1.write(TTBR0_EL1::ASID.val(asid.hw_id() as u64) + TTBR0_EL1::BADDR.val(root >> 1));
+/// ```
+#[derive(Clone)]
+pub struct AsidAllocator {
+    inner: IdAllocator,
+}
+
+impl AsidAllocator {
+    /// Create an allocator sized from ID_AA64MMFR0_EL1.ASIDBits
+    ///
+    /// 16-bit ASIDs additionally require TCR_EL1.AS = 1.
+    pub fn new() -> Self {
+        Self::with_bits(asid_bits())
+    }
+
+    /// Create an allocator for `bits` wide ASIDs (8 or 16)
+    pub const fn with_bits(bits: u32) -> Self {
+        Self {
+            inner: IdAllocator::new(bits),
+        }
+    }
+
+    /// Size the allocator from ID_AA64MMFR0_EL1.ASIDBits in place
+    ///
+    /// [`new`](Self::new) returns the allocator, about 16KB, by value. A `static` allocator
+    /// can instead be created with [`with_bits`](Self::with_bits) and sized once before the
+    /// first allocation:
+    ///
+    /// ```ignore
+    /// static ASIDS: Mutex<AsidAllocator> = Mutex::new(AsidAllocator::with_bits(16));
+    ///
+    /// ASIDS.lock().init();
+    /// ```
+    pub fn init(&mut self) {
+        self.inner.reset(asid_bits());
+    }
+
+    /// Width of the ASIDs in bits
+    pub const fn bits(&self) -> u32 {
+        self.inner.bits()
+    }
+
+    /// Check if `asid` is still valid without reallocation
+    pub const fn is_current(&self, asid: GenerationalId) -> bool {
+        self.inner.is_current(asid)
+    }
+
+    /// Return an ASID valid in the current generation, see [`IdAllocator::allocate`]
+    ///
+    /// Invalidates the EL1&0 TLB on all cores in the inner shareable domain on rollover.
+    pub fn allocate(
+        &mut self,
+        current: Option<GenerationalId>,
+        active: &[GenerationalId],
+    ) -> GenerationalId {
+        let alloc = self.inner.allocate(current, active);
+        if alloc.rolled_over {
+            let mut flush = TlbFlush::<1>::new();
+            flush.all();
+            flush.commit();
+        }
+        alloc.id
+    }
+
+    /// Release an ASID and invalidate its TLB entries on all cores
+    pub fn free(&mut self, asid: GenerationalId) {
+        if self.inner.is_current(asid) {
+            let mut flush = TlbFlush::<1>::new();
+            flush.asid(asid.hw_id());
+            flush.commit();
+        }
+        self.inner.free(asid);
+    }
+}
+
+impl Default for AsidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read the supported ASID width from ID_AA64MMFR0_EL1.ASIDBits
+pub fn asid_bits() -> u32 {
+    match ID_AA64MMFR0_EL1.read(ID_AA64MMFR0_EL1::ASIDBits) {
+        0b0010 => 16,
+        _ => 8,
+    }
+}
@@ -0,0 +1,75 @@
+//! Address translation (AT) instructions.
+//!
+//! An AT instruction translates an address as the named regime and stage would and writes
+//! the result, an output address or a fault, to PAR_EL1. [`at`] executes the operation,
+//! the ISB that makes the result visible and the read of PAR_EL1.
+//!
+//! PAR_EL1 is not banked per context: an exception handler executing AT between the
+//! operation and the read replaces the result. Callers sharing the core with such
+//! handlers must mask them around [`at`].
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{PAR_EL1, Readable},
+};
+
+/// Translate `addr` with `op` and return the resulting PAR_EL1
+#[inline]
+pub fn at(op: impl sealed::At, addr: usize) -> u64 {
+    op.at(addr as u64);
+    isb(SY);
+    PAR_EL1.get()
+}
+
+mod sealed {
+    pub trait At {
+        fn at(&self, addr: u64);
+    }
+}
+
+macro_rules! at_op {
+    ($A:ident, $T:ident, $insn:literal) => {
+        pub struct $T;
+        pub const $A: $T = $T {};
+
+        impl sealed::At for $T {
+            #[inline(always)]
+            fn at(&self, addr: u64) {
+                match () {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!($insn, ", {}"), in(reg) addr, options(nostack, preserves_flags))
+                    },
+
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    };
+}
+
+// EL1&0 stage 1, as EL0 or EL1 would see it
+at_op!(S1E0R, S1e0r, "at s1e0r");
+at_op!(S1E0W, S1e0w, "at s1e0w");
+at_op!(S1E1R, S1e1r, "at s1e1r");
+at_op!(S1E1W, S1e1w, "at s1e1w");
+
+// S1E1RP and S1E1WP honour PSTATE.PAN and require FEAT_PAN2, encoded as SYS as the
+// assembler may not know them
+at_op!(S1E1RP, S1e1rp, "sys #0, c7, c9, #0");
+at_op!(S1E1WP, S1e1wp, "sys #0, c7, c9, #1");
+
+// EL2 stage 1, or EL2&0 with HCR_EL2.E2H set
+at_op!(S1E2R, S1e2r, "at s1e2r");
+at_op!(S1E2W, S1e2w, "at s1e2w");
+
+// EL1&0 stage 1 and 2 from EL2, the result is a PA. Stage 1 only with stage 2 disabled.
+at_op!(S12E0R, S12e0r, "at s12e0r");
+at_op!(S12E0W, S12e0w, "at s12e0w");
+at_op!(S12E1R, S12e1r, "at s12e1r");
+at_op!(S12E1W, S12e1w, "at s12e1w");
+
+// EL3
+at_op!(S1E3R, S1e3r, "at s1e3r");
+at_op!(S1E3W, S1e3w, "at s1e3w");
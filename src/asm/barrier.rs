@@ -0,0 +1,55 @@
+//! Barrier functions.
+//!
+//! Re-exports the DMB/DSB/ISB wrappers of `aarch64_cpu` for every shareability domain and
+//! access type (`SY`, `ST`, `LD`, `ISH*`, `NSH*`, `OSH*`) and adds the speculation barriers.
+
+pub use aarch64_cpu::asm::barrier::*;
+
+macro_rules! barrier {
+    ($(#[$doc:meta])* $name:ident, $insn:literal) => {
+        $(#[$doc])*
+        #[inline(always)]
+        pub fn $name() {
+            match () {
+                #[cfg(target_arch = "aarch64")]
+                () => unsafe { core::arch::asm!($insn, options(nostack)) },
+
+                #[cfg(not(target_arch = "aarch64"))]
+                () => unimplemented!(),
+            }
+        }
+    };
+}
+
+barrier!(
+    /// Speculation Barrier, requires FEAT_SB.
+    ///
+    /// No instruction after the barrier is speculatively executed until the barrier completes.
+    sb,
+    ".arch_extension sb\nsb"
+);
+
+barrier!(
+    /// Consumption of Speculative Data Barrier.
+    ///
+    /// Executes as a NOP on cores without FEAT_CSV2 style speculation controls.
+    csdb,
+    "csdb"
+);
+
+barrier!(
+    /// Speculative Store Bypass Barrier.
+    ///
+    /// A load after the barrier cannot speculatively read a value older than a store to the
+    /// same virtual address before it.
+    ssbb,
+    "ssbb"
+);
+
+barrier!(
+    /// Physical Speculative Store Bypass Barrier.
+    ///
+    /// As [`ssbb`] but for stores and loads to the same physical address.
+    pssbb,
+    "pssbb"
+);
@@ -4,6 +4,10 @@ mod sealed {
         fn ic(&self);
     }
 
+    pub trait IcVa {
+        fn ic_va(&self, addr: u64);
+    }
+
     pub trait Dc {
         fn dc(&self, addr: u64);
     }
@@ -31,8 +35,28 @@ macro_rules! ic {
     };
 }
 
-macro_rules! dc {
+macro_rules! ic_va {
     ($A: ident, $T: ident) => {
+        pub struct $T;
+        pub const $A: $T = $T{};
+        impl sealed::IcVa for $T {
+            #[inline(always)]
+            fn ic_va(&self, addr:u64){
+                match() {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!("ic ",stringify!($A), ",{}"), in(reg) addr, options(nostack))
+                    },
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    }
+}
+
+macro_rules! dc {
+    ($A: ident, $T: ident $(, $ext:literal)?) => {
         pub struct $T;
         pub const $A: $T = $T{};
         impl sealed::Dc for $T {
@@ -41,7 +65,7 @@ macro_rules! dc {
                 match() {
                     #[cfg(target_arch = "aarch64")]
                     () => unsafe {
-                        core::arch::asm!(concat!("dc ",stringify!($A), ",{}"), in(reg) addr, options(nostack))
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "dc ",stringify!($A), ",{}"), in(reg) addr, options(nostack))
                     },
                     #[cfg(not(target_arch = "aarch64"))]
                     () => unimplemented!(),
@@ -53,18 +77,38 @@ macro_rules! dc {
 
 ic!(IALLU, Iallu);
 ic!(IALLUIS, Ialluis);
+ic_va!(IVAU, Ivau);
 dc!(CVAC, Cvac);
+dc!(CVAU, Cvau);
+// Requires FEAT_DPB / FEAT_DPB2
+dc!(CVAP, Cvap, "ccpp");
+dc!(CVADP, Cvadp, "ccdp");
+// Allocation tag operations, require FEAT_MTE2
+dc!(GVA, Gva, "memtag");
+dc!(GZVA, Gzva, "memtag");
+dc!(CGVAC, Cgvac, "memtag");
+dc!(CGDVAC, Cgdvac, "memtag");
+dc!(CIGVAC, Cigvac, "memtag");
+dc!(CIGDVAC, Cigdvac, "memtag");
+dc!(IGVAC, Igvac, "memtag");
+dc!(IGDVAC, Igdvac, "memtag");
 dc!(IVAC, Ivac);
 dc!(CIVAC, Civac);
 dc!(CISW, Cisw);
 dc!(ISW, Isw);
 dc!(CSW, Csw);
+dc!(ZVA, Zva);
 
 #[inline(always)]
 pub fn ic(_arg: impl sealed::Ic) {
     _arg.ic();
 }
 
+#[inline(always)]
+pub fn ic_va(_arg: impl sealed::IcVa, addr: u64) {
+    _arg.ic_va(addr);
+}
+
 #[inline(always)]
 pub fn dc(_arg: impl sealed::Dc, addr: u64) {
     _arg.dc(addr);
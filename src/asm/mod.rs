@@ -1,3 +1,6 @@
 pub use aarch64_cpu::asm::*;
+pub mod at;
+pub mod barrier;
 pub mod cache;
+pub mod prefetch;
 pub mod tlb;
@@ -0,0 +1,98 @@
+//! Prefetch memory (PRFM) hints.
+//!
+//! Prefetches are hints only, they never fault and may be ignored by the core.
+
+/// Kind of access the prefetched memory is expected to see
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchType {
+    /// Prefetch for load (PLD)
+    Load = 0b00,
+    /// Preload instructions (PLI)
+    Instruction = 0b01,
+    /// Prefetch for store (PST)
+    Store = 0b10,
+}
+
+/// Cache level the memory is prefetched into
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchTarget {
+    L1 = 0b00,
+    L2 = 0b01,
+    L3 = 0b10,
+}
+
+/// Expected reuse of the prefetched memory
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchPolicy {
+    /// Temporal, the data is expected to be used more than once (KEEP)
+    Keep = 0,
+    /// Streaming, the data is expected to be used only once (STRM)
+    Stream = 1,
+}
+
+macro_rules! prfm {
+    ($op:expr, $addr:expr, $($imm:literal)*) => {
+        match $op {
+            $(
+                $imm => unsafe {
+                    core::arch::asm!(
+                        concat!("prfm #", $imm, ", [{}]"),
+                        in(reg) $addr,
+                        options(nostack, readonly, preserves_flags)
+                    )
+                },
+            )*
+            _ => unreachable!(),
+        }
+    };
+}
+
+/// Issue a PRFM hint for `addr`
+#[inline(always)]
+pub fn prefetch<T>(
+    addr: *const T,
+    ty: PrefetchType,
+    target: PrefetchTarget,
+    policy: PrefetchPolicy,
+) {
+    // prfop = type:target:policy
+    let op = ((ty as u8) << 3) | ((target as u8) << 1) | policy as u8;
+    prfm!(op, addr, 0 1 2 3 4 5 8 9 10 11 12 13 16 17 18 19 20 21);
+}
+
+/// Prefetch `addr` into L1 for reading (PLDL1KEEP)
+#[inline(always)]
+pub fn prefetch_read<T>(addr: *const T) {
+    prefetch(
+        addr,
+        PrefetchType::Load,
+        PrefetchTarget::L1,
+        PrefetchPolicy::Keep,
+    );
+}
+
+/// Prefetch `addr` into L1 for writing (PSTL1KEEP)
+#[inline(always)]
+pub fn prefetch_write<T>(addr: *const T) {
+    prefetch(
+        addr,
+        PrefetchType::Store,
+        PrefetchTarget::L1,
+        PrefetchPolicy::Keep,
+    );
+}
+
+/// Prefetch `addr` for a single streaming write (PSTL1STRM), e.g. a buffer that is filled
+/// once and handed to a device
+#[inline(always)]
+pub fn prefetch_write_stream<T>(addr: *const T) {
+    prefetch(
+        addr,
+        PrefetchType::Store,
+        PrefetchTarget::L1,
+        PrefetchPolicy::Stream,
+    );
+}
@@ -1,5 +1,7 @@
 use tock_registers::register_bitfields;
 
+use crate::structures::tte::Granule;
+
 register_bitfields![u64,
     TlbiVA [
         VA OFFSET(0) NUMBITS(44) [],
@@ -28,6 +30,11 @@ register_bitfields![u64,
     TlbiASID [
         ASID OFFSET(48) NUMBITS(16) [],
     ],
+    TlbiIPA [
+        IPA OFFSET(0) NUMBITS(40) [],
+        TTL OFFSET(44) NUMBITS(4) [],
+        NS OFFSET(63) NUMBITS(1) [],
+    ],
 ];
 
 #[inline]
@@ -42,7 +49,7 @@ mod sealed {
 }
 
 macro_rules! tlbi_all {
-    ($A:ident) => {
+    ($A:ident $(, $ext:literal)?) => {
         pub struct $A;
 
         impl sealed::Tlbi for $A {
@@ -51,7 +58,7 @@ macro_rules! tlbi_all {
                 match () {
                     #[cfg(target_arch = "aarch64")]
                     () => unsafe {
-                        core::arch::asm!(concat!("tlbi ", stringify!($A)), options(nostack))
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "tlbi ", stringify!($A)), options(nostack))
                     },
 
                     #[cfg(not(target_arch = "aarch64"))]
@@ -62,22 +69,35 @@ macro_rules! tlbi_all {
     };
 }
 
+// Outer shareable (*OS) operations require FEAT_TLBIOS and are UNDEFINED otherwise,
+// check `tlb::has_tlbi_os()` before use. Range operations require FEAT_TLBIRANGE.
+
 tlbi_all!(ALLE1);
 tlbi_all!(ALLE2);
 tlbi_all!(ALLE3);
 
 tlbi_all!(ALLE1IS);
-// tlbi_all!(ALLE1OS);
+tlbi_all!(ALLE1OS, "tlb-rmi");
 
 tlbi_all!(ALLE2IS);
-// tlbi_all!(ALLE2OS);
+tlbi_all!(ALLE2OS, "tlb-rmi");
 
 tlbi_all!(ALLE3IS);
-// tlbi_all!(ALLE3OS);
+tlbi_all!(ALLE3OS, "tlb-rmi");
 
 tlbi_all!(VMALLE1);
 tlbi_all!(VMALLE1IS);
-// tlbi_all!(VMALLE1OS);
+tlbi_all!(VMALLE1OS, "tlb-rmi");
+
+tlbi_all!(VMALLS12E1);
+tlbi_all!(VMALLS12E1IS);
+tlbi_all!(VMALLS12E1OS, "tlb-rmi");
+
+#[inline]
+fn ipa_to_tlbi_ipa(ipa: usize) -> u64 {
+    const IPA_MASK: u64 = (1 << 40) - 1; // IPA[51:12] => bits[39:0]
+    (ipa as u64 >> 12) & IPA_MASK
+}
 
 #[inline]
 fn va_to_tlbi_va(va: usize) -> u64 {
@@ -86,7 +106,7 @@ fn va_to_tlbi_va(va: usize) -> u64 {
 }
 
 macro_rules! tlbi_va {
-    ($A:ident) => {
+    ($A:ident $(, $ext:literal)?) => {
         pub struct $A(u64);
 
         impl $A {
@@ -103,7 +123,7 @@ macro_rules! tlbi_va {
                 match () {
                     #[cfg(target_arch = "aarch64")]
                     () => unsafe {
-                        core::arch::asm!(concat!("tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
                     },
 
                     #[cfg(not(target_arch = "aarch64"))]
@@ -119,16 +139,16 @@ tlbi_va!(VAE2);
 tlbi_va!(VAE3);
 
 tlbi_va!(VAE1IS);
-// tlbi_va!(VAE1OS);
+tlbi_va!(VAE1OS, "tlb-rmi");
 
 tlbi_va!(VAE2IS);
-// tlbi_va!(VAE2OS);
+tlbi_va!(VAE2OS, "tlb-rmi");
 
 tlbi_va!(VAE3IS);
-// tlbi_va!(VAE3OS);
+tlbi_va!(VAE3OS, "tlb-rmi");
 
 macro_rules! tlbi_asid {
-    ($A:ident) => {
+    ($A:ident $(, $ext:literal)?) => {
         pub struct $A(u64);
 
         impl $A {
@@ -144,7 +164,7 @@ macro_rules! tlbi_asid {
                 match () {
                     #[cfg(target_arch = "aarch64")]
                     () => unsafe {
-                        core::arch::asm!(concat!("tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
                     },
 
                     #[cfg(not(target_arch = "aarch64"))]
@@ -157,10 +177,10 @@ macro_rules! tlbi_asid {
 
 tlbi_asid!(ASIDE1);
 tlbi_asid!(ASIDE1IS);
-// tlbi_asid!(ASIDE1OS);
+tlbi_asid!(ASIDE1OS, "tlb-rmi");
 
 macro_rules! tlbi_vaa {
-    ($A:ident) => {
+    ($A:ident $(, $ext:literal)?) => {
         pub struct $A(u64);
 
         impl $A {
@@ -176,7 +196,7 @@ macro_rules! tlbi_vaa {
                 match () {
                     #[cfg(target_arch = "aarch64")]
                     () => unsafe {
-                        core::arch::asm!(concat!("tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
                     },
 
                     #[cfg(not(target_arch = "aarch64"))]
@@ -189,4 +209,264 @@ macro_rules! tlbi_vaa {
 
 tlbi_vaa!(VAAE1);
 tlbi_vaa!(VAAE1IS);
-// tlbi_vaa!(VAAE1OS);
+tlbi_vaa!(VAAE1OS, "tlb-rmi");
+
+/// Computes the operands of range TLBI operations covering a range of pages.
+///
+/// A single range operation invalidates `(NUM + 1) * 2^(5 * SCALE + 1)` pages starting at
+/// `BaseADDR`. The page count is consumed from the lowest SCALE upwards, so a range needs at
+/// most one operation per SCALE plus one per additional 8GB (4KB granule) of length.
+/// Odd page counts are rounded up, as the smallest range operation covers two pages.
+#[derive(Clone)]
+struct RangeOperands {
+    page: u64,
+    pages: u64,
+    scale: u32,
+    tg: u64,
+}
+
+impl RangeOperands {
+    fn new<G: Granule>(va: usize, len: usize) -> Self {
+        let start = va as u64 >> G::M;
+        let pages = if len == 0 {
+            0
+        } else {
+            // Last page inclusive, a range may end at the top of the address space
+            let last = (va as u64).saturating_add(len as u64 - 1) >> G::M;
+            (last - start + 2) & !1
+        };
+        let tg = match G::M {
+            12 => 0b01,
+            14 => 0b10,
+            16 => 0b11,
+            _ => panic!("Invalid granule size"),
+        };
+        Self {
+            page: start,
+            pages,
+            scale: 0,
+            tg,
+        }
+    }
+}
+
+impl Iterator for RangeOperands {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.pages > 0 {
+            let shift = 5 * self.scale + 1;
+            let num = if self.scale == 3 {
+                (self.pages >> shift).min(32)
+            } else {
+                (self.pages >> shift) & 0x1f
+            };
+            let scale = self.scale;
+            if self.scale < 3 {
+                self.scale += 1;
+            }
+            if num == 0 {
+                continue;
+            }
+
+            let op = TlbiRVA::BassADDR.val(self.page & ((1 << 37) - 1))
+                + TlbiRVA::NUM.val(num - 1)
+                + TlbiRVA::SCALE.val(scale as u64)
+                + TlbiRVA::TG.val(self.tg);
+            self.page += num << shift;
+            self.pages -= num << shift;
+            return Some(op.value);
+        }
+        None
+    }
+}
+
+macro_rules! tlbi_rva {
+    ($A:ident) => {
+        pub struct $A(u64);
+
+        impl $A {
+            /// Build the operations invalidating `[va, va + len)` for `asid`
+            ///
+            /// Large or irregular ranges need more than one operation.
+            #[inline]
+            pub fn new<G: Granule>(asid: usize, va: usize, len: usize) -> impl Iterator<Item = Self> {
+                let asid = TlbiRVA::ASID.val(asid as u64).value;
+                RangeOperands::new::<G>(va, len).map(move |op| Self(op | asid))
+            }
+        }
+
+        impl sealed::Tlbi for $A {
+            #[inline(always)]
+            fn tlbi(&self) {
+                match () {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!(".arch_extension tlb-rmi\ntlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                    },
+
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    };
+}
+
+tlbi_rva!(RVAE1);
+tlbi_rva!(RVAE1IS);
+tlbi_rva!(RVALE1);
+tlbi_rva!(RVALE1IS);
+tlbi_rva!(RVAE1OS);
+tlbi_rva!(RVALE1OS);
+
+tlbi_rva!(RVAE2);
+tlbi_rva!(RVAE2IS);
+tlbi_rva!(RVALE2);
+tlbi_rva!(RVALE2IS);
+
+macro_rules! tlbi_rvaa {
+    ($A:ident) => {
+        pub struct $A(u64);
+
+        impl $A {
+            /// Build the operations invalidating `[va, va + len)` for all ASIDs
+            ///
+            /// Large or irregular ranges need more than one operation.
+            #[inline]
+            pub fn new<G: Granule>(va: usize, len: usize) -> impl Iterator<Item = Self> {
+                RangeOperands::new::<G>(va, len).map(Self)
+            }
+        }
+
+        impl sealed::Tlbi for $A {
+            #[inline(always)]
+            fn tlbi(&self) {
+                match () {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!(".arch_extension tlb-rmi\ntlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                    },
+
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    };
+}
+
+tlbi_rvaa!(RVAAE1);
+tlbi_rvaa!(RVAAE1IS);
+tlbi_rvaa!(RVAALE1);
+tlbi_rvaa!(RVAALE1IS);
+tlbi_rvaa!(RVAAE1OS);
+tlbi_rvaa!(RVAALE1OS);
+
+macro_rules! tlbi_ipa {
+    ($A:ident $(, $ext:literal)?) => {
+        pub struct $A(u64);
+
+        impl $A {
+            /// Invalidate the stage 2 entries for `ipa` in the current VMID
+            #[inline]
+            pub fn new(ipa: usize) -> Self {
+                Self(TlbiIPA::IPA.val(ipa_to_tlbi_ipa(ipa)).value)
+            }
+        }
+
+        impl sealed::Tlbi for $A {
+            #[inline(always)]
+            fn tlbi(&self) {
+                match () {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!($(".arch_extension ", $ext, "\n",)? "tlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                    },
+
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    };
+}
+
+// Stage 2 operations by IPA only invalidate stage 2 entries. Entries combining stage 1 and
+// stage 2 must be removed with VMALLE1(IS) afterwards, see `tlb::flush_ipa_range()`.
+
+tlbi_ipa!(IPAS2E1);
+tlbi_ipa!(IPAS2E1IS);
+tlbi_ipa!(IPAS2E1OS, "tlb-rmi");
+tlbi_ipa!(IPAS2LE1);
+tlbi_ipa!(IPAS2LE1IS);
+tlbi_ipa!(IPAS2LE1OS, "tlb-rmi");
+
+macro_rules! tlbi_ripa {
+    ($A:ident) => {
+        pub struct $A(u64);
+
+        impl $A {
+            /// Build the operations invalidating the stage 2 entries of `[ipa, ipa + len)`
+            /// in the current VMID
+            ///
+            /// Large or irregular ranges need more than one operation.
+            #[inline]
+            pub fn new<G: Granule>(ipa: usize, len: usize) -> impl Iterator<Item = Self> {
+                RangeOperands::new::<G>(ipa, len).map(Self)
+            }
+        }
+
+        impl sealed::Tlbi for $A {
+            #[inline(always)]
+            fn tlbi(&self) {
+                match () {
+                    #[cfg(target_arch = "aarch64")]
+                    () => unsafe {
+                        core::arch::asm!(concat!(".arch_extension tlb-rmi\ntlbi ", stringify!($A), ", {}"), in(reg) self.0, options(nostack))
+                    },
+
+                    #[cfg(not(target_arch = "aarch64"))]
+                    () => unimplemented!(),
+                }
+            }
+        }
+    };
+}
+
+tlbi_ripa!(RIPAS2E1);
+tlbi_ripa!(RIPAS2E1IS);
+tlbi_ripa!(RIPAS2E1OS);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::tte::Granule4KB;
+
+    /// First page and number of pages of each operation
+    fn ranges(va: usize, len: usize) -> impl Iterator<Item = (u64, u64)> {
+        RangeOperands::new::<Granule4KB>(va, len).map(|op| {
+            let num = (op >> 39) & 0x1F;
+            let scale = (op >> 44) & 0b11;
+            (op & ((1 << 37) - 1), (num + 1) << (5 * scale + 1))
+        })
+    }
+
+    #[test]
+    fn test_range_operands() {
+        // A single page is rounded up to the smallest range of two pages
+        assert!(ranges(0x4000_0000, 0x1000).eq([(0x4_0000, 2)]));
+        // 66 pages: 2 at SCALE 0, 64 at SCALE 1
+        assert!(ranges(0, 66 * 0x1000).eq([(0, 2), (2, 64)]));
+        assert_eq!(ranges(0x1000, 0).count(), 0);
+
+        // The last page of the address space
+        let va = usize::MAX - Granule4KB::SIZE + 1;
+        let pages: u64 = ranges(va, Granule4KB::SIZE).map(|(_, n)| n).sum();
+        assert_eq!(pages, 2);
+        let pages: u64 = ranges(va - Granule4KB::SIZE, 2 * Granule4KB::SIZE)
+            .map(|(_, n)| n)
+            .sum();
+        assert_eq!(pages, 2);
+    }
+}
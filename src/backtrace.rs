@@ -0,0 +1,168 @@
+//! Frame pointer based stack unwinding.
+//!
+//! With frame pointers enabled (`-C force-frame-pointers=yes`), x29 points at a 16-byte
+//! frame record holding the caller's x29 and the return address x30. [`Backtrace`] follows
+//! that chain and yields the return addresses. Every record is checked against the given
+//! stack bounds before it is read, so a corrupted chain ends the walk instead of faulting.
+//!
+//! Return addresses signed by pointer authentication are yielded with the PAC removed.
+
+use core::ops::Range;
+
+use crate::exception::TrapFrame;
+
+/// Iterator over the return addresses of a frame pointer chain
+#[derive(Debug, Clone)]
+pub struct Backtrace {
+    fp: usize,
+    bounds: Range<usize>,
+    /// Address yielded before walking the chain, e.g. ELR of an exception
+    first: Option<usize>,
+    remaining: usize,
+}
+
+impl Backtrace {
+    /// Default limit on the number of yielded addresses
+    pub const MAX_DEPTH: usize = 64;
+
+    /// Walk the chain starting at the frame record `fp`
+    ///
+    /// # Safety
+    ///
+    /// `bounds` must be readable memory, typically the stack the frame records live on.
+    pub unsafe fn new(fp: usize, bounds: Range<usize>) -> Self {
+        Self {
+            fp,
+            bounds,
+            first: None,
+            remaining: Self::MAX_DEPTH,
+        }
+    }
+
+    /// Walk the interrupted context of an exception: ELR first, then the chain from its x29
+    ///
+    /// The link register of the interrupted function is not yielded, it may not have been
+    /// saved to a frame record yet.
+    ///
+    /// # Safety
+    ///
+    /// `bounds` must be readable memory, the stack of the interrupted context.
+    pub unsafe fn from_trap_frame(frame: &TrapFrame, bounds: Range<usize>) -> Self {
+        Self {
+            first: Some(frame.elr as usize),
+            ..unsafe { Self::new(frame.x[29] as usize, bounds) }
+        }
+    }
+
+    /// Limit the number of yielded addresses
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.remaining = depth;
+        self
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if let Some(first) = self.first.take() {
+            self.remaining -= 1;
+            return Some(first);
+        }
+
+        let fp = self.fp;
+        if fp == 0
+            || !fp.is_multiple_of(8)
+            || fp < self.bounds.start
+            || fp.checked_add(16).is_none_or(|end| end > self.bounds.end)
+        {
+            return None;
+        }
+        // SAFETY: the record lies within the readable bounds
+        let (next_fp, lr) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if lr == 0 {
+            return None;
+        }
+        #[cfg(target_arch = "aarch64")]
+        let lr = crate::pauth::strip_pac(lr);
+        // The stack grows down, callers' records are at higher addresses. Anything else is
+        // a corrupted chain, stop after this frame to avoid loops.
+        self.fp = if next_fp > fp { next_fp } else { 0 };
+        self.remaining -= 1;
+        Some(lr)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod current {
+    use core::{arch::asm, ops::Range};
+
+    use super::Backtrace;
+
+    /// Current frame pointer (x29)
+    #[inline(always)]
+    pub fn frame_pointer() -> usize {
+        let fp: usize;
+        unsafe { asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+        fp
+    }
+
+    impl Backtrace {
+        /// Walk the chain of the calling function
+        ///
+        /// # Safety
+        ///
+        /// `bounds` must be readable memory, the current stack.
+        #[inline(always)]
+        pub unsafe fn current(bounds: Range<usize>) -> Self {
+            unsafe { Self::new(frame_pointer(), bounds) }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use current::frame_pointer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backtrace() {
+        let mut stack = [0usize; 8];
+        let base = stack.as_mut_ptr() as usize;
+        let word = core::mem::size_of::<usize>();
+        // Three records at stack[0], stack[2], stack[4], the last one ends the chain
+        stack[..6].copy_from_slice(&[base + 2 * word, 0x1000, base + 4 * word, 0x2000, 0, 0x3000]);
+        let bounds = base..base + stack.len() * word;
+
+        let frames: Vec<usize> = unsafe { Backtrace::new(base, bounds.clone()) }.collect();
+        assert_eq!(frames, [0x1000, 0x2000, 0x3000]);
+
+        let trap = TrapFrame {
+            elr: 0x500,
+            x: {
+                let mut x = [0; 31];
+                x[29] = base as u64;
+                x
+            },
+            ..Default::default()
+        };
+        let frames: Vec<usize> = unsafe { Backtrace::from_trap_frame(&trap, bounds.clone()) }
+            .max_depth(2)
+            .collect();
+        assert_eq!(frames, [0x500, 0x1000]);
+
+        // A loop in the chain stops after the offending frame
+        stack[2] = base;
+        let frames: Vec<usize> =
+            unsafe { Backtrace::new(stack.as_ptr() as usize, bounds) }.collect();
+        assert_eq!(frames, [0x1000, 0x2000]);
+    }
+}
@@ -0,0 +1,550 @@
+//! Early boot: leaving the exception level the core was started at.
+//!
+//! [`drop_to_el1`] performs the EL2 to EL1 transition a kernel started at EL2 needs: it
+//! configures EL1 as the AArch64 host of everything below, gives it access to the physical
+//! timer and FP/SIMD, and enters it with ERET. [`drop_from_el3`] is the firmware
+//! counterpart, leaving EL3 for EL2 or EL1 in either security state.
+//!
+//! [`enable_identity_mmu`] then gets EL1 from physical to virtual execution: it builds an
+//! identity map of the given regions with 4KB pages and blocks, programs MAIR_EL1, TCR_EL1
+//! and TTBR0_EL1 and enables the MMU and caches.
+
+use crate::structures::{
+    mapping::MappingRequest,
+    tte::{AccessPermission, Granule, Granule4KB, RangeChunk, Shareability, TTE4K48, split_range},
+};
+
+/// Configuration of the EL1 environment set up by [`drop_to_el1`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct El1Config {
+    /// Leave HCR_EL2.E2H set if it is, otherwise EL2 is switched to the non-VHE layout
+    pub keep_e2h: bool,
+    /// Allow EL1 and EL0 to access the physical counter and timer (CNTHCTL_EL2)
+    pub timer_access: bool,
+    /// Do not trap FP/SIMD, SVE and SME to EL2 (CPTR_EL2)
+    pub fp_simd: bool,
+    /// Enter EL1 with D, A, I and F masked
+    pub mask_interrupts: bool,
+}
+
+impl El1Config {
+    /// Non-VHE, physical timer and FP/SIMD accessible, exceptions masked
+    pub const fn new() -> Self {
+        Self {
+            keep_e2h: false,
+            timer_access: true,
+            fp_simd: true,
+            mask_interrupts: true,
+        }
+    }
+}
+
+impl Default for El1Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Memory type of a [`MemoryRegion`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Normal memory, inner and outer write-back cacheable
+    Normal,
+    /// Device-nGnRnE memory, never executable
+    Device,
+}
+
+/// A physical range to be identity mapped by [`enable_identity_mmu`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub size: usize,
+    pub kind: MemoryKind,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl MemoryRegion {
+    /// Read/write normal memory, not executable
+    pub const fn normal(base: u64, size: usize) -> Self {
+        Self {
+            base,
+            size,
+            kind: MemoryKind::Normal,
+            writable: true,
+            executable: false,
+        }
+    }
+
+    /// Read-only executable normal memory, e.g. the kernel text
+    pub const fn code(base: u64, size: usize) -> Self {
+        Self {
+            writable: false,
+            executable: true,
+            ..Self::normal(base, size)
+        }
+    }
+
+    /// Device memory, e.g. MMIO
+    pub const fn device(base: u64, size: usize) -> Self {
+        Self {
+            kind: MemoryKind::Device,
+            ..Self::normal(base, size)
+        }
+    }
+
+    fn mapping(&self) -> MappingRequest {
+        let mut req = MappingRequest::identity(self.base, self.size);
+        req.access = if self.writable {
+            AccessPermission::PrivilegedReadWrite
+        } else {
+            AccessPermission::PrivilegedReadOnly
+        };
+        match self.kind {
+            MemoryKind::Normal => {
+                req.attr_index = MAIR_NORMAL_INDEX;
+                req.privileged_executable = self.executable;
+            }
+            MemoryKind::Device => {
+                req.attr_index = MAIR_DEVICE_INDEX;
+                req.shareability = Shareability::OuterShareable;
+            }
+        }
+        req
+    }
+}
+
+/// One 4KB translation table
+#[repr(C, align(4096))]
+#[derive(Clone)]
+pub struct PageTableStorage([u64; 512]);
+
+impl PageTableStorage {
+    pub const fn new() -> Self {
+        Self([0; 512])
+    }
+}
+
+impl Default for PageTableStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Errors returned by [`build_identity_map`] and [`enable_identity_mmu`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// A region is not aligned to 4KB
+    Unaligned,
+    /// More translation tables are needed than provided
+    OutOfTables,
+    /// Two regions overlap
+    Overlap,
+}
+
+/// MAIR_EL1 attribute index of Device-nGnRnE memory
+pub const MAIR_DEVICE_INDEX: u64 = 0;
+/// MAIR_EL1 attribute index of normal write-back memory
+pub const MAIR_NORMAL_INDEX: u64 = 1;
+/// MAIR_EL1 attribute index of normal non-cacheable memory
+pub const MAIR_NON_CACHEABLE_INDEX: u64 = 2;
+/// MAIR_EL1 value matching the attribute indices above, Device-nGnRnE is 0x00
+pub const MAIR_DEFAULT: u64 =
+    (0xFF << (8 * MAIR_NORMAL_INDEX)) | (0x44 << (8 * MAIR_NON_CACHEABLE_INDEX));
+
+/// Build an identity map of `regions` in `tables`, `tables[0]` being the level 0 table
+///
+/// Uses the largest blocks the regions are aligned to. Returns the number of tables used.
+pub fn build_identity_map(
+    regions: &[MemoryRegion],
+    tables: &mut [PageTableStorage],
+) -> Result<usize, MapError> {
+    if tables.is_empty() {
+        return Err(MapError::OutOfTables);
+    }
+    tables[0] = PageTableStorage::new();
+    let mut used = 1;
+    for region in regions.iter().filter(|r| r.size != 0) {
+        if region.base & Granule4KB::MASK != 0 || region.size as u64 & Granule4KB::MASK != 0 {
+            return Err(MapError::Unaligned);
+        }
+        let req = region.mapping();
+        for chunk in split_range::<Granule4KB>(region.base, region.base, region.size) {
+            map_chunk(tables, &mut used, &chunk, &req)?;
+        }
+    }
+    Ok(used)
+}
+
+fn map_chunk(
+    tables: &mut [PageTableStorage],
+    used: &mut usize,
+    chunk: &RangeChunk,
+    req: &MappingRequest,
+) -> Result<(), MapError> {
+    let base = tables.as_ptr() as u64;
+    let mut table = 0;
+    for level in 0..chunk.level {
+        let index = TTE4K48::calculate_index(chunk.va, level);
+        let entry = TTE4K48::new(tables[table].0[index]);
+        table = if entry.is_table() {
+            ((entry.address() - base) / size_of::<PageTableStorage>() as u64) as usize
+        } else if entry.is_valid() {
+            return Err(MapError::Overlap);
+        } else {
+            let next = *used;
+            if next == tables.len() {
+                return Err(MapError::OutOfTables);
+            }
+            *used += 1;
+            tables[next] = PageTableStorage::new();
+            let addr = &tables[next] as *const PageTableStorage as u64;
+            tables[table].0[index] = TTE4K48::new_table(addr).get();
+            next
+        };
+    }
+
+    let index = TTE4K48::calculate_index(chunk.va, chunk.level);
+    if TTE4K48::new(tables[table].0[index]).is_valid() {
+        return Err(MapError::Overlap);
+    }
+    let mut entry = TTE4K48::new_block_mapping(chunk.pa, req);
+    if chunk.level == 3 {
+        // Page descriptors share the encoding of table descriptors
+        entry.set_is_table();
+    }
+    tables[table].0[index] = entry.get();
+    Ok(())
+}
+
+#[cfg(target_arch = "aarch64")]
+mod ops {
+    use core::arch::asm;
+
+    use aarch64_cpu::{
+        asm::barrier::{SY, isb},
+        registers::*,
+    };
+
+    use super::{
+        El1Config, MAIR_DEFAULT, MapError, MemoryRegion, PageTableStorage, build_identity_map,
+    };
+    use crate::{
+        cache::{CacheOp, dcache_range},
+        el::ExceptionLevel,
+        exception::Spsr,
+        features::{Feature, is_supported},
+        mmu::{Sctlr, enable_mmu},
+        timer::el2::{GuestTimerAccess, set_guest_access, set_virtual_offset},
+        traps::{CptrEl2Builder, El2Layout},
+    };
+
+    const HCR_RW: u64 = 1 << 31;
+    const HCR_E2H: u64 = 1 << 34;
+    const HCR_APK: u64 = 1 << 40;
+    const HCR_API: u64 = 1 << 41;
+    const HCR_ATA: u64 = 1 << 56;
+
+    /// HCR_EL2 for an AArch64 EL1 without stage 2, with pointer authentication (API, APK)
+    /// and allocation tag accesses (ATA) left untrapped where implemented
+    fn hcr_el1_host() -> u64 {
+        let mut hcr = HCR_RW;
+        if is_supported(Feature::PAuth) {
+            hcr |= HCR_API | HCR_APK;
+        }
+        if is_supported(Feature::Mte2) {
+            hcr |= HCR_ATA;
+        }
+        hcr
+    }
+
+    /// SPSR of ELxh with all exceptions masked
+    const fn spsr_elh(el: u8) -> u64 {
+        Spsr::new().el(el).sp_elx(true).mask_all(true).value()
+    }
+
+    /// Program the EL2 controls [`drop_to_el1`] documents for an AArch64 EL1 host
+    fn setup_el2_for_el1(config: El1Config) {
+        let e2h = config.keep_e2h && HCR_EL2.get() & HCR_E2H != 0;
+        HCR_EL2.set(hcr_el1_host() | if e2h { HCR_E2H } else { 0 });
+        isb(SY);
+
+        set_guest_access(if config.timer_access {
+            GuestTimerAccess::ALL
+        } else {
+            GuestTimerAccess::NONE
+        });
+        set_virtual_offset(0);
+
+        let cptr = CptrEl2Builder::new()
+            .trap_fp(!config.fp_simd)
+            .trap_sve(!config.fp_simd || !is_supported(Feature::Sve))
+            .trap_sme(!config.fp_simd || !is_supported(Feature::Sme));
+        // E2H is RES1 without FEAT_E2H0, encode for the layout that is actually in effect
+        CPTR_EL2.set(cptr.build(El2Layout::current()));
+
+        unsafe {
+            asm!(
+                "msr vpidr_el2, {midr}",
+                "msr vmpidr_el2, {mpidr}",
+                midr = in(reg) MIDR_EL1.get(),
+                mpidr = in(reg) MPIDR_EL1.get(),
+                options(nomem, nostack),
+            );
+        }
+    }
+
+    /// Switch from EL2 to EL1 and continue at `entry` with the stack `stack`
+    ///
+    /// Programs HCR_EL2 (EL1 is AArch64, no stage 2, pointer authentication and MTE tag
+    /// accesses are not trapped where implemented), CNTHCTL_EL2 and CNTVOFF_EL2, CPTR_EL2,
+    /// VPIDR/VMPIDR_EL2 (EL1 sees the real MIDR and MPIDR) and a safe SCTLR_EL1 with the
+    /// MMU and caches off, then sets SP_EL1, SPSR_EL2 and ELR_EL2 and executes ERET.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL2. `stack` must be the top of a 16-byte aligned stack that
+    /// `entry` may use. Nothing at EL2 is reachable afterwards unless an EL2 vector table
+    /// is installed.
+    pub unsafe fn drop_to_el1(entry: extern "C" fn() -> !, stack: usize, config: El1Config) -> ! {
+        setup_el2_for_el1(config);
+        SCTLR_EL1.set(Sctlr::new().value());
+
+        let spsr = Spsr::new()
+            .el(1)
+            .sp_elx(true)
+            .mask_all(config.mask_interrupts)
+            .value();
+        unsafe {
+            asm!(
+                "msr sp_el1, {stack}",
+                "msr elr_el2, {entry}",
+                "msr spsr_el2, {spsr}",
+                "eret",
+                stack = in(reg) stack,
+                entry = in(reg) entry as usize,
+                spsr = in(reg) spsr,
+                options(noreturn),
+            )
+        }
+    }
+
+    const SCR_NS: u64 = 1 << 0;
+    const SCR_RES1: u64 = (1 << 4) | (1 << 5);
+    const SCR_HCE: u64 = 1 << 8;
+    const SCR_RW: u64 = 1 << 10;
+    const SCR_APK: u64 = 1 << 16;
+    const SCR_API: u64 = 1 << 17;
+    const SCR_EEL2: u64 = 1 << 18;
+    const SCR_ATA: u64 = 1 << 26;
+
+    /// SCTLR_EL2 with only the RES1 bits set, MMU and caches off
+    const SCTLR_EL2_RES1: u64 = 0x30C5_0830;
+
+    /// Switch from EL3 to `target_el` (EL2 or EL1) and continue at `entry` with the stack
+    /// `stack`
+    ///
+    /// Programs SCR_EL3: NS from `secure`, the lower levels are AArch64 (RW), HVC is
+    /// enabled when entering EL2 (HCE) and SMC stays enabled (SMD clear). Secure EL2
+    /// additionally sets EEL2. Pointer authentication keys and instructions (APK, API) and
+    /// allocation tag accesses (ATA) are not trapped to EL3 where implemented, nor is
+    /// FP/SIMD (CPTR_EL3). The target's SCTLR is reset with the MMU and caches off and,
+    /// when entering EL1 with EL2 implemented, EL2 is programmed like [`drop_to_el1`] does
+    /// with [`El1Config::new`] (HCR_EL2, timers, CPTR_EL2, VPIDR/VMPIDR_EL2). Then SP_ELx,
+    /// SPSR_EL3 and ELR_EL3 are set and ERET is executed with all exceptions masked.
+    ///
+    /// # Panics
+    ///
+    /// If `target_el` is not EL1 or EL2, or Secure EL2 is requested without FEAT_SEL2.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL3. `stack` must be the top of a 16-byte aligned stack that
+    /// `entry` may use.
+    pub unsafe fn drop_from_el3(
+        target_el: ExceptionLevel,
+        entry: extern "C" fn() -> !,
+        stack: usize,
+        secure: bool,
+    ) -> ! {
+        let mut scr = SCR_RES1 | SCR_RW;
+        if !secure {
+            scr |= SCR_NS;
+        }
+        if is_supported(Feature::PAuth) {
+            scr |= SCR_API | SCR_APK;
+        }
+        if is_supported(Feature::Mte2) {
+            scr |= SCR_ATA;
+        }
+        match target_el {
+            ExceptionLevel::EL2 => {
+                scr |= SCR_HCE;
+                if secure {
+                    assert!(is_supported(Feature::Sel2), "Secure EL2 is not implemented");
+                    scr |= SCR_EEL2;
+                }
+            }
+            ExceptionLevel::EL1 => {}
+            el => panic!("cannot drop from EL3 to {el:?}"),
+        }
+
+        unsafe {
+            asm!(
+                "msr scr_el3, {scr}",
+                "msr cptr_el3, xzr",
+                "isb",
+                scr = in(reg) scr,
+                options(nomem, nostack),
+            );
+        }
+
+        if target_el == ExceptionLevel::EL2 {
+            SCTLR_EL2.set(SCTLR_EL2_RES1);
+            unsafe {
+                asm!(
+                    "msr sp_el2, {stack}",
+                    "msr elr_el3, {entry}",
+                    "msr spsr_el3, {spsr}",
+                    "eret",
+                    stack = in(reg) stack,
+                    entry = in(reg) entry as usize,
+                    spsr = in(reg) spsr_elh(2),
+                    options(noreturn),
+                )
+            }
+        } else {
+            if is_supported(Feature::El2) {
+                setup_el2_for_el1(El1Config::new());
+            }
+            SCTLR_EL1.set(Sctlr::new().value());
+            unsafe {
+                asm!(
+                    "msr sp_el1, {stack}",
+                    "msr elr_el3, {entry}",
+                    "msr spsr_el3, {spsr}",
+                    "eret",
+                    stack = in(reg) stack,
+                    entry = in(reg) entry as usize,
+                    spsr = in(reg) spsr_elh(1),
+                    options(noreturn),
+                )
+            }
+        }
+    }
+
+    /// TCR_EL1 for a 48-bit TTBR0 region with 4KB granule and write-back inner shareable
+    /// walks, TTBR1 walks disabled, without IPS. TG1 is set to 4KB as well, its reset value
+    /// 0b00 is reserved even with the walks disabled.
+    const TCR_DEFAULT: u64 = 16 // T0SZ
+        | (0b01 << 8) // IRGN0 write-back write-allocate
+        | (0b01 << 10) // ORGN0 write-back write-allocate
+        | (0b11 << 12) // SH0 inner shareable
+        | (1 << 23) // EPD1
+        | (0b10 << 30); // TG1 4KB
+
+    /// Identity map `regions` and enable the MMU and caches at EL1
+    ///
+    /// Builds the map with [`build_identity_map`], cleans and invalidates the tables to the
+    /// PoC (they were written with the MMU off, the walker reads them cacheably), programs
+    /// MAIR_EL1 with [`MAIR_DEFAULT`], TCR_EL1 for a 48-bit TTBR0 region with the IPS the
+    /// core supports and TTBR0_EL1 and finally enables the MMU with the data and
+    /// instruction caches through [`enable_mmu`].
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL1 with the MMU off. The regions must cover the executing code,
+    /// its stack and `tables`, and `tables` must not be touched while the map is in use.
+    pub unsafe fn enable_identity_mmu(
+        regions: &[MemoryRegion],
+        tables: &mut [PageTableStorage],
+    ) -> Result<(), MapError> {
+        let used = build_identity_map(regions, tables)?;
+        let root = tables.as_ptr() as usize;
+        dcache_range(
+            CacheOp::CleanAndInvalidate,
+            root,
+            used * size_of::<PageTableStorage>(),
+        );
+
+        // PARange encodings above 48 bits need 52-bit descriptors
+        let ips = (ID_AA64MMFR0_EL1.get() & 0xF).min(0b101);
+        MAIR_EL1.set(MAIR_DEFAULT);
+        TCR_EL1.set(TCR_DEFAULT | (ips << 32));
+        TTBR0_EL1.set(root as u64);
+        isb(SY);
+
+        unsafe { enable_mmu(Sctlr::new().dcache(true).icache(true)) };
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use ops::{drop_from_el3, drop_to_el1, enable_identity_mmu};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(table: &PageTableStorage, va: u64, level: usize) -> TTE4K48 {
+        TTE4K48::new(table.0[TTE4K48::calculate_index(va, level)])
+    }
+
+    #[test]
+    fn test_build_identity_map() {
+        let mut tables = [const { PageTableStorage::new() }; 6];
+        let regions = [
+            // A 2MB block followed by a 4KB page
+            MemoryRegion::code(0x4000_0000, 0x20_1000),
+            MemoryRegion::device(0x0900_0000, 0x1000),
+        ];
+        assert_eq!(build_identity_map(&regions, &mut tables), Ok(6));
+
+        let l1 = entry(&tables[0], 0x4000_0000, 0);
+        assert!(l1.is_table());
+        assert_eq!(l1.address(), &tables[1] as *const _ as u64);
+
+        let block = entry(&tables[2], 0x4000_0000, 2);
+        assert!(block.is_block());
+        assert_eq!(block.address_with_page_level(2), 0x4000_0000);
+        assert_eq!(block.attr_index(), MAIR_NORMAL_INDEX);
+        assert_eq!(
+            block.access_permission(),
+            AccessPermission::PrivilegedReadOnly
+        );
+        assert!(block.is_privileged_executable());
+
+        // The page past the block needs a level 3 table
+        assert!(entry(&tables[2], 0x4020_0000, 2).is_table());
+        let page = entry(&tables[3], 0x4020_0000, 3);
+        assert!(page.is_valid());
+        assert_eq!(page.address_with_page_level(3), 0x4020_0000);
+        assert_eq!(page.attr_index(), MAIR_NORMAL_INDEX);
+
+        let device = entry(&tables[5], 0x0900_0000, 3);
+        assert_eq!(device.address_with_page_level(3), 0x0900_0000);
+        assert_eq!(device.attr_index(), MAIR_DEVICE_INDEX);
+        assert_eq!(device.shareability(), Shareability::OuterShareable);
+        assert!(!device.is_privileged_executable());
+        assert_eq!((MAIR_DEFAULT >> (8 * MAIR_DEVICE_INDEX)) & 0xFF, 0x00);
+        assert_eq!((MAIR_DEFAULT >> (8 * MAIR_NORMAL_INDEX)) & 0xFF, 0xFF);
+    }
+
+    #[test]
+    fn test_build_identity_map_errors() {
+        let mut tables = [const { PageTableStorage::new() }; 4];
+        let normal = MemoryRegion::normal(0x4000_0000, 0x1000);
+        assert_eq!(
+            build_identity_map(&[normal, normal], &mut tables),
+            Err(MapError::Overlap)
+        );
+        assert_eq!(
+            build_identity_map(&[MemoryRegion::normal(0x4000_0800, 0x1000)], &mut tables),
+            Err(MapError::Unaligned)
+        );
+        assert_eq!(
+            build_identity_map(&[normal], &mut tables[..3]),
+            Err(MapError::OutOfTables)
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use aarch64_cpu::registers::*;
+
+use super::{CacheTypeInfo, dc_zva_block_size};
+
+/// Support for cleaning to the Point of Persistence (ID_AA64ISAR1_EL1.DPB)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PersistenceSupport {
+    /// Neither DC CVAP nor DC CVADP is implemented
+    None,
+    /// FEAT_DPB, DC CVAP is implemented
+    Dpb,
+    /// FEAT_DPB2, DC CVAP and DC CVADP are implemented
+    Dpb2,
+}
+
+impl PersistenceSupport {
+    /// Read the support level of the executing core
+    pub fn detect() -> Self {
+        match ID_AA64ISAR1_EL1.get() & 0xF {
+            0 => Self::None,
+            1 => Self::Dpb,
+            _ => Self::Dpb2,
+        }
+    }
+}
+
+/// Optional cache maintenance instructions implemented by the executing core
+///
+/// Instructions that are not implemented are UNDEFINED, check here before issuing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheCapabilities {
+    /// DC CVAP / DC CVADP
+    pub persistence: PersistenceSupport,
+    /// DC *G*VA* allocation tag operations (FEAT_MTE2, ID_AA64PFR1_EL1.MTE >= 2)
+    pub mte_tags: bool,
+    /// DC ZVA block size, `None` if DC ZVA is prohibited
+    pub zva_block_size: Option<usize>,
+    /// D-cache clean to PoU not required for instruction coherence (CTR_EL0.IDC)
+    pub idc: bool,
+    /// I-cache invalidation not required for instruction coherence (CTR_EL0.DIC)
+    pub dic: bool,
+}
+
+/// Probe the optional cache maintenance instructions of the executing core
+pub fn capabilities() -> CacheCapabilities {
+    let ctr = CacheTypeInfo::read();
+    CacheCapabilities {
+        persistence: PersistenceSupport::detect(),
+        mte_tags: ID_AA64PFR1_EL1.read(ID_AA64PFR1_EL1::MTE) >= 2,
+        zva_block_size: dc_zva_block_size(),
+        idc: ctr.idc,
+        dic: ctr.dic,
+    }
+}
@@ -0,0 +1,114 @@
+use core::arch::asm;
+
+/// Copies shorter than this are not worth the setup of the non-temporal loop
+const NONTEMPORAL_THRESHOLD: usize = 256;
+
+/// Bytes moved per iteration of the non-temporal loop
+const NONTEMPORAL_BLOCK: usize = 64;
+
+/// Copies `len` bytes from `src` to `dst` with non-temporal loads and stores (LDNP/STNP).
+///
+/// The hint tells the core the data is not going to be reused, so large copies such as
+/// framebuffers or DMA staging buffers do not evict the working set from the caches. Copies
+/// shorter than 256 bytes and the unaligned edges use [`core::ptr::copy_nonoverlapping`].
+///
+/// # Safety
+///
+/// Same contract as [`core::ptr::copy_nonoverlapping`]. Both ranges must be Normal memory.
+pub unsafe fn copy_nontemporal(dst: *mut u8, src: *const u8, len: usize) {
+    if len < NONTEMPORAL_THRESHOLD {
+        unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+        return;
+    }
+
+    // Align the destination, stores crossing cache lines are the expensive part
+    let head = dst.align_offset(16).min(len);
+    unsafe { core::ptr::copy_nonoverlapping(src, dst, head) };
+
+    let blocks = (len - head) / NONTEMPORAL_BLOCK;
+    let body = blocks * NONTEMPORAL_BLOCK;
+    let (dst_body, src_body) = unsafe { (dst.add(head), src.add(head)) };
+
+    if blocks > 0 {
+        unsafe {
+            asm!(
+                "2:",
+                "ldnp {a}, {b}, [{src}]",
+                "ldnp {c}, {d}, [{src}, #16]",
+                "stnp {a}, {b}, [{dst}]",
+                "stnp {c}, {d}, [{dst}, #16]",
+                "ldnp {a}, {b}, [{src}, #32]",
+                "ldnp {c}, {d}, [{src}, #48]",
+                "stnp {a}, {b}, [{dst}, #32]",
+                "stnp {c}, {d}, [{dst}, #48]",
+                "add {src}, {src}, #64",
+                "add {dst}, {dst}, #64",
+                "subs {n}, {n}, #1",
+                "b.ne 2b",
+                src = inout(reg) src_body => _,
+                dst = inout(reg) dst_body => _,
+                n = inout(reg) blocks => _,
+                a = out(reg) _,
+                b = out(reg) _,
+                c = out(reg) _,
+                d = out(reg) _,
+                options(nostack),
+            );
+        }
+    }
+
+    let tail = len - head - body;
+    unsafe { core::ptr::copy_nonoverlapping(src_body.add(body), dst_body.add(body), tail) };
+}
+
+/// Copies `len` bytes from Normal memory at `src` to Device memory at `dst`.
+///
+/// Device memory faults on unaligned accesses and pair or vector accesses may not be
+/// supported by the device, so only naturally aligned single accesses are used: bytes up to
+/// the first 8-byte boundary of `dst`, then 64-bit words, then the remaining bytes.
+///
+/// # Safety
+///
+/// `src` must be valid for reads and `dst` valid for writes of `len` bytes.
+pub unsafe fn mmio_copy_to(dst: *mut u8, src: *const u8, len: usize) {
+    let head = dst.align_offset(8).min(len);
+    let words = (len - head) / 8;
+    unsafe {
+        for i in 0..head {
+            dst.add(i).write_volatile(src.add(i).read());
+        }
+        for i in 0..words {
+            let offset = head + i * 8;
+            let word = src.add(offset).cast::<u64>().read_unaligned();
+            dst.add(offset).cast::<u64>().write_volatile(word);
+        }
+        for i in head + words * 8..len {
+            dst.add(i).write_volatile(src.add(i).read());
+        }
+    }
+}
+
+/// Copies `len` bytes from Device memory at `src` to Normal memory at `dst`.
+///
+/// See [`mmio_copy_to`] for the access pattern, alignment is taken from `src`.
+///
+/// # Safety
+///
+/// `src` must be valid for reads and `dst` valid for writes of `len` bytes.
+pub unsafe fn mmio_copy_from(dst: *mut u8, src: *const u8, len: usize) {
+    let head = src.align_offset(8).min(len);
+    let words = (len - head) / 8;
+    unsafe {
+        for i in 0..head {
+            dst.add(i).write(src.add(i).read_volatile());
+        }
+        for i in 0..words {
+            let offset = head + i * 8;
+            let word = src.add(offset).cast::<u64>().read_volatile();
+            dst.add(offset).cast::<u64>().write_unaligned(word);
+        }
+        for i in head + words * 8..len {
+            dst.add(i).write(src.add(i).read_volatile());
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use core::arch::asm;
+
+/// Level 1 instruction cache indexing and tagging policy (CTR_EL0.L1Ip)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum L1IPolicy {
+    /// VMID-aware Physical Index, Physical Tag
+    Vpipt,
+    /// ASID-tagged Virtual Index, Virtual Tag
+    Aivivt,
+    /// Virtual Index, Physical Tag
+    Vipt,
+    /// Physical Index, Physical Tag
+    Pipt,
+}
+
+/// Decoded Cache Type Register (CTR_EL0)
+///
+/// All sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheTypeInfo {
+    /// Smallest instruction cache line size (IminLine)
+    pub icache_line_size: usize,
+    /// Smallest data or unified cache line size (DminLine)
+    pub dcache_line_size: usize,
+    /// Level 1 instruction cache policy (L1Ip)
+    pub l1i_policy: L1IPolicy,
+    /// Exclusives reservation granule (ERG), 2KB when not reported
+    pub exclusive_reservation_granule: usize,
+    /// Cache writeback granule (CWG), 2KB when not reported
+    ///
+    /// The largest block of memory that can be overwritten by a writeback of a dirty line.
+    /// DMA buffers must be aligned to this size so that unrelated data never shares a line.
+    pub cache_writeback_granule: usize,
+    /// Smallest allocation tag cache line size (TminLine), 0 if not reported
+    pub tag_line_size: usize,
+    /// D-cache clean to PoU is not required for instruction to data coherence (IDC)
+    pub idc: bool,
+    /// I-cache invalidation to PoU is not required for data to instruction coherence (DIC)
+    pub dic: bool,
+}
+
+impl CacheTypeInfo {
+    /// Read CTR_EL0 of the executing core
+    #[inline(always)]
+    pub fn read() -> Self {
+        let ctr_el0: u64;
+        unsafe {
+            asm!("mrs {}, ctr_el0", out(reg) ctr_el0, options(nomem, nostack));
+        }
+        Self::from_bits(ctr_el0)
+    }
+
+    /// Decode a CTR_EL0 value
+    ///
+    /// Line sizes and granules are encoded as log2 of the number of 4-byte words.
+    pub const fn from_bits(ctr_el0: u64) -> Self {
+        const fn words(log2: u64) -> usize {
+            4 << log2
+        }
+        const fn granule(log2: u64) -> usize {
+            // 0 means the granule is not reported, use the architectural maximum of 2KB
+            if log2 == 0 { 2048 } else { words(log2) }
+        }
+
+        let tmin = (ctr_el0 >> 32) & 0x3F;
+        Self {
+            icache_line_size: words(ctr_el0 & 0xF),
+            dcache_line_size: words((ctr_el0 >> 16) & 0xF),
+            l1i_policy: match (ctr_el0 >> 14) & 0b11 {
+                0b00 => L1IPolicy::Vpipt,
+                0b01 => L1IPolicy::Aivivt,
+                0b10 => L1IPolicy::Vipt,
+                _ => L1IPolicy::Pipt,
+            },
+            exclusive_reservation_granule: granule((ctr_el0 >> 20) & 0xF),
+            cache_writeback_granule: granule((ctr_el0 >> 24) & 0xF),
+            tag_line_size: if tmin == 0 { 0 } else { words(tmin) },
+            idc: ctr_el0 & (1 << 28) != 0,
+            dic: ctr_el0 & (1 << 29) != 0,
+        }
+    }
+}
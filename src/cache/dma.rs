@@ -0,0 +1,73 @@
+//! Cache maintenance for buffers shared with non-coherent DMA masters.
+//!
+//! Ownership of a buffer moves to the device with [`sync_for_device`] before the transfer
+//! is started and back to the CPU with [`sync_for_cpu`] once it has completed.
+
+use super::{CacheOp, CacheTypeInfo, dcache_range};
+
+/// Direction of a DMA transfer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// The device reads the buffer
+    ToDevice,
+    /// The device writes the buffer
+    FromDevice,
+    /// The device reads and writes the buffer
+    Bidirectional,
+}
+
+impl DmaDirection {
+    const fn device_writes(self) -> bool {
+        !matches!(self, Self::ToDevice)
+    }
+}
+
+/// Hands the buffer at `addr..addr + len` over to the device.
+///
+/// Dirty lines are cleaned so the device reads what the CPU wrote. For
+/// [`DmaDirection::FromDevice`] the lines are invalidated as well, so no dirty line can be
+/// evicted over the data written by the device.
+///
+/// # Panics
+///
+/// If the device writes the buffer and `addr` or `len` is not a multiple of the cache
+/// writeback granule.
+pub fn sync_for_device(addr: usize, len: usize, direction: DmaDirection) {
+    check_alignment(addr, len, direction);
+    let op = match direction {
+        DmaDirection::ToDevice => CacheOp::Clean,
+        DmaDirection::FromDevice => CacheOp::Invalidate,
+        DmaDirection::Bidirectional => CacheOp::CleanAndInvalidate,
+    };
+    dcache_range(op, addr, len);
+}
+
+/// Hands the buffer at `addr..addr + len` back to the CPU.
+///
+/// Lines speculatively fetched while the device owned the buffer are invalidated, so the
+/// CPU reads what the device wrote. Nothing is needed for [`DmaDirection::ToDevice`].
+///
+/// # Panics
+///
+/// If the device writes the buffer and `addr` or `len` is not a multiple of the cache
+/// writeback granule.
+pub fn sync_for_cpu(addr: usize, len: usize, direction: DmaDirection) {
+    if direction.device_writes() {
+        check_alignment(addr, len, direction);
+        dcache_range(CacheOp::Invalidate, addr, len);
+    }
+}
+
+/// Buffers written by a device must not share a writeback granule with other data,
+/// invalidation would discard that data and a writeback could overwrite the DMA data.
+#[inline]
+fn check_alignment(addr: usize, len: usize, direction: DmaDirection) {
+    if direction.device_writes() {
+        let cwg = CacheTypeInfo::read().cache_writeback_granule;
+        assert!(
+            addr.is_multiple_of(cwg) && len.is_multiple_of(cwg),
+            "DMA buffer {addr:#x}+{len:#x} is not aligned to the cache writeback granule {cwg:#x}"
+        );
+    }
+}
@@ -0,0 +1,552 @@
+use core::arch::asm;
+
+use aarch64_cpu::{
+    asm::barrier::{ISH, NSH, SY, dsb, isb},
+    registers::*,
+};
+
+mod capabilities;
+mod copy;
+mod ctr;
+pub mod dma;
+mod outer;
+mod topology;
+
+pub use capabilities::*;
+pub use copy::*;
+pub use ctr::*;
+pub use outer::{OuterCache, outer_cache, set_outer_cache};
+pub use topology::*;
+
+use crate::asm::cache::{
+    CISW, CIVAC, CSW, CVAC, CVADP, CVAP, CVAU, IALLU, ISW, IVAC, IVAU, ZVA, dc, ic, ic_va,
+};
+
+pub fn icache_flush_all() {
+    ic(IALLU);
+    dsb(NSH);
+    isb(SY);
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum CacheOp {
+    /// Write back to memory
+    Clean,
+    /// Invalidate cache
+    Invalidate,
+    /// Clean and invalidate
+    CleanAndInvalidate,
+}
+
+/// Errors returned by cache maintenance entry points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheError {
+    /// The range to maintain is empty
+    ZeroSize,
+    /// The cache level is not implemented or holds no data
+    InvalidLevel,
+    /// The instruction is not implemented by the executing core
+    NotSupported,
+}
+
+/// Point in the memory system a cache maintenance by VA must reach
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CacheTarget {
+    /// Point of Coherency, where all observers including non-coherent DMA masters see the
+    /// same copy of memory
+    #[default]
+    PoC,
+    /// Point of Unification, where the instruction and data caches and translation table
+    /// walks of a core see the same copy of memory
+    PoU,
+}
+
+/// Returns the smallest data cache line size in bytes (CTR_EL0.DminLine).
+#[inline(always)]
+pub fn cache_line_size() -> usize {
+    CacheTypeInfo::read().dcache_line_size
+}
+
+/// Returns the smallest instruction cache line size in bytes (CTR_EL0.IminLine).
+#[inline(always)]
+pub fn icache_line_size() -> usize {
+    CacheTypeInfo::read().icache_line_size
+}
+
+/// Invalidates the instruction cache to the PoU for a range of memory.
+///
+/// Used after code has been written, e.g. by a JIT or module loader. The new instructions
+/// must already be cleaned from the data cache to the PoU, otherwise the I-cache may refill
+/// with stale data.
+pub fn icache_invalidate_range(addr: usize, size: usize) {
+    let end = addr + size;
+    let line_size = icache_line_size();
+
+    let mut aligned_addr = addr & !(line_size - 1);
+
+    while aligned_addr < end {
+        ic_va(IVAU, aligned_addr as u64);
+        aligned_addr += line_size;
+    }
+
+    dsb(ISH);
+    isb(SY);
+}
+
+/// Makes instructions written to `addr..addr + size` visible to instruction fetch.
+///
+/// Same as [`publish_instructions`].
+#[inline]
+pub fn sync_icache_for_execution(addr: usize, size: usize) {
+    publish_instructions(addr, size);
+}
+
+/// Publishes newly written or patched instructions at `addr..addr + len`.
+///
+/// Performs the architectural sequence for modifying code:
+///
+/// ```text
+/// DC CVAU (each line)
+/// DSB ISH
+/// IC IVAU (each line)
+/// DSB ISH
+/// ISB
+/// ```
+///
+/// The D-cache clean is skipped when CTR_EL0.IDC is set, the I-cache invalidation and the DSB
+/// that follows it when CTR_EL0.DIC is set. The first DSB and the ISB are always issued. Other
+/// cores that may execute the code must still perform an ISB (or take an exception) before
+/// doing so.
+pub fn publish_instructions(addr: usize, len: usize) {
+    let info = CacheTypeInfo::read();
+    let end = addr + len;
+
+    if !info.idc {
+        let mut aligned_addr = addr & !(info.dcache_line_size - 1);
+        while aligned_addr < end {
+            dc(CVAU, aligned_addr as u64);
+            aligned_addr += info.dcache_line_size;
+        }
+    }
+    dsb(ISH);
+
+    if !info.dic {
+        let mut aligned_addr = addr & !(info.icache_line_size - 1);
+        while aligned_addr < end {
+            ic_va(IVAU, aligned_addr as u64);
+            aligned_addr += info.icache_line_size;
+        }
+        dsb(ISH);
+    }
+
+    isb(SY);
+}
+
+/// Returns the DC ZVA block size in bytes, or `None` if DC ZVA is prohibited.
+#[inline(always)]
+pub fn dc_zva_block_size() -> Option<usize> {
+    unsafe {
+        let mut dczid_el0: u64;
+        asm!("mrs {}, dczid_el0", out(reg) dczid_el0);
+        // DCZID_EL0.DZP (bit 4) - DC ZVA is prohibited
+        if dczid_el0 & (1 << 4) != 0 {
+            return None;
+        }
+        // DCZID_EL0.BS (bits 3:0) - log2 of the block size in words
+        Some(4 << (dczid_el0 & 0xF))
+    }
+}
+
+/// Zeroes `len` bytes starting at `ptr`.
+///
+/// The block aligned middle portion is zeroed with DC ZVA, the unaligned edges and the
+/// whole range when DC ZVA is prohibited with ordinary stores.
+///
+/// # Safety
+///
+/// `ptr` must be valid for writes of `len` bytes and mapped as Normal memory, DC ZVA
+/// generates an alignment fault on Device memory.
+pub unsafe fn zero_memory(ptr: *mut u8, len: usize) {
+    let Some(block_size) = dc_zva_block_size() else {
+        unsafe { core::ptr::write_bytes(ptr, 0, len) };
+        return;
+    };
+
+    let head = ptr.align_offset(block_size).min(len);
+    let blocks = (len - head) / block_size;
+    if blocks == 0 {
+        unsafe { core::ptr::write_bytes(ptr, 0, len) };
+        return;
+    }
+    let tail = len - head - blocks * block_size;
+
+    unsafe {
+        core::ptr::write_bytes(ptr, 0, head);
+        let mut block = ptr.add(head);
+        for _ in 0..blocks {
+            dc(ZVA, block as u64);
+            block = block.add(block_size);
+        }
+        core::ptr::write_bytes(block, 0, tail);
+    }
+}
+
+/// Performs a cache operation on a single cache line.
+#[inline]
+fn _dcache_line(op: CacheOp, target: CacheTarget, addr: usize) {
+    let addr = addr as u64;
+    match op {
+        CacheOp::Clean if target == CacheTarget::PoU => dc(CVAU, addr),
+        CacheOp::Clean => dc(CVAC, addr),
+        CacheOp::Invalidate => dc(IVAC, addr),
+        CacheOp::CleanAndInvalidate => dc(CIVAC, addr),
+    }
+}
+
+/// Performs a cache operation to the PoC on a range of memory.
+#[inline]
+pub fn dcache_range(op: CacheOp, addr: usize, size: usize) {
+    dcache_range_to(op, CacheTarget::PoC, addr, size);
+}
+
+/// Performs a cache operation to `target` on a range of memory.
+///
+/// Only clean has a PoU variant (DC CVAU), invalidation is always performed to the PoC
+/// which also covers the PoU.
+///
+/// A registered [`OuterCache`] is cleaned after the CPU caches, so the data written back
+/// from them reaches memory, and invalidated before them, so a CPU line refilled from a
+/// stale outer line cannot survive.
+#[inline]
+pub fn dcache_range_to(op: CacheOp, target: CacheTarget, addr: usize, size: usize) {
+    let start = addr;
+    let end = start + size;
+    let cache_line_size = cache_line_size();
+    let outer = target == CacheTarget::PoC;
+
+    if outer && matches!(op, CacheOp::Invalidate) {
+        outer::outer_range(op, addr, size);
+    }
+
+    let mut aligned_addr = addr & !(cache_line_size - 1);
+
+    while aligned_addr < end {
+        _dcache_line(op, target, aligned_addr);
+        aligned_addr += cache_line_size;
+    }
+
+    dsb(SY);
+    if outer && !matches!(op, CacheOp::Invalidate) {
+        outer::outer_range(op, addr, size);
+    }
+    isb(SY);
+}
+
+/// Performs a cache operation on a range of memory in chunks of at most
+/// `max_lines_per_chunk` cache lines, calling `between` after every chunk but the last.
+///
+/// Bounds the time spent in cache maintenance for large ranges, `between` can e.g. briefly
+/// unmask interrupts or yield. The maintenance of each chunk is completed with a DSB before
+/// `between` is called.
+pub fn dcache_range_chunked(
+    op: CacheOp,
+    addr: usize,
+    size: usize,
+    max_lines_per_chunk: usize,
+    mut between: impl FnMut(),
+) {
+    assert!(max_lines_per_chunk > 0, "chunk must hold at least one line");
+
+    let end = addr + size;
+    let line_size = cache_line_size();
+    let chunk_size = max_lines_per_chunk * line_size;
+
+    let mut aligned_addr = addr & !(line_size - 1);
+    while aligned_addr < end {
+        let chunk_start = aligned_addr.max(addr);
+        let chunk_end = end.min(aligned_addr.saturating_add(chunk_size));
+        if matches!(op, CacheOp::Invalidate) {
+            outer::outer_range(op, chunk_start, chunk_end - chunk_start);
+        }
+        while aligned_addr < chunk_end {
+            _dcache_line(op, CacheTarget::PoC, aligned_addr);
+            aligned_addr += line_size;
+        }
+        dsb(SY);
+        if !matches!(op, CacheOp::Invalidate) {
+            outer::outer_range(op, chunk_start, chunk_end - chunk_start);
+        }
+        if aligned_addr < end {
+            between();
+        }
+    }
+
+    isb(SY);
+}
+
+/// Cleans a range of memory to the Point of Persistence, e.g. for NVDIMM backed memory.
+///
+/// Uses DC CVAP, fails with [`CacheError::NotSupported`] if FEAT_DPB is not implemented.
+/// The range is persistent once this function returns.
+pub fn persist_range(addr: usize, len: usize) -> Result<(), CacheError> {
+    if capabilities().persistence < PersistenceSupport::Dpb {
+        return Err(CacheError::NotSupported);
+    }
+    let end = addr + len;
+    let line_size = cache_line_size();
+
+    let mut aligned_addr = addr & !(line_size - 1);
+    while aligned_addr < end {
+        dc(CVAP, aligned_addr as u64);
+        aligned_addr += line_size;
+    }
+
+    dsb(SY);
+    Ok(())
+}
+
+/// Cleans a range of memory to the Point of Deep Persistence with DC CVADP.
+///
+/// Fails with [`CacheError::NotSupported`] if FEAT_DPB2 is not implemented.
+pub fn deep_persist_range(addr: usize, len: usize) -> Result<(), CacheError> {
+    if capabilities().persistence < PersistenceSupport::Dpb2 {
+        return Err(CacheError::NotSupported);
+    }
+    let end = addr + len;
+    let line_size = cache_line_size();
+
+    let mut aligned_addr = addr & !(line_size - 1);
+    while aligned_addr < end {
+        dc(CVADP, aligned_addr as u64);
+        aligned_addr += line_size;
+    }
+
+    dsb(SY);
+    Ok(())
+}
+
+/// Performs a cache operation on the data and allocation tags of a range of memory.
+///
+/// Required when memory that is tagged is shared with a non-coherent DMA master, the tags
+/// are held in the same cache lines as the data. Fails with [`CacheError::NotSupported`] if
+/// FEAT_MTE2 is not implemented, invalidation is only available at EL1 and above.
+#[cfg(feature = "mte")]
+pub fn dcache_range_tags(op: CacheOp, addr: usize, len: usize) -> Result<(), CacheError> {
+    use crate::asm::cache::{CGDVAC, CIGDVAC, IGDVAC};
+
+    if !capabilities().mte_tags {
+        return Err(CacheError::NotSupported);
+    }
+
+    let end = addr + len;
+    let line_size = cache_line_size();
+
+    let mut aligned_addr = addr & !(line_size - 1);
+    while aligned_addr < end {
+        let addr = aligned_addr as u64;
+        match op {
+            CacheOp::Clean => dc(CGDVAC, addr),
+            CacheOp::Invalidate => dc(IGDVAC, addr),
+            CacheOp::CleanAndInvalidate => dc(CIGDVAC, addr),
+        }
+        aligned_addr += line_size;
+    }
+
+    dsb(SY);
+    isb(SY);
+    Ok(())
+}
+
+/// Performs a cache operation on a value.
+pub fn dcache_value<T>(op: CacheOp, v: &T) {
+    // Get the pointer to the value
+    let ptr = v as *const T as usize;
+    // Calculate the size of the value in bytes
+    let size = core::mem::size_of_val(v);
+    // Perform cache operation on the value
+    dcache_range(op, ptr, size);
+}
+
+/// Performs a cache operation on the memory of a slice.
+///
+/// [`CacheOp::Invalidate`] discards writes not yet cleaned to memory, including writes
+/// made through other references to the same cache lines. Prefer [`dcache_slice_mut`] for
+/// invalidation, it guarantees no other reference to the slice exists.
+pub fn dcache_slice<T>(op: CacheOp, s: &[T]) {
+    dcache_range(op, s.as_ptr() as usize, core::mem::size_of_val(s));
+}
+
+/// Performs a cache operation on the memory of a mutable slice.
+///
+/// Data sharing a cache line with the slice but lying outside it is affected as well, the
+/// slice should be aligned to the cache writeback granule when invalidating.
+pub fn dcache_slice_mut<T>(op: CacheOp, s: &mut [T]) {
+    dcache_range(op, s.as_mut_ptr() as usize, core::mem::size_of_val(s));
+}
+
+/// Performs a cache operation on `len` bytes starting at `ptr`.
+///
+/// # Safety
+///
+/// The range must be mapped. For [`CacheOp::Invalidate`] no other code may hold a reference
+/// to any cache line overlapping the range, pending writes to those lines are discarded.
+pub unsafe fn dcache_raw(op: CacheOp, ptr: *const u8, len: usize) -> Result<(), CacheError> {
+    if len == 0 {
+        return Err(CacheError::ZeroSize);
+    }
+    dcache_range(op, ptr as usize, len);
+    Ok(())
+}
+
+/// Performs a cache operation on `len` bytes starting at `ptr`.
+///
+/// # Safety
+///
+/// See [`dcache_raw`].
+pub unsafe fn dcache_raw_mut(op: CacheOp, ptr: *mut u8, len: usize) -> Result<(), CacheError> {
+    unsafe { dcache_raw(op, ptr, len) }
+}
+
+/// Performs a cache operation on a cache level.
+/// https://developer.arm.com/documentation/ddi0601/2024-09/AArch64-Instructions/DC-CISW--Data-or-unified-Cache-line-Clean-and-Invalidate-by-Set-Way
+/// https://developer.arm.com/documentation/ddi0601/2024-09/AArch64-Registers/CTR-EL0--Cache-Type-Register?lang=en
+/// https://developer.arm.com/documentation/ddi0601/2024-09/AArch64-Registers/CCSIDR-EL1--Current-Cache-Size-ID-Register?lang=en
+/// https://github.com/u-boot/u-boot/blob/master/arch/arm/cpu/armv8/cache.S
+///
+/// DC instruction set/way format:
+/// - Bits [63:32]: Reserved, RES0
+/// - Bits [31:4]: SetWay field containing:
+///   - Way field: bits[31:32-A] where A = Log2(ASSOCIATIVITY)  
+///   - Set field: bits[B-1:L] where B = L + S, L = Log2(LINELEN), S = Log2(NSETS)
+///   - Bits[L-1:4]: RES0
+/// - Bits [3:1]: Level (cache level minus 1)
+/// - Bit [0]: Reserved, RES0
+#[inline]
+fn dcache_set_way(op: CacheOp, level: u64) {
+    assert!(level < 8, "armv8 level range is 0-7");
+
+    let geometry = CacheGeometry::read(level, false);
+
+    let line_size_log2_bytes = geometry.line_size.trailing_zeros(); // Actual log2 of line size in bytes
+    let associativity = geometry.associativity as u32; // Actual associativity
+    let num_sets = geometry.num_sets as u32; // Actual number of sets
+    let associativity_raw = associativity - 1;
+
+    // Calculate bit positions for set/way encoding according to ARM spec:
+    // L = Log2(LINELEN) where LINELEN is line length in bytes
+    // S = Log2(NSETS)
+    // A = Log2(ASSOCIATIVITY)
+    // Way field: bits[31:32-A]
+    // Set field: bits[B-1:L] where B = L + S
+
+    let l = line_size_log2_bytes; // Log2 of line length in bytes
+
+    // Calculate the number of bits needed to represent the way index
+    // leading_zeros on (associativity-1) gives us the position of the MSB needed
+    let way_shift = associativity_raw.leading_zeros(); // Way field starts at bit (32-A)
+    let set_shift = l; // Set field starts at bit L (line size offset)
+
+    // Loop over all sets and ways (0-based indexing for hardware)
+    for set in 0..num_sets {
+        for way in 0..associativity {
+            // Construct the set/way value according to ARM DC instruction format:
+            // Way field: bits[31:32-A] - way value shifted to proper bit position
+            // Set field: bits[B-1:L] - set value shifted to proper bit position
+            //
+            // Example: If associativity=4, way indices are 0,1,2,3
+            // We need A=2 bits (Log2(4)=2), so way field is at bits[31:30]
+            // way_shift = 32 - 2 = 30, so way values are shifted left by 30 bits
+            let set_way = (way << way_shift) | (set << set_shift);
+
+            // Complete operand: set_way in bits [31:4], level in bits [3:1], bit [0] is RES0
+            let cisw = (set_way as u64) | (level << 1);
+            match op {
+                CacheOp::Invalidate => dc(ISW, cisw),
+                CacheOp::Clean => dc(CSW, cisw),
+                CacheOp::CleanAndInvalidate => dc(CISW, cisw),
+            }
+        }
+    }
+}
+
+/// Performs a cache operation on all memory.
+pub fn dcache_all(op: CacheOp) {
+    let clidr = CLIDR_EL1.get();
+    if matches!(op, CacheOp::Invalidate) {
+        outer::outer_all(op);
+    }
+
+    for level in 0..7 {
+        let ty = (clidr >> (level * 3)) & 0b111;
+
+        // Cache type values:
+        // 0b000 = No cache
+        // 0b001 = Instruction cache only
+        // 0b010 = Data cache only
+        // 0b011 = Separate instruction and data caches
+        // 0b100 = Unified cache
+        // Only process data caches (0b010) and unified caches (0b100)
+        // or separate I+D caches (0b011) - for 0b011, we process the data cache
+        match ty {
+            0b000 => break,    // No cache at this level, we're done
+            0b001 => continue, // Instruction cache only, skip
+            0b010..=0b100 => {
+                // Data cache (0b010), separate I+D caches (0b011), or unified cache (0b100) - process it
+                dcache_set_way(op, level);
+            }
+            _ => continue, // Reserved values, skip
+        }
+    }
+    dsb(SY);
+    if !matches!(op, CacheOp::Invalidate) {
+        outer::outer_all(op);
+    }
+    isb(SY);
+}
+
+/// Performs a cache operation by set/way on a single cache level.
+///
+/// `level` is 1-based (1 = L1), as in [`CacheHierarchy`]. Fails with
+/// [`CacheError::InvalidLevel`] if the level does not exist or only holds instructions.
+///
+/// Set/way operations are only meaningful with the cache disabled or on the executing core
+/// during power management, they are not broadcast and cannot be used for coherency.
+pub fn dcache_level(op: CacheOp, level: usize) -> Result<(), CacheError> {
+    if !(1..=7).contains(&level) {
+        return Err(CacheError::InvalidLevel);
+    }
+    let level = level as u64 - 1;
+    match CacheType::from_bits(CLIDR_EL1.get() >> (level * 3)) {
+        Some(ty) if ty.has_data() => {}
+        _ => return Err(CacheError::InvalidLevel),
+    }
+    dcache_set_way(op, level);
+    dsb(SY);
+    isb(SY);
+    Ok(())
+}
+
+/// Performs a cache operation by set/way on the levels up to the Point of Unification,
+/// Inner Shareable (CLIDR_EL1.LoUIS).
+pub fn dcache_all_to_pou(op: CacheOp) {
+    dcache_levels(op, CLIDR_EL1.read(CLIDR_EL1::LoUIS));
+}
+
+/// Performs a cache operation by set/way on the levels up to the Point of Coherency
+/// (CLIDR_EL1.LoC).
+pub fn dcache_all_to_poc(op: CacheOp) {
+    dcache_levels(op, CLIDR_EL1.read(CLIDR_EL1::LoC));
+}
+
+fn dcache_levels(op: CacheOp, count: u64) {
+    let clidr = CLIDR_EL1.get();
+    for level in 0..count.min(7) {
+        if CacheType::from_bits(clidr >> (level * 3)).is_some_and(CacheType::has_data) {
+            dcache_set_way(op, level);
+        }
+    }
+    dsb(SY);
+    isb(SY);
+}
@@ -0,0 +1,95 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use super::CacheOp;
+
+/// Maintenance of a non-architectural outer or system level cache
+///
+/// Implemented by board crates for caches that are not covered by the DC instructions,
+/// e.g. an SoC system level cache maintained through MMIO. Once registered with
+/// [`set_outer_cache`], it is called by [`dcache_range`], [`dcache_range_chunked`] and
+/// [`dcache_all`]: after the CPU cache maintenance has completed for clean and clean and
+/// invalidate, before it for invalidate.
+///
+/// [`dcache_range`]: super::dcache_range
+/// [`dcache_range_chunked`]: super::dcache_range_chunked
+/// [`dcache_all`]: super::dcache_all
+pub trait OuterCache: Sync {
+    /// Write back the lines covering `addr..addr + len`
+    fn clean_range(&self, addr: usize, len: usize);
+
+    /// Discard the lines covering `addr..addr + len`
+    fn invalidate_range(&self, addr: usize, len: usize);
+
+    /// Write back and discard the lines covering `addr..addr + len`
+    fn clean_and_invalidate_range(&self, addr: usize, len: usize) {
+        self.clean_range(addr, len);
+        self.invalidate_range(addr, len);
+    }
+
+    /// Perform `op` on the whole cache, does nothing by default
+    fn maintain_all(&self, _op: CacheOp) {}
+
+    /// Wait until all previously issued maintenance has completed
+    fn sync(&self);
+}
+
+const EMPTY: u8 = 0;
+const BUSY: u8 = 1;
+const SET: u8 = 2;
+
+struct OuterSlot {
+    state: AtomicU8,
+    cache: UnsafeCell<Option<&'static dyn OuterCache>>,
+}
+
+// The cell is written once while `state` is BUSY and only read once it is SET
+unsafe impl Sync for OuterSlot {}
+
+static OUTER: OuterSlot = OuterSlot {
+    state: AtomicU8::new(EMPTY),
+    cache: UnsafeCell::new(None),
+};
+
+/// Register the outer cache, returns `false` if one is already registered
+pub fn set_outer_cache(cache: &'static dyn OuterCache) -> bool {
+    if OUTER
+        .state
+        .compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return false;
+    }
+    unsafe { *OUTER.cache.get() = Some(cache) };
+    OUTER.state.store(SET, Ordering::Release);
+    true
+}
+
+/// Get the registered outer cache
+pub fn outer_cache() -> Option<&'static dyn OuterCache> {
+    if OUTER.state.load(Ordering::Acquire) == SET {
+        unsafe { *OUTER.cache.get() }
+    } else {
+        None
+    }
+}
+
+pub(super) fn outer_range(op: CacheOp, addr: usize, len: usize) {
+    if let Some(outer) = outer_cache() {
+        match op {
+            CacheOp::Clean => outer.clean_range(addr, len),
+            CacheOp::Invalidate => outer.invalidate_range(addr, len),
+            CacheOp::CleanAndInvalidate => outer.clean_and_invalidate_range(addr, len),
+        }
+        outer.sync();
+    }
+}
+
+pub(super) fn outer_all(op: CacheOp) {
+    if let Some(outer) = outer_cache() {
+        outer.maintain_all(op);
+        outer.sync();
+    }
+}
@@ -0,0 +1,177 @@
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::*,
+};
+
+/// Cache type of a single level as reported by CLIDR_EL1.Ctype<n>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheType {
+    /// Instruction cache only
+    Instruction,
+    /// Data cache only
+    Data,
+    /// Separate instruction and data caches
+    Separate,
+    /// Unified cache
+    Unified,
+}
+
+impl CacheType {
+    /// Decode a 3-bit Ctype field, `None` for no cache or reserved values
+    pub const fn from_bits(bits: u64) -> Option<Self> {
+        match bits & 0b111 {
+            0b001 => Some(Self::Instruction),
+            0b010 => Some(Self::Data),
+            0b011 => Some(Self::Separate),
+            0b100 => Some(Self::Unified),
+            _ => None,
+        }
+    }
+
+    /// Check if this level holds data, i.e. is affected by data cache maintenance
+    pub const fn has_data(self) -> bool {
+        !matches!(self, Self::Instruction)
+    }
+
+    /// Check if this level holds instructions
+    pub const fn has_instruction(self) -> bool {
+        !matches!(self, Self::Data)
+    }
+}
+
+/// Check if FEAT_CCIDX is implemented, selecting the 64-bit CCSIDR_EL1 layout
+#[inline]
+pub fn has_ccidx() -> bool {
+    ID_AA64MMFR2_EL1.read(ID_AA64MMFR2_EL1::CCIDX) != 0
+}
+
+/// Geometry of a single cache as reported by CCSIDR_EL1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheGeometry {
+    /// Line size in bytes
+    pub line_size: usize,
+    /// Number of ways
+    pub associativity: usize,
+    /// Number of sets
+    pub num_sets: usize,
+}
+
+impl CacheGeometry {
+    /// Decode a CCSIDR_EL1 value
+    ///
+    /// `ccidx` selects the 64-bit layout used when FEAT_CCIDX is implemented.
+    /// All fields are encoded as the actual value minus one, LineSize as Log2(bytes) - 4.
+    pub const fn from_ccsidr(ccsidr: u64, ccidx: bool) -> Self {
+        let (associativity, num_sets) = if ccidx {
+            ((ccsidr >> 3) & 0x1F_FFFF, (ccsidr >> 32) & 0xFF_FFFF)
+        } else {
+            ((ccsidr >> 3) & 0x3FF, (ccsidr >> 13) & 0x7FFF)
+        };
+        Self {
+            line_size: 16 << (ccsidr & 0b111),
+            associativity: associativity as usize + 1,
+            num_sets: num_sets as usize + 1,
+        }
+    }
+
+    /// Select the cache through CSSELR_EL1 and read its geometry
+    ///
+    /// `level` is 0-based (0 = L1). Interrupts should be masked, as CSSELR_EL1 is not
+    /// preserved by every exception handler.
+    pub fn read(level: u64, instruction: bool) -> Self {
+        assert!(level < 7, "armv8 cache level range is 0-6");
+
+        let ind = if instruction {
+            CSSELR_EL1::InD::Instruction
+        } else {
+            CSSELR_EL1::InD::Data
+        };
+        isb(SY);
+        CSSELR_EL1.write(ind + CSSELR_EL1::Level.val(level));
+        isb(SY);
+        Self::from_ccsidr(CCSIDR_EL1.get(), has_ccidx())
+    }
+
+    /// Total size in bytes
+    pub const fn size(&self) -> usize {
+        self.line_size * self.associativity * self.num_sets
+    }
+}
+
+/// Description of one level of the cache hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheLevel {
+    /// 1-based cache level (1 = L1)
+    pub level: usize,
+    pub ty: CacheType,
+    /// Data or unified cache, `None` for instruction-only levels
+    pub data: Option<CacheGeometry>,
+    /// Instruction cache, `None` for data-only and unified levels
+    pub instruction: Option<CacheGeometry>,
+}
+
+/// The cache hierarchy as described by CLIDR_EL1 and CCSIDR_EL1
+///
+/// The coherency levels are reported as the number of levels that must be maintained,
+/// e.g. LoC = 2 means L1 and L2 need cleaning to reach the Point of Coherency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheHierarchy {
+    levels: [Option<CacheLevel>; 7],
+    louis: usize,
+    loc: usize,
+    louu: usize,
+}
+
+impl CacheHierarchy {
+    /// Read the hierarchy of the executing core
+    pub fn read() -> Self {
+        let clidr = CLIDR_EL1.get();
+        let mut levels = [None; 7];
+
+        for (level, slot) in levels.iter_mut().enumerate() {
+            let Some(ty) = CacheType::from_bits(clidr >> (level * 3)) else {
+                break;
+            };
+            let level = level as u64;
+            *slot = Some(CacheLevel {
+                level: level as usize + 1,
+                ty,
+                data: ty.has_data().then(|| CacheGeometry::read(level, false)),
+                instruction: matches!(ty, CacheType::Instruction | CacheType::Separate)
+                    .then(|| CacheGeometry::read(level, true)),
+            });
+        }
+
+        Self {
+            levels,
+            louis: CLIDR_EL1.read(CLIDR_EL1::LoUIS) as usize,
+            loc: CLIDR_EL1.read(CLIDR_EL1::LoC) as usize,
+            louu: CLIDR_EL1.read(CLIDR_EL1::LoUU) as usize,
+        }
+    }
+
+    /// Iterate over the implemented cache levels, innermost first
+    pub fn levels(&self) -> impl Iterator<Item = &CacheLevel> {
+        self.levels.iter().map_while(Option::as_ref)
+    }
+
+    /// Get a level by its 1-based number
+    pub fn level(&self, level: usize) -> Option<&CacheLevel> {
+        self.levels.get(level.checked_sub(1)?)?.as_ref()
+    }
+
+    /// Level of Unification, Inner Shareable
+    pub const fn louis(&self) -> usize {
+        self.louis
+    }
+
+    /// Level of Coherence
+    pub const fn loc(&self) -> usize {
+        self.loc
+    }
+
+    /// Level of Unification, Uniprocessor
+    pub const fn louu(&self) -> usize {
+        self.louu
+    }
+}
@@ -0,0 +1,54 @@
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{OSLAR_EL1, Writeable},
+};
+
+use super::{DebugAuthStatus, breakpoint::read_mdscr};
+
+const OSLSR_OSLK: u64 = 1 << 1;
+const MDSCR_HDE: u64 = 1 << 14;
+
+/// Set the OS lock (OSLAR_EL1), blocking debug exceptions and external debugger access to
+/// the debug registers while they are saved or restored around a power down
+pub fn os_lock() {
+    OSLAR_EL1.set(1);
+    isb(SY);
+}
+
+/// Clear the OS lock (OSLAR_EL1)
+pub fn os_unlock() {
+    OSLAR_EL1.set(0);
+    isb(SY);
+}
+
+/// Check if the OS lock is set (OSLSR_EL1.OSLK)
+pub fn is_os_locked() -> bool {
+    crate::sysreg!(read "oslsr_el1") & OSLSR_OSLK != 0
+}
+
+/// Debug authentication status of the core (DBGAUTHSTATUS_EL1)
+pub fn auth_status() -> DebugAuthStatus {
+    DebugAuthStatus::from_value(crate::sysreg!(read "dbgauthstatus_el1"))
+}
+
+/// Check if an external debugger has enabled halting debug on this core
+///
+/// A debugger attached through the external debug interface sets EDSCR.HDE to halt the
+/// core on breakpoints, so a panic handler can hand over with a BRK instead of rebooting.
+/// EDSCR.HDE is read through MDSCR_EL1.HDE, which reflects it only while the OS lock is
+/// set, so the lock is taken briefly, debug exceptions are blocked meanwhile. Always false
+/// when non-secure invasive debug is not allowed (DBGAUTHSTATUS_EL1.NSID).
+pub fn is_external_debugger_attached() -> bool {
+    if !auth_status().non_secure_invasive.is_enabled() {
+        return false;
+    }
+    let locked = is_os_locked();
+    if !locked {
+        os_lock();
+    }
+    let hde = read_mdscr() & MDSCR_HDE != 0;
+    if !locked {
+        os_unlock();
+    }
+    hde
+}
@@ -0,0 +1,120 @@
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{ID_AA64DFR0_EL1, Readable},
+};
+
+use super::{BreakpointKind, DebugTarget, breakpoint_control, os_unlock};
+
+const MDSCR_KDE: u64 = 1 << 13;
+const MDSCR_MDE: u64 = 1 << 15;
+
+const BCR_ENABLE: u64 = 1 << 0;
+
+#[inline]
+pub(super) fn read_mdscr() -> u64 {
+    crate::sysreg!(read "mdscr_el1")
+}
+
+#[inline]
+pub(super) fn write_mdscr(value: u64) {
+    unsafe { crate::sysreg!(write "mdscr_el1", value) };
+    isb(SY);
+}
+
+/// Let breakpoints and watchpoints generate exceptions at EL0 and EL1
+///
+/// Unlocks the OS lock (OSLAR_EL1), which otherwise suppresses debug exceptions, and sets
+/// MDSCR_EL1.MDE and KDE. PSTATE.D must also be clear for exceptions at EL1.
+pub fn enable_debug_exceptions() {
+    os_unlock();
+    write_mdscr(read_mdscr() | MDSCR_MDE | MDSCR_KDE);
+}
+
+/// Stop breakpoints and watchpoints from generating exceptions (MDSCR_EL1.MDE and KDE)
+pub fn disable_debug_exceptions() {
+    write_mdscr(read_mdscr() & !(MDSCR_MDE | MDSCR_KDE));
+}
+
+/// Number of breakpoints implemented (ID_AA64DFR0_EL1.BRPs + 1)
+#[inline]
+pub fn breakpoint_count() -> usize {
+    ID_AA64DFR0_EL1.read(ID_AA64DFR0_EL1::BRPs) as usize + 1
+}
+
+/// Check if address mismatch breakpoints are implemented (FEAT_BWE2)
+#[inline]
+pub fn is_mismatch_supported() -> bool {
+    // ID_AA64DFR2_EL1.BWE, the register reads as zero on cores predating it
+    (crate::sysreg!(read "S3_0_C0_C5_2") >> 4) & 0xF >= 2
+}
+
+/// A hardware breakpoint slot (DBGBVR<n>_EL1, DBGBCR<n>_EL1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwBreakpoint {
+    slot: usize,
+}
+
+impl HwBreakpoint {
+    /// Program breakpoint `slot` on the instruction at `addr` and enable it
+    ///
+    /// Returns `None` if the core has no breakpoint `slot`, see [`breakpoint_count`], or
+    /// does not implement [`BreakpointKind::Mismatch`], see [`is_mismatch_supported`].
+    pub fn set(
+        slot: usize,
+        addr: usize,
+        kind: BreakpointKind,
+        target: DebugTarget,
+    ) -> Option<Self> {
+        if slot >= breakpoint_count()
+            || (kind == BreakpointKind::Mismatch && !is_mismatch_supported())
+        {
+            return None;
+        }
+        let bp = Self { slot };
+        bp.disable();
+        // Instructions are word aligned, DBGBVR bits [1:0] are RES0
+        dbg_indexed!(write "dbgbvr", slot, addr as u64 & !0b11);
+        dbg_indexed!(write "dbgbcr", slot, breakpoint_control(kind, target));
+        isb(SY);
+        Some(bp)
+    }
+
+    /// Handle of breakpoint `slot` without changing it, `None` if it is not implemented
+    pub fn get(slot: usize) -> Option<Self> {
+        (slot < breakpoint_count()).then_some(Self { slot })
+    }
+
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Programmed instruction address
+    pub fn address(&self) -> usize {
+        dbg_indexed!(read "dbgbvr", self.slot) as usize
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        dbg_indexed!(read "dbgbcr", self.slot) & BCR_ENABLE != 0
+    }
+
+    /// Re-enable the breakpoint with its programmed address and control
+    pub fn enable(&self) {
+        let bcr = dbg_indexed!(read "dbgbcr", self.slot);
+        dbg_indexed!(write "dbgbcr", self.slot, bcr | BCR_ENABLE);
+        isb(SY);
+    }
+
+    /// Disable the breakpoint, keeping its address and control
+    pub fn disable(&self) {
+        let bcr = dbg_indexed!(read "dbgbcr", self.slot);
+        dbg_indexed!(write "dbgbcr", self.slot, bcr & !BCR_ENABLE);
+        isb(SY);
+    }
+
+    /// Disable the breakpoint and clear its registers
+    pub fn clear(self) {
+        dbg_indexed!(write "dbgbcr", self.slot, 0);
+        dbg_indexed!(write "dbgbvr", self.slot, 0);
+        isb(SY);
+    }
+}
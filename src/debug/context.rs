@@ -0,0 +1,74 @@
+use aarch64_cpu::asm::barrier::{SY, isb};
+
+use super::{
+    breakpoint::{read_mdscr, write_mdscr},
+    breakpoint_count, watchpoint_count,
+};
+
+/// Breakpoint and watchpoint state of a task or guest, switched with
+/// [`DebugContext::save`] and [`DebugContext::restore`]
+///
+/// Covers MDSCR_EL1 and the value and control registers of every implemented breakpoint
+/// and watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugContext {
+    mdscr: u64,
+    bvr: [u64; 16],
+    bcr: [u64; 16],
+    wvr: [u64; 16],
+    wcr: [u64; 16],
+}
+
+impl DebugContext {
+    /// State with every breakpoint and watchpoint disabled and stepping off, for a task
+    /// that is not being debugged
+    pub const fn new() -> Self {
+        Self {
+            mdscr: 0,
+            bvr: [0; 16],
+            bcr: [0; 16],
+            wvr: [0; 16],
+            wcr: [0; 16],
+        }
+    }
+
+    /// Save the debug state of the executing core
+    pub fn save() -> Self {
+        let mut ctx = Self {
+            mdscr: read_mdscr(),
+            ..Self::new()
+        };
+        for n in 0..breakpoint_count() {
+            ctx.bvr[n] = dbg_indexed!(read "dbgbvr", n);
+            ctx.bcr[n] = dbg_indexed!(read "dbgbcr", n);
+        }
+        for n in 0..watchpoint_count() {
+            ctx.wvr[n] = dbg_indexed!(read "dbgwvr", n);
+            ctx.wcr[n] = dbg_indexed!(read "dbgwcr", n);
+        }
+        ctx
+    }
+
+    /// Load this state into the executing core, replacing the current one
+    ///
+    /// Every breakpoint and watchpoint is written, so none of the previous owner's is left
+    /// enabled. MDSCR_EL1 is written last.
+    pub fn restore(&self) {
+        for n in 0..breakpoint_count() {
+            dbg_indexed!(write "dbgbvr", n, self.bvr[n]);
+            dbg_indexed!(write "dbgbcr", n, self.bcr[n]);
+        }
+        for n in 0..watchpoint_count() {
+            dbg_indexed!(write "dbgwvr", n, self.wvr[n]);
+            dbg_indexed!(write "dbgwcr", n, self.wcr[n]);
+        }
+        isb(SY);
+        write_mdscr(self.mdscr);
+    }
+}
+
+impl Default for DebugContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,357 @@
+//! Self-hosted debug: hardware breakpoints and watchpoints.
+//!
+//! A [`HwBreakpoint`] programs a DBGBVR<n>_EL1/DBGBCR<n>_EL1 pair, a [`HwWatchpoint`] a
+//! DBGWVR<n>_EL1/DBGWCR<n>_EL1 pair. They only generate exceptions once debug exceptions
+//! are enabled with [`enable_debug_exceptions`] and PSTATE.D is clear, see
+//! [`Mask::DEBUG`](crate::interrupts::Mask::DEBUG). The exceptions are taken to EL1 (or
+//! EL2 when routed there by MDCR_EL2.TDE) and decode as
+//! [`Syndrome::Breakpoint`](crate::exception::syndrome::Syndrome::Breakpoint) and
+//! [`Syndrome::Watchpoint`](crate::exception::syndrome::Syndrome::Watchpoint).
+//!
+//! [`single_step_enable`] makes the context returned to from an exception execute a single
+//! instruction, then take a software step exception, decoded with [`StepInfo`].
+//!
+//! The registers are per core and not banked per task, [`DebugContext`] switches them.
+//! After a reset or a power down the OS lock is usually set, blocking debug exceptions
+//! until [`os_unlock`] (done by [`enable_debug_exceptions`]).
+//!
+//! Software breakpoints, BRK instructions with handlers per immediate, are in
+//! [`sw_breakpoint`].
+
+/// Access DBGBVR<n>_EL1 and similar banked debug registers by runtime index, 0-15
+#[cfg(target_arch = "aarch64")]
+macro_rules! dbg_indexed {
+    (read $prefix:literal, $index:expr) => {
+        dbg_indexed!(@read $prefix, $index; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+    };
+    (write $prefix:literal, $index:expr, $value:expr) => {
+        dbg_indexed!(@write $prefix, $index, $value; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+    };
+    (@read $prefix:literal, $index:expr; $($n:literal)*) => {
+        match $index {
+            $($n => crate::sysreg!(read concat!($prefix, stringify!($n), "_el1")),)*
+            _ => panic!("invalid debug register index"),
+        }
+    };
+    (@write $prefix:literal, $index:expr, $value:expr; $($n:literal)*) => {
+        match $index {
+            $($n => unsafe {
+                crate::sysreg!(write concat!($prefix, stringify!($n), "_el1"), $value)
+            },)*
+            _ => panic!("invalid debug register index"),
+        }
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+mod auth;
+#[cfg(target_arch = "aarch64")]
+mod breakpoint;
+#[cfg(target_arch = "aarch64")]
+mod context;
+#[cfg(target_arch = "aarch64")]
+mod step;
+pub mod sw_breakpoint;
+#[cfg(target_arch = "aarch64")]
+mod watchpoint;
+
+#[cfg(target_arch = "aarch64")]
+pub use auth::*;
+#[cfg(target_arch = "aarch64")]
+pub use breakpoint::*;
+#[cfg(target_arch = "aarch64")]
+pub use context::DebugContext;
+#[cfg(target_arch = "aarch64")]
+pub use step::*;
+#[cfg(target_arch = "aarch64")]
+pub use watchpoint::*;
+
+/// Exception levels a breakpoint or watchpoint matches at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugTarget {
+    El0,
+    El1,
+    El0And1,
+    El2,
+}
+
+impl DebugTarget {
+    /// HMC, SSC and PMC fields of DBGBCR<n>_EL1, at bits 13, 15:14 and 2:1
+    const fn control_bits(self) -> u64 {
+        let (hmc, ssc, pmc) = match self {
+            Self::El0 => (0, 0b00, 0b10),
+            Self::El1 => (0, 0b00, 0b01),
+            Self::El0And1 => (0, 0b00, 0b11),
+            Self::El2 => (1, 0b11, 0b00),
+        };
+        (hmc << 13) | (ssc << 14) | (pmc << 1)
+    }
+}
+
+/// What an instruction address breakpoint matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointKind {
+    /// Executing the instruction at the address
+    Match,
+    /// Executing any instruction but the one at the address
+    ///
+    /// Address mismatch is only defined for AArch64 with FEAT_BWE2, see
+    /// [`is_mismatch_supported`].
+    Mismatch,
+}
+
+/// DBGBCR<n>_EL1 value for an enabled, unlinked instruction address breakpoint
+pub const fn breakpoint_control(kind: BreakpointKind, target: DebugTarget) -> u64 {
+    const ENABLE: u64 = 1 << 0;
+    // Match all four bytes of an A64 instruction
+    const BAS_A64: u64 = 0b1111 << 5;
+    let bt = match kind {
+        BreakpointKind::Match => 0b0000,
+        BreakpointKind::Mismatch => 0b0100,
+    };
+    ENABLE | BAS_A64 | target.control_bits() | (bt << 20)
+}
+
+/// Accesses a watchpoint matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// DBGWVR<n>_EL1 and DBGWCR<n>_EL1 values for an enabled watchpoint on `len` bytes at
+/// `addr`
+///
+/// Regions of 1 to 8 bytes within one doubleword use the byte select (BAS), larger regions
+/// must be a power of two of at most 2GB, aligned to their size, and use the address mask
+/// (MASK). Returns `None` for regions neither can express.
+pub const fn watchpoint_encoding(
+    addr: u64,
+    len: u64,
+    access: WatchAccess,
+    target: DebugTarget,
+) -> Option<(u64, u64)> {
+    const ENABLE: u64 = 1 << 0;
+    let lsc: u64 = match access {
+        WatchAccess::Read => 0b01,
+        WatchAccess::Write => 0b10,
+        WatchAccess::ReadWrite => 0b11,
+    };
+    let offset = addr & 0b111;
+    let (wvr, bas, mask) = if len >= 1 && offset + len <= 8 {
+        (addr - offset, ((1 << len) - 1) << offset, 0)
+    } else if len > 8 && len.is_power_of_two() && len <= 1 << 31 && addr & (len - 1) == 0 {
+        (addr, 0xFF, len.trailing_zeros() as u64)
+    } else {
+        return None;
+    };
+    let wcr = ENABLE | (lsc << 3) | (bas << 5) | target.control_bits() | (mask << 24);
+    Some((wvr, wcr))
+}
+
+/// Byte range `[start, end)` watched by a DBGWVR<n>_EL1/DBGWCR<n>_EL1 pair
+pub const fn watched_range(wvr: u64, wcr: u64) -> (u64, u64) {
+    let mask = (wcr >> 24) & 0x1F;
+    if mask != 0 {
+        let start = wvr & !((1 << mask) - 1);
+        return (start, start + (1 << mask));
+    }
+    let bas = (wcr >> 5) & 0xFF;
+    if bas == 0 {
+        return (wvr, wvr);
+    }
+    let first = bas.trailing_zeros() as u64;
+    let last = 63 - bas.leading_zeros() as u64;
+    (wvr + first, wvr + last + 1)
+}
+
+/// Watchpoint exception syndrome (ISS of EC 0x34/0x35)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointHit {
+    /// The access was a write (WnR)
+    pub write: bool,
+    /// FAR holds an address within the access (FnV clear)
+    pub far_valid: bool,
+    /// Watchpoint that fired, reported with FEAT_Debugv8p9 (WPTV, WPT)
+    pub slot: Option<usize>,
+}
+
+impl WatchpointHit {
+    pub const fn from_iss(iss: u32) -> Self {
+        Self {
+            write: iss & (1 << 6) != 0,
+            far_valid: iss & (1 << 10) == 0,
+            slot: if iss & (1 << 17) != 0 {
+                Some(((iss >> 18) & 0x3F) as usize)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Software step exception syndrome (ISS of EC 0x32/0x33)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    /// An instruction was stepped, the exception is taken after it. Clear when the step
+    /// exception was taken before executing anything, e.g. stepping into an exception
+    /// handler that returned with PSTATE.SS clear.
+    pub stepped: bool,
+    /// The stepped instruction was a load-exclusive (EX), whose exclusive monitor the step
+    /// exception has cleared, so the following store-exclusive will fail. Only valid with
+    /// `stepped`.
+    pub load_exclusive: bool,
+}
+
+impl StepInfo {
+    pub const fn from_iss(iss: u32) -> Self {
+        let isv = iss & (1 << 24) != 0;
+        Self {
+            stepped: isv,
+            load_exclusive: isv && iss & (1 << 6) != 0,
+        }
+    }
+}
+
+/// Authentication state of one kind of debug (a DBGAUTHSTATUS_EL1 field)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthState {
+    NotImplemented,
+    Disabled,
+    Enabled,
+}
+
+impl AuthState {
+    const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0b11 => Self::Enabled,
+            0b10 => Self::Disabled,
+            _ => Self::NotImplemented,
+        }
+    }
+
+    pub const fn is_enabled(self) -> bool {
+        matches!(self, Self::Enabled)
+    }
+}
+
+/// Debug authentication status (DBGAUTHSTATUS_EL1), set by the authentication signals of
+/// the SoC, usually fused or controlled by the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugAuthStatus {
+    /// Non-secure invasive debug (NSID): breakpoints, watchpoints, halting
+    pub non_secure_invasive: AuthState,
+    /// Non-secure non-invasive debug (NSNID): trace and profiling
+    pub non_secure_non_invasive: AuthState,
+    /// Secure invasive debug (SID)
+    pub secure_invasive: AuthState,
+    /// Secure non-invasive debug (SNID)
+    pub secure_non_invasive: AuthState,
+}
+
+impl DebugAuthStatus {
+    pub const fn from_value(value: u64) -> Self {
+        Self {
+            non_secure_invasive: AuthState::from_bits(value),
+            non_secure_non_invasive: AuthState::from_bits(value >> 2),
+            secure_invasive: AuthState::from_bits(value >> 4),
+            secure_non_invasive: AuthState::from_bits(value >> 6),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakpoint_control() {
+        // Linux's EL1 kernel breakpoint: E, PMC=01, BAS=1111
+        assert_eq!(
+            breakpoint_control(BreakpointKind::Match, DebugTarget::El1),
+            0x1E3
+        );
+        assert_eq!(
+            breakpoint_control(BreakpointKind::Match, DebugTarget::El0),
+            0x1E5
+        );
+        assert_eq!(
+            breakpoint_control(BreakpointKind::Mismatch, DebugTarget::El0And1),
+            0x40_01E7
+        );
+        assert_eq!(
+            breakpoint_control(BreakpointKind::Match, DebugTarget::El2),
+            0xE1E1
+        );
+    }
+
+    #[test]
+    fn test_watchpoint_encoding() {
+        // 4 bytes at offset 4 of a doubleword, writes at EL1
+        let (wvr, wcr) =
+            watchpoint_encoding(0x1004, 4, WatchAccess::Write, DebugTarget::El1).unwrap();
+        assert_eq!(wvr, 0x1000);
+        assert_eq!(wcr, 1 | (0b10 << 3) | (0xF0 << 5) | (0b01 << 1));
+        assert_eq!(watched_range(wvr, wcr), (0x1004, 0x1008));
+
+        // 4KB page with the address mask
+        let (wvr, wcr) =
+            watchpoint_encoding(0x4000, 0x1000, WatchAccess::ReadWrite, DebugTarget::El0).unwrap();
+        assert_eq!(wvr, 0x4000);
+        assert_eq!((wcr >> 24) & 0x1F, 12);
+        assert_eq!((wcr >> 5) & 0xFF, 0xFF);
+        assert_eq!(watched_range(wvr, wcr), (0x4000, 0x5000));
+
+        // Crosses a doubleword, not a power of two, misaligned
+        assert!(watchpoint_encoding(0x1006, 4, WatchAccess::Read, DebugTarget::El1).is_none());
+        assert!(watchpoint_encoding(0x1000, 24, WatchAccess::Read, DebugTarget::El1).is_none());
+        assert!(watchpoint_encoding(0x1010, 32, WatchAccess::Read, DebugTarget::El1).is_none());
+        assert!(watchpoint_encoding(0x1000, 0, WatchAccess::Read, DebugTarget::El1).is_none());
+    }
+
+    #[test]
+    fn test_watchpoint_hit() {
+        let hit = WatchpointHit::from_iss((1 << 17) | (3 << 18) | (1 << 6) | 0x22);
+        assert_eq!(
+            hit,
+            WatchpointHit {
+                write: true,
+                far_valid: true,
+                slot: Some(3)
+            }
+        );
+        let hit = WatchpointHit::from_iss((1 << 10) | 0x22);
+        assert!(!hit.write);
+        assert!(!hit.far_valid);
+        assert_eq!(hit.slot, None);
+    }
+
+    #[test]
+    fn test_step_info() {
+        assert_eq!(
+            StepInfo::from_iss((1 << 24) | (1 << 6) | 0x22),
+            StepInfo {
+                stepped: true,
+                load_exclusive: true
+            }
+        );
+        let info = StepInfo::from_iss(0x22);
+        assert!(!info.stepped);
+        assert!(!info.load_exclusive);
+    }
+
+    #[test]
+    fn test_auth_status() {
+        // Non-secure debug enabled, secure debug disabled
+        let status = DebugAuthStatus::from_value(0b1010_1111);
+        assert!(status.non_secure_invasive.is_enabled());
+        assert_eq!(status.non_secure_non_invasive, AuthState::Enabled);
+        assert_eq!(status.secure_invasive, AuthState::Disabled);
+        assert_eq!(status.secure_non_invasive, AuthState::Disabled);
+        assert_eq!(
+            DebugAuthStatus::from_value(0).secure_invasive,
+            AuthState::NotImplemented
+        );
+    }
+}
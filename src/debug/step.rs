@@ -0,0 +1,28 @@
+use crate::exception::{Spsr, TrapFrame};
+
+use super::breakpoint::{read_mdscr, write_mdscr};
+
+const MDSCR_SS: u64 = 1 << 0;
+
+/// Step one instruction of the context `frame` returns to
+///
+/// Sets MDSCR_EL1.SS and the SS bit of the saved SPSR. After the exception return, the
+/// context executes one instruction and takes a software step exception. Call again from
+/// that exception to step the next instruction. Debug exceptions must be enabled with
+/// [`enable_debug_exceptions`](super::enable_debug_exceptions) and not masked in the SPSR,
+/// stepping EL1 itself also requires MDSCR_EL1.KDE, which that sets.
+pub fn single_step_enable(frame: &mut TrapFrame) {
+    write_mdscr(read_mdscr() | MDSCR_SS);
+    frame.spsr = Spsr::from_value(frame.spsr).software_step(true).value();
+}
+
+/// Stop stepping, `frame` then runs freely after the exception return
+pub fn single_step_disable(frame: &mut TrapFrame) {
+    write_mdscr(read_mdscr() & !MDSCR_SS);
+    frame.spsr = Spsr::from_value(frame.spsr).software_step(false).value();
+}
+
+/// Check if software step is enabled (MDSCR_EL1.SS)
+pub fn is_single_step_enabled() -> bool {
+    read_mdscr() & MDSCR_SS != 0
+}
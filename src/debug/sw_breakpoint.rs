@@ -0,0 +1,174 @@
+//! Software breakpoints: BRK instructions with handlers selected by immediate.
+//!
+//! A BRK takes a synchronous exception with its 16-bit immediate in the syndrome and ELR
+//! pointing at the BRK itself. Handlers are registered per immediate with [`register`],
+//! [`dispatch`] is a [`BrkHandler`](crate::exception::BrkHandler) that runs them and is
+//! installed with `on_brk(sw_breakpoint::dispatch)`. A handler returns
+//! [`Action::Skip`] to resume after the BRK or [`Action::Panic`] to report it, BRKs without
+//! a handler are reported.
+
+use core::{
+    hint, mem, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering},
+};
+
+use crate::exception::{
+    Action, BrkHandler, TrapFrame,
+    syndrome::{Esr, Syndrome},
+};
+
+/// Number of immediates that can have a handler at the same time
+pub const MAX_HANDLERS: usize = 16;
+
+/// [`register`] found no free entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableFull;
+
+const EMPTY: u32 = 0;
+
+struct Entry {
+    /// Immediate plus one, so zero can mark an empty entry
+    key: AtomicU32,
+    handler: AtomicPtr<()>,
+}
+
+static TABLE: [Entry; MAX_HANDLERS] = [const {
+    Entry {
+        key: AtomicU32::new(EMPTY),
+        handler: AtomicPtr::new(ptr::null_mut()),
+    }
+}; MAX_HANDLERS];
+
+/// Held while the table is modified, lookups by [`handler`] do not take it
+static LOCK: AtomicBool = AtomicBool::new(false);
+
+const fn key(imm: u16) -> u32 {
+    imm as u32 + 1
+}
+
+/// Run `f` with [`LOCK`] held, so a lookup and the update depending on it are atomic
+fn locked<R>(f: impl FnOnce() -> R) -> R {
+    while LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        hint::spin_loop();
+    }
+    let result = f();
+    LOCK.store(false, Ordering::Release);
+    result
+}
+
+/// Run `handler` for `BRK #imm`, replacing the handler already registered for `imm`
+///
+/// Registrations are serialized by a spin lock, so this must not be called from a
+/// handler that can interrupt another registration on the same core.
+pub fn register(imm: u16, handler: BrkHandler) -> Result<(), TableFull> {
+    locked(|| {
+        let entry = TABLE
+            .iter()
+            .find(|e| e.key.load(Ordering::Relaxed) == key(imm));
+        if let Some(entry) = entry {
+            entry.handler.store(handler as *mut (), Ordering::Release);
+            return Ok(());
+        }
+        let entry = TABLE
+            .iter()
+            .find(|e| e.key.load(Ordering::Relaxed) == EMPTY)
+            .ok_or(TableFull)?;
+        entry.handler.store(handler as *mut (), Ordering::Relaxed);
+        entry.key.store(key(imm), Ordering::Release);
+        Ok(())
+    })
+}
+
+/// Remove the handler of `BRK #imm`, a later BRK with it is reported
+pub fn unregister(imm: u16) {
+    locked(|| {
+        if let Some(entry) = TABLE
+            .iter()
+            .find(|e| e.key.load(Ordering::Relaxed) == key(imm))
+        {
+            entry.key.store(EMPTY, Ordering::Release);
+        }
+    })
+}
+
+/// Handler registered for `BRK #imm`
+pub fn handler(imm: u16) -> Option<BrkHandler> {
+    let entry = TABLE
+        .iter()
+        .find(|e| e.key.load(Ordering::Acquire) == key(imm))?;
+    let handler = entry.handler.load(Ordering::Acquire);
+    // SAFETY: the handler is only ever set to a BrkHandler before the key is published
+    Some(unsafe { mem::transmute::<*mut (), BrkHandler>(handler) })
+}
+
+/// Run the handler registered for `imm`, [`Action::Panic`] if there is none
+pub fn dispatch(frame: &mut TrapFrame, imm: u16) -> Action {
+    match handler(imm) {
+        Some(handler) => handler(frame, imm),
+        None => Action::Panic,
+    }
+}
+
+/// Immediate of the BRK that caused the exception with syndrome `esr`
+pub const fn brk_immediate(esr: Esr) -> Option<u16> {
+    match esr.decode() {
+        Syndrome::Brk { comment } => Some(comment),
+        _ => None,
+    }
+}
+
+/// Execute `BRK #IMM`
+///
+/// Returns once a handler resumes after the BRK. The handler may inspect the frame, but
+/// changes to the general purpose registers are not visible to the caller.
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+pub fn brk<const IMM: u16>() {
+    unsafe { core::arch::asm!("brk #{imm}", imm = const IMM, options(nostack)) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_dispatch() {
+        let mut frame = TrapFrame::default();
+        assert_eq!(dispatch(&mut frame, 0x800), Action::Panic);
+
+        register(0x800, |frame, imm| {
+            frame.x[0] = imm as u64;
+            Action::Skip
+        })
+        .unwrap();
+        assert_eq!(dispatch(&mut frame, 0x800), Action::Skip);
+        assert_eq!(frame.x[0], 0x800);
+
+        // Registering again replaces the handler
+        register(0x800, |_, _| Action::Resume).unwrap();
+        assert_eq!(dispatch(&mut frame, 0x800), Action::Resume);
+        unregister(0x800);
+        assert!(handler(0x800).is_none());
+
+        assert_eq!(brk_immediate(Esr(0xF200_0800)), Some(0x800));
+        assert_eq!(brk_immediate(Esr(0x5600_0042)), None);
+    }
+
+    #[test]
+    fn test_register_concurrent() {
+        let threads: Vec<_> = (0..8)
+            .map(|_| std::thread::spawn(|| register(0x900, |_, _| Action::Skip).unwrap()))
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        let entries = TABLE
+            .iter()
+            .filter(|e| e.key.load(Ordering::Relaxed) == key(0x900));
+        assert_eq!(entries.count(), 1);
+        unregister(0x900);
+        assert!(handler(0x900).is_none());
+    }
+}
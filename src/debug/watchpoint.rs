@@ -0,0 +1,112 @@
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{ID_AA64DFR0_EL1, Readable},
+};
+
+use super::{DebugTarget, WatchAccess, WatchpointHit, watched_range, watchpoint_encoding};
+
+const WCR_ENABLE: u64 = 1 << 0;
+
+/// Number of watchpoints implemented (ID_AA64DFR0_EL1.WRPs + 1)
+#[inline]
+pub fn watchpoint_count() -> usize {
+    ID_AA64DFR0_EL1.read(ID_AA64DFR0_EL1::WRPs) as usize + 1
+}
+
+/// A hardware watchpoint slot (DBGWVR<n>_EL1, DBGWCR<n>_EL1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwWatchpoint {
+    slot: usize,
+}
+
+impl HwWatchpoint {
+    /// Program watchpoint `slot` on `len` bytes at `addr` and enable it
+    ///
+    /// Returns `None` if the core has no watchpoint `slot` or the region cannot be encoded,
+    /// see [`watchpoint_encoding`].
+    pub fn set(
+        slot: usize,
+        addr: usize,
+        len: usize,
+        access: WatchAccess,
+        target: DebugTarget,
+    ) -> Option<Self> {
+        if slot >= watchpoint_count() {
+            return None;
+        }
+        let (wvr, wcr) = watchpoint_encoding(addr as u64, len as u64, access, target)?;
+        let wp = Self { slot };
+        wp.disable();
+        dbg_indexed!(write "dbgwvr", slot, wvr);
+        dbg_indexed!(write "dbgwcr", slot, wcr);
+        isb(SY);
+        Some(wp)
+    }
+
+    /// Handle of watchpoint `slot` without changing it, `None` if it is not implemented
+    pub fn get(slot: usize) -> Option<Self> {
+        (slot < watchpoint_count()).then_some(Self { slot })
+    }
+
+    /// Watchpoint that fired for a watchpoint exception with syndrome `iss` and fault
+    /// address `far`
+    ///
+    /// Uses the slot reported in the syndrome if there is one. Otherwise looks for an
+    /// enabled watchpoint whose region overlaps the doubleword at `far`, which is ambiguous
+    /// if several watchpoints cover it.
+    pub fn hit(iss: u32, far: usize) -> Option<Self> {
+        let hit = WatchpointHit::from_iss(iss);
+        if let Some(slot) = hit.slot {
+            return Self::get(slot);
+        }
+        if !hit.far_valid {
+            return None;
+        }
+        let start = far as u64 & !0b111;
+        let end = start + 8;
+        (0..watchpoint_count())
+            .map(|slot| Self { slot })
+            .filter(|wp| wp.is_enabled())
+            .find(|wp| {
+                let (first, last) = wp.range();
+                first < end && start < last
+            })
+    }
+
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Watched byte range `[start, end)`
+    pub fn range(&self) -> (u64, u64) {
+        watched_range(
+            dbg_indexed!(read "dbgwvr", self.slot),
+            dbg_indexed!(read "dbgwcr", self.slot),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        dbg_indexed!(read "dbgwcr", self.slot) & WCR_ENABLE != 0
+    }
+
+    /// Re-enable the watchpoint with its programmed region and control
+    pub fn enable(&self) {
+        let wcr = dbg_indexed!(read "dbgwcr", self.slot);
+        dbg_indexed!(write "dbgwcr", self.slot, wcr | WCR_ENABLE);
+        isb(SY);
+    }
+
+    /// Disable the watchpoint, keeping its region and control
+    pub fn disable(&self) {
+        let wcr = dbg_indexed!(read "dbgwcr", self.slot);
+        dbg_indexed!(write "dbgwcr", self.slot, wcr & !WCR_ENABLE);
+        isb(SY);
+    }
+
+    /// Disable the watchpoint and clear its registers
+    pub fn clear(self) {
+        dbg_indexed!(write "dbgwcr", self.slot, 0);
+        dbg_indexed!(write "dbgwvr", self.slot, 0);
+        isb(SY);
+    }
+}
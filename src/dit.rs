@@ -0,0 +1,84 @@
+//! Data Independent Timing (FEAT_DIT).
+//!
+//! With PSTATE.DIT set, the timing of the data processing instructions listed by the
+//! architecture is independent of the values they operate on, which constant-time
+//! cryptographic code relies on. [`enabled_scope`] sets DIT for a scope and restores the
+//! previous value afterwards.
+//!
+//! PSTATE.DIT is saved and restored on exception entry and return, an interrupted scope
+//! resumes with DIT still set.
+
+use core::{arch::asm, marker::PhantomData};
+
+use crate::features::{Feature, is_supported as has_feature};
+
+const DIT_BIT: u64 = 1 << 24;
+
+/// Check if the core implements PSTATE.DIT
+#[inline]
+pub fn is_supported() -> bool {
+    has_feature(Feature::Dit)
+}
+
+/// Set PSTATE.DIT, requires FEAT_DIT
+#[inline]
+pub fn enable() {
+    // MSR DIT, #1, encoded as the assembler may not know FEAT_DIT
+    unsafe { asm!(".inst 0xd503415f", options(nostack)) };
+}
+
+/// Clear PSTATE.DIT, requires FEAT_DIT
+#[inline]
+pub fn disable() {
+    // MSR DIT, #0
+    unsafe { asm!(".inst 0xd503405f", options(nostack)) };
+}
+
+/// Check if PSTATE.DIT is set, requires FEAT_DIT
+#[inline]
+pub fn is_enabled() -> bool {
+    // DIT
+    crate::sysreg!(read "S3_3_C4_C2_5") & DIT_BIT != 0
+}
+
+/// Set PSTATE.DIT until the returned guard is dropped
+///
+/// Does nothing on cores without FEAT_DIT, see [`DitGuard::is_active`].
+#[inline]
+pub fn enabled_scope() -> DitGuard {
+    let saved = is_supported().then(|| {
+        let saved = is_enabled();
+        enable();
+        saved
+    });
+    DitGuard {
+        saved,
+        _not_send: PhantomData,
+    }
+}
+
+/// Keeps PSTATE.DIT set while alive, restoring the saved value on drop
+///
+/// The guard is tied to the core it was created on and therefore neither `Send` nor `Sync`.
+#[must_use = "the previous value is restored when the guard is dropped"]
+pub struct DitGuard {
+    /// DIT before the guard was created, `None` without FEAT_DIT
+    saved: Option<bool>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl DitGuard {
+    /// Check if DIT is set by this guard, false on cores without FEAT_DIT
+    pub fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+}
+
+impl Drop for DitGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.saved == Some(false) {
+            disable();
+        }
+    }
+}
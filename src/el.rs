@@ -0,0 +1,158 @@
+//! Exception level aware register access.
+//!
+//! Code that runs at EL1 or at EL2 (e.g. a kernel under a VHE hypervisor or booted at EL2)
+//! selects the `_EL1` or `_EL2` instance of a register with the accessors of this module.
+//! The accessors take the exception level explicitly, so it can be read once with
+//! [`current_el`] and passed down.
+
+use core::arch::asm;
+
+use aarch64_cpu::registers::{CurrentEL, Readable};
+
+/// An AArch64 exception level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExceptionLevel {
+    EL0,
+    EL1,
+    EL2,
+    EL3,
+}
+
+impl ExceptionLevel {
+    /// Decode CurrentEL.EL
+    pub const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0 => Self::EL0,
+            1 => Self::EL1,
+            2 => Self::EL2,
+            _ => Self::EL3,
+        }
+    }
+}
+
+/// The exception level the core is executing at
+#[inline]
+pub fn current_el() -> ExceptionLevel {
+    ExceptionLevel::from_bits(CurrentEL.read(CurrentEL::EL))
+}
+
+macro_rules! el_reg {
+    (@impl [$($doc:tt)*] [$($panics:tt)*] $read:ident, $write:ident, $name:literal, $el0:ident) => {
+        $($doc)*
+        $($panics)*
+        #[inline]
+        pub fn $read(el: ExceptionLevel) -> u64 {
+            let value: u64;
+            unsafe {
+                match el {
+                    ExceptionLevel::EL1 => asm!(concat!("mrs {}, ", $name, "_el1"), out(reg) value, options(nomem, nostack)),
+                    ExceptionLevel::EL2 => asm!(concat!("mrs {}, ", $name, "_el2"), out(reg) value, options(nomem, nostack)),
+                    ExceptionLevel::EL3 => asm!(concat!("mrs {}, ", $name, "_el3"), out(reg) value, options(nomem, nostack)),
+                    ExceptionLevel::EL0 => el_reg!(@el0 $el0 read $name, value),
+                }
+            }
+            value
+        }
+
+        $($doc)*
+        ///
+        /// # Safety
+        ///
+        /// `el` must be the current exception level or a lower one accessible from it, the
+        /// usual safety requirements of writing the register apply.
+        #[inline]
+        pub unsafe fn $write(el: ExceptionLevel, value: u64) {
+            unsafe {
+                match el {
+                    ExceptionLevel::EL1 => asm!(concat!("msr ", $name, "_el1, {}"), in(reg) value, options(nostack)),
+                    ExceptionLevel::EL2 => asm!(concat!("msr ", $name, "_el2, {}"), in(reg) value, options(nostack)),
+                    ExceptionLevel::EL3 => asm!(concat!("msr ", $name, "_el3, {}"), in(reg) value, options(nostack)),
+                    ExceptionLevel::EL0 => el_reg!(@el0 $el0 write $name, value),
+                }
+            }
+        }
+    };
+    (@el0 none $op:ident $name:literal, $value:ident) => {
+        panic!(concat!($name, " has no EL0 instance"))
+    };
+    (@el0 el0 read $name:literal, $value:ident) => {
+        asm!(concat!("mrs {}, ", $name, "_el0"), out(reg) $value, options(nomem, nostack))
+    };
+    (@el0 el0 write $name:literal, $value:ident) => {
+        asm!(concat!("msr ", $name, "_el0, {}"), in(reg) $value, options(nostack))
+    };
+    ($(#[$doc:meta])* $read:ident, $write:ident, $name:literal) => {
+        el_reg!(@impl [$(#[$doc])*] [
+            ///
+            /// # Panics
+            ///
+            /// At EL0, the register has no EL0 instance.
+        ] $read, $write, $name, none);
+    };
+    // The register has an EL0 instance, `_EL0` is accessed at EL0
+    ($(#[$doc:meta])* $read:ident, $write:ident, $name:literal, el0) => {
+        el_reg!(@impl [$(#[$doc])*] [] $read, $write, $name, el0);
+    };
+}
+
+el_reg!(
+    /// Translation Table Base Register 0 (TTBR0_ELx)
+    read_ttbr0,
+    write_ttbr0,
+    "ttbr0"
+);
+el_reg!(
+    /// System Control Register (SCTLR_ELx)
+    read_sctlr,
+    write_sctlr,
+    "sctlr"
+);
+el_reg!(
+    /// Translation Control Register (TCR_ELx)
+    read_tcr,
+    write_tcr,
+    "tcr"
+);
+el_reg!(
+    /// Memory Attribute Indirection Register (MAIR_ELx)
+    read_mair,
+    write_mair,
+    "mair"
+);
+el_reg!(
+    /// Vector Base Address Register (VBAR_ELx)
+    read_vbar,
+    write_vbar,
+    "vbar"
+);
+el_reg!(
+    /// Exception Syndrome Register (ESR_ELx)
+    read_esr,
+    write_esr,
+    "esr"
+);
+el_reg!(
+    /// Fault Address Register (FAR_ELx)
+    read_far,
+    write_far,
+    "far"
+);
+el_reg!(
+    /// Exception Link Register (ELR_ELx)
+    read_elr,
+    write_elr,
+    "elr"
+);
+el_reg!(
+    /// Saved Program Status Register (SPSR_ELx)
+    read_spsr,
+    write_spsr,
+    "spsr"
+);
+el_reg!(
+    /// Thread ID Register (TPIDR_ELx), TPIDR_EL0 at EL0
+    read_tpidr,
+    write_tpidr,
+    "tpidr",
+    el0
+);
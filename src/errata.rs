@@ -0,0 +1,184 @@
+//! CPU errata keyed by MIDR_EL1.
+//!
+//! [`Erratum::affects`] matches a decoded [`Midr`] against the implementer, part number and
+//! revision range of the erratum. On AArch64, [`requires_workaround`] checks the executing
+//! core and a few helpers apply workarounds that only need CPU-level instruction sequences.
+
+/// Decoded Main ID Register (MIDR_EL1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Midr {
+    pub implementer: u8,
+    pub variant: u8,
+    pub architecture: u8,
+    pub part_num: u16,
+    pub revision: u8,
+}
+
+impl Midr {
+    /// Arm Limited
+    pub const IMPLEMENTER_ARM: u8 = 0x41;
+
+    pub const PART_CORTEX_A53: u16 = 0xD03;
+    pub const PART_CORTEX_A55: u16 = 0xD05;
+    pub const PART_CORTEX_A57: u16 = 0xD07;
+    pub const PART_CORTEX_A72: u16 = 0xD08;
+    pub const PART_CORTEX_A76: u16 = 0xD0B;
+    pub const PART_NEOVERSE_N1: u16 = 0xD0C;
+
+    /// Decode a MIDR_EL1 value
+    pub const fn from_value(midr: u64) -> Self {
+        Self {
+            implementer: (midr >> 24) as u8,
+            variant: ((midr >> 20) & 0xF) as u8,
+            architecture: ((midr >> 16) & 0xF) as u8,
+            part_num: ((midr >> 4) & 0xFFF) as u16,
+            revision: (midr & 0xF) as u8,
+        }
+    }
+
+    /// Encode as MIDR_EL1 value
+    pub const fn value(self) -> u64 {
+        ((self.implementer as u64) << 24)
+            | ((self.variant as u64) << 20)
+            | ((self.architecture as u64) << 16)
+            | ((self.part_num as u64) << 4)
+            | self.revision as u64
+    }
+
+    /// Variant and revision combined, e.g. r2p1 = 0x21
+    pub const fn rev(self) -> u8 {
+        (self.variant << 4) | self.revision
+    }
+
+    /// Check for an Arm Limited core with part number `part_num`
+    pub const fn is_arm(self, part_num: u16) -> bool {
+        self.implementer == Self::IMPLEMENTER_ARM && self.part_num == part_num
+    }
+}
+
+/// A known CPU erratum
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Erratum {
+    /// Cortex-A53 r0p0-r0p4: a load may read stale data after a switch between AArch32
+    /// and AArch64 at EL0
+    CortexA53_845719,
+    /// Cortex-A55 r0p0-r2p0: speculative AT instructions may corrupt the TLB
+    CortexA55_1530923,
+    /// Cortex-A57 r0p0-r1p2: device loads may deadlock with other memory accesses
+    CortexA57_832075,
+    /// Cortex-A57 r0p0-r1p2: an ISB is required when switching the EL1 system register
+    /// context
+    CortexA57_852523,
+    /// Cortex-A76 r0p0-r2p0: speculative AT instructions may corrupt the TLB
+    CortexA76_1165522,
+    /// Cortex-A76 r0p0-r3p0: a TLBI may not invalidate all entries and must be repeated
+    CortexA76_1286807,
+    /// Cortex-A76 r0p0-r3p1: Software Step might prevent interrupt recognition. Stepping
+    /// into an SVC whose handler disables stepping can leave later interrupts
+    /// unrecognised
+    CortexA76_1463225,
+}
+
+impl Erratum {
+    /// Every known erratum
+    pub const ALL: [Self; 7] = [
+        Self::CortexA53_845719,
+        Self::CortexA55_1530923,
+        Self::CortexA57_832075,
+        Self::CortexA57_852523,
+        Self::CortexA76_1165522,
+        Self::CortexA76_1286807,
+        Self::CortexA76_1463225,
+    ];
+
+    /// Part number and inclusive revision range affected
+    const fn range(self) -> (u16, u8, u8) {
+        match self {
+            Self::CortexA53_845719 => (Midr::PART_CORTEX_A53, 0x00, 0x04),
+            Self::CortexA55_1530923 => (Midr::PART_CORTEX_A55, 0x00, 0x20),
+            Self::CortexA57_832075 => (Midr::PART_CORTEX_A57, 0x00, 0x12),
+            Self::CortexA57_852523 => (Midr::PART_CORTEX_A57, 0x00, 0x12),
+            Self::CortexA76_1165522 => (Midr::PART_CORTEX_A76, 0x00, 0x20),
+            Self::CortexA76_1286807 => (Midr::PART_CORTEX_A76, 0x00, 0x30),
+            Self::CortexA76_1463225 => (Midr::PART_CORTEX_A76, 0x00, 0x31),
+        }
+    }
+
+    /// Check if a core with the given MIDR is affected
+    pub const fn affects(self, midr: Midr) -> bool {
+        let (part, min, max) = self.range();
+        midr.is_arm(part) && midr.rev() >= min && midr.rev() <= max
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod workaround {
+    use aarch64_cpu::{
+        asm::barrier::{ISH, SY, dsb, isb},
+        registers::{MIDR_EL1, Readable},
+    };
+
+    use super::{Erratum, Midr};
+
+    impl Midr {
+        /// Read MIDR_EL1 of the executing core
+        pub fn read() -> Self {
+            Self::from_value(MIDR_EL1.get())
+        }
+    }
+
+    /// Check if the executing core needs the workaround for `erratum`
+    pub fn requires_workaround(erratum: Erratum) -> bool {
+        erratum.affects(Midr::read())
+    }
+
+    /// Run a TLB maintenance sequence, repeating it on cores affected by
+    /// [`Erratum::CortexA76_1286807`]
+    ///
+    /// `tlbi` issues the TLBI instructions, each run is completed with DSB ISH.
+    pub fn tlbi_with_repeat(mut tlbi: impl FnMut()) {
+        tlbi();
+        dsb(ISH);
+        if requires_workaround(Erratum::CortexA76_1286807) {
+            tlbi();
+            dsb(ISH);
+        }
+    }
+
+    /// Issue an ISB if the executing core is affected by `erratum`
+    ///
+    /// For errata whose workaround is an extra context synchronization, e.g.
+    /// [`Erratum::CortexA57_852523`].
+    #[inline]
+    pub fn isb_if_required(erratum: Erratum) {
+        if requires_workaround(erratum) {
+            isb(SY);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use workaround::{isb_if_required, requires_workaround, tlbi_with_repeat};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errata() {
+        // Cortex-A76 r3p0
+        let a76 = Midr::from_value(0x413F_D0B0);
+        assert_eq!(a76.part_num, Midr::PART_CORTEX_A76);
+        assert_eq!(a76.rev(), 0x30);
+        assert_eq!(a76.value(), 0x413F_D0B0);
+        assert!(Erratum::CortexA76_1286807.affects(a76));
+        assert!(Erratum::CortexA76_1463225.affects(a76));
+        assert!(!Erratum::CortexA76_1165522.affects(a76));
+        assert!(!Erratum::CortexA57_852523.affects(a76));
+
+        // Cortex-A76 r4p0 is fixed
+        let fixed = Midr::from_value(0x414F_D0B0);
+        assert!(Erratum::ALL.iter().all(|e| !e.affects(fixed)));
+    }
+}
@@ -0,0 +1,340 @@
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::{
+    ExecutionState, Spsr, TrapFrame,
+    syndrome::{Esr, FaultInfo, Syndrome},
+};
+
+/// What to do after a synchronous exception handler returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Return to ELR unchanged, e.g. to retry a faulting access after fixing the mapping
+    Resume,
+    /// Return to the instruction following the one that caused the exception
+    ///
+    /// SVC and HVC already return past the calling instruction, for them this is the same
+    /// as [`Resume`](Self::Resume).
+    Skip,
+    /// Panic with the syndrome and the frame
+    Panic,
+}
+
+/// Data abort handler, called with the decoded fault and FAR_ELx
+pub type DataAbortHandler = fn(&mut TrapFrame, &FaultInfo, u64) -> Action;
+/// SVC handler, called with the immediate of the instruction
+pub type SvcHandler = fn(&mut TrapFrame, u16) -> Action;
+/// BRK handler, called with the comment of the instruction
+pub type BrkHandler = fn(&mut TrapFrame, u16) -> Action;
+/// Handler of all other synchronous exceptions
+pub type SyncHandler = fn(&mut TrapFrame, Esr) -> Action;
+
+/// A replaceable function pointer, null while no handler is installed
+struct HandlerSlot(AtomicPtr<()>);
+
+impl HandlerSlot {
+    const fn new() -> Self {
+        Self(AtomicPtr::new(ptr::null_mut()))
+    }
+
+    fn set(&self, handler: *mut ()) {
+        self.0.store(handler, Ordering::Release);
+    }
+
+    fn get(&self) -> *mut () {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+static DATA_ABORT: HandlerSlot = HandlerSlot::new();
+static SVC: HandlerSlot = HandlerSlot::new();
+static BRK: HandlerSlot = HandlerSlot::new();
+static OTHER: HandlerSlot = HandlerSlot::new();
+
+/// Install the handler of data aborts, replacing the previous one
+pub fn on_data_abort(handler: DataAbortHandler) {
+    DATA_ABORT.set(handler as *mut ());
+}
+
+/// Install the handler of AArch64 SVC instructions, replacing the previous one
+pub fn on_svc(handler: SvcHandler) {
+    SVC.set(handler as *mut ());
+}
+
+/// Install the handler of BRK instructions, replacing the previous one
+pub fn on_brk(handler: BrkHandler) {
+    BRK.set(handler as *mut ());
+}
+
+/// Install the handler of synchronous exceptions without a dedicated handler
+pub fn on_other_sync(handler: SyncHandler) {
+    OTHER.set(handler as *mut ());
+}
+
+/// Dispatch a synchronous exception to the installed handler and apply its [`Action`]
+///
+/// Exceptions without a handler resolve to [`Action::Panic`]. Returns the action taken,
+/// [`Action::Panic`] does not return.
+pub fn dispatch_sync(frame: &mut TrapFrame, esr: Esr, far: u64) -> Action {
+    let syndrome = esr.decode();
+    let (data_abort, svc, brk, other) = (DATA_ABORT.get(), SVC.get(), BRK.get(), OTHER.get());
+    // SAFETY: the slots only ever hold null or a handler of the matching type
+    let action = unsafe {
+        match syndrome {
+            Syndrome::DataAbort { iss, .. } if !data_abort.is_null() => {
+                let handler = mem::transmute::<*mut (), DataAbortHandler>(data_abort);
+                handler(frame, &FaultInfo::from_data_abort_iss(iss), far)
+            }
+            Syndrome::Svc {
+                imm,
+                aarch32: false,
+            } if !svc.is_null() => mem::transmute::<*mut (), SvcHandler>(svc)(frame, imm),
+            Syndrome::Brk { comment } if !brk.is_null() => {
+                mem::transmute::<*mut (), BrkHandler>(brk)(frame, comment)
+            }
+            _ if !other.is_null() => mem::transmute::<*mut (), SyncHandler>(other)(frame, esr),
+            _ => Action::Panic,
+        }
+    };
+
+    match action {
+        Action::Resume => {}
+        Action::Skip => skip_faulting_instruction(frame, esr),
+        Action::Panic => panic!(
+            "unhandled synchronous exception {:?}, ESR {:#x}, FAR {:#x}\n{:#x?}",
+            syndrome, esr.0, far, frame
+        ),
+    }
+    action
+}
+
+/// Advance the return address of `frame` past the instruction that caused the exception
+/// with syndrome `esr`
+///
+/// For handlers that emulate the instruction, e.g. a trapped MMIO access. ELR is advanced by
+/// the length given by ESR.IL, 2 bytes for a 16-bit T32 instruction. When returning to
+/// AArch32 T32 code, the IT state in the SPSR is advanced as well, so a skipped
+/// instruction inside an IT block does not leave the next one predicated wrongly. SVC and
+/// HVC already return past the instruction and are left unchanged.
+///
+/// IL is RES1 for an unknown exception (EC 0x00) and for a data abort without a valid
+/// instruction syndrome (ISV). For T32 code taken from EL0 to EL1 the length is then
+/// decoded from the first halfword of the instruction, read with an unprivileged load from
+/// ELR.
+///
+/// # Panics
+///
+/// If the length is unknown for T32 code not taken from EL0 to EL1, e.g. guest code
+/// trapped to EL2, whose ELR does not translate through the current regime. Decode the
+/// length from guest memory with [`t32_instruction_len`] and call [`skip_instruction`]
+/// instead.
+pub fn skip_faulting_instruction(frame: &mut TrapFrame, esr: Esr) {
+    if matches!(esr.decode(), Syndrome::Svc { .. } | Syndrome::Hvc { .. }) {
+        return;
+    }
+    let spsr = Spsr::from_value(frame.spsr);
+    let t32 = spsr.execution_state() == ExecutionState::AArch32 && spsr.value() & SPSR_T != 0;
+    let len = if t32 && !instruction_len_valid(esr) {
+        assert!(
+            spsr.aarch32_mode() == AARCH32_MODE_USR && is_el1(),
+            "T32 instruction length at {:#x} unknown, ESR {:#x}: decode it with \
+             t32_instruction_len and call skip_instruction",
+            frame.elr,
+            esr.0
+        );
+        t32_instruction_len(unsafe { read_user_halfword(frame.elr as usize) })
+    } else {
+        esr.instruction_len()
+    };
+    skip_instruction(frame, len);
+}
+
+/// Advance the return address of `frame` by the `len` bytes of the instruction at ELR
+///
+/// Like [`skip_faulting_instruction`] with a length provided by the caller, the IT state
+/// is advanced as well when returning to T32 code.
+pub fn skip_instruction(frame: &mut TrapFrame, len: usize) {
+    frame.elr = frame.elr.wrapping_add(len as u64);
+
+    let spsr = Spsr::from_value(frame.spsr);
+    if spsr.execution_state() == ExecutionState::AArch32 && spsr.value() & SPSR_T != 0 {
+        frame.spsr = it_advance(frame.spsr);
+    }
+}
+
+/// Length in bytes of the T32 instruction starting with the halfword `first`
+///
+/// Instructions whose first halfword has bits [15:11] 0b11101, 0b11110 or 0b11111 are
+/// 32-bit, all others 16-bit.
+pub const fn t32_instruction_len(first: u16) -> usize {
+    match first >> 11 {
+        0b11101..=0b11111 => 4,
+        _ => 2,
+    }
+}
+
+/// Check if ESR.IL gives the instruction length, it is RES1 for EC 0x00 and for data aborts
+/// without a valid instruction syndrome
+const fn instruction_len_valid(esr: Esr) -> bool {
+    match esr.decode() {
+        Syndrome::Unknown => false,
+        Syndrome::DataAbort { iss, .. } => iss & ISS_ISV != 0,
+        _ => true,
+    }
+}
+
+/// Check if the exception is handled at EL1, where LDTR translates like EL0 code
+fn is_el1() -> bool {
+    match () {
+        #[cfg(target_arch = "aarch64")]
+        () => crate::el::current_el() == crate::el::ExceptionLevel::EL1,
+
+        #[cfg(not(target_arch = "aarch64"))]
+        () => false,
+    }
+}
+
+/// Read the halfword at `addr` as EL0 would
+///
+/// # Safety
+///
+/// `addr` must be mapped and readable at EL0.
+unsafe fn read_user_halfword(addr: usize) -> u16 {
+    match () {
+        #[cfg(target_arch = "aarch64")]
+        () => unsafe { crate::pan::read_user(addr) },
+
+        #[cfg(not(target_arch = "aarch64"))]
+        () => unimplemented!("{addr:#x}"),
+    }
+}
+
+/// ISV bit of a data abort ISS
+const ISS_ISV: u32 = 1 << 24;
+
+/// AArch32 User mode, M[3:0]
+const AARCH32_MODE_USR: u8 = 0b0000;
+
+/// T bit of an AArch32 SPSR
+const SPSR_T: u64 = 1 << 5;
+
+/// ITAdvance() on the IT bits of an AArch32 SPSR, IT[1:0] at bits 26:25 and IT[7:2] at
+/// bits 15:10
+const fn it_advance(spsr: u64) -> u64 {
+    let it = ((spsr >> 25) & 0b11) | (((spsr >> 10) & 0x3F) << 2);
+    let it = if it & 0b111 == 0 {
+        0
+    } else {
+        (it & 0xE0) | ((it << 1) & 0x1F)
+    };
+    (spsr & !((0b11 << 25) | (0x3F << 10))) | ((it & 0b11) << 25) | ((it >> 2) << 10)
+}
+
+#[cfg(target_arch = "aarch64")]
+mod entry {
+    use crate::el::{current_el, read_far};
+
+    use super::{super::TrapFrame, Esr, dispatch_sync};
+
+    /// Synchronous exception entry for [`vector_table!`](crate::vector_table)
+    ///
+    /// Reads ESR_ELx and FAR_ELx of the current exception level and calls
+    /// [`dispatch_sync`].
+    pub extern "C" fn handle_sync(frame: &mut TrapFrame) {
+        let el = current_el();
+        dispatch_sync(frame, Esr::read(), read_far(el));
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use entry::handle_sync;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch() {
+        on_brk(|frame, comment| {
+            frame.x[0] = comment as u64;
+            Action::Skip
+        });
+        on_svc(|frame, imm| {
+            frame.x[8] = imm as u64;
+            Action::Skip
+        });
+
+        let mut frame = TrapFrame {
+            elr: 0x8000,
+            ..Default::default()
+        };
+        assert_eq!(dispatch_sync(&mut frame, Esr(0xF200_0007), 0), Action::Skip);
+        assert_eq!(frame.x[0], 7);
+        assert_eq!(frame.elr, 0x8004);
+
+        // ELR already points past the SVC
+        assert_eq!(dispatch_sync(&mut frame, Esr(0x5600_0042), 0), Action::Skip);
+        assert_eq!(frame.x[8], 0x42);
+        assert_eq!(frame.elr, 0x8004);
+    }
+
+    #[test]
+    fn test_skip_faulting_instruction() {
+        // 16-bit T32 load outside an IT block, data abort with ISV set and IL clear
+        let mut frame = TrapFrame {
+            elr: 0x1000,
+            spsr: 0x30,
+            ..Default::default()
+        };
+        skip_faulting_instruction(&mut frame, Esr(0x9100_0000));
+        assert_eq!(frame.elr, 0x1002);
+        assert_eq!(frame.spsr, 0x30);
+
+        // ITTE EQ: IT = 0b0000_0110 with the condition in IT[7:5] = 0b000
+        let it: u64 = 0b0000_0110;
+        frame.spsr = 0x30 | ((it & 0b11) << 25) | ((it >> 2) << 10);
+        skip_faulting_instruction(&mut frame, Esr(0x9300_0000));
+        assert_eq!(frame.elr, 0x1006);
+        let it = ((frame.spsr >> 25) & 0b11) | (((frame.spsr >> 10) & 0x3F) << 2);
+        assert_eq!(it, 0b0000_1100);
+
+        // Last instruction of the block leaves the IT state empty
+        frame.spsr = 0x30 | (0b10 << 10);
+        skip_faulting_instruction(&mut frame, Esr(0x9300_0000));
+        assert_eq!(frame.spsr, 0x30);
+
+        // Length provided by the caller
+        skip_instruction(&mut frame, 4);
+        assert_eq!(frame.elr, 0x100E);
+    }
+
+    #[test]
+    #[should_panic(expected = "T32 instruction length")]
+    fn test_skip_unknown_t32_len() {
+        // T32 data abort without ISV, the length cannot be taken from the syndrome
+        let mut frame = TrapFrame {
+            elr: 0x1000,
+            spsr: 0x30,
+            ..Default::default()
+        };
+        skip_faulting_instruction(&mut frame, Esr(0x9200_0000));
+    }
+
+    #[test]
+    fn test_t32_instruction_len() {
+        // MOVS r0, #1
+        assert_eq!(t32_instruction_len(0x2001), 2);
+        // B <label>, 16-bit
+        assert_eq!(t32_instruction_len(0xE7FE), 2);
+        // LDRD, BL prefix and STRB.W: first halfword 0b11101, 0b11110 and 0b11111
+        assert_eq!(t32_instruction_len(0xE9D0), 4);
+        assert_eq!(t32_instruction_len(0xF000), 4);
+        assert_eq!(t32_instruction_len(0xF800), 4);
+
+        assert!(!instruction_len_valid(Esr(0x0200_0000)));
+        assert!(!instruction_len_valid(Esr(0x9200_0000)));
+        assert!(instruction_len_valid(Esr(0x9300_0000)));
+    }
+}
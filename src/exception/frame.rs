@@ -0,0 +1,231 @@
+/// Register state of the interrupted context, saved on the stack by the vector entries
+///
+/// Changes made by the handler are restored on exception return. `sp` is the stack pointer
+/// of the interrupted context: writes take effect for exceptions taken from SP_EL0 (EL0 or
+/// current EL with SP0), for exceptions taken from SP_ELx the value is informational.
+///
+/// Layout, offsets in bytes:
+///
+/// ```text
+///   0  x0 .. x30   (X_OFFSET)
+/// 248  sp          (SP_OFFSET)
+/// 256  elr         (ELR_OFFSET)
+/// 264  spsr        (SPSR_OFFSET)
+/// 272  end         (SIZE)
+/// ```
+///
+/// Custom vector stubs can build and consume the frame with
+/// [`trap_frame_save!`](crate::trap_frame_save) and
+/// [`trap_frame_restore!`](crate::trap_frame_restore).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrapFrame {
+    /// General purpose registers x0-x30, x30 being the link register
+    pub x: [u64; 31],
+    /// Stack pointer of the interrupted context
+    pub sp: u64,
+    /// Exception Link Register, the return address
+    pub elr: u64,
+    /// Saved Program Status Register
+    pub spsr: u64,
+}
+
+impl TrapFrame {
+    pub const X_OFFSET: usize = 0;
+    pub const SP_OFFSET: usize = 248;
+    pub const ELR_OFFSET: usize = 256;
+    pub const SPSR_OFFSET: usize = 264;
+    /// Size of the frame in bytes, a multiple of 16 to keep the stack aligned
+    pub const SIZE: usize = 272;
+}
+
+const _: () = {
+    assert!(core::mem::offset_of!(TrapFrame, x) == TrapFrame::X_OFFSET);
+    assert!(core::mem::offset_of!(TrapFrame, sp) == TrapFrame::SP_OFFSET);
+    assert!(core::mem::offset_of!(TrapFrame, elr) == TrapFrame::ELR_OFFSET);
+    assert!(core::mem::offset_of!(TrapFrame, spsr) == TrapFrame::SPSR_OFFSET);
+    assert!(core::mem::size_of::<TrapFrame>() == TrapFrame::SIZE);
+};
+
+/// FP/SIMD register state, for handlers or context switches that use FP/SIMD registers
+///
+/// Layout: q0-q31 at offset 0, FPCR at 512, FPSR at 520, 528 bytes in total. Saved and
+/// restored with [`fp_frame_save!`](crate::fp_frame_save) and
+/// [`fp_frame_restore!`](crate::fp_frame_restore).
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FpFrame {
+    pub q: [u128; 32],
+    pub fpcr: u64,
+    pub fpsr: u64,
+}
+
+impl FpFrame {
+    pub const FPCR_OFFSET: usize = 512;
+    pub const FPSR_OFFSET: usize = 520;
+    pub const SIZE: usize = 528;
+}
+
+const _: () = {
+    assert!(core::mem::offset_of!(FpFrame, fpcr) == FpFrame::FPCR_OFFSET);
+    assert!(core::mem::offset_of!(FpFrame, fpsr) == FpFrame::FPSR_OFFSET);
+    assert!(core::mem::size_of::<FpFrame>() == FpFrame::SIZE);
+};
+
+/// Assembly pushing a [`TrapFrame`] on the stack
+///
+/// Expands to a string usable in `global_asm!` or `naked_asm!`. `$el` (`el1`, `el2`, `el3`)
+/// selects ELR/SPSR, `$sp` is `sp_el0` when the exception was taken from SP_EL0 and `sp_elx`
+/// otherwise. Afterwards `sp` points at the frame and x0/x1 are free. 22 instructions.
+#[macro_export]
+macro_rules! trap_frame_save {
+    ($el:ident, $sp:ident) => {
+        concat!(
+            "sub sp, sp, #272\n",
+            "stp x0, x1, [sp, #0]\n",
+            "stp x2, x3, [sp, #16]\n",
+            "stp x4, x5, [sp, #32]\n",
+            "stp x6, x7, [sp, #48]\n",
+            "stp x8, x9, [sp, #64]\n",
+            "stp x10, x11, [sp, #80]\n",
+            "stp x12, x13, [sp, #96]\n",
+            "stp x14, x15, [sp, #112]\n",
+            "stp x16, x17, [sp, #128]\n",
+            "stp x18, x19, [sp, #144]\n",
+            "stp x20, x21, [sp, #160]\n",
+            "stp x22, x23, [sp, #176]\n",
+            "stp x24, x25, [sp, #192]\n",
+            "stp x26, x27, [sp, #208]\n",
+            "stp x28, x29, [sp, #224]\n",
+            "str x30, [sp, #240]\n",
+            $crate::__trap_frame_sp!($sp),
+            "str x0, [sp, #248]\n",
+            "mrs x0, elr_",
+            stringify!($el),
+            "\n",
+            "mrs x1, spsr_",
+            stringify!($el),
+            "\n",
+            "stp x0, x1, [sp, #256]\n",
+        )
+    };
+}
+
+/// Assembly popping a [`TrapFrame`] from the stack, the inverse of
+/// [`trap_frame_save!`](crate::trap_frame_save)
+///
+/// Restores ELR/SPSR, SP_EL0 for `sp_el0` and all general purpose registers. The caller
+/// issues the ERET.
+#[macro_export]
+macro_rules! trap_frame_restore {
+    ($el:ident, $sp:ident) => {
+        concat!(
+            "ldp x0, x1, [sp, #256]\n",
+            "msr elr_",
+            stringify!($el),
+            ", x0\n",
+            "msr spsr_",
+            stringify!($el),
+            ", x1\n",
+            $crate::__trap_frame_restore_sp!($sp),
+            "ldp x0, x1, [sp, #0]\n",
+            "ldp x2, x3, [sp, #16]\n",
+            "ldp x4, x5, [sp, #32]\n",
+            "ldp x6, x7, [sp, #48]\n",
+            "ldp x8, x9, [sp, #64]\n",
+            "ldp x10, x11, [sp, #80]\n",
+            "ldp x12, x13, [sp, #96]\n",
+            "ldp x14, x15, [sp, #112]\n",
+            "ldp x16, x17, [sp, #128]\n",
+            "ldp x18, x19, [sp, #144]\n",
+            "ldp x20, x21, [sp, #160]\n",
+            "ldp x22, x23, [sp, #176]\n",
+            "ldp x24, x25, [sp, #192]\n",
+            "ldp x26, x27, [sp, #208]\n",
+            "ldp x28, x29, [sp, #224]\n",
+            "ldr x30, [sp, #240]\n",
+            "add sp, sp, #272\n",
+        )
+    };
+}
+
+/// Assembly saving the FP/SIMD registers to the [`FpFrame`] pointed to by x0, clobbers x1
+#[macro_export]
+macro_rules! fp_frame_save {
+    () => {
+        concat!(
+            "stp q0, q1, [x0, #0]\n",
+            "stp q2, q3, [x0, #32]\n",
+            "stp q4, q5, [x0, #64]\n",
+            "stp q6, q7, [x0, #96]\n",
+            "stp q8, q9, [x0, #128]\n",
+            "stp q10, q11, [x0, #160]\n",
+            "stp q12, q13, [x0, #192]\n",
+            "stp q14, q15, [x0, #224]\n",
+            "stp q16, q17, [x0, #256]\n",
+            "stp q18, q19, [x0, #288]\n",
+            "stp q20, q21, [x0, #320]\n",
+            "stp q22, q23, [x0, #352]\n",
+            "stp q24, q25, [x0, #384]\n",
+            "stp q26, q27, [x0, #416]\n",
+            "stp q28, q29, [x0, #448]\n",
+            "stp q30, q31, [x0, #480]\n",
+            "mrs x1, fpcr\n",
+            "str x1, [x0, #512]\n",
+            "mrs x1, fpsr\n",
+            "str x1, [x0, #520]\n",
+        )
+    };
+}
+
+/// Assembly restoring the FP/SIMD registers from the [`FpFrame`] pointed to by x0, clobbers
+/// x1
+#[macro_export]
+macro_rules! fp_frame_restore {
+    () => {
+        concat!(
+            "ldp q0, q1, [x0, #0]\n",
+            "ldp q2, q3, [x0, #32]\n",
+            "ldp q4, q5, [x0, #64]\n",
+            "ldp q6, q7, [x0, #96]\n",
+            "ldp q8, q9, [x0, #128]\n",
+            "ldp q10, q11, [x0, #160]\n",
+            "ldp q12, q13, [x0, #192]\n",
+            "ldp q14, q15, [x0, #224]\n",
+            "ldp q16, q17, [x0, #256]\n",
+            "ldp q18, q19, [x0, #288]\n",
+            "ldp q20, q21, [x0, #320]\n",
+            "ldp q22, q23, [x0, #352]\n",
+            "ldp q24, q25, [x0, #384]\n",
+            "ldp q26, q27, [x0, #416]\n",
+            "ldp q28, q29, [x0, #448]\n",
+            "ldp q30, q31, [x0, #480]\n",
+            "ldr x1, [x0, #512]\n",
+            "msr fpcr, x1\n",
+            "ldr x1, [x0, #520]\n",
+            "msr fpsr, x1\n",
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trap_frame_sp {
+    (sp_el0) => {
+        "mrs x0, sp_el0\n"
+    };
+    (sp_elx) => {
+        "add x0, sp, #272\n"
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trap_frame_restore_sp {
+    (sp_el0) => {
+        "ldr x0, [sp, #248]\nmsr sp_el0, x0\n"
+    };
+    (sp_elx) => {
+        ""
+    };
+}
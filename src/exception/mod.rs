@@ -0,0 +1,30 @@
+//! Exception handling support.
+//!
+//! [`vector_table!`](crate::vector_table) generates the vector table, each entry saves the
+//! interrupted context as a [`TrapFrame`] and calls a Rust handler with it, the table is
+//! installed with `install_vector_table`. Synchronous
+//! exceptions can be routed through [`dispatch_sync`] to handlers installed per exception
+//! class, e.g. with [`on_data_abort`].
+
+mod dispatch;
+mod frame;
+mod snapshot;
+mod spsr;
+mod stage2;
+pub mod syndrome;
+mod vector;
+
+#[cfg(target_arch = "aarch64")]
+pub use dispatch::handle_sync;
+pub use dispatch::{
+    Action, BrkHandler, DataAbortHandler, SvcHandler, SyncHandler, dispatch_sync, on_brk,
+    on_data_abort, on_other_sync, on_svc, skip_faulting_instruction, skip_instruction,
+    t32_instruction_len,
+};
+pub use frame::{FpFrame, TrapFrame};
+pub use snapshot::FaultSnapshot;
+pub use spsr::{ExecutionState, Spsr};
+pub use stage2::{MmioAccess, Stage2Fault};
+pub use vector::{VectorTable, VectorTableError};
+#[cfg(target_arch = "aarch64")]
+pub use vector::{install_vector_table, installed_vector_table};
@@ -0,0 +1,179 @@
+use core::fmt;
+
+use super::{
+    Spsr,
+    spsr::ExecutionState,
+    syndrome::{Esr, FaultInfo, Syndrome},
+};
+use crate::debug::WatchpointHit;
+
+/// Exception state registers of one exception level, captured together
+///
+/// Taken at the start of a handler, before anything can cause a nested exception that
+/// overwrites the registers. The [`Display`](fmt::Display) output is a single line, e.g.
+///
+/// ```text
+/// EL1 DataAbort: ESR 0x96000047 FAR 0xffff000012345678 ELR 0xffff000000081234 SPSR 0x3c5 (from EL1h, DAIF 0b1111), Translation { level: 3 } write
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultSnapshot {
+    /// Exception level the registers were read at
+    pub el: u8,
+    pub esr: Esr,
+    pub far: u64,
+    pub elr: u64,
+    pub spsr: u64,
+}
+
+impl FaultSnapshot {
+    pub fn syndrome(&self) -> Syndrome {
+        self.esr.decode()
+    }
+
+    /// Decoded fault of a data or instruction abort
+    pub fn fault_info(&self) -> Option<FaultInfo> {
+        self.syndrome().fault_info()
+    }
+
+    /// Exception level the exception was taken from, SPSR.M[3:2]
+    pub const fn source_el(&self) -> u8 {
+        Spsr::from_value(self.spsr).target_el()
+    }
+
+    /// The interrupted context used SP_ELx rather than SP_EL0, SPSR.M[0]
+    pub const fn source_sp_elx(&self) -> bool {
+        Spsr::from_value(self.spsr).uses_sp_elx()
+    }
+
+    /// The interrupted context executed in AArch32 state, SPSR.M[4]
+    pub const fn source_aarch32(&self) -> bool {
+        matches!(
+            Spsr::from_value(self.spsr).execution_state(),
+            ExecutionState::AArch32
+        )
+    }
+
+    /// DAIF mask bits of the interrupted context, SPSR[9:6]
+    pub const fn source_daif(&self) -> u8 {
+        Spsr::from_value(self.spsr).daif()
+    }
+
+    /// FAR holds a valid address for this exception
+    pub fn far_valid(&self) -> bool {
+        match self.syndrome() {
+            Syndrome::DataAbort { .. } | Syndrome::InstructionAbort { .. } => {
+                self.fault_info().is_some_and(|info| info.far_valid)
+            }
+            Syndrome::Watchpoint { iss, .. } => WatchpointHit::from_iss(iss).far_valid,
+            Syndrome::PcAlignment => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FaultSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let syndrome = self.syndrome();
+        let name = match syndrome {
+            Syndrome::Unknown => "Unknown",
+            Syndrome::WfxTrap { .. } => "WfxTrap",
+            Syndrome::FpAccess => "FpAccess",
+            Syndrome::BranchTarget { .. } => "BranchTarget",
+            Syndrome::IllegalExecutionState => "IllegalExecutionState",
+            Syndrome::Svc { .. } => "Svc",
+            Syndrome::Hvc { .. } => "Hvc",
+            Syndrome::Smc { .. } => "Smc",
+            Syndrome::MsrMrsTrap(_) => "MsrMrsTrap",
+            Syndrome::SveAccess => "SveAccess",
+            Syndrome::PointerAuthFailure => "PointerAuthFailure",
+            Syndrome::InstructionAbort { .. } => "InstructionAbort",
+            Syndrome::PcAlignment => "PcAlignment",
+            Syndrome::DataAbort { .. } => "DataAbort",
+            Syndrome::SpAlignment => "SpAlignment",
+            Syndrome::FpException => "FpException",
+            Syndrome::SError { .. } => "SError",
+            Syndrome::Breakpoint { .. } => "Breakpoint",
+            Syndrome::SoftwareStep { .. } => "SoftwareStep",
+            Syndrome::Watchpoint { .. } => "Watchpoint",
+            Syndrome::Brk { .. } => "Brk",
+            Syndrome::Other { .. } => "Other",
+        };
+        write!(
+            f,
+            "EL{} {}: ESR {:#x} FAR {:#x} ELR {:#x} SPSR {:#x} ",
+            self.el, name, self.esr.0, self.far, self.elr, self.spsr
+        )?;
+        if self.source_aarch32() {
+            write!(f, "(from AArch32")?;
+        } else {
+            let sp = if self.source_sp_elx() { 'h' } else { 't' };
+            write!(f, "(from EL{}{}", self.source_el(), sp)?;
+        }
+        write!(f, ", DAIF {:#06b})", self.source_daif())?;
+
+        match syndrome {
+            Syndrome::DataAbort { .. } | Syndrome::InstructionAbort { .. } => {
+                if let Some(info) = self.fault_info() {
+                    write!(f, ", {:?}", info.status)?;
+                    if info.write {
+                        write!(f, " write")?;
+                    }
+                    if !info.far_valid {
+                        write!(f, " FAR invalid")?;
+                    }
+                }
+            }
+            Syndrome::Unknown | Syndrome::Other { .. } => {}
+            other => write!(f, ", {other:?}")?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl FaultSnapshot {
+    /// Read ESR, FAR, ELR and SPSR of the current exception level
+    ///
+    /// The registers are read with all exceptions masked, so an interrupt cannot change them
+    /// in between.
+    pub fn capture() -> Self {
+        use crate::{
+            el::{current_el, read_elr, read_esr, read_far, read_spsr},
+            interrupts::{IrqGuard, Mask},
+        };
+
+        let _guard = IrqGuard::with_mask(Mask::ALL);
+        let el = current_el();
+        Self {
+            el: el as u8,
+            esr: Esr(read_esr(el)),
+            far: read_far(el),
+            elr: read_elr(el),
+            spsr: read_spsr(el),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_display() {
+        let snapshot = FaultSnapshot {
+            el: 1,
+            esr: Esr(0x9600_0047),
+            far: 0xffff_0000_1234_5678,
+            elr: 0xffff_0000_0008_1234,
+            spsr: 0x3c5,
+        };
+        assert_eq!(snapshot.source_el(), 1);
+        assert!(snapshot.source_sp_elx());
+        assert!(snapshot.far_valid());
+        assert_eq!(
+            snapshot.to_string(),
+            "EL1 DataAbort: ESR 0x96000047 FAR 0xffff000012345678 ELR 0xffff000000081234 \
+             SPSR 0x3c5 (from EL1h, DAIF 0b1111), Translation { level: 3 } write"
+        );
+    }
+}
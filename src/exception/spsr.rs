@@ -0,0 +1,160 @@
+/// Execution state of the context an SPSR returns to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionState {
+    AArch64,
+    AArch32,
+}
+
+/// Builder and decoder for SPSR_ELx values
+///
+/// Describes the state entered by ERET. [`Spsr::new`] is EL0 in AArch64 with all exceptions
+/// unmasked, the usual return state into user space.
+///
+/// ```ignore
+/// // Enter EL1 on SP_EL1 with D, A, I and F masked
+/// let spsr = Spsr::new().el(1).sp_elx(true).mask_all(true);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Spsr(u64);
+
+impl Spsr {
+    const M_SP: u64 = 1 << 0;
+    const M_EL: u64 = 0b11 << 2;
+    const M_AARCH32: u64 = 1 << 4;
+    const T32: u64 = 1 << 5;
+    const F: u64 = 1 << 6;
+    const I: u64 = 1 << 7;
+    const A: u64 = 1 << 8;
+    const D: u64 = 1 << 9;
+    const SS: u64 = 1 << 21;
+
+    /// EL0t, AArch64, nothing masked
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw SPSR value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    const fn bit(self, mask: u64, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// AArch64 exception level to return to (M[3:2]), 0-3
+    pub const fn el(self, el: u8) -> Self {
+        assert!(el <= 3, "invalid exception level");
+        Self((self.0 & !Self::M_EL) | ((el as u64) << 2))
+    }
+
+    /// Use SP_ELx instead of SP_EL0 (M[0]), ignored for EL0
+    pub const fn sp_elx(self, enable: bool) -> Self {
+        self.bit(Self::M_SP, enable)
+    }
+
+    /// Return to AArch32 in the given M[3:0] mode, e.g. 0b0000 for User
+    pub const fn aarch32(self, mode: u8) -> Self {
+        Self((self.0 & !0b1_1111) | Self::M_AARCH32 | (mode as u64 & 0b1111))
+    }
+
+    /// AArch32 T32 instruction set (T)
+    pub const fn t32(self, enable: bool) -> Self {
+        self.bit(Self::T32, enable)
+    }
+
+    /// Mask watchpoint, breakpoint and software step exceptions (D)
+    pub const fn mask_debug(self, mask: bool) -> Self {
+        self.bit(Self::D, mask)
+    }
+
+    /// Mask SError interrupts (A)
+    pub const fn mask_serror(self, mask: bool) -> Self {
+        self.bit(Self::A, mask)
+    }
+
+    /// Mask IRQs (I)
+    pub const fn mask_irq(self, mask: bool) -> Self {
+        self.bit(Self::I, mask)
+    }
+
+    /// Mask FIQs (F)
+    pub const fn mask_fiq(self, mask: bool) -> Self {
+        self.bit(Self::F, mask)
+    }
+
+    /// Mask or unmask D, A, I and F
+    pub const fn mask_all(self, mask: bool) -> Self {
+        self.bit(Self::D | Self::A | Self::I | Self::F, mask)
+    }
+
+    /// Software step pending after the return (SS)
+    pub const fn software_step(self, enable: bool) -> Self {
+        self.bit(Self::SS, enable)
+    }
+
+    pub const fn execution_state(self) -> ExecutionState {
+        if self.0 & Self::M_AARCH32 != 0 {
+            ExecutionState::AArch32
+        } else {
+            ExecutionState::AArch64
+        }
+    }
+
+    /// AArch64 exception level, meaningless for AArch32
+    pub const fn target_el(self) -> u8 {
+        ((self.0 & Self::M_EL) >> 2) as u8
+    }
+
+    /// Check if SP_ELx is selected (AArch64)
+    pub const fn uses_sp_elx(self) -> bool {
+        self.0 & Self::M_SP != 0
+    }
+
+    /// AArch32 mode M[3:0]
+    pub const fn aarch32_mode(self) -> u8 {
+        (self.0 & 0b1111) as u8
+    }
+
+    /// D, A, I and F as in the DAIFSet immediate, D being bit 3
+    pub const fn daif(self) -> u8 {
+        ((self.0 >> 6) & 0xF) as u8
+    }
+
+    pub const fn is_irq_masked(self) -> bool {
+        self.0 & Self::I != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spsr() {
+        let el1h = Spsr::new().el(1).sp_elx(true).mask_all(true);
+        assert_eq!(el1h.value(), 0x3C5);
+        assert_eq!(el1h.target_el(), 1);
+        assert!(el1h.uses_sp_elx());
+        assert_eq!(el1h.daif(), 0xF);
+
+        let el0 = Spsr::from_value(0x3C5).el(0).sp_elx(false).mask_all(false);
+        assert_eq!(el0, Spsr::new());
+        assert_eq!(el0.execution_state(), ExecutionState::AArch64);
+
+        // AArch32 User mode, T32
+        let usr = Spsr::new().aarch32(0b0000).t32(true);
+        assert_eq!(usr.value(), 0x30);
+        assert_eq!(usr.execution_state(), ExecutionState::AArch32);
+        assert!(!usr.is_irq_masked());
+    }
+}
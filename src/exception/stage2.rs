@@ -0,0 +1,153 @@
+use super::syndrome::{DataAccess, Esr, FaultInfo, FaultStatus, Syndrome};
+
+/// Guest abort taken to EL2 on a stage 2 translation, decoded from ESR_EL2, FAR_EL2 and
+/// HPFAR_EL2
+///
+/// A guest access to an IPA without a stage 2 mapping, the usual way of trapping MMIO,
+/// arrives as a stage 2 translation fault. With a valid instruction syndrome (ISV),
+/// [`mmio`](Self::mmio) describes the load or store well enough to emulate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage2Fault {
+    /// Faulting IPA, `None` if HPFAR_EL2 is not valid for the fault
+    ///
+    /// HPFAR_EL2 is only written for translation, access flag and address size faults,
+    /// and for faults on a stage 1 walk (S1PTW). Without a valid FAR the offset into the
+    /// page is zero.
+    pub ipa: Option<u64>,
+    /// Faulting virtual address (FAR_EL2), `None` if not valid (FnV)
+    pub va: Option<u64>,
+    /// Instruction fetch rather than data access
+    pub instruction: bool,
+    pub info: FaultInfo,
+}
+
+/// Guest load or store to be emulated by the VMM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioAccess {
+    /// Accessed IPA
+    pub ipa: u64,
+    /// Store rather than load (WnR)
+    pub write: bool,
+    /// Size and transfer register, see [`DataAccess::load_value`] and
+    /// [`DataAccess::store_value`]
+    pub access: DataAccess,
+}
+
+impl Stage2Fault {
+    const HPFAR_FIPA: u64 = 0x0000_0FFF_FFFF_FFF0;
+
+    /// Decode an abort from a lower exception level with syndrome `esr`, fault address
+    /// `far` and faulting IPA page `hpfar`
+    ///
+    /// `None` if `esr` is not an instruction or data abort from a lower exception level.
+    pub const fn decode(esr: Esr, far: u64, hpfar: u64) -> Option<Self> {
+        let (instruction, info) = match esr.decode() {
+            Syndrome::DataAbort {
+                lower_el: true,
+                iss,
+            } => (false, FaultInfo::from_data_abort_iss(iss)),
+            Syndrome::InstructionAbort {
+                lower_el: true,
+                iss,
+            } => (true, FaultInfo::from_instruction_abort_iss(iss)),
+            _ => return None,
+        };
+        let hpfar_valid = info.s1ptw
+            || matches!(
+                info.status,
+                FaultStatus::Translation { .. }
+                    | FaultStatus::AccessFlag { .. }
+                    | FaultStatus::AddressSize { .. }
+            );
+        let ipa = if !hpfar_valid {
+            None
+        } else if info.far_valid {
+            Some(((hpfar & Self::HPFAR_FIPA) << 8) | (far & 0xFFF))
+        } else {
+            Some((hpfar & Self::HPFAR_FIPA) << 8)
+        };
+        Some(Self {
+            ipa,
+            va: if info.far_valid { Some(far) } else { None },
+            instruction,
+            info,
+        })
+    }
+
+    /// The guest load or store to emulate, `None` unless this is a data abort with a
+    /// valid instruction syndrome, a known IPA and not caused by a stage 1 walk or a cache
+    /// maintenance instruction
+    pub const fn mmio(&self) -> Option<MmioAccess> {
+        if self.instruction || self.info.s1ptw || self.info.cache_maintenance {
+            return None;
+        }
+        match (self.ipa, self.info.access) {
+            (Some(ipa), Some(access)) => Some(MmioAccess {
+                ipa,
+                write: self.info.write,
+                access,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Stage2Fault {
+    /// Decode the current exception from ESR_EL2, FAR_EL2 and HPFAR_EL2, must be called
+    /// at EL2
+    pub fn read() -> Option<Self> {
+        use aarch64_cpu::registers::{ESR_EL2, FAR_EL2, HPFAR_EL2, Readable};
+
+        Self::decode(Esr(ESR_EL2.get()), FAR_EL2.get(), HPFAR_EL2.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mmio_store() {
+        // STR w3, [x0] at 0x0900_0004, stage 2 translation fault level 2
+        let iss = (1 << 24) | (2 << 22) | (3 << 16) | (1 << 6) | 0x06;
+        let esr = Esr((0x24 << 26) | (1 << 25) | iss);
+        let fault = Stage2Fault::decode(esr, 0xffff_0000_1234_5004, 0x0900_0000 >> 8).unwrap();
+        assert_eq!(fault.ipa, Some(0x0900_0004));
+        let mmio = fault.mmio().unwrap();
+        assert!(mmio.write);
+        assert_eq!(mmio.access.size, 4);
+        assert_eq!(mmio.access.register, 3);
+        assert_eq!(mmio.access.store_value(0x1122_3344_5566_7788), 0x5566_7788);
+    }
+
+    #[test]
+    fn test_mmio_load() {
+        // LDRSH x1, [x2], sign extended into a 64-bit register
+        let iss = (1 << 24) | (1 << 22) | (1 << 21) | (1 << 16) | (1 << 15) | 0x07;
+        let esr = Esr((0x24 << 26) | (1 << 25) | iss);
+        let fault = Stage2Fault::decode(esr, 0x10, 0x1000_0000 >> 8).unwrap();
+        let mmio = fault.mmio().unwrap();
+        assert!(!mmio.write);
+        assert_eq!(mmio.ipa, 0x1000_0010);
+        assert_eq!(mmio.access.load_value(0xABCD_8001), 0xFFFF_FFFF_FFFF_8001);
+
+        // Same access without ISV cannot be emulated from the syndrome
+        let esr = Esr((0x24 << 26) | (1 << 25) | 0x07);
+        assert!(Stage2Fault::decode(esr, 0x10, 0).unwrap().mmio().is_none());
+
+        // Aborts taken from EL2 itself are not stage 2 guest faults
+        let esr = Esr((0x25 << 26) | (1 << 25) | iss);
+        assert!(Stage2Fault::decode(esr, 0x10, 0).is_none());
+    }
+
+    #[test]
+    fn test_permission_fault() {
+        // Stage 2 permission faults do not write HPFAR_EL2
+        let esr = Esr((0x24 << 26) | (1 << 25) | (1 << 6) | 0x0F);
+        let fault = Stage2Fault::decode(esr, 0x4000, 0x1234).unwrap();
+        assert_eq!(fault.ipa, None);
+        assert_eq!(fault.va, Some(0x4000));
+        assert!(fault.mmio().is_none());
+    }
+}
@@ -0,0 +1,473 @@
+//! Exception Syndrome Register (ESR_ELx) decoding.
+
+/// Raw value of ESR_EL1, ESR_EL2 or ESR_EL3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Esr(pub u64);
+
+impl Esr {
+    /// Exception Class, bits [31:26]
+    pub const fn ec(self) -> u8 {
+        ((self.0 >> 26) & 0x3F) as u8
+    }
+
+    /// Instruction Length, bit 25: the trapped instruction was 32-bit
+    pub const fn il(self) -> bool {
+        self.0 & (1 << 25) != 0
+    }
+
+    /// Instruction Specific Syndrome, bits [24:0]
+    pub const fn iss(self) -> u32 {
+        (self.0 & 0x1FF_FFFF) as u32
+    }
+
+    /// ISS2, bits [55:32]
+    pub const fn iss2(self) -> u32 {
+        ((self.0 >> 32) & 0xFF_FFFF) as u32
+    }
+
+    /// Length in bytes of the instruction that caused the exception
+    pub const fn instruction_len(self) -> usize {
+        if self.il() { 4 } else { 2 }
+    }
+
+    /// Decode the exception class and class specific syndrome
+    pub const fn decode(self) -> Syndrome {
+        let iss = self.iss();
+        match self.ec() {
+            0x00 => Syndrome::Unknown,
+            0x01 => Syndrome::WfxTrap {
+                kind: match iss & 0b11 {
+                    0b00 => WfxKind::Wfi,
+                    0b01 => WfxKind::Wfe,
+                    0b10 => WfxKind::Wfit,
+                    _ => WfxKind::Wfet,
+                },
+            },
+            0x07 => Syndrome::FpAccess,
+            0x0D => Syndrome::BranchTarget {
+                btype: (iss & 0b11) as u8,
+            },
+            0x0E => Syndrome::IllegalExecutionState,
+            0x11 | 0x15 => Syndrome::Svc {
+                imm: iss as u16,
+                aarch32: self.ec() == 0x11,
+            },
+            0x12 | 0x16 => Syndrome::Hvc { imm: iss as u16 },
+            0x13 | 0x17 => Syndrome::Smc { imm: iss as u16 },
+            0x18 => Syndrome::MsrMrsTrap(SysRegAccess::from_iss(iss)),
+            0x19 => Syndrome::SveAccess,
+            0x1C => Syndrome::PointerAuthFailure,
+            0x20 | 0x21 => Syndrome::InstructionAbort {
+                lower_el: self.ec() == 0x20,
+                iss,
+            },
+            0x22 => Syndrome::PcAlignment,
+            0x24 | 0x25 => Syndrome::DataAbort {
+                lower_el: self.ec() == 0x24,
+                iss,
+            },
+            0x26 => Syndrome::SpAlignment,
+            0x28 | 0x2C => Syndrome::FpException,
+            0x2F => Syndrome::SError { iss },
+            0x30 | 0x31 => Syndrome::Breakpoint {
+                lower_el: self.ec() == 0x30,
+            },
+            0x32 | 0x33 => Syndrome::SoftwareStep {
+                lower_el: self.ec() == 0x32,
+                iss,
+            },
+            0x34 | 0x35 => Syndrome::Watchpoint {
+                lower_el: self.ec() == 0x34,
+                iss,
+            },
+            0x38 | 0x3C => Syndrome::Brk {
+                comment: iss as u16,
+            },
+            ec => Syndrome::Other { ec, iss },
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Esr {
+    /// Read ESR_ELx of the current exception level
+    pub fn read() -> Self {
+        use crate::el::{current_el, read_esr};
+
+        Self(read_esr(current_el()))
+    }
+}
+
+/// Which wait instruction was trapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WfxKind {
+    Wfi,
+    Wfe,
+    Wfit,
+    Wfet,
+}
+
+/// A trapped MSR, MRS or System instruction (EC 0x18)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysRegAccess {
+    pub op0: u8,
+    pub op1: u8,
+    pub crn: u8,
+    pub crm: u8,
+    pub op2: u8,
+    /// Transfer register
+    pub rt: u8,
+    /// MRS (read) rather than MSR (write)
+    pub read: bool,
+}
+
+impl SysRegAccess {
+    const fn from_iss(iss: u32) -> Self {
+        Self {
+            op0: ((iss >> 20) & 0b11) as u8,
+            op2: ((iss >> 17) & 0b111) as u8,
+            op1: ((iss >> 14) & 0b111) as u8,
+            crn: ((iss >> 10) & 0xF) as u8,
+            rt: ((iss >> 5) & 0x1F) as u8,
+            crm: ((iss >> 1) & 0xF) as u8,
+            read: iss & 1 != 0,
+        }
+    }
+
+    /// The register encoding as (op0, op1, CRn, CRm, op2)
+    pub const fn encoding(&self) -> (u8, u8, u8, u8, u8) {
+        (self.op0, self.op1, self.crn, self.crm, self.op2)
+    }
+}
+
+/// Decoded exception syndrome
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syndrome {
+    /// Unknown reason, e.g. an UNDEFINED instruction
+    Unknown,
+    /// Trapped WFI/WFE/WFIT/WFET
+    WfxTrap {
+        kind: WfxKind,
+    },
+    /// Trapped FP or Advanced SIMD access (CPACR/CPTR)
+    FpAccess,
+    /// Branch Target Identification exception, `btype` is PSTATE.BTYPE
+    BranchTarget {
+        btype: u8,
+    },
+    IllegalExecutionState,
+    Svc {
+        imm: u16,
+        aarch32: bool,
+    },
+    Hvc {
+        imm: u16,
+    },
+    Smc {
+        imm: u16,
+    },
+    /// Trapped MSR, MRS or System instruction
+    MsrMrsTrap(SysRegAccess),
+    /// Trapped SVE access
+    SveAccess,
+    /// FPAC, pointer authentication instruction failure
+    PointerAuthFailure,
+    InstructionAbort {
+        lower_el: bool,
+        iss: u32,
+    },
+    PcAlignment,
+    DataAbort {
+        lower_el: bool,
+        iss: u32,
+    },
+    SpAlignment,
+    /// Trapped floating point exception
+    FpException,
+    SError {
+        iss: u32,
+    },
+    Breakpoint {
+        lower_el: bool,
+    },
+    /// Decoded by [`StepInfo`](crate::debug::StepInfo)
+    SoftwareStep {
+        lower_el: bool,
+        iss: u32,
+    },
+    /// Decoded by [`WatchpointHit`](crate::debug::WatchpointHit)
+    Watchpoint {
+        lower_el: bool,
+        iss: u32,
+    },
+    /// BRK (AArch64) or BKPT (AArch32) instruction
+    Brk {
+        comment: u16,
+    },
+    /// Any other exception class
+    Other {
+        ec: u8,
+        iss: u32,
+    },
+}
+
+impl Syndrome {
+    /// Decode the fault of a data or instruction abort
+    pub const fn fault_info(&self) -> Option<FaultInfo> {
+        match *self {
+            Self::DataAbort { iss, .. } => Some(FaultInfo::from_data_abort_iss(iss)),
+            Self::InstructionAbort { iss, .. } => Some(FaultInfo::from_instruction_abort_iss(iss)),
+            _ => None,
+        }
+    }
+}
+
+/// Fault status code of an abort (DFSC / IFSC)
+///
+/// Levels are the translation table level the fault occurred at, -1 with FEAT_LPA2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultStatus {
+    AddressSize {
+        level: i8,
+    },
+    Translation {
+        level: i8,
+    },
+    AccessFlag {
+        level: i8,
+    },
+    Permission {
+        level: i8,
+    },
+    /// Synchronous External abort, not on a translation table walk
+    SynchronousExternal,
+    /// Synchronous Tag Check Fault (MTE)
+    TagCheck,
+    /// Synchronous External abort on a translation table walk
+    SynchronousExternalOnWalk {
+        level: i8,
+    },
+    /// Synchronous parity or ECC error, not on a translation table walk
+    SynchronousParity,
+    /// Synchronous parity or ECC error on a translation table walk
+    SynchronousParityOnWalk {
+        level: i8,
+    },
+    Alignment,
+    /// Granule Protection Fault, not on a translation table walk (RME)
+    GranuleProtection,
+    TlbConflict,
+    /// Unsupported atomic hardware update
+    UnsupportedAtomic,
+    /// Any other or IMPLEMENTATION DEFINED code
+    Other(u8),
+}
+
+impl FaultStatus {
+    /// Decode a 6-bit DFSC or IFSC value
+    pub const fn from_bits(fsc: u8) -> Self {
+        let level = (fsc & 0b11) as i8;
+        match fsc & 0x3F {
+            0x00..=0x03 => Self::AddressSize { level },
+            0x29 => Self::AddressSize { level: -1 },
+            0x04..=0x07 => Self::Translation { level },
+            0x2B => Self::Translation { level: -1 },
+            0x08..=0x0B => Self::AccessFlag { level },
+            0x0C..=0x0F => Self::Permission { level },
+            0x10 => Self::SynchronousExternal,
+            0x11 => Self::TagCheck,
+            0x13 => Self::SynchronousExternalOnWalk { level: -1 },
+            0x14..=0x17 => Self::SynchronousExternalOnWalk { level },
+            0x18 => Self::SynchronousParity,
+            0x1B => Self::SynchronousParityOnWalk { level: -1 },
+            0x1C..=0x1F => Self::SynchronousParityOnWalk { level },
+            0x21 => Self::Alignment,
+            0x28 => Self::GranuleProtection,
+            0x30 => Self::TlbConflict,
+            0x31 => Self::UnsupportedAtomic,
+            fsc => Self::Other(fsc),
+        }
+    }
+
+    /// Translation table level of the fault, if reported
+    pub const fn level(self) -> Option<i8> {
+        match self {
+            Self::AddressSize { level }
+            | Self::Translation { level }
+            | Self::AccessFlag { level }
+            | Self::Permission { level }
+            | Self::SynchronousExternalOnWalk { level }
+            | Self::SynchronousParityOnWalk { level } => Some(level),
+            _ => None,
+        }
+    }
+}
+
+/// Decoded load or store of a data abort with a valid instruction syndrome (ISV)
+///
+/// Describes the access well enough to emulate it, e.g. for MMIO trapped by a hypervisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataAccess {
+    /// Access size in bytes (SAS)
+    pub size: usize,
+    /// The loaded value must be sign extended (SSE)
+    pub sign_extend: bool,
+    /// Transfer register number, 31 is XZR (SRT)
+    pub register: u8,
+    /// The transfer register is 64-bit, otherwise 32-bit (SF)
+    pub sixty_four: bool,
+    /// Load-acquire or store-release semantics (AR)
+    pub acquire_release: bool,
+}
+
+impl DataAccess {
+    /// Register value after loading the `size` bytes `data`, sign extended (SSE) and
+    /// truncated to 32 bits unless the register is 64-bit (SF)
+    pub const fn load_value(&self, data: u64) -> u64 {
+        let bits = self.size as u32 * 8;
+        let data = if bits < 64 {
+            let data = data & ((1 << bits) - 1);
+            if self.sign_extend {
+                ((data << (64 - bits)) as i64 >> (64 - bits)) as u64
+            } else {
+                data
+            }
+        } else {
+            data
+        };
+        if self.sixty_four {
+            data
+        } else {
+            data & 0xFFFF_FFFF
+        }
+    }
+
+    /// Bytes written by a store of the register value `value`
+    pub const fn store_value(&self, value: u64) -> u64 {
+        let bits = self.size as u32 * 8;
+        if bits < 64 {
+            value & ((1 << bits) - 1)
+        } else {
+            value
+        }
+    }
+}
+
+/// Decoded ISS of a data or instruction abort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultInfo {
+    pub status: FaultStatus,
+    /// Caused by a write (WnR), always `false` for instruction aborts
+    pub write: bool,
+    /// Caused by a cache maintenance or address translation instruction (CM)
+    pub cache_maintenance: bool,
+    /// Stage 2 fault on a stage 1 translation table walk (S1PTW)
+    pub s1ptw: bool,
+    /// FAR_ELx holds the faulting address (inverse of FnV)
+    pub far_valid: bool,
+    /// External abort type, IMPLEMENTATION DEFINED (EA)
+    pub external: bool,
+    /// The load or store, if the syndrome is valid (ISV)
+    pub access: Option<DataAccess>,
+}
+
+impl FaultInfo {
+    /// Decode the ISS of a data abort (EC 0x24 / 0x25)
+    pub const fn from_data_abort_iss(iss: u32) -> Self {
+        let access = if iss & (1 << 24) != 0 {
+            Some(DataAccess {
+                size: 1 << ((iss >> 22) & 0b11),
+                sign_extend: iss & (1 << 21) != 0,
+                register: ((iss >> 16) & 0x1F) as u8,
+                sixty_four: iss & (1 << 15) != 0,
+                acquire_release: iss & (1 << 14) != 0,
+            })
+        } else {
+            None
+        };
+        Self {
+            status: FaultStatus::from_bits(iss as u8),
+            write: iss & (1 << 6) != 0,
+            cache_maintenance: iss & (1 << 8) != 0,
+            s1ptw: iss & (1 << 7) != 0,
+            far_valid: iss & (1 << 10) == 0,
+            external: iss & (1 << 9) != 0,
+            access,
+        }
+    }
+
+    /// Decode the ISS of an instruction abort (EC 0x20 / 0x21)
+    pub const fn from_instruction_abort_iss(iss: u32) -> Self {
+        Self {
+            status: FaultStatus::from_bits(iss as u8),
+            write: false,
+            cache_maintenance: false,
+            s1ptw: iss & (1 << 7) != 0,
+            far_valid: iss & (1 << 10) == 0,
+            external: iss & (1 << 9) != 0,
+            access: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // SVC #0x42 from AArch64
+        let esr = Esr(0x5600_0042);
+        assert_eq!(esr.ec(), 0x15);
+        assert!(esr.il());
+        assert_eq!(
+            esr.decode(),
+            Syndrome::Svc {
+                imm: 0x42,
+                aarch32: false
+            }
+        );
+
+        // MRS x3, CNTVCT_EL0 (3, 3, 14, 0, 2)
+        let iss = (3 << 20) | (2 << 17) | (3 << 14) | (14 << 10) | (3 << 5) | 1;
+        let Syndrome::MsrMrsTrap(access) = Esr((0x18 << 26) | (1 << 25) | iss).decode() else {
+            panic!("not a sysreg trap");
+        };
+        assert_eq!(access.encoding(), (3, 3, 14, 0, 2));
+        assert_eq!(access.rt, 3);
+        assert!(access.read);
+
+        assert_eq!(Esr(0xF200_1234).decode(), Syndrome::Brk { comment: 0x1234 });
+        assert_eq!(Esr(0x0200_0000).decode(), Syndrome::Unknown);
+    }
+
+    #[test]
+    fn test_fault_info() {
+        // STR w2, [x1] to an unmapped page, translation fault level 3
+        let iss = (1 << 24) | (2 << 22) | (2 << 16) | (1 << 6) | 0x07;
+        let info = Esr((0x24 << 26) | (1 << 25) | iss)
+            .decode()
+            .fault_info()
+            .unwrap();
+        assert_eq!(info.status, FaultStatus::Translation { level: 3 });
+        assert!(info.write);
+        assert!(info.far_valid);
+        assert_eq!(
+            info.access,
+            Some(DataAccess {
+                size: 4,
+                sign_extend: false,
+                register: 2,
+                sixty_four: false,
+                acquire_release: false,
+            })
+        );
+
+        let info = FaultInfo::from_instruction_abort_iss(0x0F);
+        assert_eq!(info.status, FaultStatus::Permission { level: 3 });
+        assert_eq!(info.status.level(), Some(3));
+        assert_eq!(FaultStatus::from_bits(0x2B).level(), Some(-1));
+        assert_eq!(FaultStatus::from_bits(0x21), FaultStatus::Alignment);
+        assert!(Syndrome::Unknown.fault_info().is_none());
+    }
+}
@@ -0,0 +1,190 @@
+/// A 16-entry AArch64 exception vector table, as generated by
+/// [`vector_table!`](crate::vector_table)
+///
+/// Only used as the type of the table symbol, so its address can be taken with
+/// `&raw const` and written to VBAR_ELx.
+#[repr(C, align(2048))]
+pub struct VectorTable([u8; 2048]);
+
+/// Errors returned by [`install_vector_table`](crate::exception::install_vector_table)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorTableError {
+    /// The address is not aligned to 2KB
+    Misaligned,
+}
+
+impl VectorTable {
+    /// Required alignment of a vector table in bytes
+    pub const ALIGN: usize = 2048;
+
+    /// Check if `addr` can be written to VBAR_ELx
+    pub const fn is_aligned(addr: usize) -> bool {
+        addr.is_multiple_of(Self::ALIGN)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod install {
+    use aarch64_cpu::asm::barrier::{SY, isb};
+
+    use super::{VectorTable, VectorTableError};
+    use crate::el::{current_el, read_vbar, write_vbar};
+
+    /// Install the vector table at `addr` for the current exception level
+    ///
+    /// Writes VBAR_ELx and synchronizes the context, exceptions taken after the return use
+    /// the new table. Returns the previously installed address, e.g. to restore it after
+    /// temporarily swapping in a debug table.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to a valid, executable vector table that stays in place while it
+    /// is installed.
+    pub unsafe fn install_vector_table(addr: usize) -> Result<usize, VectorTableError> {
+        if !VectorTable::is_aligned(addr) {
+            return Err(VectorTableError::Misaligned);
+        }
+        let el = current_el();
+        let previous = read_vbar(el) as usize;
+        unsafe { write_vbar(el, addr as u64) };
+        isb(SY);
+        Ok(previous)
+    }
+
+    /// Address of the vector table installed for the current exception level
+    pub fn installed_vector_table() -> usize {
+        read_vbar(current_el()) as usize
+    }
+
+    impl VectorTable {
+        /// Install this table for the current exception level, see
+        /// [`install_vector_table`]
+        ///
+        /// # Safety
+        ///
+        /// The table must have been generated for the current exception level.
+        pub unsafe fn install(&'static self) -> usize {
+            let addr = self as *const Self as usize;
+            unsafe { install_vector_table(addr) }.expect("VectorTable is 2KB aligned")
+        }
+
+        /// Check if this table is the one installed for the current exception level
+        pub fn is_installed(&'static self) -> bool {
+            installed_vector_table() == self as *const Self as usize
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use install::{install_vector_table, installed_vector_table};
+
+/// Generate an exception vector table
+///
+/// The table is placed in `.text.vectors`, aligned to 2KB, with the 16 entries 0x80 bytes
+/// apart. Every entry saves a [`TrapFrame`](crate::exception::TrapFrame) on the stack of the
+/// current exception level, calls its handler with it and restores the (possibly modified)
+/// frame before returning with ERET. Handlers are `extern "C" fn(&mut TrapFrame)`, given in
+/// architectural order: current EL with SP0, current EL with SPx, lower EL using AArch64,
+/// lower EL using AArch32, each as synchronous, IRQ, FIQ, SError.
+///
+/// The table is declared as `static $name: VectorTable`. `$el` selects the ELR/SPSR
+/// instances saved and must be the exception level the table is installed at.
+///
+/// Only general purpose registers are saved, handlers must not modify FP/SIMD registers
+/// unless they save them (e.g. when built for a soft-float target).
+///
+/// ```ignore
+/// vector_table!(VECTORS, el1, [
+///     sync_sp0, irq_sp0, fiq_sp0, serror_sp0,
+///     sync_spx, irq_spx, fiq_spx, serror_spx,
+///     sync_a64, irq_a64, fiq_a64, serror_a64,
+///     sync_a32, irq_a32, fiq_a32, serror_a32,
+/// ]);
+/// ```
+#[macro_export]
+macro_rules! vector_table {
+    ($name:ident, $el:ident, [
+        $h0:path, $h1:path, $h2:path, $h3:path,
+        $h4:path, $h5:path, $h6:path, $h7:path,
+        $h8:path, $h9:path, $h10:path, $h11:path,
+        $h12:path, $h13:path, $h14:path, $h15:path $(,)?
+    ]) => {
+        const _: () = {
+            type Handler = extern "C" fn(&mut $crate::exception::TrapFrame);
+            let _: [Handler; 16] = [
+                $h0, $h1, $h2, $h3, $h4, $h5, $h6, $h7,
+                $h8, $h9, $h10, $h11, $h12, $h13, $h14, $h15,
+            ];
+        };
+
+        unsafe extern "C" {
+            static $name: $crate::exception::VectorTable;
+        }
+
+        core::arch::global_asm!(
+            ".pushsection .text.vectors, \"ax\"",
+            ".balign 2048",
+            concat!(".global ", stringify!($name)),
+            concat!(stringify!($name), ":"),
+            $crate::__vector_entry!($el, h0, sp_el0, $name),
+            $crate::__vector_entry!($el, h1, sp_el0, $name),
+            $crate::__vector_entry!($el, h2, sp_el0, $name),
+            $crate::__vector_entry!($el, h3, sp_el0, $name),
+            $crate::__vector_entry!($el, h4, sp_elx, $name),
+            $crate::__vector_entry!($el, h5, sp_elx, $name),
+            $crate::__vector_entry!($el, h6, sp_elx, $name),
+            $crate::__vector_entry!($el, h7, sp_elx, $name),
+            $crate::__vector_entry!($el, h8, sp_el0, $name),
+            $crate::__vector_entry!($el, h9, sp_el0, $name),
+            $crate::__vector_entry!($el, h10, sp_el0, $name),
+            $crate::__vector_entry!($el, h11, sp_el0, $name),
+            $crate::__vector_entry!($el, h12, sp_el0, $name),
+            $crate::__vector_entry!($el, h13, sp_el0, $name),
+            $crate::__vector_entry!($el, h14, sp_el0, $name),
+            $crate::__vector_entry!($el, h15, sp_el0, $name),
+            $crate::__vector_restore!($el, sp_el0, $name),
+            $crate::__vector_restore!($el, sp_elx, $name),
+            ".popsection",
+            h0 = sym $h0, h1 = sym $h1, h2 = sym $h2, h3 = sym $h3,
+            h4 = sym $h4, h5 = sym $h5, h6 = sym $h6, h7 = sym $h7,
+            h8 = sym $h8, h9 = sym $h9, h10 = sym $h10, h11 = sym $h11,
+            h12 = sym $h12, h13 = sym $h13, h14 = sym $h14, h15 = sym $h15,
+        );
+    };
+}
+
+/// One 0x80 byte vector entry, 25 instructions
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __vector_entry {
+    ($el:ident, $h:ident, $sp:ident, $name:ident) => {
+        concat!(
+            ".balign 0x80\n",
+            $crate::trap_frame_save!($el, $sp),
+            "mov x0, sp\n",
+            "bl {",
+            stringify!($h),
+            "}\n",
+            "b ",
+            stringify!($name),
+            "_restore_",
+            stringify!($sp),
+            "\n",
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __vector_restore {
+    ($el:ident, $sp:ident, $name:ident) => {
+        concat!(
+            stringify!($name),
+            "_restore_",
+            stringify!($sp),
+            ":\n",
+            $crate::trap_frame_restore!($el, $sp),
+            "eret\n",
+        )
+    };
+}
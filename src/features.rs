@@ -0,0 +1,593 @@
+//! CPU feature discovery from the AArch64 ID registers.
+//!
+//! [`CpuFeatures`] holds a snapshot of the ID registers and decodes individual
+//! [`Feature`]s from it. On AArch64, [`cpu_features`] reads the registers once and caches
+//! the snapshot, [`is_supported`] queries the cached snapshot.
+//!
+//! The snapshot describes the core it was read on. Heterogeneous systems should use the
+//! features common to all cores, see [`SanitizedFeatures`].
+
+/// An ID register covered by [`CpuFeatures`]
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdReg {
+    /// ID_AA64ISAR0_EL1
+    Isar0,
+    /// ID_AA64ISAR1_EL1
+    Isar1,
+    /// ID_AA64ISAR2_EL1
+    Isar2,
+    /// ID_AA64PFR0_EL1
+    Pfr0,
+    /// ID_AA64PFR1_EL1
+    Pfr1,
+    /// ID_AA64MMFR0_EL1
+    Mmfr0,
+    /// ID_AA64MMFR1_EL1
+    Mmfr1,
+    /// ID_AA64MMFR2_EL1
+    Mmfr2,
+    /// ID_AA64MMFR3_EL1
+    Mmfr3,
+}
+
+const ID_REG_COUNT: usize = 9;
+
+/// An architectural feature (FEAT_*)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    // ID_AA64ISAR0_EL1
+    Aes,
+    Pmull,
+    Sha1,
+    Sha256,
+    Sha512,
+    Crc32,
+    /// Large System Extensions, atomic instructions
+    Lse,
+    Rdm,
+    Sha3,
+    Sm3,
+    Sm4,
+    DotProd,
+    Fhm,
+    FlagM,
+    /// Outer Shareable TLB maintenance
+    TlbiOs,
+    /// TLB range maintenance
+    TlbiRange,
+    /// RNDR/RNDRRS random number registers
+    Rng,
+
+    // ID_AA64ISAR1_EL1
+    /// DC CVAP
+    Dpb,
+    /// DC CVADP
+    Dpb2,
+    /// Pointer authentication with any algorithm
+    PAuth,
+    /// Pointer authentication without the PAC field XOR, FEAT_PAuth2
+    PAuth2,
+    /// Faulting AUT* instructions
+    Fpac,
+    Jscvt,
+    Fcma,
+    Lrcpc,
+    Lrcpc2,
+    FrintTs,
+    /// Speculation barrier
+    Sb,
+    /// CFP/DVP/CPP prediction restriction instructions
+    SpecRes,
+    Bf16,
+    Dgh,
+    I8mm,
+    /// XS attribute and nXS barriers
+    Xs,
+    Ls64,
+
+    // ID_AA64ISAR2_EL1
+    /// WFET/WFIT
+    WfxT,
+    Rpres,
+    /// Memory copy and set instructions
+    Mops,
+    /// Hinted conditional branches
+    Hbc,
+    /// CLRBHB
+    Clrbhb,
+
+    // ID_AA64PFR0_EL1
+    /// EL2 is implemented
+    El2,
+    /// EL3 is implemented
+    El3,
+    /// Floating point
+    Fp,
+    /// Advanced SIMD
+    AdvSimd,
+    /// System register interface to the GIC CPU interface
+    Gic,
+    Ras,
+    Sve,
+    /// Secure EL2
+    Sel2,
+    /// Activity Monitors
+    Amu,
+    /// Data Independent Timing
+    Dit,
+    /// Realm Management Extension
+    Rme,
+    Csv2,
+    Csv3,
+
+    // ID_AA64PFR1_EL1
+    /// Branch Target Identification
+    Bti,
+    /// Speculative Store Bypass Safe
+    Ssbs,
+    /// MSR and MRS access to PSTATE.SSBS
+    Ssbs2,
+    /// Instruction-only MTE
+    Mte,
+    /// Full MTE with allocation tags in memory
+    Mte2,
+    /// MTE asymmetric fault handling
+    Mte3,
+    Sme,
+    /// Non-maskable interrupts
+    Nmi,
+
+    // ID_AA64MMFR0_EL1
+    /// 16-bit ASIDs
+    Asid16,
+    /// Fine grained traps
+    Fgt,
+    /// Enhanced counter virtualization
+    Ecv,
+
+    // ID_AA64MMFR1_EL1
+    /// Hardware Access flag update
+    Haf,
+    /// Hardware Access flag and dirty state update
+    Hafdbs,
+    /// 16-bit VMIDs
+    Vmid16,
+    /// Virtualization Host Extensions
+    Vhe,
+    Lor,
+    /// Privileged Access Never
+    Pan,
+    /// PAN with AT S1E1RP/S1E1WP
+    Pan2,
+    /// PAN for instruction fetches (SCTLR_EL1.EPAN)
+    Pan3,
+    Xnx,
+    Twed,
+    Ets,
+    Hcx,
+    Afp,
+
+    // ID_AA64MMFR2_EL1
+    /// Common not Private translations
+    Cnp,
+    /// User Access Override
+    Uao,
+    Lsm,
+    Iesb,
+    /// 52-bit virtual addresses
+    Lva,
+    /// 64-bit CCSIDR_EL1 layout
+    Ccidx,
+    /// Nested virtualization
+    Nv,
+    /// Enhanced nested virtualization
+    Nv2,
+    Ttst,
+    /// Unaligned single-copy atomicity and atomic functions
+    Lse2,
+    Ids,
+    /// Stage 2 forced write-back
+    Fwb,
+    /// Translation table level hint
+    Ttl,
+    Bbm,
+    Evt,
+    E0pd,
+
+    // ID_AA64MMFR3_EL1
+    /// TCR2_EL1 and TCR2_EL2
+    Tcrx,
+    /// SCTLR2_EL1 and SCTLR2_EL2
+    Sctlrx,
+    /// Stage 1 permission indirection
+    S1Pie,
+    /// Stage 2 permission indirection
+    S2Pie,
+    /// Stage 1 permission overlays
+    S1Poe,
+    /// Stage 2 permission overlays
+    S2Poe,
+    Aie,
+    Mec,
+    D128,
+}
+
+impl Feature {
+    /// ID register, field offset and minimum field value indicating support
+    const fn field(self) -> (IdReg, u32, u8) {
+        use IdReg::*;
+        match self {
+            Self::Aes => (Isar0, 4, 1),
+            Self::Pmull => (Isar0, 4, 2),
+            Self::Sha1 => (Isar0, 8, 1),
+            Self::Sha256 => (Isar0, 12, 1),
+            Self::Sha512 => (Isar0, 12, 2),
+            Self::Crc32 => (Isar0, 16, 1),
+            Self::Lse => (Isar0, 20, 2),
+            Self::Rdm => (Isar0, 28, 1),
+            Self::Sha3 => (Isar0, 32, 1),
+            Self::Sm3 => (Isar0, 36, 1),
+            Self::Sm4 => (Isar0, 40, 1),
+            Self::DotProd => (Isar0, 44, 1),
+            Self::Fhm => (Isar0, 48, 1),
+            Self::FlagM => (Isar0, 52, 1),
+            Self::TlbiOs => (Isar0, 56, 1),
+            Self::TlbiRange => (Isar0, 56, 2),
+            Self::Rng => (Isar0, 60, 1),
+
+            Self::Dpb => (Isar1, 0, 1),
+            Self::Dpb2 => (Isar1, 0, 2),
+            // APA, API and APA3 are checked in `has`
+            Self::PAuth => (Isar1, 4, 1),
+            Self::PAuth2 => (Isar1, 4, 3),
+            Self::Fpac => (Isar1, 4, 4),
+            Self::Jscvt => (Isar1, 12, 1),
+            Self::Fcma => (Isar1, 16, 1),
+            Self::Lrcpc => (Isar1, 20, 1),
+            Self::Lrcpc2 => (Isar1, 20, 2),
+            Self::FrintTs => (Isar1, 32, 1),
+            Self::Sb => (Isar1, 36, 1),
+            Self::SpecRes => (Isar1, 40, 1),
+            Self::Bf16 => (Isar1, 44, 1),
+            Self::Dgh => (Isar1, 48, 1),
+            Self::I8mm => (Isar1, 52, 1),
+            Self::Xs => (Isar1, 56, 1),
+            Self::Ls64 => (Isar1, 60, 1),
+
+            Self::WfxT => (Isar2, 0, 2),
+            Self::Rpres => (Isar2, 4, 1),
+            Self::Mops => (Isar2, 16, 1),
+            Self::Hbc => (Isar2, 20, 1),
+            Self::Clrbhb => (Isar2, 28, 1),
+
+            Self::El2 => (Pfr0, 8, 1),
+            Self::El3 => (Pfr0, 12, 1),
+            // FP and AdvSIMD are signed, 0xF means not implemented, checked in `has`
+            Self::Fp => (Pfr0, 16, 0),
+            Self::AdvSimd => (Pfr0, 20, 0),
+            Self::Gic => (Pfr0, 24, 1),
+            Self::Ras => (Pfr0, 28, 1),
+            Self::Sve => (Pfr0, 32, 1),
+            Self::Sel2 => (Pfr0, 36, 1),
+            Self::Amu => (Pfr0, 44, 1),
+            Self::Dit => (Pfr0, 48, 1),
+            Self::Rme => (Pfr0, 52, 1),
+            Self::Csv2 => (Pfr0, 56, 1),
+            Self::Csv3 => (Pfr0, 60, 1),
+
+            Self::Bti => (Pfr1, 0, 1),
+            Self::Ssbs => (Pfr1, 4, 1),
+            Self::Ssbs2 => (Pfr1, 4, 2),
+            Self::Mte => (Pfr1, 8, 1),
+            Self::Mte2 => (Pfr1, 8, 2),
+            Self::Mte3 => (Pfr1, 8, 3),
+            Self::Sme => (Pfr1, 24, 1),
+            Self::Nmi => (Pfr1, 36, 1),
+
+            Self::Asid16 => (Mmfr0, 4, 2),
+            Self::Fgt => (Mmfr0, 56, 1),
+            Self::Ecv => (Mmfr0, 60, 1),
+
+            Self::Haf => (Mmfr1, 0, 1),
+            Self::Hafdbs => (Mmfr1, 0, 2),
+            Self::Vmid16 => (Mmfr1, 4, 2),
+            Self::Vhe => (Mmfr1, 8, 1),
+            Self::Lor => (Mmfr1, 16, 1),
+            Self::Pan => (Mmfr1, 20, 1),
+            Self::Pan2 => (Mmfr1, 20, 2),
+            Self::Pan3 => (Mmfr1, 20, 3),
+            Self::Xnx => (Mmfr1, 28, 1),
+            Self::Twed => (Mmfr1, 32, 1),
+            Self::Ets => (Mmfr1, 36, 1),
+            Self::Hcx => (Mmfr1, 40, 1),
+            Self::Afp => (Mmfr1, 44, 1),
+
+            Self::Cnp => (Mmfr2, 0, 1),
+            Self::Uao => (Mmfr2, 4, 1),
+            Self::Lsm => (Mmfr2, 8, 1),
+            Self::Iesb => (Mmfr2, 12, 1),
+            Self::Lva => (Mmfr2, 16, 1),
+            Self::Ccidx => (Mmfr2, 20, 1),
+            Self::Nv => (Mmfr2, 24, 1),
+            Self::Nv2 => (Mmfr2, 24, 2),
+            Self::Ttst => (Mmfr2, 28, 1),
+            Self::Lse2 => (Mmfr2, 32, 1),
+            Self::Ids => (Mmfr2, 36, 1),
+            Self::Fwb => (Mmfr2, 40, 1),
+            Self::Ttl => (Mmfr2, 48, 1),
+            Self::Bbm => (Mmfr2, 52, 1),
+            Self::Evt => (Mmfr2, 56, 1),
+            Self::E0pd => (Mmfr2, 60, 1),
+
+            Self::Tcrx => (Mmfr3, 0, 1),
+            Self::Sctlrx => (Mmfr3, 4, 1),
+            Self::S1Pie => (Mmfr3, 8, 1),
+            Self::S2Pie => (Mmfr3, 12, 1),
+            Self::S1Poe => (Mmfr3, 16, 1),
+            Self::S2Poe => (Mmfr3, 20, 1),
+            Self::Aie => (Mmfr3, 24, 1),
+            Self::Mec => (Mmfr3, 28, 1),
+            Self::D128 => (Mmfr3, 32, 1),
+        }
+    }
+}
+
+/// Snapshot of the ID registers describing the implemented features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuFeatures {
+    regs: [u64; ID_REG_COUNT],
+}
+
+impl CpuFeatures {
+    /// Create from raw register values, e.g. for a virtual CPU or sanitized values
+    pub const fn from_raw(regs: [u64; ID_REG_COUNT]) -> Self {
+        Self { regs }
+    }
+
+    /// Get the raw value of an ID register
+    pub const fn reg(&self, reg: IdReg) -> u64 {
+        self.regs[reg as usize]
+    }
+
+    /// Get a 4-bit field of an ID register
+    pub const fn field(&self, reg: IdReg, offset: u32) -> u8 {
+        ((self.reg(reg) >> offset) & 0xF) as u8
+    }
+
+    /// Check if `feature` is implemented
+    pub const fn has(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::PAuth | Feature::PAuth2 | Feature::Fpac => {
+                // Only one of APA, API and APA3 is non-zero
+                let (_, _, min) = feature.field();
+                let level = self.field(IdReg::Isar1, 4)
+                    | self.field(IdReg::Isar1, 8)
+                    | self.field(IdReg::Isar2, 12);
+                level >= min
+            }
+            Feature::Fp | Feature::AdvSimd => {
+                let (reg, offset, _) = feature.field();
+                self.field(reg, offset) != 0xF
+            }
+            _ => {
+                let (reg, offset, min) = feature.field();
+                self.field(reg, offset) >= min
+            }
+        }
+    }
+}
+
+/// Signed ID register fields, 0xF (-1) means not implemented
+const SIGNED_FIELDS: [(IdReg, u32); 4] = [
+    // FP, AdvSIMD
+    (IdReg::Pfr0, 16),
+    (IdReg::Pfr0, 20),
+    // TGran64, TGran4
+    (IdReg::Mmfr0, 24),
+    (IdReg::Mmfr0, 28),
+];
+
+const fn is_signed(reg: IdReg, offset: u32) -> bool {
+    let mut i = 0;
+    while i < SIGNED_FIELDS.len() {
+        let (r, o) = SIGNED_FIELDS[i];
+        if r as usize == reg as usize && o == offset {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Features common to a set of cores
+///
+/// Heterogeneous systems such as big.LITTLE report different ID register values per core.
+/// Every core contributes its [`CpuFeatures`] with [`add`](Self::add), the result only
+/// reports a feature if all contributing cores implement it. Each 4-bit field is reduced
+/// to its minimum, signed fields (FP, AdvSIMD, TGran4, TGran64) with signed comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SanitizedFeatures {
+    regs: [u64; ID_REG_COUNT],
+    cores: usize,
+}
+
+impl SanitizedFeatures {
+    pub const fn new() -> Self {
+        Self {
+            regs: [0; ID_REG_COUNT],
+            cores: 0,
+        }
+    }
+
+    /// Number of cores that contributed
+    pub const fn cores(&self) -> usize {
+        self.cores
+    }
+
+    /// Merge the features of one core
+    pub fn add(&mut self, features: CpuFeatures) {
+        if self.cores == 0 {
+            self.regs = features.regs;
+        } else {
+            for (i, reg) in ID_REGS.into_iter().enumerate() {
+                self.regs[i] = sanitize(reg, self.regs[i], features.regs[i]);
+            }
+        }
+        self.cores += 1;
+    }
+
+    /// The features implemented by every contributing core
+    pub const fn features(&self) -> CpuFeatures {
+        CpuFeatures::from_raw(self.regs)
+    }
+}
+
+const ID_REGS: [IdReg; ID_REG_COUNT] = [
+    IdReg::Isar0,
+    IdReg::Isar1,
+    IdReg::Isar2,
+    IdReg::Pfr0,
+    IdReg::Pfr1,
+    IdReg::Mmfr0,
+    IdReg::Mmfr1,
+    IdReg::Mmfr2,
+    IdReg::Mmfr3,
+];
+
+fn sanitize(reg: IdReg, a: u64, b: u64) -> u64 {
+    let mut value = 0;
+    for offset in (0..64).step_by(4) {
+        let fa = (a >> offset) & 0xF;
+        let fb = (b >> offset) & 0xF;
+        let field = if is_signed(reg, offset) {
+            // Sign extend the 4-bit fields
+            let sa = ((fa as i8) << 4) >> 4;
+            let sb = ((fb as i8) << 4) >> 4;
+            (sa.min(sb) as u64) & 0xF
+        } else {
+            fa.min(fb)
+        };
+        value |= field << offset;
+    }
+    value
+}
+
+#[cfg(target_arch = "aarch64")]
+mod detect {
+    use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    use super::{CpuFeatures, Feature, ID_REG_COUNT};
+
+    macro_rules! read_id {
+        ($reg:literal) => {{
+            let value: u64;
+            unsafe {
+                core::arch::asm!(concat!("mrs {}, ", $reg), out(reg) value, options(nomem, nostack));
+            }
+            value
+        }};
+    }
+
+    impl CpuFeatures {
+        /// Read the ID registers of the executing core
+        pub fn read() -> Self {
+            Self::from_raw([
+                read_id!("id_aa64isar0_el1"),
+                read_id!("id_aa64isar1_el1"),
+                // ID_AA64ISAR2_EL1
+                read_id!("S3_0_C0_C6_2"),
+                read_id!("id_aa64pfr0_el1"),
+                read_id!("id_aa64pfr1_el1"),
+                read_id!("id_aa64mmfr0_el1"),
+                read_id!("id_aa64mmfr1_el1"),
+                read_id!("id_aa64mmfr2_el1"),
+                // ID_AA64MMFR3_EL1
+                read_id!("S3_0_C0_C7_3"),
+            ])
+        }
+    }
+
+    static READY: AtomicBool = AtomicBool::new(false);
+    static CACHE: [AtomicU64; ID_REG_COUNT] = [const { AtomicU64::new(0) }; ID_REG_COUNT];
+
+    /// Get the cached feature snapshot, reading the ID registers on first use
+    pub fn cpu_features() -> CpuFeatures {
+        if !READY.load(Ordering::Acquire) {
+            // Racing initializers store the same values
+            let features = CpuFeatures::read();
+            for (slot, value) in CACHE.iter().zip(features.regs) {
+                slot.store(value, Ordering::Relaxed);
+            }
+            READY.store(true, Ordering::Release);
+            return features;
+        }
+        CpuFeatures::from_raw(core::array::from_fn(|i| CACHE[i].load(Ordering::Relaxed)))
+    }
+
+    /// Check if `feature` is implemented, using the cached snapshot
+    #[inline]
+    pub fn is_supported(feature: Feature) -> bool {
+        cpu_features().has(feature)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use detect::{cpu_features, is_supported};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut regs = [0; ID_REG_COUNT];
+        // ISAR0.TLB = 2, ISAR0.Atomic = 2
+        regs[IdReg::Isar0 as usize] = (2 << 56) | (2 << 20);
+        // PFR0.FP = 0xF, AdvSIMD = 0
+        regs[IdReg::Pfr0 as usize] = 0xF << 16;
+        // ISAR2.APA3 = 1
+        regs[IdReg::Isar2 as usize] = 1 << 12;
+        // PFR1.MTE = 2
+        regs[IdReg::Pfr1 as usize] = 2 << 8;
+        let f = CpuFeatures::from_raw(regs);
+
+        assert!(f.has(Feature::TlbiOs));
+        assert!(f.has(Feature::TlbiRange));
+        assert!(f.has(Feature::Lse));
+        assert!(!f.has(Feature::Fp));
+        assert!(f.has(Feature::AdvSimd));
+        assert!(f.has(Feature::PAuth));
+        assert!(!f.has(Feature::PAuth2));
+        assert!(f.has(Feature::Mte2));
+        assert!(!f.has(Feature::Mte3));
+        assert!(!f.has(Feature::Bti));
+        assert!(!f.has(Feature::S1Pie));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut big = [0; ID_REG_COUNT];
+        big[IdReg::Isar0 as usize] = (2 << 56) | (2 << 20);
+        big[IdReg::Pfr0 as usize] = 1 << 16;
+        big[IdReg::Mmfr0 as usize] = 1 << 28;
+        let mut little = [0; ID_REG_COUNT];
+        little[IdReg::Isar0 as usize] = (1 << 56) | (2 << 20);
+        little[IdReg::Pfr0 as usize] = 0xF << 16;
+        little[IdReg::Mmfr0 as usize] = 0;
+
+        let mut sanitized = SanitizedFeatures::new();
+        sanitized.add(CpuFeatures::from_raw(big));
+        sanitized.add(CpuFeatures::from_raw(little));
+        assert_eq!(sanitized.cores(), 2);
+
+        let f = sanitized.features();
+        assert!(f.has(Feature::Lse));
+        assert!(f.has(Feature::TlbiOs));
+        assert!(!f.has(Feature::TlbiRange));
+        // FP: min(1, -1) = -1, not implemented
+        assert!(!f.has(Feature::Fp));
+        // TGran4: min(1, 0) = 0
+        assert_eq!(f.field(IdReg::Mmfr0, 28), 0);
+    }
+}
@@ -0,0 +1,121 @@
+//! FP/SIMD, SVE and SME access control.
+//!
+//! At EL1 the traps are configured in CPACR_EL1. At EL2 CPTR_EL2 is used, whose layout
+//! depends on HCR_EL2.E2H: with E2H set it matches CPACR_EL1, otherwise it holds single
+//! TFP/TZ/TSM trap bits.
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::*,
+};
+
+use crate::el::{ExceptionLevel, current_el};
+
+/// Trap configuration of a CPACR_EL1 style 2-bit enable field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessTrap {
+    /// Accesses from EL0 and EL1 (or EL2) are trapped
+    All,
+    /// Only accesses from EL0 are trapped
+    El0,
+    /// No accesses are trapped
+    None,
+}
+
+impl AccessTrap {
+    const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0b01 => Self::El0,
+            0b11 => Self::None,
+            _ => Self::All,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Field {
+    /// Offset of the 2-bit enable field in CPACR_EL1 and CPTR_EL2 with E2H set
+    enable: u32,
+    /// Trap bit in CPTR_EL2 with E2H clear
+    trap: u32,
+}
+
+const FP: Field = Field {
+    enable: 20,
+    trap: 10,
+};
+const SVE: Field = Field {
+    enable: 16,
+    trap: 8,
+};
+const SME: Field = Field {
+    enable: 24,
+    trap: 12,
+};
+
+fn at_el2() -> bool {
+    current_el() == ExceptionLevel::EL2
+}
+
+fn el2_uses_cpacr_layout() -> bool {
+    HCR_EL2.read(HCR_EL2::E2H) != 0
+}
+
+fn enable(field: Field) {
+    if at_el2() {
+        let cptr = CPTR_EL2.get();
+        if el2_uses_cpacr_layout() {
+            CPTR_EL2.set(cptr | (0b11 << field.enable));
+        } else {
+            CPTR_EL2.set(cptr & !(1 << field.trap));
+        }
+    } else {
+        CPACR_EL1.set(CPACR_EL1.get() | (0b11 << field.enable));
+    }
+    isb(SY);
+}
+
+fn access(field: Field) -> AccessTrap {
+    if at_el2() {
+        let cptr = CPTR_EL2.get();
+        if el2_uses_cpacr_layout() {
+            AccessTrap::from_bits(cptr >> field.enable)
+        } else if cptr & (1 << field.trap) != 0 {
+            AccessTrap::All
+        } else {
+            AccessTrap::None
+        }
+    } else {
+        AccessTrap::from_bits(CPACR_EL1.get() >> field.enable)
+    }
+}
+
+/// Stop trapping FP and Advanced SIMD instructions at the current and lower levels
+pub fn enable_fp_simd() {
+    enable(FP);
+}
+
+/// Stop trapping SVE instructions, FP/SIMD must be enabled as well
+pub fn enable_sve() {
+    enable(SVE);
+}
+
+/// Stop trapping SME instructions, FP/SIMD must be enabled as well
+pub fn enable_sme() {
+    enable(SME);
+}
+
+/// Current trap configuration of FP and Advanced SIMD instructions
+pub fn fp_simd_access() -> AccessTrap {
+    access(FP)
+}
+
+/// Current trap configuration of SVE instructions
+pub fn sve_access() -> AccessTrap {
+    access(SVE)
+}
+
+/// Current trap configuration of SME instructions
+pub fn sme_access() -> AccessTrap {
+    access(SME)
+}
@@ -0,0 +1,174 @@
+//! Interrupt masking through the PSTATE.DAIF bits.
+//!
+//! Masking uses `MSR DAIFSet`/`MSR DAIFClr` with immediates, which only touch the selected
+//! bits. [`IrqGuard`] saves the complete DAIF state and restores it on drop, so guards nest.
+//! All accesses act as compiler barriers: memory accesses are not moved across them.
+
+use core::{arch::asm, marker::PhantomData, ops::BitOr};
+
+/// A set of DAIF exception mask bits, in the encoding of the DAIFSet/DAIFClr immediate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mask(u8);
+
+impl Mask {
+    /// Watchpoint, breakpoint and software step exceptions
+    pub const DEBUG: Self = Self(0b1000);
+    /// SError interrupts
+    pub const SERROR: Self = Self(0b0100);
+    pub const IRQ: Self = Self(0b0010);
+    pub const FIQ: Self = Self(0b0001);
+    pub const ALL: Self = Self(0b1111);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// The 4-bit DAIFSet/DAIFClr immediate
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Mask bits set in a DAIF register value
+    pub const fn from_daif(daif: u64) -> Self {
+        Self(((daif >> 6) & 0xF) as u8)
+    }
+}
+
+impl BitOr for Mask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Emit `MSR <op>, #imm` for a runtime mask, the immediate has to be encoded in the
+/// instruction
+macro_rules! daif_imm {
+    ($op:literal, $mask:expr, [$($n:literal),*]) => {
+        match $mask.bits() {
+            $($n => unsafe { asm!(concat!("msr ", $op, ", #", $n), options(nostack)) },)*
+            _ => unreachable!(),
+        }
+    };
+}
+
+/// Set the given mask bits, leaving the others unchanged
+#[inline]
+pub fn mask(mask: Mask) {
+    daif_imm!(
+        "daifset",
+        mask,
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+    );
+}
+
+/// Clear the given mask bits, leaving the others unchanged
+#[inline]
+pub fn unmask(mask: Mask) {
+    daif_imm!(
+        "daifclr",
+        mask,
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+    );
+}
+
+/// Mask IRQs
+#[inline]
+pub fn disable() {
+    unsafe { asm!("msr daifset, #2", options(nostack)) };
+}
+
+/// Unmask IRQs, a pending IRQ is taken right after
+#[inline]
+pub fn enable() {
+    unsafe { asm!("msr daifclr, #2", options(nostack)) };
+}
+
+/// Current DAIF register value
+#[inline]
+pub fn daif() -> u64 {
+    let daif: u64;
+    unsafe { asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags)) };
+    daif
+}
+
+/// Currently masked exceptions
+#[inline]
+pub fn masked() -> Mask {
+    Mask::from_daif(daif())
+}
+
+/// Check if IRQs are unmasked
+#[inline]
+pub fn is_enabled() -> bool {
+    !masked().contains(Mask::IRQ)
+}
+
+/// Restore a DAIF value saved with [`daif`]
+#[inline]
+pub fn restore(daif: u64) {
+    unsafe { asm!("msr daif, {}", in(reg) daif, options(nostack)) };
+}
+
+/// Run `f` with IRQs masked, restoring the previous state afterwards
+#[inline]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = IrqGuard::new();
+    f()
+}
+
+/// Masks exceptions while alive, restoring the saved DAIF state on drop
+///
+/// The guard is tied to the core it was created on and therefore neither `Send` nor `Sync`.
+///
+/// ```ignore
+/// let _guard = IrqGuard::new();
+/// // IRQs are masked until the end of the scope
+/// ```
+#[must_use = "the previous state is restored when the guard is dropped"]
+pub struct IrqGuard {
+    saved: u64,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl IrqGuard {
+    /// Mask IRQs
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_mask(Mask::IRQ)
+    }
+
+    /// Mask the given exceptions, e.g. `Mask::IRQ | Mask::FIQ`
+    #[inline]
+    pub fn with_mask(m: Mask) -> Self {
+        let saved = daif();
+        mask(m);
+        Self {
+            saved,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// The DAIF value restored on drop
+    pub fn saved(&self) -> u64 {
+        self.saved
+    }
+}
+
+impl Default for IrqGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for IrqGuard {
+    #[inline]
+    fn drop(&mut self) {
+        restore(self.saved);
+    }
+}
@@ -1,15 +1,62 @@
 #![cfg_attr(not(test), no_std)]
 
+pub mod affinity;
+pub mod amu;
+#[cfg(target_arch = "aarch64")]
+pub mod asid;
 #[cfg(target_arch = "aarch64")]
 pub mod asm;
+pub mod backtrace;
+pub mod boot;
 #[cfg(target_arch = "aarch64")]
 pub mod cache;
+pub mod debug;
+#[cfg(target_arch = "aarch64")]
+pub mod dit;
+#[cfg(target_arch = "aarch64")]
+pub mod el;
+pub mod errata;
+pub mod exception;
+pub mod features;
+#[cfg(target_arch = "aarch64")]
+pub mod fpsimd;
+#[cfg(target_arch = "aarch64")]
+pub mod interrupts;
+pub mod mmu;
+pub mod mte;
+pub mod nv;
+#[cfg(target_arch = "aarch64")]
+pub mod pan;
+pub mod pauth;
+#[cfg(target_arch = "aarch64")]
+pub mod percpu;
+pub mod pmu;
+pub mod psci;
+#[cfg(target_arch = "aarch64")]
+pub mod random;
 #[cfg(target_arch = "aarch64")]
 pub mod registers {
     pub use aarch64_cpu::registers::*;
 }
 
+pub mod semihosting;
+pub mod smccc;
+#[cfg(target_arch = "aarch64")]
+pub mod smp;
+pub mod speculation;
 pub mod structures;
+mod sysreg;
+pub mod time;
+pub mod timer;
+#[cfg(target_arch = "aarch64")]
+pub mod tlb;
+pub mod traps;
+#[cfg(target_arch = "aarch64")]
+pub mod vcpu;
+#[cfg(target_arch = "aarch64")]
+pub mod virt;
+#[cfg(target_arch = "aarch64")]
+pub mod vmid;
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,359 @@
+//! SCTLR_EL1 configuration, the MMU enable/disable sequences and address translation
+//! lookups.
+
+use crate::{mte::TagCheckFault, pauth::Key};
+
+/// Builder for SCTLR_EL1
+///
+/// [`Sctlr::new`] starts from the RES1 bits of Armv8.0 (EOS, TSCXT, EIS, SPAN, nTLSMD,
+/// LSMAOE) with the MMU, caches and all optional behaviour disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sctlr(u64);
+
+impl Sctlr {
+    const M: u64 = 1 << 0;
+    const A: u64 = 1 << 1;
+    const C: u64 = 1 << 2;
+    const SA: u64 = 1 << 3;
+    const SA0: u64 = 1 << 4;
+    const UMA: u64 = 1 << 9;
+    const I: u64 = 1 << 12;
+    const EN_DB: u64 = 1 << 13;
+    const DZE: u64 = 1 << 14;
+    const UCT: u64 = 1 << 15;
+    const NTWI: u64 = 1 << 16;
+    const NTWE: u64 = 1 << 18;
+    const WXN: u64 = 1 << 19;
+    const SPAN: u64 = 1 << 23;
+    const E0E: u64 = 1 << 24;
+    const EE: u64 = 1 << 25;
+    const UCI: u64 = 1 << 26;
+    const EN_DA: u64 = 1 << 27;
+    const EN_IB: u64 = 1 << 30;
+    const EN_IA: u64 = 1 << 31;
+    const TCF0_SHIFT: u64 = 38;
+    const TCF_SHIFT: u64 = 40;
+    const ATA0: u64 = 1 << 42;
+    const ATA: u64 = 1 << 43;
+    const EPAN: u64 = 1 << 57;
+
+    const RES1: u64 = (1 << 11) | (1 << 20) | (1 << 22) | (1 << 28) | (1 << 29) | Self::SPAN;
+
+    /// SCTLR_EL1 with only the RES1 bits set
+    pub const fn new() -> Self {
+        Self(Self::RES1)
+    }
+
+    /// Create from a raw SCTLR_EL1 value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    const fn bit(self, mask: u64, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// Stage 1 address translation (M)
+    pub const fn mmu(self, enable: bool) -> Self {
+        self.bit(Self::M, enable)
+    }
+
+    /// Alignment fault checking (A)
+    pub const fn alignment_check(self, enable: bool) -> Self {
+        self.bit(Self::A, enable)
+    }
+
+    /// Data and unified caches (C)
+    pub const fn dcache(self, enable: bool) -> Self {
+        self.bit(Self::C, enable)
+    }
+
+    /// Instruction caches (I)
+    pub const fn icache(self, enable: bool) -> Self {
+        self.bit(Self::I, enable)
+    }
+
+    /// SP alignment checking at EL1 (SA)
+    pub const fn stack_alignment_check(self, enable: bool) -> Self {
+        self.bit(Self::SA, enable)
+    }
+
+    /// SP alignment checking at EL0 (SA0)
+    pub const fn el0_stack_alignment_check(self, enable: bool) -> Self {
+        self.bit(Self::SA0, enable)
+    }
+
+    /// Writeable memory is never executable (WXN)
+    pub const fn wxn(self, enable: bool) -> Self {
+        self.bit(Self::WXN, enable)
+    }
+
+    /// Set PSTATE.PAN on exception entry to EL1, clears SPAN
+    pub const fn set_pan_on_exception(self, enable: bool) -> Self {
+        self.bit(Self::SPAN, !enable)
+    }
+
+    /// Apply PAN to memory executable at EL0, not only readable or writable (EPAN)
+    pub const fn epan(self, enable: bool) -> Self {
+        self.bit(Self::EPAN, enable)
+    }
+
+    /// Trap EL0 WFE to EL1, clears nTWE
+    pub const fn trap_wfe(self, trap: bool) -> Self {
+        self.bit(Self::NTWE, !trap)
+    }
+
+    /// Trap EL0 WFI to EL1, clears nTWI
+    pub const fn trap_wfi(self, trap: bool) -> Self {
+        self.bit(Self::NTWI, !trap)
+    }
+
+    /// Big endian data accesses at EL1 (EE)
+    pub const fn big_endian(self, enable: bool) -> Self {
+        self.bit(Self::EE, enable)
+    }
+
+    /// Big endian data accesses at EL0 (E0E)
+    pub const fn el0_big_endian(self, enable: bool) -> Self {
+        self.bit(Self::E0E, enable)
+    }
+
+    /// Allow EL0 access to the DAIF interrupt masks (UMA)
+    pub const fn el0_interrupt_masking(self, enable: bool) -> Self {
+        self.bit(Self::UMA, enable)
+    }
+
+    /// Allow EL0 DC ZVA (DZE)
+    pub const fn el0_dc_zva(self, enable: bool) -> Self {
+        self.bit(Self::DZE, enable)
+    }
+
+    /// Allow EL0 access to CTR_EL0 (UCT)
+    pub const fn el0_ctr_access(self, enable: bool) -> Self {
+        self.bit(Self::UCT, enable)
+    }
+
+    /// Allow EL0 cache maintenance by VA (UCI)
+    pub const fn el0_cache_maintenance(self, enable: bool) -> Self {
+        self.bit(Self::UCI, enable)
+    }
+
+    /// Pointer authentication with `key` at EL0 and EL1 (EnIA, EnIB, EnDA, EnDB)
+    ///
+    /// The generic key cannot be disabled, [`Key::GA`] leaves the value unchanged.
+    pub const fn pointer_auth(self, key: Key, enable: bool) -> Self {
+        self.bit(Self::pauth_bit(key), enable)
+    }
+
+    const fn pauth_bit(key: Key) -> u64 {
+        match key {
+            Key::IA => Self::EN_IA,
+            Key::IB => Self::EN_IB,
+            Key::DA => Self::EN_DA,
+            Key::DB => Self::EN_DB,
+            Key::GA => 0,
+        }
+    }
+
+    /// Access to allocation tags at EL1 (ATA)
+    pub const fn allocation_tags(self, enable: bool) -> Self {
+        self.bit(Self::ATA, enable)
+    }
+
+    /// Access to allocation tags at EL0 (ATA0)
+    pub const fn el0_allocation_tags(self, enable: bool) -> Self {
+        self.bit(Self::ATA0, enable)
+    }
+
+    /// Reporting of tag check faults at EL1 (TCF)
+    pub const fn tag_check_fault(self, mode: TagCheckFault) -> Self {
+        Self((self.0 & !(0b11 << Self::TCF_SHIFT)) | ((mode as u64) << Self::TCF_SHIFT))
+    }
+
+    /// Reporting of tag check faults at EL0 (TCF0)
+    pub const fn el0_tag_check_fault(self, mode: TagCheckFault) -> Self {
+        Self((self.0 & !(0b11 << Self::TCF0_SHIFT)) | ((mode as u64) << Self::TCF0_SHIFT))
+    }
+
+    /// Check if stage 1 translation is enabled
+    pub const fn is_mmu_enabled(self) -> bool {
+        self.0 & Self::M != 0
+    }
+
+    /// Check if the data cache is enabled
+    pub const fn is_dcache_enabled(self) -> bool {
+        self.0 & Self::C != 0
+    }
+
+    /// Reporting of tag check faults at EL1
+    pub const fn tag_check_fault_mode(self) -> TagCheckFault {
+        TagCheckFault::from_bits(self.0 >> Self::TCF_SHIFT)
+    }
+
+    /// Reporting of tag check faults at EL0
+    pub const fn el0_tag_check_fault_mode(self) -> TagCheckFault {
+        TagCheckFault::from_bits(self.0 >> Self::TCF0_SHIFT)
+    }
+
+    /// Check if pointer authentication with `key` is enabled, always true for [`Key::GA`]
+    pub const fn is_pointer_auth_enabled(self, key: Key) -> bool {
+        self.0 & Self::pauth_bit(key) == Self::pauth_bit(key)
+    }
+}
+
+impl Default for Sctlr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod ops {
+    use aarch64_cpu::{
+        asm::barrier::{NSH, SY, dsb, isb},
+        registers::{Readable, SCTLR_EL1, Writeable},
+    };
+
+    use super::Sctlr;
+    use crate::{
+        asm::{
+            at::{self, at},
+            cache::{IALLU, ic},
+            tlb::{VMALLE1, tlbi},
+        },
+        interrupts::without_interrupts,
+        structures::par::{Par, PhysInfo, TranslateKind, TranslationFault},
+    };
+
+    impl Sctlr {
+        /// Read SCTLR_EL1
+        pub fn read() -> Self {
+            Self(SCTLR_EL1.get())
+        }
+    }
+
+    /// Write `sctlr` to SCTLR_EL1, enabling the MMU with it
+    ///
+    /// Stale TLB entries and instructions fetched with the MMU off are invalidated before
+    /// the write, the write is followed by an ISB so the next instruction is fetched with
+    /// translation enabled.
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL1. MAIR_EL1, TCR_EL1 and TTBR0/1_EL1 must be programmed and the
+    /// code executing this function must be identity mapped.
+    pub unsafe fn enable_mmu(sctlr: Sctlr) {
+        let sctlr = sctlr.mmu(true);
+        ic(IALLU);
+        tlbi(VMALLE1);
+        dsb(NSH);
+        isb(SY);
+        SCTLR_EL1.set(sctlr.value());
+        isb(SY);
+    }
+
+    /// Disable the MMU and the data cache
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL1 from identity mapped code. Memory written with the data cache
+    /// enabled must be cleaned to the PoC before, e.g. with
+    /// [`dcache_all`](crate::cache::dcache_all), otherwise it is lost once the cache is
+    /// bypassed.
+    pub unsafe fn disable_mmu() {
+        let sctlr = Sctlr::read().mmu(false).dcache(false);
+        dsb(SY);
+        SCTLR_EL1.set(sctlr.value());
+        isb(SY);
+        ic(IALLU);
+        tlbi(VMALLE1);
+        dsb(NSH);
+        isb(SY);
+    }
+
+    /// Translate `va` with the AT instruction selected by `kind` and decode PAR_EL1
+    ///
+    /// The returned PA includes the offset of `va` into its 4KB page. Interrupts are
+    /// masked between the translation and the read of PAR_EL1, so a handler using AT
+    /// cannot replace the result. The operations of `kind` must be available at the
+    /// current exception level.
+    pub fn translate_va(va: usize, kind: TranslateKind) -> Result<PhysInfo, TranslationFault> {
+        let par = without_interrupts(|| match kind {
+            TranslateKind::El0Read => at(at::S1E0R, va),
+            TranslateKind::El0Write => at(at::S1E0W, va),
+            TranslateKind::El1Read => at(at::S1E1R, va),
+            TranslateKind::El1Write => at(at::S1E1W, va),
+            TranslateKind::El1ReadPan => at(at::S1E1RP, va),
+            TranslateKind::El1WritePan => at(at::S1E1WP, va),
+            TranslateKind::El2Read => at(at::S1E2R, va),
+            TranslateKind::El2Write => at(at::S1E2W, va),
+            TranslateKind::GuestEl0Read => at(at::S12E0R, va),
+            TranslateKind::GuestEl0Write => at(at::S12E0W, va),
+            TranslateKind::GuestEl1Read => at(at::S12E1R, va),
+            TranslateKind::GuestEl1Write => at(at::S12E1W, va),
+            TranslateKind::El3Read => at(at::S1E3R, va),
+            TranslateKind::El3Write => at(at::S1E3W, va),
+        });
+        let mut info = Par::from_value(par).decode()?;
+        info.pa |= va as u64 & 0xFFF;
+        Ok(info)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use ops::{disable_mmu, enable_mmu, translate_va};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sctlr() {
+        let sctlr = Sctlr::new();
+        assert_eq!(sctlr.value(), 0x30d0_0800);
+        assert!(!sctlr.is_mmu_enabled());
+
+        let sctlr = sctlr
+            .mmu(true)
+            .dcache(true)
+            .icache(true)
+            .stack_alignment_check(true)
+            .trap_wfe(false)
+            .set_pan_on_exception(true);
+        assert_eq!(sctlr.value(), 0x3054_180d);
+        let pauth = sctlr
+            .pointer_auth(Key::IA, true)
+            .pointer_auth(Key::IB, true)
+            .pointer_auth(Key::DA, true)
+            .pointer_auth(Key::DB, true)
+            .pointer_auth(Key::GA, false);
+        assert_eq!(pauth.value(), 0xf854_380d);
+        assert!(pauth.is_pointer_auth_enabled(Key::DB));
+        assert!(!sctlr.is_pointer_auth_enabled(Key::IA));
+
+        let mte = sctlr
+            .allocation_tags(true)
+            .tag_check_fault(TagCheckFault::Sync)
+            .el0_tag_check_fault(TagCheckFault::Asymmetric);
+        assert_eq!(mte.value(), 0x0000_09c0_3054_180d);
+        assert_eq!(mte.tag_check_fault_mode(), TagCheckFault::Sync);
+        assert_eq!(mte.el0_tag_check_fault_mode(), TagCheckFault::Asymmetric);
+        assert_eq!(
+            mte.tag_check_fault(TagCheckFault::None).value(),
+            0x0000_08c0_3054_180d
+        );
+        assert!(sctlr.is_mmu_enabled());
+        assert!(sctlr.is_dcache_enabled());
+        assert_eq!(sctlr.mmu(false).value(), 0x3054_180c);
+        assert_eq!(sctlr.epan(true).value(), 0x0200_0000_3054_180d);
+    }
+}
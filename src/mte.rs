@@ -0,0 +1,278 @@
+//! Memory Tagging Extension (FEAT_MTE2) control.
+//!
+//! Every 16-byte granule of tagged memory has a 4-bit allocation tag, pointers carry a
+//! logical tag in bits [59:56]. A tag check fault is raised when a tagged access uses a
+//! logical tag that differs from the allocation tag of the memory it accesses.
+//!
+//! How a tag check fault is reported is selected per exception level with SCTLR_EL1.TCF
+//! and TCF0, see [`TagCheckFault`]. Synchronous faults are data aborts decoded by
+//! [`TagFault::from_abort`], asynchronous faults only set a bit in TFSR_EL1 or TFSRE0_EL1
+//! that the kernel polls, e.g. on return to EL0, with [`take_async_faults`].
+//!
+//! Random tags generated by IRG skip the tags excluded in GCR_EL1, see [`Gcr`].
+
+use crate::exception::syndrome::{Esr, FaultInfo, FaultStatus, Syndrome};
+
+/// Reporting of tag check faults (SCTLR_EL1.TCF, TCF0)
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TagCheckFault {
+    /// Tag check faults are ignored
+    None = 0b00,
+    /// Synchronous data abort
+    Sync = 0b01,
+    /// Accumulated asynchronously in TFSR_EL1 or TFSRE0_EL1
+    Async = 0b10,
+    /// Synchronous for reads, asynchronous for writes, requires FEAT_MTE3
+    Asymmetric = 0b11,
+}
+
+impl TagCheckFault {
+    pub const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::None,
+            0b01 => Self::Sync,
+            0b10 => Self::Async,
+            _ => Self::Asymmetric,
+        }
+    }
+}
+
+/// Builder for GCR_EL1, the tags excluded from random tag generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Gcr(u64);
+
+impl Gcr {
+    const EXCLUDE: u64 = 0xFFFF;
+    const RRND: u64 = 1 << 16;
+
+    /// No tags excluded, tags generated from RGSR_EL1
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw GCR_EL1 value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Exclude the tags set in `mask`, bit n for tag n (Exclude)
+    pub const fn exclude_mask(self, mask: u16) -> Self {
+        Self((self.0 & !Self::EXCLUDE) | mask as u64)
+    }
+
+    /// Exclude `tag` from random tag generation
+    pub const fn exclude(self, tag: u8) -> Self {
+        Self(self.0 | (1 << (tag & 0xF)))
+    }
+
+    /// Allow `tag` in random tag generation
+    pub const fn include(self, tag: u8) -> Self {
+        Self(self.0 & !(1 << (tag & 0xF)))
+    }
+
+    /// Generate random tags with an IMPLEMENTATION DEFINED algorithm instead of the
+    /// seed in RGSR_EL1 (RRND)
+    pub const fn implementation_defined_random(self, enable: bool) -> Self {
+        if enable {
+            Self(self.0 | Self::RRND)
+        } else {
+            Self(self.0 & !Self::RRND)
+        }
+    }
+
+    /// Excluded tags, bit n for tag n
+    pub const fn excluded(self) -> u16 {
+        (self.0 & Self::EXCLUDE) as u16
+    }
+
+    pub const fn is_excluded(self, tag: u8) -> bool {
+        self.0 & (1 << (tag & 0xF)) != 0
+    }
+}
+
+/// Asynchronous tag check faults recorded in TFSR_EL1 or TFSRE0_EL1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TagFaultStatus {
+    /// A fault on an address translated by TTBR0 (TF0)
+    pub ttbr0: bool,
+    /// A fault on an address translated by TTBR1 (TF1)
+    pub ttbr1: bool,
+}
+
+impl TagFaultStatus {
+    pub const fn from_value(value: u64) -> Self {
+        Self {
+            ttbr0: value & (1 << 0) != 0,
+            ttbr1: value & (1 << 1) != 0,
+        }
+    }
+
+    /// Check if any fault was recorded
+    pub const fn any(self) -> bool {
+        self.ttbr0 || self.ttbr1
+    }
+}
+
+/// A synchronous tag check fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TagFault {
+    /// Faulting address with the tag bits [63:56] replaced by the sign extension of bit 55
+    pub address: u64,
+    /// Logical tag of the faulting pointer, FAR bits [59:56]
+    pub tag: u8,
+    /// Caused by a write
+    pub write: bool,
+    /// Taken from a lower exception level
+    pub lower_el: bool,
+}
+
+impl TagFault {
+    /// Decode a data abort with syndrome `esr` and fault address `far`, `None` if it is not
+    /// a synchronous tag check fault
+    pub const fn from_abort(esr: Esr, far: u64) -> Option<Self> {
+        let Syndrome::DataAbort { lower_el, iss } = esr.decode() else {
+            return None;
+        };
+        let info = FaultInfo::from_data_abort_iss(iss);
+        if !matches!(info.status, FaultStatus::TagCheck) {
+            return None;
+        }
+        Some(Self {
+            address: ((far << 8) as i64 >> 8) as u64,
+            tag: ((far >> 56) & 0xF) as u8,
+            write: info.write,
+            lower_el,
+        })
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::{
+        asm::barrier::{NSH, SY, dsb, isb},
+        registers::{SCTLR_EL1, Writeable},
+    };
+
+    use super::{Gcr, TagCheckFault, TagFaultStatus};
+    use crate::{
+        features::{Feature, is_supported as has_feature},
+        mmu::Sctlr,
+    };
+
+    /// Check if the core implements tag checking with tags in memory (FEAT_MTE2)
+    #[inline]
+    pub fn is_supported() -> bool {
+        has_feature(Feature::Mte2)
+    }
+
+    /// Read GCR_EL1
+    #[inline]
+    pub fn gcr() -> Gcr {
+        Gcr::from_value(crate::sysreg!(read "S3_0_C1_C0_6"))
+    }
+
+    /// Write GCR_EL1
+    pub fn set_gcr(gcr: Gcr) {
+        unsafe { crate::sysreg!(write "S3_0_C1_C0_6", gcr.value()) };
+        isb(SY);
+    }
+
+    /// Seed the random tag generation of IRG (RGSR_EL1.SEED), used unless GCR_EL1.RRND
+    /// is set
+    ///
+    /// A zero seed makes IRG always return the first non-excluded tag.
+    pub fn seed_random_tags(seed: u16) {
+        unsafe { crate::sysreg!(write "S3_0_C1_C0_5", (seed as u64) << 8) };
+        isb(SY);
+    }
+
+    /// Select how tag check faults at EL1 are reported (SCTLR_EL1.TCF)
+    pub fn set_tag_check_fault(mode: TagCheckFault) {
+        SCTLR_EL1.set(Sctlr::read().tag_check_fault(mode).value());
+        isb(SY);
+    }
+
+    /// Select how tag check faults at EL0 are reported (SCTLR_EL1.TCF0)
+    pub fn set_el0_tag_check_fault(mode: TagCheckFault) {
+        SCTLR_EL1.set(Sctlr::read().el0_tag_check_fault(mode).value());
+        isb(SY);
+    }
+
+    /// Asynchronous faults at EL1 recorded in TFSR_EL1
+    #[inline]
+    pub fn tfsr() -> TagFaultStatus {
+        TagFaultStatus::from_value(crate::sysreg!(read "S3_0_C5_C6_0"))
+    }
+
+    /// Asynchronous faults at EL0 recorded in TFSRE0_EL1
+    #[inline]
+    pub fn tfsre0() -> TagFaultStatus {
+        TagFaultStatus::from_value(crate::sysreg!(read "S3_0_C5_C6_1"))
+    }
+
+    /// Read and clear the asynchronous faults at EL1 and EL0, as (EL1, EL0)
+    ///
+    /// Faults of outstanding accesses are only recorded once they complete, a DSB is
+    /// issued before reading the registers.
+    pub fn take_async_faults() -> (TagFaultStatus, TagFaultStatus) {
+        dsb(NSH);
+        isb(SY);
+        let el1 = tfsr();
+        let el0 = tfsre0();
+        unsafe {
+            crate::sysreg!(write "S3_0_C5_C6_0", 0);
+            crate::sysreg!(write "S3_0_C5_C6_1", 0);
+        }
+        isb(SY);
+        (el1, el0)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use access::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcr() {
+        let gcr = Gcr::new().exclude(0).exclude(15);
+        assert_eq!(gcr.value(), 0x8001);
+        assert!(gcr.is_excluded(15));
+        assert!(!gcr.include(15).is_excluded(15));
+        let gcr = gcr.exclude_mask(0x00F0).implementation_defined_random(true);
+        assert_eq!(gcr.value(), 0x1_00F0);
+        assert_eq!(gcr.excluded(), 0x00F0);
+    }
+
+    #[test]
+    fn test_tag_fault() {
+        // STR to 0x0b00_ffff_0000_1230 with a mismatching tag, EL0
+        let esr = Esr((0x24 << 26) | (1 << 25) | (1 << 6) | 0x11);
+        let fault = TagFault::from_abort(esr, 0x0b00_ffff_0000_1230).unwrap();
+        assert_eq!(fault.address, 0xffff_0000_1230);
+        assert_eq!(fault.tag, 0xb);
+        assert!(fault.write);
+        assert!(fault.lower_el);
+
+        // Kernel address, bit 55 set
+        let esr = Esr((0x25 << 26) | (1 << 25) | 0x11);
+        let fault = TagFault::from_abort(esr, 0xf3ff_8000_0000_0040).unwrap();
+        assert_eq!(fault.address, 0xffff_8000_0000_0040);
+        assert_eq!(fault.tag, 0x3);
+
+        // Translation fault
+        assert!(TagFault::from_abort(Esr((0x24 << 26) | 0x07), 0).is_none());
+
+        assert!(TagFaultStatus::from_value(0b10).ttbr1);
+        assert!(!TagFaultStatus::from_value(0).any());
+        assert_eq!(TagCheckFault::from_bits(0b11), TagCheckFault::Asymmetric);
+    }
+}
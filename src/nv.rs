@@ -0,0 +1,314 @@
+//! Nested virtualization (FEAT_NV, FEAT_NV2).
+//!
+//! With HCR_EL2.NV set, a guest hypervisor runs at EL1 while believing it runs at EL2:
+//! its accesses to EL2 registers and its ERET trap to the host hypervisor. HCR_EL2.NV1
+//! selects a guest hypervisor without VHE (HCR_EL2.E2H = 0 in the guest's view), where
+//! the EL1 registers hold the guest's EL2 state and trap as well.
+//!
+//! FEAT_NV2 (HCR_EL2.NV2) turns most of these traps into memory accesses: reads and
+//! writes of the redirected registers go to a page whose address is held in VNCR_EL2,
+//! and the host hypervisor loads and saves the guest's state from there. [`VncrPage`]
+//! models that page, [`VncrReg`] gives the architected offset of each register.
+
+use core::ops::{Index, IndexMut};
+
+/// Nested virtualization controls of HCR_EL2 (NV, NV1, NV2, AT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NvControl(u64);
+
+impl NvControl {
+    const NV: u64 = 1 << 42;
+    const NV1: u64 = 1 << 43;
+    const AT: u64 = 1 << 44;
+    const NV2: u64 = 1 << 45;
+
+    /// HCR_EL2 bits owned by this type
+    pub const MASK: u64 = Self::NV | Self::NV1 | Self::AT | Self::NV2;
+
+    /// Nested virtualization disabled
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Extract the controls from an HCR_EL2 value
+    pub const fn from_hcr(hcr: u64) -> Self {
+        Self(hcr & Self::MASK)
+    }
+
+    /// Get the HCR_EL2 bits
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Replace the nested virtualization bits of `hcr`
+    pub const fn apply(self, hcr: u64) -> u64 {
+        (hcr & !Self::MASK) | self.0
+    }
+
+    const fn bit(self, mask: u64, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// Trap the EL2 register accesses and ERET of a guest hypervisor at EL1 (NV),
+    /// requires FEAT_NV
+    pub const fn nested(self, enable: bool) -> Self {
+        self.bit(Self::NV, enable)
+    }
+
+    /// Guest hypervisor without VHE, also trap EL1 register accesses (NV1)
+    ///
+    /// Changes the meaning of bit 54 of stage 1 EL1&0 descriptors, see
+    /// [`TTE64`](crate::structures::tte::TTE64).
+    pub const fn non_vhe(self, enable: bool) -> Self {
+        self.bit(Self::NV1, enable)
+    }
+
+    /// Trap AT S1E0* and AT S1E1* executed by the guest hypervisor (AT)
+    pub const fn trap_at(self, enable: bool) -> Self {
+        self.bit(Self::AT, enable)
+    }
+
+    /// Redirect register accesses of the guest hypervisor to the VNCR page (NV2),
+    /// requires FEAT_NV2
+    pub const fn redirect(self, enable: bool) -> Self {
+        self.bit(Self::NV2, enable)
+    }
+
+    /// Check if a guest hypervisor runs at EL1 (NV)
+    pub const fn is_nested(self) -> bool {
+        self.0 & Self::NV != 0
+    }
+
+    /// Check if register accesses are redirected to the VNCR page (NV2)
+    pub const fn is_redirected(self) -> bool {
+        self.0 & Self::NV2 != 0
+    }
+}
+
+/// Registers backed by the VNCR page, the value is the byte offset into the page
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VncrReg {
+    VttbrEl2 = 0x020,
+    VtcrEl2 = 0x040,
+    VmpidrEl2 = 0x050,
+    CntvoffEl2 = 0x060,
+    HcrEl2 = 0x078,
+    HstrEl2 = 0x080,
+    VpidrEl2 = 0x088,
+    TpidrEl2 = 0x090,
+    HcrxEl2 = 0x0A0,
+    VncrEl2 = 0x0B0,
+    CpacrEl1 = 0x100,
+    ContextidrEl1 = 0x108,
+    SctlrEl1 = 0x110,
+    ActlrEl1 = 0x118,
+    TcrEl1 = 0x120,
+    Afsr0El1 = 0x128,
+    Afsr1El1 = 0x130,
+    EsrEl1 = 0x138,
+    MairEl1 = 0x140,
+    AmairEl1 = 0x148,
+    MdscrEl1 = 0x158,
+    SpsrEl1 = 0x160,
+    CntvCvalEl0 = 0x168,
+    CntvCtlEl0 = 0x170,
+    CntpCvalEl0 = 0x178,
+    CntpCtlEl0 = 0x180,
+    ScxtnumEl1 = 0x188,
+    TfsrEl1 = 0x190,
+    HfgrtrEl2 = 0x1B8,
+    HfgwtrEl2 = 0x1C0,
+    HfgitrEl2 = 0x1C8,
+    HdfgrtrEl2 = 0x1D0,
+    HdfgwtrEl2 = 0x1D8,
+    ZcrEl1 = 0x1E0,
+    HafgrtrEl2 = 0x1E8,
+    Ttbr0El1 = 0x200,
+    Ttbr1El1 = 0x210,
+    FarEl1 = 0x220,
+    ElrEl1 = 0x230,
+    SpEl1 = 0x240,
+    VbarEl1 = 0x250,
+    Tcr2El1 = 0x270,
+    Pire0El1 = 0x290,
+    PirEl1 = 0x2A0,
+    PorEl1 = 0x2A8,
+    IchHcrEl2 = 0x4C0,
+    IchVmcrEl2 = 0x4C8,
+    VdisrEl2 = 0x500,
+    VsesrEl2 = 0x508,
+}
+
+impl VncrReg {
+    /// Byte offset into the VNCR page
+    pub const fn offset(self) -> usize {
+        self as usize
+    }
+}
+
+/// Number of ICH_LR<n>_EL2 list registers in the VNCR page
+pub const VNCR_ICH_LRS: usize = 16;
+const ICH_LR0_OFFSET: usize = 0x400;
+
+/// Byte offset of ICH_LR<n>_EL2 in the VNCR page
+///
+/// # Panics
+///
+/// Panics if `n` is 16 or more.
+pub const fn ich_lr_offset(n: usize) -> usize {
+    assert!(n < VNCR_ICH_LRS);
+    ICH_LR0_OFFSET + n * 8
+}
+
+/// The page VNCR_EL2 points to, holding the redirected registers of a guest hypervisor
+///
+/// Indexed by [`VncrReg`]. The hypervisor reads the guest's state from here after a
+/// trap and loads the page before resuming it; bytes not backing a register are RES0.
+#[repr(C, align(4096))]
+#[derive(Clone)]
+pub struct VncrPage([u64; 512]);
+
+impl VncrPage {
+    /// Page with every register zero
+    pub const fn new() -> Self {
+        Self([0; 512])
+    }
+
+    /// Read a redirected register
+    pub const fn get(&self, reg: VncrReg) -> u64 {
+        self.0[reg.offset() / 8]
+    }
+
+    /// Write a redirected register
+    pub const fn set(&mut self, reg: VncrReg, value: u64) {
+        self.0[reg.offset() / 8] = value;
+    }
+
+    /// Read ICH_LR<n>_EL2, see [`ich_lr_offset`]
+    pub const fn ich_lr(&self, n: usize) -> u64 {
+        self.0[ich_lr_offset(n) / 8]
+    }
+
+    /// Write ICH_LR<n>_EL2, see [`ich_lr_offset`]
+    pub const fn set_ich_lr(&mut self, n: usize, value: u64) {
+        self.0[ich_lr_offset(n) / 8] = value;
+    }
+}
+
+impl Default for VncrPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<VncrReg> for VncrPage {
+    type Output = u64;
+
+    fn index(&self, reg: VncrReg) -> &u64 {
+        &self.0[reg.offset() / 8]
+    }
+}
+
+impl IndexMut<VncrReg> for VncrPage {
+    fn index_mut(&mut self, reg: VncrReg) -> &mut u64 {
+        &mut self.0[reg.offset() / 8]
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::{
+        asm::barrier::{SY, isb},
+        registers::{HCR_EL2, Readable, Writeable},
+    };
+
+    use super::{NvControl, VncrPage};
+    use crate::features::{Feature, is_supported as has_feature};
+
+    /// Check if the core implements HCR_EL2.NV and NV1 (FEAT_NV)
+    #[inline]
+    pub fn is_supported() -> bool {
+        has_feature(Feature::Nv)
+    }
+
+    /// Check if the core implements register redirection (FEAT_NV2)
+    #[inline]
+    pub fn is_nv2_supported() -> bool {
+        has_feature(Feature::Nv2)
+    }
+
+    /// Read the nested virtualization controls from HCR_EL2
+    pub fn control() -> NvControl {
+        NvControl::from_hcr(HCR_EL2.get())
+    }
+
+    /// Write the nested virtualization controls to HCR_EL2, leaving the other bits alone
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL2 before entering the guest hypervisor. With
+    /// [`redirect`](NvControl::redirect) set, VNCR_EL2 must point to a valid page, see
+    /// [`set_vncr`].
+    pub unsafe fn set_control(control: NvControl) {
+        HCR_EL2.set(control.apply(HCR_EL2.get()));
+        isb(SY);
+    }
+
+    /// Address of the VNCR page
+    pub fn vncr() -> usize {
+        // VNCR_EL2
+        crate::sysreg!(read "S3_4_C2_C2_0") as usize
+    }
+
+    /// Point VNCR_EL2 at `page`, requires FEAT_NV2
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL2. `page` must stay mapped at EL2 and must not be accessed
+    /// through other references while a guest hypervisor using it runs.
+    pub unsafe fn set_vncr(page: *mut VncrPage) {
+        // VNCR_EL2
+        unsafe { crate::sysreg!(write "S3_4_C2_C2_0", page as u64) };
+        isb(SY);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use access::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nv_control() {
+        let ctl = NvControl::new().nested(true).redirect(true);
+        assert_eq!(ctl.value(), 0x0000_2400_0000_0000);
+        assert!(ctl.is_nested() && ctl.is_redirected());
+
+        let hcr = ctl.non_vhe(true).apply(0x8000_0000 | 1 << 44);
+        assert_eq!(hcr, 0x0000_2c00_8000_0000);
+        assert_eq!(NvControl::from_hcr(hcr).non_vhe(false), ctl);
+    }
+
+    #[test]
+    fn test_vncr_page() {
+        let mut page = VncrPage::new();
+        page.set(VncrReg::HcrEl2, 0x8000_0000);
+        page[VncrReg::SctlrEl1] = 0x30d0_0800;
+        page.set_ich_lr(15, 0xa0);
+
+        assert_eq!(page.0[0x78 / 8], 0x8000_0000);
+        assert_eq!(page.0[0x110 / 8], 0x30d0_0800);
+        assert_eq!(page.0[0x478 / 8], 0xa0);
+        assert_eq!(page.get(VncrReg::SctlrEl1), 0x30d0_0800);
+        assert_eq!(page.ich_lr(15), 0xa0);
+        assert_eq!(VncrReg::VsesrEl2.offset(), 0x508);
+        assert_eq!(core::mem::align_of::<VncrPage>(), 4096);
+    }
+}
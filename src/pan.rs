@@ -0,0 +1,282 @@
+//! Privileged Access Never (FEAT_PAN) and unprivileged memory accesses.
+//!
+//! With PSTATE.PAN set, privileged loads and stores to memory accessible from EL0 fault,
+//! so the kernel cannot accidentally dereference user pointers. Access to user memory is
+//! made explicit either by clearing PAN for a scope with [`PanGuard`], or with
+//! [`read_user`] and [`write_user`], which use the LDTR/STTR instructions. These perform
+//! the access with EL0 permissions and are therefore allowed with PAN set, and they fault
+//! on memory EL0 cannot access even if EL1 could.
+//!
+//! With PSTATE.UAO set (FEAT_UAO), LDTR and STTR use the permissions of the current
+//! exception level instead, so the same accessors can be pointed at kernel memory, see
+//! [`enable_uao`].
+//!
+//! Without FEAT_PAN3, PAN only checks data accesses to memory readable or writable at EL0.
+//! An execute-only user mapping is then accessible to the kernel even with PAN set,
+//! SCTLR_EL1.EPAN extends PAN to memory executable at EL0, see [`set_epan`].
+
+use core::{arch::asm, marker::PhantomData, mem::MaybeUninit};
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{SCTLR_EL1, Writeable},
+};
+
+use crate::{
+    features::{Feature, is_supported as has_feature},
+    mmu::Sctlr,
+};
+
+const PAN_BIT: u64 = 1 << 22;
+const UAO_BIT: u64 = 1 << 23;
+
+/// Check if the core implements PAN (FEAT_PAN)
+#[inline]
+pub fn is_supported() -> bool {
+    has_feature(Feature::Pan)
+}
+
+/// Check if the core implements PSTATE.UAO (FEAT_UAO)
+#[inline]
+pub fn is_uao_supported() -> bool {
+    has_feature(Feature::Uao)
+}
+
+/// Check if the core implements SCTLR_EL1.EPAN (FEAT_PAN3)
+#[inline]
+pub fn is_epan_supported() -> bool {
+    has_feature(Feature::Pan3)
+}
+
+/// Set PSTATE.PAN, privileged accesses to EL0 memory fault
+#[inline]
+pub fn enable() {
+    unsafe { asm!(".arch_extension pan\nmsr pan, #1", options(nostack)) };
+}
+
+/// Clear PSTATE.PAN, privileged accesses to EL0 memory are allowed
+#[inline]
+pub fn disable() {
+    unsafe { asm!(".arch_extension pan\nmsr pan, #0", options(nostack)) };
+}
+
+/// Check if PSTATE.PAN is set
+#[inline]
+pub fn is_enabled() -> bool {
+    // PAN
+    crate::sysreg!(read "S3_0_C4_C2_3") & PAN_BIT != 0
+}
+
+/// Set PSTATE.UAO, LDTR and STTR at EL1 use EL1 permissions
+#[inline]
+pub fn enable_uao() {
+    // MSR UAO, #1, encoded as the assembler may not know FEAT_UAO
+    unsafe { asm!(".inst 0xd500417f", options(nostack)) };
+}
+
+/// Clear PSTATE.UAO, LDTR and STTR at EL1 use EL0 permissions
+#[inline]
+pub fn disable_uao() {
+    // MSR UAO, #0
+    unsafe { asm!(".inst 0xd500407f", options(nostack)) };
+}
+
+/// Check if PSTATE.UAO is set
+#[inline]
+pub fn is_uao_enabled() -> bool {
+    // UAO
+    crate::sysreg!(read "S3_0_C4_C2_4") & UAO_BIT != 0
+}
+
+/// Extend PAN to memory executable at EL0 (SCTLR_EL1.EPAN), requires FEAT_PAN3
+pub fn set_epan(enable: bool) {
+    SCTLR_EL1.set(Sctlr::read().epan(enable).value());
+    isb(SY);
+}
+
+/// Run `f` with PAN cleared, restoring the previous state afterwards
+#[inline]
+pub fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = PanGuard::new();
+    f()
+}
+
+/// Clears PAN while alive, restoring the saved state on drop
+///
+/// The guard is tied to the core it was created on and therefore neither `Send` nor `Sync`.
+/// PSTATE.PAN is saved and restored on exception entry and return, so an exception taken
+/// inside the scope does not observe the cleared state unless SCTLR_EL1.SPAN is set.
+#[must_use = "the previous state is restored when the guard is dropped"]
+pub struct PanGuard {
+    saved: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl PanGuard {
+    /// Clear PAN
+    #[inline]
+    pub fn new() -> Self {
+        let saved = is_enabled();
+        disable();
+        Self {
+            saved,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Whether PAN is set again on drop
+    pub fn saved(&self) -> bool {
+        self.saved
+    }
+}
+
+impl Default for PanGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PanGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.saved {
+            enable();
+        }
+    }
+}
+
+/// Read a `T` from user memory at `addr` with unprivileged loads (LDTR)
+///
+/// Naturally aligned values of 1, 2, 4 or 8 bytes are read with a single load, anything
+/// else piecewise.
+///
+/// # Safety
+///
+/// `addr` must be mapped and readable at EL0, otherwise the load faults. Every bit pattern
+/// must be a valid `T`, the memory is controlled by the user.
+pub unsafe fn read_user<T: Copy>(addr: usize) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+    let dst = value.as_mut_ptr() as *mut u8;
+    let mut offset = 0;
+    while offset < size_of::<T>() {
+        let src = addr + offset;
+        let len = chunk_len(src, size_of::<T>() - offset);
+        unsafe {
+            let dst = dst.add(offset);
+            match len {
+                8 => {
+                    let data: u64;
+                    asm!(
+                        "ldtr {}, [{}]",
+                        out(reg) data,
+                        in(reg) src,
+                        options(nostack, readonly, preserves_flags)
+                    );
+                    dst.cast::<u64>().write_unaligned(data);
+                }
+                4 => {
+                    let data: u32;
+                    asm!(
+                        "ldtr {:w}, [{}]",
+                        out(reg) data,
+                        in(reg) src,
+                        options(nostack, readonly, preserves_flags)
+                    );
+                    dst.cast::<u32>().write_unaligned(data);
+                }
+                2 => {
+                    let data: u32;
+                    asm!(
+                        "ldtrh {:w}, [{}]",
+                        out(reg) data,
+                        in(reg) src,
+                        options(nostack, readonly, preserves_flags)
+                    );
+                    dst.cast::<u16>().write_unaligned(data as u16);
+                }
+                _ => {
+                    let data: u32;
+                    asm!(
+                        "ldtrb {:w}, [{}]",
+                        out(reg) data,
+                        in(reg) src,
+                        options(nostack, readonly, preserves_flags)
+                    );
+                    dst.write(data as u8);
+                }
+            }
+        }
+        offset += len;
+    }
+    unsafe { value.assume_init() }
+}
+
+/// Write `value` to user memory at `addr` with unprivileged stores (STTR)
+///
+/// Naturally aligned values of 1, 2, 4 or 8 bytes are written with a single store,
+/// anything else piecewise.
+///
+/// # Safety
+///
+/// `addr` must be mapped and writable at EL0, otherwise the store faults. `T` must not
+/// contain padding: every byte of `value` is read and copied out, and padding bytes are
+/// uninitialized and may hold kernel stack contents.
+pub unsafe fn write_user<T: Copy>(addr: usize, value: T) {
+    let src = &value as *const T as *const u8;
+    let mut offset = 0;
+    while offset < size_of::<T>() {
+        let dst = addr + offset;
+        let len = chunk_len(dst, size_of::<T>() - offset);
+        unsafe {
+            let src = src.add(offset);
+            match len {
+                8 => {
+                    let data = src.cast::<u64>().read_unaligned();
+                    asm!(
+                        "sttr {}, [{}]",
+                        in(reg) data,
+                        in(reg) dst,
+                        options(nostack, preserves_flags)
+                    );
+                }
+                4 => {
+                    let data = src.cast::<u32>().read_unaligned();
+                    asm!(
+                        "sttr {:w}, [{}]",
+                        in(reg) data,
+                        in(reg) dst,
+                        options(nostack, preserves_flags)
+                    );
+                }
+                2 => {
+                    let data = src.cast::<u16>().read_unaligned() as u32;
+                    asm!(
+                        "sttrh {:w}, [{}]",
+                        in(reg) data,
+                        in(reg) dst,
+                        options(nostack, preserves_flags)
+                    );
+                }
+                _ => {
+                    let data = src.read() as u32;
+                    asm!(
+                        "sttrb {:w}, [{}]",
+                        in(reg) data,
+                        in(reg) dst,
+                        options(nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+        offset += len;
+    }
+}
+
+/// Largest naturally aligned access of at most `remaining` bytes at `addr`
+const fn chunk_len(addr: usize, remaining: usize) -> usize {
+    let mut len = 8;
+    while len > 1 && (remaining < len || !addr.is_multiple_of(len)) {
+        len /= 2;
+    }
+    len
+}
@@ -0,0 +1,116 @@
+use core::arch::asm;
+
+macro_rules! pac_op {
+    ($(#[$attr:meta])* $name:ident, $insn:literal) => {
+        $(#[$attr])*
+        #[inline(always)]
+        pub fn $name(ptr: usize, modifier: u64) -> usize {
+            let mut ptr = ptr;
+            unsafe {
+                asm!(
+                    concat!(".arch_extension pauth\n", $insn, " {}, {}"),
+                    inout(reg) ptr,
+                    in(reg) modifier,
+                    options(nomem, nostack, preserves_flags)
+                )
+            };
+            ptr
+        }
+    };
+}
+
+macro_rules! xpac_op {
+    ($(#[$attr:meta])* $name:ident, $insn:literal) => {
+        $(#[$attr])*
+        #[inline(always)]
+        pub fn $name(ptr: usize) -> usize {
+            let mut ptr = ptr;
+            unsafe {
+                asm!(
+                    concat!(".arch_extension pauth\n", $insn, " {}"),
+                    inout(reg) ptr,
+                    options(nomem, nostack, preserves_flags)
+                )
+            };
+            ptr
+        }
+    };
+}
+
+pac_op!(
+    /// Sign the code pointer `ptr` with `modifier` and key IA (PACIA)
+    ///
+    /// Like the other PAC instructions outside the hint space, PACIA is UNDEFINED without
+    /// FEAT_PAuth, see [`is_supported`](super::is_supported). With the key disabled in
+    /// SCTLR_EL1 the pointer is returned unchanged.
+    pacia,
+    "pacia"
+);
+pac_op!(
+    /// Sign the code pointer `ptr` with `modifier` and key IB (PACIB)
+    pacib,
+    "pacib"
+);
+pac_op!(
+    /// Sign the data pointer `ptr` with `modifier` and key DA (PACDA)
+    pacda,
+    "pacda"
+);
+pac_op!(
+    /// Sign the data pointer `ptr` with `modifier` and key DB (PACDB)
+    pacdb,
+    "pacdb"
+);
+pac_op!(
+    /// Authenticate the code pointer `ptr` signed by [`pacia`] with `modifier` (AUTIA)
+    ///
+    /// Returns the pointer without its PAC on success. On failure the pointer is made
+    /// invalid so that using it faults, or with FEAT_FPAC the instruction itself generates
+    /// an exception.
+    autia,
+    "autia"
+);
+pac_op!(
+    /// Authenticate the code pointer `ptr` signed by [`pacib`] with `modifier` (AUTIB)
+    autib,
+    "autib"
+);
+pac_op!(
+    /// Authenticate the data pointer `ptr` signed by [`pacda`] with `modifier` (AUTDA)
+    ///
+    /// See [`autia`] for the behaviour on failure.
+    autda,
+    "autda"
+);
+pac_op!(
+    /// Authenticate the data pointer `ptr` signed by [`pacdb`] with `modifier` (AUTDB)
+    autdb,
+    "autdb"
+);
+xpac_op!(
+    /// Remove the PAC from the code pointer `ptr` without authenticating it (XPACI)
+    xpaci,
+    "xpaci"
+);
+xpac_op!(
+    /// Remove the PAC from the data pointer `ptr` without authenticating it (XPACD)
+    xpacd,
+    "xpacd"
+);
+
+/// Remove the PAC from the code pointer `ptr`, e.g. a return address found on the stack
+///
+/// Uses XPACLRI, which is in the hint space and leaves `ptr` unchanged on cores without
+/// pointer authentication, so unlike [`xpaci`] it can be used unconditionally.
+#[inline(always)]
+pub fn strip_pac(ptr: usize) -> usize {
+    let mut ptr = ptr;
+    unsafe {
+        asm!(
+            ".arch_extension pauth\nxpaclri",
+            inout("x30") ptr,
+            options(nomem, nostack, preserves_flags)
+        )
+    };
+    ptr
+}
@@ -0,0 +1,147 @@
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{SCTLR_EL1, Writeable},
+};
+
+use super::{Key, KeyValue, PauthKeys, PauthSupport};
+use crate::{
+    features::{Feature, cpu_features, is_supported as has_feature},
+    mmu::Sctlr,
+};
+
+/// Check if the core implements pointer authentication
+#[inline]
+pub fn is_supported() -> bool {
+    has_feature(Feature::PAuth)
+}
+
+/// Implemented flavour of pointer authentication, `None` if there is none
+pub fn support() -> Option<PauthSupport> {
+    PauthSupport::from_features(&cpu_features())
+}
+
+/// Read the value of `key` (AP*KeyLo_EL1, AP*KeyHi_EL1)
+pub fn read_key(key: Key) -> KeyValue {
+    let (lo, hi) = match key {
+        Key::IA => (
+            crate::sysreg!(read "S3_0_C2_C1_0"),
+            crate::sysreg!(read "S3_0_C2_C1_1"),
+        ),
+        Key::IB => (
+            crate::sysreg!(read "S3_0_C2_C1_2"),
+            crate::sysreg!(read "S3_0_C2_C1_3"),
+        ),
+        Key::DA => (
+            crate::sysreg!(read "S3_0_C2_C2_0"),
+            crate::sysreg!(read "S3_0_C2_C2_1"),
+        ),
+        Key::DB => (
+            crate::sysreg!(read "S3_0_C2_C2_2"),
+            crate::sysreg!(read "S3_0_C2_C2_3"),
+        ),
+        Key::GA => (
+            crate::sysreg!(read "S3_0_C2_C3_0"),
+            crate::sysreg!(read "S3_0_C2_C3_1"),
+        ),
+    };
+    KeyValue { lo, hi }
+}
+
+/// Write both halves of `key`, the caller issues the ISB
+#[inline(always)]
+unsafe fn write_key(key: Key, value: KeyValue) {
+    unsafe {
+        match key {
+            Key::IA => {
+                crate::sysreg!(write "S3_0_C2_C1_0", value.lo);
+                crate::sysreg!(write "S3_0_C2_C1_1", value.hi);
+            }
+            Key::IB => {
+                crate::sysreg!(write "S3_0_C2_C1_2", value.lo);
+                crate::sysreg!(write "S3_0_C2_C1_3", value.hi);
+            }
+            Key::DA => {
+                crate::sysreg!(write "S3_0_C2_C2_0", value.lo);
+                crate::sysreg!(write "S3_0_C2_C2_1", value.hi);
+            }
+            Key::DB => {
+                crate::sysreg!(write "S3_0_C2_C2_2", value.lo);
+                crate::sysreg!(write "S3_0_C2_C2_3", value.hi);
+            }
+            Key::GA => {
+                crate::sysreg!(write "S3_0_C2_C3_0", value.lo);
+                crate::sysreg!(write "S3_0_C2_C3_1", value.hi);
+            }
+        }
+    }
+}
+
+/// Replace the value of `key`, followed by an ISB so following PAC instructions use it
+///
+/// # Safety
+///
+/// Pointers signed with the old key no longer authenticate. The caller must not return
+/// through a frame whose return address was signed with the old key, and must not use
+/// other pointers signed with it.
+#[inline(always)]
+pub unsafe fn set_key(key: Key, value: KeyValue) {
+    unsafe { write_key(key, value) };
+    isb(SY);
+}
+
+impl PauthKeys {
+    /// Read all five keys of the executing core
+    pub fn read() -> Self {
+        Self {
+            ia: read_key(Key::IA),
+            ib: read_key(Key::IB),
+            da: read_key(Key::DA),
+            db: read_key(Key::DB),
+            ga: read_key(Key::GA),
+        }
+    }
+
+    /// Install all five keys, followed by a single ISB
+    ///
+    /// # Safety
+    ///
+    /// See [`set_key`], this replaces every key.
+    #[inline(always)]
+    pub unsafe fn install(&self) {
+        for key in Key::ALL {
+            unsafe { write_key(key, self.get(key)) };
+        }
+        isb(SY);
+    }
+}
+
+/// Enable pointer authentication with `key` at EL0 and EL1 (SCTLR_EL1.EnIA/EnIB/EnDA/EnDB)
+///
+/// # Safety
+///
+/// Functions compiled with return address signing skip the signing while their key is
+/// disabled, but authenticate once it is enabled. The caller must not return to a frame
+/// that was entered with `key` disabled.
+#[inline(always)]
+pub unsafe fn enable(key: Key) {
+    SCTLR_EL1.set(Sctlr::read().pointer_auth(key, true).value());
+    isb(SY);
+}
+
+/// Disable pointer authentication with `key`, the PAC instructions leave pointers unchanged
+///
+/// # Safety
+///
+/// Signed return addresses are no longer stripped on authentication. The caller must not
+/// return to a frame that was entered with `key` enabled.
+#[inline(always)]
+pub unsafe fn disable(key: Key) {
+    SCTLR_EL1.set(Sctlr::read().pointer_auth(key, false).value());
+    isb(SY);
+}
+
+/// Check if pointer authentication with `key` is enabled in SCTLR_EL1
+#[inline]
+pub fn is_enabled(key: Key) -> bool {
+    Sctlr::read().is_pointer_auth_enabled(key)
+}
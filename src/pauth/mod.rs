@@ -0,0 +1,247 @@
+//! Pointer Authentication (FEAT_PAuth).
+//!
+//! A pointer authentication code (PAC) is computed from a pointer, a 64-bit modifier and
+//! one of five 128-bit keys, and stored in the unused upper bits of the pointer. The
+//! instruction keys (IA, IB) sign code pointers such as return addresses, the data keys
+//! (DA, DB) data pointers and the generic key (GA) computes a PAC over arbitrary data.
+//!
+//! The keys are shared by EL0 and EL1, a kernel installs the keys of a task when switching
+//! to it with [`PauthKeys::install`]. The instruction and data keys only take effect while
+//! enabled in SCTLR_EL1 with [`enable`], otherwise the PAC instructions leave pointers
+//! unchanged.
+//!
+//! [`pacia`], [`autia`] and the related functions sign and authenticate pointers with a
+//! modifier, [`strip_pac`] removes the PAC from a return address for unwinding.
+//!
+//! The implemented algorithm and the behaviour of failed authentications differ between
+//! cores, [`PauthSupport`] decodes them from the ID registers.
+
+use crate::features::{CpuFeatures, IdReg};
+
+#[cfg(target_arch = "aarch64")]
+mod intrinsics;
+#[cfg(target_arch = "aarch64")]
+mod keys;
+
+#[cfg(target_arch = "aarch64")]
+pub use intrinsics::*;
+#[cfg(target_arch = "aarch64")]
+pub use keys::*;
+
+/// A pointer authentication key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// Instruction key A (APIAKey_EL1)
+    IA,
+    /// Instruction key B (APIBKey_EL1)
+    IB,
+    /// Data key A (APDAKey_EL1)
+    DA,
+    /// Data key B (APDBKey_EL1)
+    DB,
+    /// Generic key (APGAKey_EL1)
+    GA,
+}
+
+impl Key {
+    pub const ALL: [Key; 5] = [Key::IA, Key::IB, Key::DA, Key::DB, Key::GA];
+}
+
+/// The 128-bit value of a key, split into the Hi and Lo registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct KeyValue {
+    pub lo: u64,
+    pub hi: u64,
+}
+
+impl KeyValue {
+    pub const fn new(lo: u64, hi: u64) -> Self {
+        Self { lo, hi }
+    }
+
+    pub const fn from_u128(value: u128) -> Self {
+        Self {
+            lo: value as u64,
+            hi: (value >> 64) as u64,
+        }
+    }
+
+    pub const fn as_u128(self) -> u128 {
+        ((self.hi as u128) << 64) | self.lo as u128
+    }
+}
+
+/// The keys of one task, see [`PauthKeys::install`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PauthKeys {
+    pub ia: KeyValue,
+    pub ib: KeyValue,
+    pub da: KeyValue,
+    pub db: KeyValue,
+    pub ga: KeyValue,
+}
+
+impl PauthKeys {
+    /// Value of `key`
+    pub const fn get(&self, key: Key) -> KeyValue {
+        match key {
+            Key::IA => self.ia,
+            Key::IB => self.ib,
+            Key::DA => self.da,
+            Key::DB => self.db,
+            Key::GA => self.ga,
+        }
+    }
+
+    /// Change the value of `key`
+    pub fn set(&mut self, key: Key, value: KeyValue) {
+        match key {
+            Key::IA => self.ia = value,
+            Key::IB => self.ib = value,
+            Key::DA => self.da = value,
+            Key::DB => self.db = value,
+            Key::GA => self.ga = value,
+        }
+    }
+}
+
+/// PAC algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PauthAlgorithm {
+    /// QARMA5 (APA, GPA)
+    Qarma5,
+    /// QARMA3 (APA3, GPA3)
+    Qarma3,
+    /// IMPLEMENTATION DEFINED algorithm (API, GPI)
+    ImplementationDefined,
+}
+
+/// Pointer authentication features, the value of the APA, API or APA3 field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PauthLevel {
+    /// FEAT_PAuth, the PAC of a failed authentication is corrupted by flipping a bit
+    PAuth = 1,
+    /// FEAT_EPAC, the PAC field of a failed authentication is cleared
+    Epac = 2,
+    /// FEAT_PAuth2, the PAC is XORed into the pointer and failures are detected on use
+    PAuth2 = 3,
+    /// FEAT_FPAC, a failed AUT* instruction generates an exception
+    Fpac = 4,
+    /// FEAT_FPACCOMBINE, also for the combined instructions such as RETAA
+    FpacCombine = 5,
+}
+
+impl PauthLevel {
+    const fn from_field(value: u8) -> Option<Self> {
+        match value {
+            0 => None,
+            1 => Some(Self::PAuth),
+            2 => Some(Self::Epac),
+            3 => Some(Self::PAuth2),
+            4 => Some(Self::Fpac),
+            _ => Some(Self::FpacCombine),
+        }
+    }
+}
+
+/// Implemented flavour of pointer authentication
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PauthSupport {
+    /// Algorithm of the address keys (IA, IB, DA, DB)
+    pub algorithm: PauthAlgorithm,
+    pub level: PauthLevel,
+    /// Algorithm of the generic key, `None` if PACGA is not implemented
+    pub generic: Option<PauthAlgorithm>,
+}
+
+impl PauthSupport {
+    /// Decode from the ID registers, `None` without pointer authentication
+    ///
+    /// Only one algorithm is implemented, at most one of the APA, API and APA3 fields is
+    /// non-zero.
+    pub const fn from_features(features: &CpuFeatures) -> Option<Self> {
+        let apa = features.field(IdReg::Isar1, 4);
+        let api = features.field(IdReg::Isar1, 8);
+        let apa3 = features.field(IdReg::Isar2, 12);
+        let (algorithm, field) = if apa != 0 {
+            (PauthAlgorithm::Qarma5, apa)
+        } else if apa3 != 0 {
+            (PauthAlgorithm::Qarma3, apa3)
+        } else {
+            (PauthAlgorithm::ImplementationDefined, api)
+        };
+        let level = match PauthLevel::from_field(field) {
+            Some(level) => level,
+            None => return None,
+        };
+
+        let generic = if features.field(IdReg::Isar1, 24) != 0 {
+            Some(PauthAlgorithm::Qarma5)
+        } else if features.field(IdReg::Isar2, 8) != 0 {
+            Some(PauthAlgorithm::Qarma3)
+        } else if features.field(IdReg::Isar1, 28) != 0 {
+            Some(PauthAlgorithm::ImplementationDefined)
+        } else {
+            None
+        };
+
+        Some(Self {
+            algorithm,
+            level,
+            generic,
+        })
+    }
+
+    /// Check if FEAT_PAuth2 is implemented
+    pub const fn has_pauth2(&self) -> bool {
+        self.level as u8 >= PauthLevel::PAuth2 as u8
+    }
+
+    /// Check if failed AUT* instructions generate an exception (FEAT_FPAC)
+    pub const fn has_fpac(&self) -> bool {
+        self.level as u8 >= PauthLevel::Fpac as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_value() {
+        let value = KeyValue::from_u128(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+        assert_eq!(value.hi, 0x0123_4567_89ab_cdef);
+        assert_eq!(value.lo, 0xfedc_ba98_7654_3210);
+        assert_eq!(value.as_u128(), 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+
+        let mut keys = PauthKeys::default();
+        keys.set(Key::DB, value);
+        assert_eq!(keys.get(Key::DB), value);
+        assert_eq!(keys.get(Key::DA), KeyValue::default());
+    }
+
+    #[test]
+    fn test_support() {
+        let mut regs = [0; 9];
+        assert!(PauthSupport::from_features(&CpuFeatures::from_raw(regs)).is_none());
+
+        // ISAR1.APA = 5, ISAR1.GPA = 1
+        regs[IdReg::Isar1 as usize] = (1 << 24) | (5 << 4);
+        let support = PauthSupport::from_features(&CpuFeatures::from_raw(regs)).unwrap();
+        assert_eq!(support.algorithm, PauthAlgorithm::Qarma5);
+        assert_eq!(support.level, PauthLevel::FpacCombine);
+        assert_eq!(support.generic, Some(PauthAlgorithm::Qarma5));
+        assert!(support.has_pauth2());
+        assert!(support.has_fpac());
+
+        // ISAR2.APA3 = 3, no PACGA
+        regs[IdReg::Isar1 as usize] = 0;
+        regs[IdReg::Isar2 as usize] = 3 << 12;
+        let support = PauthSupport::from_features(&CpuFeatures::from_raw(regs)).unwrap();
+        assert_eq!(support.algorithm, PauthAlgorithm::Qarma3);
+        assert_eq!(support.level, PauthLevel::PAuth2);
+        assert_eq!(support.generic, None);
+        assert!(support.has_pauth2());
+        assert!(!support.has_fpac());
+    }
+}
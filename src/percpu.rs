@@ -0,0 +1,99 @@
+//! Per-CPU data addressed through the thread ID register.
+//!
+//! Each core points TPIDR_EL1 (TPIDR_EL2 when running at EL2) at its own area once during
+//! bring-up, afterwards the area of the executing core is found by reading CurrentEL and
+//! then the matching thread ID register.
+
+use core::{cell::UnsafeCell, mem::MaybeUninit};
+
+use crate::el::{ExceptionLevel, current_el, read_tpidr, write_tpidr};
+
+/// EL2 and EL1 keep their own per-CPU base, EL3 is not supported
+fn percpu_el() -> ExceptionLevel {
+    match current_el() {
+        ExceptionLevel::EL2 => ExceptionLevel::EL2,
+        _ => ExceptionLevel::EL1,
+    }
+}
+
+/// Point the thread ID register of the current exception level at `base`
+///
+/// # Safety
+///
+/// Overwrites TPIDR_EL1 or TPIDR_EL2, which may already be used, e.g. by [`PerCpu`].
+pub unsafe fn set_percpu_base(base: *mut u8) {
+    unsafe { write_tpidr(percpu_el(), base as u64) };
+}
+
+/// Get the per-CPU base of the executing core, null if not set up
+///
+/// A direct read of the register is ordered after an earlier write on the same core, no
+/// barrier is needed after [`set_percpu_base`].
+#[inline]
+pub fn percpu_base() -> *mut u8 {
+    read_tpidr(percpu_el()) as *mut u8
+}
+
+/// Storage for one `T` per core
+///
+/// Only one `PerCpu` per system can be installed, it owns the thread ID register. `T` is
+/// typically a struct of everything the system keeps per core. Other cores may access an
+/// instance through [`get`](Self::get), so `T` must be `Sync` for remote access.
+pub struct PerCpu<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+unsafe impl<T: Sync, const N: usize> Sync for PerCpu<T, N> {}
+
+impl<T, const N: usize> PerCpu<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    /// Initialize the slot of `cpu` with `value` and point the executing core at it
+    ///
+    /// # Safety
+    ///
+    /// Must be called once on each core with a distinct `cpu < N` before that core uses
+    /// [`current`](Self::current). See [`set_percpu_base`].
+    pub unsafe fn init_current(&'static self, cpu: usize, value: T) {
+        let slot = self.slots[cpu].get();
+        unsafe {
+            (*slot).write(value);
+            set_percpu_base(slot.cast());
+        }
+    }
+
+    /// Get the instance of the executing core
+    ///
+    /// # Panics
+    ///
+    /// If the executing core has not been initialized with this container.
+    #[inline]
+    pub fn current(&'static self) -> &'static T {
+        let base = percpu_base().cast::<UnsafeCell<MaybeUninit<T>>>();
+        let range = self.slots.as_ptr_range();
+        assert!(
+            range.contains(&base.cast_const()),
+            "per-CPU area not initialized on this core"
+        );
+        unsafe { (*base).get().cast::<T>().as_ref().unwrap_unchecked() }
+    }
+
+    /// Get the instance of `cpu`
+    ///
+    /// # Safety
+    ///
+    /// `cpu` must have been initialized with [`init_current`](Self::init_current).
+    pub unsafe fn get(&'static self, cpu: usize) -> &'static T {
+        unsafe { (*self.slots[cpu].get()).assume_init_ref() }
+    }
+}
+
+impl<T, const N: usize> Default for PerCpu<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,105 @@
+use aarch64_cpu::asm::barrier::{SY, isb};
+
+use super::counter::{CYCLE_COUNTER, PMCR_E, counter_count};
+
+/// Enable bits of all event counters and the cycle counter
+const ALL_COUNTERS: u64 = CYCLE_COUNTER | 0x7FFF_FFFF;
+
+/// PMU state of a task or vCPU, switched with [`PmuContext::save`] and
+/// [`PmuContext::restore`]
+///
+/// Covers PMCR_EL0, the counter, interrupt and overflow bits, the cycle counter and its
+/// filter, PMUSERENR_EL0 and the type and count of every implemented event counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmuContext {
+    pmcr: u64,
+    cntenset: u64,
+    intenset: u64,
+    ovsset: u64,
+    ccfiltr: u64,
+    ccntr: u64,
+    userenr: u64,
+    evtyper: [u64; 31],
+    evcntr: [u64; 31],
+}
+
+impl PmuContext {
+    /// State with the PMU disabled and every counter cleared, for a task that has not used
+    /// the PMU yet
+    pub const fn new() -> Self {
+        Self {
+            pmcr: 0,
+            cntenset: 0,
+            intenset: 0,
+            ovsset: 0,
+            ccfiltr: 0,
+            ccntr: 0,
+            userenr: 0,
+            evtyper: [0; 31],
+            evcntr: [0; 31],
+        }
+    }
+
+    /// Save the PMU state of the executing core
+    ///
+    /// Counting is stopped first (PMCR_EL0.E cleared) so the saved counts are consistent,
+    /// and stays stopped until a context is restored.
+    pub fn save() -> Self {
+        let pmcr = crate::sysreg!(read "pmcr_el0");
+        unsafe { crate::sysreg!(write "pmcr_el0", pmcr & !PMCR_E) };
+        isb(SY);
+
+        let mut ctx = Self {
+            pmcr,
+            cntenset: crate::sysreg!(read "pmcntenset_el0"),
+            intenset: crate::sysreg!(read "pmintenset_el1"),
+            ovsset: crate::sysreg!(read "pmovsset_el0"),
+            ccfiltr: crate::sysreg!(read "pmccfiltr_el0"),
+            ccntr: crate::sysreg!(read "pmccntr_el0"),
+            userenr: crate::sysreg!(read "pmuserenr_el0"),
+            ..Self::new()
+        };
+        for n in 0..counter_count() {
+            ctx.evtyper[n] = pmu_indexed!(read "pmevtyper", n);
+            ctx.evcntr[n] = pmu_indexed!(read "pmevcntr", n);
+        }
+        ctx
+    }
+
+    /// Load this state into the PMU of the executing core, replacing the current one
+    ///
+    /// PMCR_EL0 is written last, so counting resumes only once everything is in place.
+    pub fn restore(&self) {
+        let pmcr = crate::sysreg!(read "pmcr_el0");
+        unsafe {
+            crate::sysreg!(write "pmcr_el0", pmcr & !PMCR_E);
+            crate::sysreg!(write "pmcntenclr_el0", ALL_COUNTERS);
+            crate::sysreg!(write "pmintenclr_el1", ALL_COUNTERS);
+            crate::sysreg!(write "pmovsclr_el0", ALL_COUNTERS);
+        }
+        isb(SY);
+
+        for n in 0..counter_count() {
+            pmu_indexed!(write "pmevtyper", n, self.evtyper[n]);
+            pmu_indexed!(write "pmevcntr", n, self.evcntr[n]);
+        }
+        unsafe {
+            crate::sysreg!(write "pmccfiltr_el0", self.ccfiltr);
+            crate::sysreg!(write "pmccntr_el0", self.ccntr);
+            crate::sysreg!(write "pmuserenr_el0", self.userenr);
+            crate::sysreg!(write "pmovsset_el0", self.ovsset);
+            crate::sysreg!(write "pmintenset_el1", self.intenset);
+            crate::sysreg!(write "pmcntenset_el0", self.cntenset);
+        }
+        isb(SY);
+
+        unsafe { crate::sysreg!(write "pmcr_el0", self.pmcr) };
+        isb(SY);
+    }
+}
+
+impl Default for PmuContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
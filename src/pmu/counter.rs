@@ -0,0 +1,209 @@
+use aarch64_cpu::asm::barrier::{SY, isb};
+
+use super::{OverflowStatus, PmuEvent};
+
+pub(super) const PMCR_E: u64 = 1 << 0;
+const PMCR_P: u64 = 1 << 1;
+const PMCR_C: u64 = 1 << 2;
+const PMCR_LC: u64 = 1 << 6;
+const PMCR_N_SHIFT: u64 = 11;
+
+/// Bit of the cycle counter in PMCNTENSET_EL0 and the other per-counter registers
+pub(super) const CYCLE_COUNTER: u64 = 1 << 31;
+
+/// Number of event counters implemented (PMCR_EL0.N), at most 31
+#[inline]
+pub fn counter_count() -> usize {
+    ((crate::sysreg!(read "pmcr_el0") >> PMCR_N_SHIFT) & 0x1F) as usize
+}
+
+/// Enable the counters that are individually enabled (PMCR_EL0.E)
+///
+/// The cycle counter is switched to 64-bit overflow (PMCR_EL0.LC).
+pub fn enable() {
+    let pmcr = crate::sysreg!(read "pmcr_el0");
+    unsafe { crate::sysreg!(write "pmcr_el0", pmcr | PMCR_E | PMCR_LC) };
+    isb(SY);
+}
+
+/// Stop all counters, they keep their values
+pub fn disable() {
+    let pmcr = crate::sysreg!(read "pmcr_el0");
+    unsafe { crate::sysreg!(write "pmcr_el0", pmcr & !PMCR_E) };
+    isb(SY);
+}
+
+/// Zero all event counters and the cycle counter
+pub fn reset() {
+    let pmcr = crate::sysreg!(read "pmcr_el0");
+    unsafe { crate::sysreg!(write "pmcr_el0", pmcr | PMCR_P | PMCR_C) };
+    isb(SY);
+}
+
+/// Start the cycle counter counting at EL0 and EL1
+pub fn enable_cycle_counter() {
+    unsafe { crate::sysreg!(write "pmccfiltr_el0", 0) };
+    unsafe { crate::sysreg!(write "pmcntenset_el0", CYCLE_COUNTER) };
+    isb(SY);
+}
+
+/// Stop the cycle counter
+pub fn disable_cycle_counter() {
+    unsafe { crate::sysreg!(write "pmcntenclr_el0", CYCLE_COUNTER) };
+    isb(SY);
+}
+
+/// Current cycle count (PMCCNTR_EL0)
+#[inline]
+pub fn cycles() -> u64 {
+    crate::sysreg!(read "pmccntr_el0")
+}
+
+/// Run `f` and count the cycles it took, returns its result and the cycle count
+///
+/// The counter reads are fenced with ISBs, so `f` starts after the first read and has
+/// completed before the second. Enables the PMU and the cycle counter. The count includes
+/// the fencing, a few tens of cycles on typical cores, and any interrupt taken meanwhile.
+///
+/// ```ignore
+/// let (sum, cycles) = measure_cycles(|| data.iter().sum::<u64>());
+/// ```
+#[inline]
+pub fn measure_cycles<R>(f: impl FnOnce() -> R) -> (R, u64) {
+    enable();
+    enable_cycle_counter();
+    isb(SY);
+    let start = cycles();
+    isb(SY);
+    let result = f();
+    isb(SY);
+    let end = cycles();
+    (result, end.wrapping_sub(start))
+}
+
+/// Raise the PMU interrupt when the cycle counter overflows
+pub fn set_cycle_overflow_interrupt(enable: bool) {
+    if enable {
+        unsafe { crate::sysreg!(write "pmintenset_el1", CYCLE_COUNTER) };
+    } else {
+        unsafe { crate::sysreg!(write "pmintenclr_el1", CYCLE_COUNTER) };
+    }
+    isb(SY);
+}
+
+/// Counters whose overflow flag is set (PMOVSSET_EL0)
+#[inline]
+pub fn overflow_status() -> OverflowStatus {
+    OverflowStatus::from_value(crate::sysreg!(read "pmovsset_el0") as u32)
+}
+
+/// Clear the overflow flags in `status`, deasserting the interrupt once none is left
+/// (PMOVSCLR_EL0)
+///
+/// Clearing only the flags that were read avoids losing an overflow that happened since.
+#[inline]
+pub fn clear_overflow(status: OverflowStatus) {
+    unsafe { crate::sysreg!(write "pmovsclr_el0", status.value() as u64) };
+    isb(SY);
+}
+
+/// A programmable event counter (PMEVCNTR<n>_EL0) configured for an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventCounter {
+    slot: usize,
+    event: PmuEvent,
+}
+
+impl EventCounter {
+    /// Count `event` at EL0 and EL1 on counter `slot`, starting from zero
+    ///
+    /// Returns `None` if the core has no counter `slot`, see [`counter_count`]. The counter
+    /// runs while the PMU is enabled.
+    pub fn configure(slot: usize, event: PmuEvent) -> Option<Self> {
+        if slot >= counter_count() {
+            return None;
+        }
+        let counter = Self { slot, event };
+        counter.disable();
+        pmu_indexed!(write "pmevtyper", slot, event.number() as u64);
+        counter.set(0);
+        counter.enable();
+        Some(counter)
+    }
+
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    pub fn event(&self) -> PmuEvent {
+        self.event
+    }
+
+    /// Current count
+    #[inline]
+    pub fn read(&self) -> u64 {
+        pmu_indexed!(read "pmevcntr", self.slot)
+    }
+
+    /// Overwrite the count
+    #[inline]
+    pub fn set(&self, value: u64) {
+        pmu_indexed!(write "pmevcntr", self.slot, value);
+    }
+
+    /// Resume counting (PMCNTENSET_EL0)
+    pub fn enable(&self) {
+        unsafe { crate::sysreg!(write "pmcntenset_el0", 1 << self.slot) };
+        isb(SY);
+    }
+
+    /// Stop counting, the count is kept (PMCNTENCLR_EL0)
+    pub fn disable(&self) {
+        unsafe { crate::sysreg!(write "pmcntenclr_el0", 1 << self.slot) };
+        isb(SY);
+    }
+
+    /// Raise the PMU interrupt when the counter overflows (PMINTENSET_EL1)
+    pub fn set_overflow_interrupt(&self, enable: bool) {
+        if enable {
+            unsafe { crate::sysreg!(write "pmintenset_el1", 1 << self.slot) };
+        } else {
+            unsafe { crate::sysreg!(write "pmintenclr_el1", 1 << self.slot) };
+        }
+        isb(SY);
+    }
+
+    /// Overflow, and raise the interrupt if enabled, after `period` more events
+    ///
+    /// Assumes 32-bit event counters (PMCR_EL0.LP clear). Call again from the interrupt
+    /// handler to take the next sample.
+    #[inline]
+    pub fn set_sample_period(&self, period: u32) {
+        self.set(period.wrapping_neg() as u64);
+    }
+}
+
+const PMUSERENR_EN: u64 = 1 << 0;
+const PMUSERENR_SW: u64 = 1 << 1;
+const PMUSERENR_CR: u64 = 1 << 2;
+const PMUSERENR_ER: u64 = 1 << 3;
+
+/// Let EL0 use the PMU (PMUSERENR_EL0)
+///
+/// With `read_only`, EL0 may read the cycle and event counters but not reconfigure them,
+/// enough for user space benchmarks. Otherwise EL0 gets full access to the PMU registers.
+pub fn allow_el0_access(read_only: bool) {
+    let value = if read_only {
+        PMUSERENR_CR | PMUSERENR_ER
+    } else {
+        PMUSERENR_EN | PMUSERENR_SW | PMUSERENR_CR | PMUSERENR_ER
+    };
+    unsafe { crate::sysreg!(write "pmuserenr_el0", value) };
+    isb(SY);
+}
+
+/// Trap all EL0 accesses to the PMU
+pub fn deny_el0_access() {
+    unsafe { crate::sysreg!(write "pmuserenr_el0", 0) };
+    isb(SY);
+}
@@ -0,0 +1,98 @@
+use super::{EventCounter, MEASURE_MAX_EVENTS, Measurement, PmuEvent, counter_count};
+
+/// Counts cycles and up to [`MEASURE_MAX_EVENTS`] events over a scope
+///
+/// The events are programmed on event counters 0 and up, replacing whatever they were
+/// counting, events beyond [`counter_count`] are not measured. The PMU and the cycle
+/// counter are enabled and left enabled.
+///
+/// ```ignore
+/// let measure = Measure::start(&[PmuEvent::InstRetired, PmuEvent::L1dCacheRefill]);
+/// work();
+/// let m = measure.finish();
+/// // Or write the result when the scope ends
+/// let mut m = Measurement::default();
+/// {
+///     let _measure = Measure::start_into(&[PmuEvent::InstRetired], &mut m);
+///     work();
+/// }
+/// ```
+#[must_use = "the measurement is taken when the guard is finished or dropped"]
+pub struct Measure<'a> {
+    counters: [Option<EventCounter>; MEASURE_MAX_EVENTS],
+    start: [u64; MEASURE_MAX_EVENTS],
+    start_cycles: u64,
+    out: Option<&'a mut Measurement>,
+}
+
+impl Measure<'static> {
+    /// Start measuring `events`, at most [`MEASURE_MAX_EVENTS`]
+    pub fn start(events: &[PmuEvent]) -> Self {
+        Self::new(events, None)
+    }
+}
+
+impl<'a> Measure<'a> {
+    /// Start measuring `events`, the result is written to `out` when the guard is dropped
+    pub fn start_into(events: &[PmuEvent], out: &'a mut Measurement) -> Self {
+        Self::new(events, Some(out))
+    }
+
+    fn new(events: &[PmuEvent], out: Option<&'a mut Measurement>) -> Self {
+        assert!(events.len() <= MEASURE_MAX_EVENTS, "too many events");
+        super::enable();
+        super::enable_cycle_counter();
+
+        let available = counter_count();
+        let mut counters = [None; MEASURE_MAX_EVENTS];
+        let mut start = [0; MEASURE_MAX_EVENTS];
+        for (slot, &event) in events.iter().enumerate().take(available) {
+            counters[slot] = EventCounter::configure(slot, event);
+        }
+        // Snapshot last so the setup is not measured
+        for (counter, start) in counters.iter().zip(&mut start) {
+            if let Some(counter) = counter {
+                *start = counter.read();
+            }
+        }
+        Self {
+            counters,
+            start,
+            start_cycles: super::cycles(),
+            out,
+        }
+    }
+
+    fn take(&self) -> Measurement {
+        let cycles = super::cycles().wrapping_sub(self.start_cycles);
+        let mut m = Measurement {
+            cycles,
+            ..Default::default()
+        };
+        for ((counter, start), entry) in self.counters.iter().zip(self.start).zip(&mut m.events) {
+            if let Some(counter) = counter {
+                // Event counters are 32 bits wide
+                let delta = counter.read().wrapping_sub(start) & 0xFFFF_FFFF;
+                *entry = Some((counter.event(), delta));
+            }
+        }
+        m
+    }
+
+    /// Stop measuring and return the deltas, also written to the output if there is one
+    pub fn finish(mut self) -> Measurement {
+        let m = self.take();
+        if let Some(out) = self.out.take() {
+            *out = m;
+        }
+        m
+    }
+}
+
+impl Drop for Measure<'_> {
+    fn drop(&mut self) {
+        if let Some(out) = self.out.take() {
+            *out = self.take();
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Performance Monitors Extension (FEAT_PMUv3).
+//!
+//! Every core has a cycle counter (PMCCNTR_EL0) and PMCR_EL0.N programmable event
+//! counters. An [`EventCounter`] counts one [`PmuEvent`] at EL0 and EL1, the counters only
+//! run while the PMU is enabled with [`enable`].
+//!
+//! The counters are per core, a counter configured on one core says nothing about the
+//! others. Code measuring with them must not migrate between cores.
+//!
+//! A counter can raise the PMU interrupt (a PPI, usually INTID 23) when it overflows.
+//! Sampling profilers set a counter to overflow after N events with
+//! [`EventCounter::set_sample_period`] and find the counters that fired with
+//! [`overflow_status`].
+//!
+//! [`Measure`] wraps this for micro-benchmarks: it counts cycles and a few events over a
+//! scope and reports the deltas as a [`Measurement`]. [`measure_cycles`] only counts the
+//! cycles of a closure.
+
+/// Access PMEVCNTR<n>_EL0 or PMEVTYPER<n>_EL0 directly, without the PMSELR_EL0 indirection
+/// that an interrupt handler using the PMU could disturb
+#[cfg(target_arch = "aarch64")]
+macro_rules! pmu_indexed {
+    (read $prefix:literal, $index:expr) => {
+        pmu_indexed!(@read $prefix, $index;
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30)
+    };
+    (write $prefix:literal, $index:expr, $value:expr) => {
+        pmu_indexed!(@write $prefix, $index, $value;
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30)
+    };
+    (@read $prefix:literal, $index:expr; $($n:literal)*) => {
+        match $index {
+            $($n => crate::sysreg!(read concat!($prefix, stringify!($n), "_el0")),)*
+            _ => panic!("invalid PMU counter index"),
+        }
+    };
+    (@write $prefix:literal, $index:expr, $value:expr; $($n:literal)*) => {
+        match $index {
+            $($n => unsafe {
+                crate::sysreg!(write concat!($prefix, stringify!($n), "_el0"), $value)
+            },)*
+            _ => panic!("invalid PMU counter index"),
+        }
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+mod context;
+#[cfg(target_arch = "aarch64")]
+mod counter;
+#[cfg(target_arch = "aarch64")]
+mod measure;
+
+#[cfg(target_arch = "aarch64")]
+pub use context::PmuContext;
+#[cfg(target_arch = "aarch64")]
+pub use counter::*;
+#[cfg(target_arch = "aarch64")]
+pub use measure::Measure;
+
+/// An event a PMU counter can count
+///
+/// The named events are the common architectural and microarchitectural events, whether a
+/// core implements one is given by PMCEID0_EL0/PMCEID1_EL0. [`PmuEvent::Raw`] is any other
+/// event number, including IMPLEMENTATION DEFINED ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PmuEvent {
+    /// SW_INCR, incremented by writes to PMSWINC_EL0
+    SwIncr,
+    /// L1I_CACHE_REFILL
+    L1iCacheRefill,
+    /// L1I_TLB_REFILL
+    L1iTlbRefill,
+    /// L1D_CACHE_REFILL
+    L1dCacheRefill,
+    /// L1D_CACHE
+    L1dCache,
+    /// L1D_TLB_REFILL
+    L1dTlbRefill,
+    /// LD_RETIRED
+    LdRetired,
+    /// ST_RETIRED
+    StRetired,
+    /// INST_RETIRED
+    InstRetired,
+    /// EXC_TAKEN
+    ExcTaken,
+    /// EXC_RETURN
+    ExcReturn,
+    /// BR_MIS_PRED
+    BrMisPred,
+    /// CPU_CYCLES
+    CpuCycles,
+    /// BR_PRED
+    BrPred,
+    /// MEM_ACCESS
+    MemAccess,
+    /// L1I_CACHE
+    L1iCache,
+    /// L2D_CACHE
+    L2dCache,
+    /// L2D_CACHE_REFILL
+    L2dCacheRefill,
+    /// BUS_ACCESS
+    BusAccess,
+    /// INST_SPEC
+    InstSpec,
+    /// BUS_CYCLES
+    BusCycles,
+    /// STALL_FRONTEND
+    StallFrontend,
+    /// STALL_BACKEND
+    StallBackend,
+    /// L2D_TLB_REFILL
+    L2dTlbRefill,
+    /// LL_CACHE_MISS_RD
+    LlCacheMissRd,
+    /// Event by number
+    Raw(u16),
+}
+
+impl PmuEvent {
+    /// Event number written to PMEVTYPER<n>_EL0.evtCount
+    pub const fn number(self) -> u16 {
+        match self {
+            Self::SwIncr => 0x00,
+            Self::L1iCacheRefill => 0x01,
+            Self::L1iTlbRefill => 0x02,
+            Self::L1dCacheRefill => 0x03,
+            Self::L1dCache => 0x04,
+            Self::L1dTlbRefill => 0x05,
+            Self::LdRetired => 0x06,
+            Self::StRetired => 0x07,
+            Self::InstRetired => 0x08,
+            Self::ExcTaken => 0x09,
+            Self::ExcReturn => 0x0A,
+            Self::BrMisPred => 0x10,
+            Self::CpuCycles => 0x11,
+            Self::BrPred => 0x12,
+            Self::MemAccess => 0x13,
+            Self::L1iCache => 0x14,
+            Self::L2dCache => 0x16,
+            Self::L2dCacheRefill => 0x17,
+            Self::BusAccess => 0x19,
+            Self::InstSpec => 0x1B,
+            Self::BusCycles => 0x1D,
+            Self::StallFrontend => 0x23,
+            Self::StallBackend => 0x24,
+            Self::L2dTlbRefill => 0x2D,
+            Self::LlCacheMissRd => 0x37,
+            Self::Raw(number) => number,
+        }
+    }
+
+    /// Decode an event number, numbers without a named variant become [`PmuEvent::Raw`]
+    pub const fn from_number(number: u16) -> Self {
+        match number {
+            0x00 => Self::SwIncr,
+            0x01 => Self::L1iCacheRefill,
+            0x02 => Self::L1iTlbRefill,
+            0x03 => Self::L1dCacheRefill,
+            0x04 => Self::L1dCache,
+            0x05 => Self::L1dTlbRefill,
+            0x06 => Self::LdRetired,
+            0x07 => Self::StRetired,
+            0x08 => Self::InstRetired,
+            0x09 => Self::ExcTaken,
+            0x0A => Self::ExcReturn,
+            0x10 => Self::BrMisPred,
+            0x11 => Self::CpuCycles,
+            0x12 => Self::BrPred,
+            0x13 => Self::MemAccess,
+            0x14 => Self::L1iCache,
+            0x16 => Self::L2dCache,
+            0x17 => Self::L2dCacheRefill,
+            0x19 => Self::BusAccess,
+            0x1B => Self::InstSpec,
+            0x1D => Self::BusCycles,
+            0x23 => Self::StallFrontend,
+            0x24 => Self::StallBackend,
+            0x2D => Self::L2dTlbRefill,
+            0x37 => Self::LlCacheMissRd,
+            number => Self::Raw(number),
+        }
+    }
+}
+
+/// Counters that overflowed, as read from PMOVSSET_EL0
+///
+/// Bit n is event counter n, bit 31 the cycle counter. Returned by [`overflow_status`] in
+/// the PMU interrupt handler to find which counters fired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct OverflowStatus(u32);
+
+impl OverflowStatus {
+    const CYCLE_COUNTER: u32 = 1 << 31;
+
+    /// Create from a raw PMOVSSET_EL0 value
+    pub const fn from_value(value: u32) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check if the cycle counter overflowed
+    pub const fn cycle_counter(self) -> bool {
+        self.0 & Self::CYCLE_COUNTER != 0
+    }
+
+    /// Check if event counter `slot` overflowed
+    pub const fn counter(self, slot: usize) -> bool {
+        slot < 31 && self.0 & (1 << slot) != 0
+    }
+
+    /// Slots of the event counters that overflowed, in increasing order
+    pub fn counters(self) -> impl Iterator<Item = usize> {
+        let mut bits = self.0 & !Self::CYCLE_COUNTER;
+        core::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let slot = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(slot)
+        })
+    }
+}
+
+/// Maximum number of events a [`Measurement`] holds besides the cycles
+pub const MEASURE_MAX_EVENTS: usize = 4;
+
+/// Counter deltas over a measured scope, see [`Measure`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Measurement {
+    /// Elapsed CPU cycles
+    pub cycles: u64,
+    /// Measured events and their counts, `None` for unused entries
+    pub events: [Option<(PmuEvent, u64)>; MEASURE_MAX_EVENTS],
+}
+
+impl Measurement {
+    /// Count of `event`, `None` if it was not measured
+    pub fn get(&self, event: PmuEvent) -> Option<u64> {
+        self.events
+            .iter()
+            .flatten()
+            .find(|(e, _)| *e == event)
+            .map(|(_, count)| *count)
+    }
+
+    /// Retired instructions (INST_RETIRED)
+    pub fn instructions(&self) -> Option<u64> {
+        self.get(PmuEvent::InstRetired)
+    }
+
+    /// L1 data cache refills (L1D_CACHE_REFILL)
+    pub fn cache_misses(&self) -> Option<u64> {
+        self.get(PmuEvent::L1dCacheRefill)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_numbers() {
+        assert_eq!(PmuEvent::InstRetired.number(), 0x08);
+        assert_eq!(PmuEvent::BrMisPred.number(), 0x10);
+        assert_eq!(PmuEvent::from_number(0x24), PmuEvent::StallBackend);
+        assert_eq!(PmuEvent::from_number(0x4004), PmuEvent::Raw(0x4004));
+        // Raw numbers of named events decode to the named variant
+        assert_eq!(
+            PmuEvent::from_number(PmuEvent::Raw(0x03).number()),
+            PmuEvent::L1dCacheRefill
+        );
+        for number in 0..0x40 {
+            assert_eq!(PmuEvent::from_number(number).number(), number);
+        }
+    }
+
+    #[test]
+    fn test_overflow_status() {
+        let status = OverflowStatus::from_value((1 << 31) | (1 << 5) | (1 << 0));
+        assert!(status.cycle_counter());
+        assert!(status.counter(0));
+        assert!(status.counter(5));
+        assert!(!status.counter(1));
+        assert!(!status.counter(31));
+        let mut slots = status.counters();
+        assert_eq!(slots.next(), Some(0));
+        assert_eq!(slots.next(), Some(5));
+        assert_eq!(slots.next(), None);
+        assert!(OverflowStatus::default().is_empty());
+    }
+
+    #[test]
+    fn test_measurement() {
+        let mut m = Measurement {
+            cycles: 1000,
+            ..Default::default()
+        };
+        m.events[0] = Some((PmuEvent::InstRetired, 800));
+        m.events[1] = Some((PmuEvent::L1dCacheRefill, 3));
+        assert_eq!(m.instructions(), Some(800));
+        assert_eq!(m.cache_misses(), Some(3));
+        assert_eq!(m.get(PmuEvent::BrMisPred), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Power State Coordination Interface (PSCI) client.
+//!
+//! PSCI is implemented by the firmware at EL3 (or by a hypervisor for its guests) and
+//! called through SMC or HVC, the [`Conduit`] is given by the `method` property of the
+//! `psci` device tree node. The calls follow the [SMCCC](crate::smccc) with the SMC64
+//! function IDs.
+
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_SUSPEND: u32 = 0xC400_0001;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const CPU_ON: u32 = 0xC400_0003;
+pub const AFFINITY_INFO: u32 = 0xC400_0004;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+
+pub use crate::smccc::Conduit;
+
+/// PSCI return codes other than SUCCESS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciError {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    /// A code not defined by the specification
+    Unknown(i32),
+}
+
+impl PsciError {
+    /// Decode a negative return code
+    pub const fn from_code(code: i32) -> Self {
+        match code {
+            -1 => Self::NotSupported,
+            -2 => Self::InvalidParameters,
+            -3 => Self::Denied,
+            -4 => Self::AlreadyOn,
+            -5 => Self::OnPending,
+            -6 => Self::InternalFailure,
+            -7 => Self::NotPresent,
+            -8 => Self::Disabled,
+            -9 => Self::InvalidAddress,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// Turn a returned x0 into a result, negative values being errors
+pub const fn check(ret: u64) -> Result<u64, PsciError> {
+    // Return codes are 32-bit signed integers
+    let code = ret as i32;
+    if code < 0 {
+        Err(PsciError::from_code(code))
+    } else {
+        Ok(ret)
+    }
+}
+
+/// PSCI version returned by PSCI_VERSION
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Version {
+    pub const fn from_value(value: u32) -> Self {
+        Self {
+            major: (value >> 16) as u16,
+            minor: value as u16,
+        }
+    }
+}
+
+/// State of an affinity instance returned by AFFINITY_INFO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AffinityState {
+    On,
+    Off,
+    OnPending,
+}
+
+impl AffinityState {
+    pub const fn from_value(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(Self::On),
+            1 => Some(Self::Off),
+            2 => Some(Self::OnPending),
+            _ => None,
+        }
+    }
+}
+
+/// PSCI client using the given conduit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Psci {
+    pub conduit: Conduit,
+}
+
+impl Psci {
+    pub const fn new(conduit: Conduit) -> Self {
+        Self { conduit }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod calls {
+    use super::*;
+    use crate::affinity::Affinity;
+
+    impl Psci {
+        /// Call PSCI function `function` with up to three arguments, returns x0
+        pub fn call(&self, function: u32, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+            self.conduit.call(function, arg0, arg1, arg2)
+        }
+
+        /// PSCI_VERSION
+        pub fn version(&self) -> Version {
+            Version::from_value(self.call(PSCI_VERSION, 0, 0, 0) as u32)
+        }
+
+        /// CPU_ON: start core `target` at the physical address `entry`, `context_id` is
+        /// passed to it in x0
+        pub fn cpu_on(
+            &self,
+            target: Affinity,
+            entry: usize,
+            context_id: u64,
+        ) -> Result<(), PsciError> {
+            check(self.call(CPU_ON, target.packed(), entry as u64, context_id)).map(|_| ())
+        }
+
+        /// CPU_OFF: power down the calling core, only returns on failure
+        pub fn cpu_off(&self) -> PsciError {
+            PsciError::from_code(self.call(CPU_OFF, 0, 0, 0) as i32)
+        }
+
+        /// CPU_SUSPEND: enter `power_state`
+        ///
+        /// Returns once the core woke up from a standby state. From a powerdown state the
+        /// core resumes at `entry` with `context_id` in x0 instead.
+        pub fn cpu_suspend(
+            &self,
+            power_state: u32,
+            entry: usize,
+            context_id: u64,
+        ) -> Result<(), PsciError> {
+            check(self.call(CPU_SUSPEND, power_state as u64, entry as u64, context_id)).map(|_| ())
+        }
+
+        /// AFFINITY_INFO: state of `target` at `lowest_level` (0 for a single core)
+        pub fn affinity_info(
+            &self,
+            target: Affinity,
+            lowest_level: u32,
+        ) -> Result<AffinityState, PsciError> {
+            let ret = check(self.call(AFFINITY_INFO, target.packed(), lowest_level as u64, 0))?;
+            AffinityState::from_value(ret).ok_or(PsciError::Unknown(ret as i32))
+        }
+
+        /// SYSTEM_OFF, only returns on failure
+        pub fn system_off(&self) -> PsciError {
+            PsciError::from_code(self.call(SYSTEM_OFF, 0, 0, 0) as i32)
+        }
+
+        /// SYSTEM_RESET, only returns on failure
+        pub fn system_reset(&self) -> PsciError {
+            PsciError::from_code(self.call(SYSTEM_RESET, 0, 0, 0) as i32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_psci_codes() {
+        assert_eq!(check(0), Ok(0));
+        assert_eq!(check(2), Ok(2));
+        assert_eq!(check(-4i64 as u64), Err(PsciError::AlreadyOn));
+        // Only the low 32 bits are significant
+        assert_eq!(check(0xFFFF_FFFE), Err(PsciError::InvalidParameters));
+        assert_eq!(PsciError::from_code(-42), PsciError::Unknown(-42));
+
+        assert_eq!(
+            Version::from_value(0x0001_0001),
+            Version { major: 1, minor: 1 }
+        );
+        assert_eq!(AffinityState::from_value(2), Some(AffinityState::OnPending));
+    }
+}
@@ -0,0 +1,40 @@
+//! Hardware random numbers (FEAT_RNG).
+//!
+//! RNDR returns a 64-bit random number from a DRBG seeded by the hardware entropy source,
+//! RNDRRS reseeds the DRBG before returning the number. Both can fail if the entropy
+//! source cannot deliver in reasonable time, which is reported through PSTATE.Z, the
+//! functions then return `None`. Failures are transient, [`rndr_with_retry`] retries a
+//! bounded number of times.
+//!
+//! Reading RNDR or RNDRRS is UNDEFINED without FEAT_RNG, check [`is_supported`] first. The
+//! registers are read through [`ArmRng`] from `aarch64-cpu`.
+
+pub use aarch64_cpu::asm::random::ArmRng;
+
+use crate::features::{Feature, is_supported as has_feature};
+
+/// Check if the core implements RNDR and RNDRRS
+#[inline]
+pub fn is_supported() -> bool {
+    has_feature(Feature::Rng)
+}
+
+/// Read a random number from RNDR, `None` if no number was available
+#[inline]
+pub fn rndr() -> Option<u64> {
+    ArmRng.rndr()
+}
+
+/// Read a random number from RNDRRS after reseeding, `None` if no number was available
+///
+/// Reseeding is slower than [`rndr`] and fails more often, use it where the number must
+/// not be derived from earlier output, e.g. for long-lived keys.
+#[inline]
+pub fn rndr_reseeded() -> Option<u64> {
+    ArmRng.rndrss()
+}
+
+/// Read a random number from RNDR, trying up to `attempts` times
+pub fn rndr_with_retry(attempts: usize) -> Option<u64> {
+    (0..attempts).find_map(|_| rndr())
+}
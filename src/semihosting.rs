@@ -0,0 +1,165 @@
+//! Arm semihosting: I/O through the debugger or emulator.
+//!
+//! A semihosting call is a `HLT #0xF000` with the operation number in w0 and a pointer to
+//! its parameter block in x1, the host performs the operation and returns in x0. QEMU
+//! (with `-semihosting`), the FVP models and most debug probes implement it, so tests and
+//! early boot code can print and exit without a UART driver.
+//!
+//! Without a host attached, `HLT` is an undefined instruction or halts the core. Only use
+//! semihosting on targets known to provide it.
+
+pub const SYS_OPEN: u32 = 0x01;
+pub const SYS_CLOSE: u32 = 0x02;
+pub const SYS_WRITEC: u32 = 0x03;
+pub const SYS_WRITE0: u32 = 0x04;
+pub const SYS_WRITE: u32 = 0x05;
+pub const SYS_READ: u32 = 0x06;
+pub const SYS_ERRNO: u32 = 0x13;
+pub const SYS_EXIT: u32 = 0x18;
+
+/// ADP_Stopped_ApplicationExit, the SYS_EXIT reason for a normal exit
+pub const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x2_0026;
+
+/// Mode of [`open`], as the C `fopen` mode string
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// `"r"`
+    Read = 0,
+    /// `"rb"`
+    ReadBinary = 1,
+    /// `"r+"`
+    ReadWrite = 2,
+    /// `"r+b"`
+    ReadWriteBinary = 3,
+    /// `"w"`
+    Write = 4,
+    /// `"wb"`
+    WriteBinary = 5,
+    /// `"w+"`
+    WriteRead = 6,
+    /// `"w+b"`
+    WriteReadBinary = 7,
+    /// `"a"`
+    Append = 8,
+    /// `"ab"`
+    AppendBinary = 9,
+    /// `"a+"`
+    AppendRead = 10,
+    /// `"a+b"`
+    AppendReadBinary = 11,
+}
+
+#[cfg(target_arch = "aarch64")]
+mod calls {
+    use core::{arch::asm, ffi::CStr, fmt};
+
+    use super::*;
+
+    /// Perform semihosting operation `op` with parameter `param`, returns x0
+    ///
+    /// # Safety
+    ///
+    /// `param` must be what the operation expects, usually a pointer to a parameter block
+    /// whose pointers are valid for the operation.
+    #[inline]
+    pub unsafe fn call(op: u32, param: usize) -> usize {
+        let ret: usize;
+        unsafe {
+            asm!(
+                "hlt #0xf000",
+                inout("x0") op as usize => ret,
+                in("x1") param,
+                options(nostack)
+            );
+        }
+        ret
+    }
+
+    /// Write a character to the debug console (SYS_WRITEC)
+    pub fn write_char(c: u8) {
+        unsafe { call(SYS_WRITEC, &c as *const u8 as usize) };
+    }
+
+    /// Write a string to the debug console (SYS_WRITE0)
+    pub fn write_str0(s: &CStr) {
+        unsafe { call(SYS_WRITE0, s.as_ptr() as usize) };
+    }
+
+    /// Host `errno` of the last failed call (SYS_ERRNO)
+    pub fn errno() -> i32 {
+        unsafe { call(SYS_ERRNO, 0) as i32 }
+    }
+
+    /// Open `name` on the host (SYS_OPEN), `None` on failure, see [`errno`]
+    ///
+    /// The name `":tt"` is the debug console, opened for reading it is stdin and for
+    /// writing stdout.
+    pub fn open(name: &CStr, mode: OpenMode) -> Option<HostFile> {
+        let block = [
+            name.as_ptr() as u64,
+            mode as u64,
+            name.to_bytes().len() as u64,
+        ];
+        let handle = unsafe { call(SYS_OPEN, block.as_ptr() as usize) } as isize;
+        (handle >= 0).then_some(HostFile(handle as u64))
+    }
+
+    /// The debug console opened for writing
+    pub fn stdout() -> Option<HostFile> {
+        open(c":tt", OpenMode::Write)
+    }
+
+    /// Report `code` to the host and end the session (SYS_EXIT with
+    /// ADP_Stopped_ApplicationExit)
+    ///
+    /// QEMU exits with `code` as its exit status. Loops if the host resumes execution.
+    pub fn exit(code: u32) -> ! {
+        let block = [ADP_STOPPED_APPLICATION_EXIT, code as u64];
+        unsafe { call(SYS_EXIT, block.as_ptr() as usize) };
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// A file handle on the host
+    #[derive(Debug)]
+    pub struct HostFile(u64);
+
+    impl HostFile {
+        /// Write `data` (SYS_WRITE), returns the number of bytes written
+        pub fn write(&self, data: &[u8]) -> usize {
+            let block = [self.0, data.as_ptr() as u64, data.len() as u64];
+            // The host returns the number of bytes not written
+            let left = unsafe { call(SYS_WRITE, block.as_ptr() as usize) };
+            data.len() - left.min(data.len())
+        }
+
+        /// Read into `buf` (SYS_READ), returns the number of bytes read, 0 at end of file
+        pub fn read(&self, buf: &mut [u8]) -> usize {
+            let block = [self.0, buf.as_mut_ptr() as u64, buf.len() as u64];
+            // The host returns the number of bytes not read
+            let left = unsafe { call(SYS_READ, block.as_ptr() as usize) };
+            buf.len() - left.min(buf.len())
+        }
+
+        /// Close the handle (SYS_CLOSE)
+        pub fn close(self) {
+            let block = [self.0];
+            unsafe { call(SYS_CLOSE, block.as_ptr() as usize) };
+        }
+    }
+
+    impl fmt::Write for HostFile {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.write(s.as_bytes()) == s.len() {
+                Ok(())
+            } else {
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use calls::*;
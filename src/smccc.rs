@@ -0,0 +1,208 @@
+//! Arm SMC Calling Convention (SMCCC) 1.2.
+//!
+//! Calls pass the function ID in w0 and arguments in x1-x17, results come back in x0-x17.
+//! The SMC32 convention uses w0-w7 only. The firmware preserves x18-x30 and the stack.
+
+/// SMCCC_VERSION
+pub const SMCCC_VERSION: u32 = 0x8000_0000;
+/// SMCCC_ARCH_FEATURES
+pub const SMCCC_ARCH_FEATURES: u32 = 0x8000_0001;
+/// SMCCC_ARCH_WORKAROUND_1, branch predictor invalidation (CVE-2017-5715)
+pub const SMCCC_ARCH_WORKAROUND_1: u32 = 0x8000_8000;
+/// SMCCC_ARCH_WORKAROUND_2, speculative store bypass control (CVE-2018-3639)
+pub const SMCCC_ARCH_WORKAROUND_2: u32 = 0x8000_7FFF;
+/// SMCCC_ARCH_WORKAROUND_3, branch history invalidation (CVE-2022-23960)
+pub const SMCCC_ARCH_WORKAROUND_3: u32 = 0x8000_3FFF;
+
+/// Instruction used to call the firmware or hypervisor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conduit {
+    Smc,
+    Hvc,
+}
+
+/// SMCCC return codes of the Arm architecture calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmcccError {
+    NotSupported,
+    NotRequired,
+    InvalidParameter,
+    /// A code not defined by the specification
+    Unknown(i32),
+}
+
+impl SmcccError {
+    /// Decode a negative return code
+    pub const fn from_code(code: i32) -> Self {
+        match code {
+            -1 => Self::NotSupported,
+            -2 => Self::NotRequired,
+            -3 => Self::InvalidParameter,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// Turn a returned w0 into a result, negative values being errors
+pub const fn check(ret: u64) -> Result<u32, SmcccError> {
+    let code = ret as i32;
+    if code < 0 {
+        Err(SmcccError::from_code(code))
+    } else {
+        Ok(code as u32)
+    }
+}
+
+/// SMCCC version returned by SMCCC_VERSION
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Version {
+    /// SMCCC 1.0, implied when SMCCC_VERSION is not supported
+    pub const V1_0: Self = Self { major: 1, minor: 0 };
+
+    pub const fn from_value(value: u32) -> Self {
+        Self {
+            major: (value >> 16) as u16 & 0x7FFF,
+            minor: value as u16,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod calls {
+    use core::arch::asm;
+
+    use super::*;
+
+    macro_rules! call64 {
+        ($insn:literal, $function:expr, $args:expr) => {{
+            let a = $args;
+            let mut r = [0u64; 18];
+            unsafe {
+                asm!(
+                    $insn,
+                    inout("x0") $function as u64 => r[0],
+                    inout("x1") a[0] => r[1],
+                    inout("x2") a[1] => r[2],
+                    inout("x3") a[2] => r[3],
+                    inout("x4") a[3] => r[4],
+                    inout("x5") a[4] => r[5],
+                    inout("x6") a[5] => r[6],
+                    inout("x7") a[6] => r[7],
+                    inout("x8") a[7] => r[8],
+                    inout("x9") a[8] => r[9],
+                    inout("x10") a[9] => r[10],
+                    inout("x11") a[10] => r[11],
+                    inout("x12") a[11] => r[12],
+                    inout("x13") a[12] => r[13],
+                    inout("x14") a[13] => r[14],
+                    inout("x15") a[14] => r[15],
+                    inout("x16") a[15] => r[16],
+                    inout("x17") a[16] => r[17],
+                    options(nostack),
+                )
+            };
+            r
+        }};
+    }
+
+    /// SMC with the SMC64 convention, `args` are x1-x17, returns x0-x17
+    #[inline]
+    pub fn smc64(function: u32, args: [u64; 17]) -> [u64; 18] {
+        call64!("smc #0", function, args)
+    }
+
+    /// HVC with the SMC64 convention, `args` are x1-x17, returns x0-x17
+    #[inline]
+    pub fn hvc64(function: u32, args: [u64; 17]) -> [u64; 18] {
+        call64!("hvc #0", function, args)
+    }
+
+    /// SMC with the SMC32 convention, `args` are w1-w7, returns w0-w7
+    ///
+    /// x8-x17 are treated as clobbered, SMC32 does not define their content.
+    #[inline]
+    pub fn smc32(function: u32, args: [u32; 7]) -> [u32; 8] {
+        let mut args64 = [0u64; 17];
+        for (arg64, arg) in args64.iter_mut().zip(args) {
+            *arg64 = arg as u64;
+        }
+        let r = smc64(function, args64);
+        core::array::from_fn(|i| r[i] as u32)
+    }
+
+    impl Conduit {
+        /// Call `function` with the SMC64 convention through this conduit
+        #[inline]
+        pub fn call64(self, function: u32, args: [u64; 17]) -> [u64; 18] {
+            match self {
+                Self::Smc => smc64(function, args),
+                Self::Hvc => hvc64(function, args),
+            }
+        }
+
+        /// Call `function` with up to three arguments, returns x0
+        #[inline]
+        pub fn call(self, function: u32, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+            let mut args = [0; 17];
+            args[..3].copy_from_slice(&[arg0, arg1, arg2]);
+            self.call64(function, args)[0]
+        }
+
+        /// SMCCC_VERSION, [`Version::V1_0`] if the call is not implemented
+        pub fn version(self) -> Version {
+            match check(self.call(SMCCC_VERSION, 0, 0, 0)) {
+                Ok(value) => Version::from_value(value),
+                Err(_) => Version::V1_0,
+            }
+        }
+
+        /// SMCCC_ARCH_FEATURES: query `function`, the meaning of the result depends on it
+        ///
+        /// Requires SMCCC 1.1, see [`version`](Self::version).
+        pub fn arch_features(self, function: u32) -> Result<u32, SmcccError> {
+            check(self.call(SMCCC_ARCH_FEATURES, function as u64, 0, 0))
+        }
+
+        /// SMCCC_ARCH_WORKAROUND_1: invalidate the branch predictor
+        ///
+        /// Only to be called if [`arch_features`](Self::arch_features) reports it.
+        pub fn arch_workaround_1(self) {
+            self.call(SMCCC_ARCH_WORKAROUND_1, 0, 0, 0);
+        }
+
+        /// SMCCC_ARCH_WORKAROUND_2: enable or disable the speculative store bypass
+        /// mitigation for the calling core
+        pub fn arch_workaround_2(self, enable: bool) {
+            self.call(SMCCC_ARCH_WORKAROUND_2, enable as u64, 0, 0);
+        }
+
+        /// SMCCC_ARCH_WORKAROUND_3: invalidate the branch history
+        pub fn arch_workaround_3(self) {
+            self.call(SMCCC_ARCH_WORKAROUND_3, 0, 0, 0);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use calls::{hvc64, smc32, smc64};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smccc_codes() {
+        assert_eq!(check(0x0001_0002), Ok(0x0001_0002));
+        assert_eq!(check(0xFFFF_FFFE), Err(SmcccError::NotRequired));
+        assert_eq!(check(-1i64 as u64), Err(SmcccError::NotSupported));
+        assert_eq!(
+            Version::from_value(0x0001_0002),
+            Version { major: 1, minor: 2 }
+        );
+    }
+}
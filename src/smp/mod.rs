@@ -0,0 +1,6 @@
+//! Secondary core bring-up.
+//!
+//! Cores are started through PSCI CPU_ON where the firmware implements it, boards without
+//! PSCI hold the secondaries in a [`spin_table`].
+
+pub mod spin_table;
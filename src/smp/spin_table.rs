@@ -0,0 +1,64 @@
+//! Spin-table enable method (`enable-method = "spin-table"` in the device tree).
+//!
+//! Secondary cores wait in WFE, polling a per-core release address until it holds a
+//! non-zero entry point. The secondaries may run with the MMU and data cache off, so the
+//! boot core cleans the release address to the PoC before signalling them with SEV.
+
+use core::ptr;
+
+use aarch64_cpu::asm::{sev, wfe};
+
+use crate::cache::{CacheOp, dcache_range};
+
+/// Release a secondary core waiting on `release_addr`, it continues at `entry`
+///
+/// Writes `entry` to the release address, cleans it to the PoC and wakes the waiting cores
+/// with SEV.
+///
+/// # Safety
+///
+/// `release_addr` must be the release address of the core from the device tree
+/// (`cpu-release-addr`), mapped and writable. `entry` must be a physical address of code
+/// that can run with the MMU off.
+pub unsafe fn release(release_addr: *mut u64, entry: usize) {
+    unsafe { ptr::write_volatile(release_addr, entry as u64) };
+    // Completed by the DSB of the maintenance, before the SEV
+    dcache_range(CacheOp::Clean, release_addr as usize, size_of::<u64>());
+    sev();
+}
+
+/// Wait until a non-zero entry point is written to `release_addr` and return it
+///
+/// The secondary-side loop for images that park their own secondaries. The line of the
+/// release address is cleaned and invalidated before every read, so a stale cached copy is
+/// never seen. Cleaning first keeps dirty data that shares the line with the release
+/// address, a plain invalidate would discard it.
+///
+/// # Safety
+///
+/// `release_addr` must be mapped and readable.
+pub unsafe fn wait_for_release(release_addr: *const u64) -> usize {
+    loop {
+        dcache_range(
+            CacheOp::CleanAndInvalidate,
+            release_addr as usize,
+            size_of::<u64>(),
+        );
+        let entry = unsafe { ptr::read_volatile(release_addr) };
+        if entry != 0 {
+            return entry as usize;
+        }
+        wfe();
+    }
+}
+
+/// Park the executing core until it is released, then branch to the entry point
+///
+/// # Safety
+///
+/// See [`wait_for_release`]. The entry point is entered with the current MMU, cache and
+/// stack state, it must not return.
+pub unsafe fn park(release_addr: *const u64) -> ! {
+    let entry = unsafe { wait_for_release(release_addr) };
+    unsafe { core::arch::asm!("br {}", in(reg) entry, options(noreturn)) }
+}
@@ -0,0 +1,197 @@
+//! Speculation hardening.
+//!
+//! Collects the controls against speculative execution side channels: the speculation
+//! barriers (SB, CSDB, SSBB, PSSBB), PSTATE.SSBS against speculative store bypass, and the
+//! prediction restriction instructions (FEAT_SPECRES), which stop predictions learned in
+//! one execution context from steering speculation in another.
+//!
+//! The prediction restriction instructions take a [`PredictionContext`] naming the context
+//! whose predictions are restricted. [`harden_context_switch`] combines them with the
+//! barriers for the usual case of switching away from a task.
+
+/// Execution context operand of CFP, DVP and CPP RCTX
+///
+/// Names an exception level, security state, ASID and VMID. The ASID and VMID can be
+/// widened to all of them, e.g. to restrict a whole virtual machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PredictionContext(u64);
+
+impl PredictionContext {
+    const ASID: u64 = 0xFFFF;
+    const GASID: u64 = 1 << 16;
+    const EL_SHIFT: u64 = 24;
+    const NS: u64 = 1 << 26;
+    const VMID_SHIFT: u64 = 32;
+    const GVMID: u64 = 1 << 48;
+
+    /// EL0 of ASID 0 and VMID 0 in the Secure state
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw operand value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Restrict the predictions of `asid`
+    pub const fn asid(self, asid: u16) -> Self {
+        Self((self.0 & !(Self::ASID | Self::GASID)) | asid as u64)
+    }
+
+    /// Restrict the predictions of every ASID (GASID)
+    pub const fn all_asids(self) -> Self {
+        Self(self.0 | Self::GASID)
+    }
+
+    /// Restrict the predictions of exception level `el`, 0-3
+    pub const fn el(self, el: u8) -> Self {
+        Self((self.0 & !(0b11 << Self::EL_SHIFT)) | (((el & 0b11) as u64) << Self::EL_SHIFT))
+    }
+
+    /// Restrict the predictions of the Non-secure state (NS)
+    pub const fn non_secure(self, ns: bool) -> Self {
+        if ns {
+            Self(self.0 | Self::NS)
+        } else {
+            Self(self.0 & !Self::NS)
+        }
+    }
+
+    /// Restrict the predictions of virtual machine `vmid`
+    pub const fn vmid(self, vmid: u16) -> Self {
+        Self(
+            (self.0 & !((0xFFFF << Self::VMID_SHIFT) | Self::GVMID))
+                | ((vmid as u64) << Self::VMID_SHIFT),
+        )
+    }
+
+    /// Restrict the predictions of every virtual machine (GVMID)
+    pub const fn all_vmids(self) -> Self {
+        Self(self.0 | Self::GVMID)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod ops {
+    use core::arch::asm;
+
+    use aarch64_cpu::asm::barrier::{SY, dsb, isb};
+
+    use super::PredictionContext;
+    pub use crate::asm::barrier::{csdb, pssbb, sb, ssbb};
+    use crate::features::{Feature, is_supported};
+
+    /// Speculation barrier that works on every core: SB with FEAT_SB, DSB SY and ISB
+    /// otherwise
+    #[inline]
+    pub fn speculation_barrier() {
+        if is_supported(Feature::Sb) {
+            sb();
+        } else {
+            dsb(SY);
+            isb(SY);
+        }
+    }
+
+    /// Set PSTATE.SSBS, stores may be speculatively bypassed by loads
+    ///
+    /// Requires FEAT_SSBS2.
+    #[inline]
+    pub fn set_ssbs() {
+        // MSR SSBS, #1, encoded as the assembler may not know FEAT_SSBS
+        unsafe { asm!(".inst 0xd503413f", options(nostack)) };
+    }
+
+    /// Clear PSTATE.SSBS, loads cannot speculatively bypass older stores to the same
+    /// address
+    ///
+    /// Requires FEAT_SSBS2.
+    #[inline]
+    pub fn clear_ssbs() {
+        // MSR SSBS, #0
+        unsafe { asm!(".inst 0xd503403f", options(nostack)) };
+    }
+
+    /// Check if PSTATE.SSBS is set, requires FEAT_SSBS2
+    #[inline]
+    pub fn is_ssbs_set() -> bool {
+        // SSBS
+        crate::sysreg!(read "S3_3_C4_C2_6") & (1 << 12) != 0
+    }
+
+    /// Restrict control flow predictions of `ctx` (CFP RCTX), requires FEAT_SPECRES
+    #[inline]
+    pub fn cfp_rctx(ctx: PredictionContext) {
+        unsafe {
+            asm!(".arch_extension predres\ncfp rctx, {}", in(reg) ctx.value(), options(nostack))
+        };
+    }
+
+    /// Restrict data value predictions of `ctx` (DVP RCTX), requires FEAT_SPECRES
+    #[inline]
+    pub fn dvp_rctx(ctx: PredictionContext) {
+        unsafe {
+            asm!(".arch_extension predres\ndvp rctx, {}", in(reg) ctx.value(), options(nostack))
+        };
+    }
+
+    /// Restrict cache prefetch predictions of `ctx` (CPP RCTX), requires FEAT_SPECRES
+    #[inline]
+    pub fn cpp_rctx(ctx: PredictionContext) {
+        unsafe {
+            asm!(".arch_extension predres\ncpp rctx, {}", in(reg) ctx.value(), options(nostack))
+        };
+    }
+
+    /// Restrict all predictions of `ctx` and wait until the restriction applies
+    ///
+    /// Requires FEAT_SPECRES.
+    pub fn restrict_predictions(ctx: PredictionContext) {
+        cfp_rctx(ctx);
+        dvp_rctx(ctx);
+        cpp_rctx(ctx);
+        dsb(SY);
+        isb(SY);
+    }
+
+    /// Harden a switch away from the task running in `outgoing`
+    ///
+    /// Restricts the predictions learned by the outgoing task if FEAT_SPECRES is
+    /// implemented, then issues a speculation barrier so nothing after the switch executes
+    /// speculatively with the old predictions.
+    pub fn harden_context_switch(outgoing: PredictionContext) {
+        if is_supported(Feature::SpecRes) {
+            restrict_predictions(outgoing);
+        }
+        speculation_barrier();
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use ops::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prediction_context() {
+        let ctx = PredictionContext::new()
+            .asid(0x42)
+            .el(0)
+            .non_secure(true)
+            .vmid(3);
+        assert_eq!(ctx.value(), 0x0000_0003_0400_0042);
+
+        let ctx = ctx.all_asids().el(1).all_vmids();
+        assert_eq!(ctx.value(), 0x0001_0003_0501_0042);
+        // Naming a single ASID or VMID again clears the global bits
+        assert_eq!(ctx.asid(7).vmid(0).value(), 0x0000_0000_0500_0007);
+    }
+}
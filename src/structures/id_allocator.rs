@@ -0,0 +1,266 @@
+//! Generation based allocator for hardware context tags (ASIDs and VMIDs).
+//!
+//! Ids are handed out from a bitmap until the space is exhausted. The allocator then starts
+//! a new generation, forgets all previous allocations and reports the rollover so that the
+//! caller can invalidate every TLB entry tagged with an id of the old generation.
+
+const MAX_IDS: usize = 1 << 16;
+
+/// An id tagged with the generation it was allocated in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GenerationalId(u64);
+
+impl GenerationalId {
+    /// The id programmed into the hardware (TTBR.ASID or VTTBR.VMID)
+    pub const fn hw_id(self) -> usize {
+        (self.0 & (MAX_IDS as u64 - 1)) as usize
+    }
+
+    /// The generation this id was allocated in
+    pub const fn generation(self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Get the raw value, suitable for storing in an atomic
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Create from a raw value returned by [`value`](Self::value)
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    const fn new(generation: u64, hw_id: usize) -> Self {
+        Self((generation << 16) | hw_id as u64)
+    }
+}
+
+/// Result of [`IdAllocator::allocate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub id: GenerationalId,
+    /// A new generation was started. All TLB entries tagged with ids of older generations
+    /// must be invalidated before `id` is used.
+    pub rolled_over: bool,
+}
+
+/// Bitmap allocator for up to 16-bit ids with generation tracking
+///
+/// Id 0 is never handed out, so it can be reserved for global or host mappings.
+/// The allocator itself is not synchronized, SMP users must wrap it in a lock.
+#[derive(Clone)]
+pub struct IdAllocator {
+    bits: u32,
+    generation: u64,
+    next: usize,
+    map: [u64; MAX_IDS / 64],
+    /// Ids carried over from the previous generation, reserved for their current owner
+    carried: [u64; MAX_IDS / 64],
+}
+
+impl IdAllocator {
+    /// Create an allocator for `bits` wide ids (8 or 16)
+    pub const fn new(bits: u32) -> Self {
+        assert!(
+            bits >= 2 && bits <= 16,
+            "id width must be between 2 and 16 bits"
+        );
+        let mut map = [0; MAX_IDS / 64];
+        // Id 0 is reserved
+        map[0] = 1;
+        Self {
+            bits,
+            generation: 1,
+            next: 1,
+            map,
+            carried: [0; MAX_IDS / 64],
+        }
+    }
+
+    /// Reset to `bits` wide ids in place, like [`new`](Self::new) without building the
+    /// allocator on the stack
+    ///
+    /// A new generation is started, so ids handed out before are no longer current.
+    pub fn reset(&mut self, bits: u32) {
+        assert!(
+            (2..=16).contains(&bits),
+            "id width must be between 2 and 16 bits"
+        );
+        self.bits = bits;
+        self.rollover(&[]);
+    }
+
+    /// Width of the ids in bits
+    pub const fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The current generation
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Check if `id` belongs to the current generation and can be used without reallocation
+    pub const fn is_current(&self, id: GenerationalId) -> bool {
+        id.generation() == self.generation
+    }
+
+    /// Return an id that is valid in the current generation
+    ///
+    /// `current` is the id the context used last, it is returned unchanged if it is still
+    /// current. An id from an older generation keeps its hardware value when that value is
+    /// still free. `active` lists the ids currently installed on the cores: on rollover
+    /// they are carried over into the new generation, reserved for the context that owns
+    /// them, so they cannot be handed to another context while still live.
+    pub fn allocate(
+        &mut self,
+        current: Option<GenerationalId>,
+        active: &[GenerationalId],
+    ) -> Allocation {
+        if let Some(id) = current {
+            if self.is_current(id) {
+                return Allocation {
+                    id,
+                    rolled_over: false,
+                };
+            }
+            let hw_id = id.hw_id();
+            let carried =
+                id.generation() + 1 == self.generation && take_bit(&mut self.carried, hw_id);
+            if carried || (hw_id != 0 && !self.is_used(hw_id)) {
+                self.set_used(hw_id);
+                return Allocation {
+                    id: GenerationalId::new(self.generation, id.hw_id()),
+                    rolled_over: false,
+                };
+            }
+        }
+
+        if let Some(hw_id) = self.find_free() {
+            return Allocation {
+                id: GenerationalId::new(self.generation, hw_id),
+                rolled_over: false,
+            };
+        }
+
+        self.rollover(active);
+        let hw_id = self.find_free().expect("no free id after rollover");
+        Allocation {
+            id: GenerationalId::new(self.generation, hw_id),
+            rolled_over: true,
+        }
+    }
+
+    /// Release an id of the current generation for reuse
+    pub fn free(&mut self, id: GenerationalId) {
+        if self.is_current(id) && id.hw_id() != 0 {
+            self.map[id.hw_id() / 64] &= !(1 << (id.hw_id() % 64));
+        }
+    }
+
+    fn rollover(&mut self, active: &[GenerationalId]) {
+        self.generation += 1;
+        self.map.fill(0);
+        self.map[0] = 1;
+        self.carried.fill(0);
+        self.next = 1;
+        for id in active {
+            if id.hw_id() != 0 {
+                self.set_used(id.hw_id());
+                self.carried[id.hw_id() / 64] |= 1 << (id.hw_id() % 64);
+            }
+        }
+    }
+
+    fn find_free(&mut self) -> Option<usize> {
+        let count = 1 << self.bits;
+        for i in 0..count {
+            let hw_id = (self.next + i) % count;
+            if !self.is_used(hw_id) {
+                self.set_used(hw_id);
+                self.next = (hw_id + 1) % count;
+                return Some(hw_id);
+            }
+        }
+        None
+    }
+
+    fn is_used(&self, hw_id: usize) -> bool {
+        self.map[hw_id / 64] & (1 << (hw_id % 64)) != 0
+    }
+
+    fn set_used(&mut self, hw_id: usize) {
+        self.map[hw_id / 64] |= 1 << (hw_id % 64);
+    }
+}
+
+fn take_bit(map: &mut [u64], bit: usize) -> bool {
+    let mask = 1 << (bit % 64);
+    let set = map[bit / 64] & mask != 0;
+    map[bit / 64] &= !mask;
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollover_8bit() {
+        let mut alloc = IdAllocator::new(8);
+
+        let ids: Vec<_> = (0..255).map(|_| alloc.allocate(None, &[])).collect();
+        assert!(ids.iter().all(|a| !a.rolled_over && a.id.hw_id() != 0));
+        assert_eq!(ids[0].id.hw_id(), 1);
+        assert_eq!(ids[254].id.hw_id(), 255);
+
+        // Still current, nothing to do
+        let again = alloc.allocate(Some(ids[3].id), &[]);
+        assert_eq!(again.id, ids[3].id);
+
+        // Exhausted, id 7 is still live on another core
+        let next = alloc.allocate(None, &[ids[6].id]);
+        assert!(next.rolled_over);
+        assert_eq!(alloc.generation(), 2);
+        assert_ne!(next.id.hw_id(), 7);
+
+        // Old ids keep their value when free or carried over for their owner
+        let kept = alloc.allocate(Some(ids[9].id), &[]);
+        assert!(!kept.rolled_over);
+        assert_eq!(kept.id.hw_id(), 10);
+        assert!(alloc.is_current(kept.id));
+        let carried = alloc.allocate(Some(ids[6].id), &[]);
+        assert_eq!(carried.id.hw_id(), 7);
+        assert!(alloc.is_current(carried.id));
+
+        // Value 1 was handed out again, so its previous owner moves
+        let moved = alloc.allocate(Some(ids[0].id), &[]);
+        assert_ne!(moved.id.hw_id(), 1);
+    }
+
+    #[test]
+    fn test_free() {
+        let mut alloc = IdAllocator::new(2);
+        let a = alloc.allocate(None, &[]).id;
+        let _ = alloc.allocate(None, &[]);
+        let _ = alloc.allocate(None, &[]);
+        alloc.free(a);
+        let b = alloc.allocate(None, &[]);
+        assert!(!b.rolled_over);
+        assert_eq!(b.id.hw_id(), a.hw_id());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut alloc = IdAllocator::new(16);
+        let a = alloc.allocate(None, &[]).id;
+        alloc.reset(2);
+        assert_eq!(alloc.bits(), 2);
+        assert!(!alloc.is_current(a));
+        let ids: Vec<_> = (0..3).map(|_| alloc.allocate(None, &[])).collect();
+        assert!(ids.iter().all(|a| !a.rolled_over));
+        assert_eq!(ids[0].id.hw_id(), 1);
+        assert!(alloc.allocate(None, &[]).rolled_over);
+    }
+}
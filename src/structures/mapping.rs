@@ -0,0 +1,85 @@
+//! Shared description of a virtual-to-physical mapping.
+
+use super::tte::{AccessPermission, Shareability};
+
+/// A request to map `size` bytes at `va` to the physical range starting at `pa`
+///
+/// This is the vocabulary type consumed by the TTE builders (see `TTE64::new_block_mapping`)
+/// and by higher level page table code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MappingRequest {
+    /// Virtual (or intermediate physical) start address
+    pub va: u64,
+    /// Physical start address
+    pub pa: u64,
+    /// Size of the mapping in bytes
+    pub size: usize,
+    /// Memory attribute index into MAIR_ELx
+    pub attr_index: u64,
+    /// Access permissions
+    pub access: AccessPermission,
+    /// Shareability
+    pub shareability: Shareability,
+    /// Execution allowed at the unprivileged level (or the single privilege level)
+    pub executable: bool,
+    /// Execution allowed at the privileged level
+    pub privileged_executable: bool,
+    /// Global mapping (nG = 0), valid for all ASIDs
+    pub global: bool,
+}
+
+impl MappingRequest {
+    /// Create a privileged read/write, non-executable, inner shareable, global mapping
+    /// with attribute index 0
+    pub const fn new(va: u64, pa: u64, size: usize) -> Self {
+        Self {
+            va,
+            pa,
+            size,
+            attr_index: 0,
+            access: AccessPermission::PrivilegedReadWrite,
+            shareability: Shareability::InnerShareable,
+            executable: false,
+            privileged_executable: false,
+            global: true,
+        }
+    }
+
+    /// Create an identity mapping (va == pa)
+    pub const fn identity(addr: u64, size: usize) -> Self {
+        Self::new(addr, addr, size)
+    }
+
+    /// Check that the mapping is not empty and neither range wraps around the end of the
+    /// address space
+    pub const fn is_valid(&self) -> bool {
+        self.size != 0
+            && self.va.checked_add(self.size as u64 - 1).is_some()
+            && self.pa.checked_add(self.size as u64 - 1).is_some()
+    }
+
+    /// End of the virtual range (exclusive), saturated at `u64::MAX` for a range reaching
+    /// the top of the address space
+    pub const fn va_end(&self) -> u64 {
+        self.va.saturating_add(self.size as u64)
+    }
+
+    /// End of the physical range (exclusive), saturated like [`va_end`](Self::va_end)
+    pub const fn pa_end(&self) -> u64 {
+        self.pa.saturating_add(self.size as u64)
+    }
+
+    /// Check if `va` falls inside this mapping
+    pub const fn contains(&self, va: u64) -> bool {
+        va >= self.va && va - self.va < self.size as u64
+    }
+
+    /// Translate a virtual address inside this mapping to its physical address
+    pub const fn translate(&self, va: u64) -> Option<u64> {
+        if self.contains(va) {
+            Some(self.pa.wrapping_add(va - self.va))
+        } else {
+            None
+        }
+    }
+}
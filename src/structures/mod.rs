@@ -1 +1,7 @@
+pub mod id_allocator;
+pub mod mapping;
+pub mod par;
+pub mod pie;
 pub mod tte;
+pub mod vtcr;
+pub mod vttbr;
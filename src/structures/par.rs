@@ -0,0 +1,172 @@
+//! PAR_EL1, the result of an address translation (AT) instruction.
+//!
+//! PAR_EL1.F selects between two layouts: a successful translation holds the output
+//! address with its shareability and memory attributes, a failed one the fault status
+//! and the stage that faulted. [`Par::decode`] returns the matching [`PhysInfo`] or
+//! [`TranslationFault`].
+
+use super::tte::Shareability;
+use crate::exception::syndrome::FaultStatus;
+
+/// Raw PAR_EL1 value (64-bit format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Par(u64);
+
+/// Successful translation reported by PAR_EL1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysInfo {
+    /// Output address, at 4KB granularity unless filled in by [`translate_va`]
+    ///
+    /// [`translate_va`]: crate::mmu::translate_va
+    pub pa: u64,
+    /// Shareability of the output address (SH)
+    pub shareability: Shareability,
+    /// Memory attributes in MAIR_ELx encoding (ATTR)
+    pub attributes: u8,
+    /// Output address is in the Non-secure address space (NS)
+    pub non_secure: bool,
+}
+
+/// Failed translation reported by PAR_EL1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslationFault {
+    /// Fault status code (FST)
+    pub status: FaultStatus,
+    /// The fault occurred at stage 2 (S)
+    pub stage2: bool,
+    /// Stage 2 fault on a stage 1 translation table walk (PTW)
+    pub s1ptw: bool,
+}
+
+impl TranslationFault {
+    /// Check if the fault is an external abort or parity error rather than a translation
+    /// table fault
+    pub const fn is_external(&self) -> bool {
+        matches!(
+            self.status,
+            FaultStatus::SynchronousExternal
+                | FaultStatus::SynchronousExternalOnWalk { .. }
+                | FaultStatus::SynchronousParity
+                | FaultStatus::SynchronousParityOnWalk { .. }
+        )
+    }
+}
+
+impl Par {
+    const F: u64 = 1 << 0;
+    const FST_SHIFT: u64 = 1;
+    const SH_SHIFT: u64 = 7;
+    const PTW: u64 = 1 << 8;
+    const S: u64 = 1 << 9;
+    const NS: u64 = 1 << 9;
+    const PA_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+    const ATTR_SHIFT: u64 = 56;
+
+    /// Create from a raw register value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Check if the translation failed (F)
+    pub const fn is_fault(self) -> bool {
+        self.0 & Self::F != 0
+    }
+
+    /// Decode the translation result
+    pub const fn decode(self) -> Result<PhysInfo, TranslationFault> {
+        if self.is_fault() {
+            return Err(TranslationFault {
+                status: FaultStatus::from_bits(((self.0 >> Self::FST_SHIFT) & 0x3F) as u8),
+                stage2: self.0 & Self::S != 0,
+                s1ptw: self.0 & Self::PTW != 0,
+            });
+        }
+        Ok(PhysInfo {
+            pa: self.0 & Self::PA_MASK,
+            shareability: match (self.0 >> Self::SH_SHIFT) & 0b11 {
+                0b10 => Shareability::OuterShareable,
+                0b11 => Shareability::InnerShareable,
+                _ => Shareability::NonShareable,
+            },
+            attributes: (self.0 >> Self::ATTR_SHIFT) as u8,
+            non_secure: self.0 & Self::NS != 0,
+        })
+    }
+}
+
+/// Translation regime, stage and access an address is translated for, selecting the AT
+/// instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranslateKind {
+    /// EL1&0 stage 1 read as EL0 (S1E0R)
+    El0Read,
+    /// EL1&0 stage 1 write as EL0 (S1E0W)
+    El0Write,
+    /// EL1&0 stage 1 read as EL1 (S1E1R)
+    El1Read,
+    /// EL1&0 stage 1 write as EL1 (S1E1W)
+    El1Write,
+    /// Like [`El1Read`](Self::El1Read), also checking PSTATE.PAN (S1E1RP), requires
+    /// FEAT_PAN2
+    El1ReadPan,
+    /// Like [`El1Write`](Self::El1Write), also checking PSTATE.PAN (S1E1WP), requires
+    /// FEAT_PAN2
+    El1WritePan,
+    /// EL2 or EL2&0 stage 1 read (S1E2R)
+    El2Read,
+    /// EL2 or EL2&0 stage 1 write (S1E2W)
+    El2Write,
+    /// EL1&0 stages 1 and 2 read as EL0, from EL2 (S12E0R)
+    GuestEl0Read,
+    /// EL1&0 stages 1 and 2 write as EL0, from EL2 (S12E0W)
+    GuestEl0Write,
+    /// EL1&0 stages 1 and 2 read as EL1, from EL2 (S12E1R)
+    GuestEl1Read,
+    /// EL1&0 stages 1 and 2 write as EL1, from EL2 (S12E1W)
+    GuestEl1Write,
+    /// EL3 stage 1 read (S1E3R)
+    El3Read,
+    /// EL3 stage 1 write (S1E3W)
+    El3Write,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_par_success() {
+        // Normal write-back memory, inner shareable, Non-secure
+        let par = Par::from_value(0xFF00_0000_4008_1380);
+        let info = par.decode().unwrap();
+        assert_eq!(info.pa, 0x4008_1000);
+        assert_eq!(info.shareability, Shareability::InnerShareable);
+        assert_eq!(info.attributes, 0xFF);
+        assert!(info.non_secure);
+    }
+
+    #[test]
+    fn test_par_fault() {
+        // Stage 1 translation fault level 3
+        let fault = Par::from_value(0x800 | (0x07 << 1) | 1)
+            .decode()
+            .unwrap_err();
+        assert_eq!(fault.status, FaultStatus::Translation { level: 3 });
+        assert!(!fault.stage2 && !fault.s1ptw && !fault.is_external());
+
+        // Stage 2 external abort on the stage 1 walk
+        let fault = Par::from_value(0x800 | (1 << 9) | (1 << 8) | (0x15 << 1) | 1)
+            .decode()
+            .unwrap_err();
+        assert_eq!(
+            fault.status,
+            FaultStatus::SynchronousExternalOnWalk { level: 1 }
+        );
+        assert!(fault.stage2 && fault.s1ptw && fault.is_external());
+    }
+}
@@ -0,0 +1,347 @@
+//! Stage 1 and stage 2 permission indirection (FEAT_S1PIE / FEAT_S2PIE).
+//!
+//! With permission indirection enabled (TCR2_EL1.PIE = 1) a stage 1 descriptor no longer
+//! encodes its permissions directly. Instead, the descriptor carries a 4-bit PIIndex which
+//! selects one of 16 permission fields in PIR_EL1 (privileged) or PIRE0_EL1 (unprivileged).
+//! Stage 2 works the same way with VTCR_EL2.S2PIE and a single S2PIR_EL2 register.
+//!
+//! Based on ARM DDI 0487K.a "Stage 1 Indirect permissions" and "Stage 2 Indirect permissions"
+
+/// Permission encoding of a single 4-bit PIR_ELx field
+///
+/// Values with bit[3] clear are subject to the permission overlay (FEAT_S1POE) when enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PiPermission {
+    /// No access
+    NoAccess = 0b0000,
+    /// Read-only, overlay applies
+    ReadOverlay = 0b0001,
+    /// Execute-only, overlay applies
+    ExecuteOverlay = 0b0010,
+    /// Read and execute, overlay applies
+    ReadExecuteOverlay = 0b0011,
+    /// Read and write, overlay applies
+    ReadWriteOverlay = 0b0101,
+    /// Read and write, execute is removed by WXN, overlay applies
+    ReadWriteNoExecuteOverlay = 0b0110,
+    /// Read, write and execute, overlay applies
+    ReadWriteExecuteOverlay = 0b0111,
+    /// Read-only
+    Read = 0b1000,
+    /// Read, and Guarded Control Stack read/write
+    ReadGcs = 0b1001,
+    /// Read and execute
+    ReadExecute = 0b1010,
+    /// Read and write
+    ReadWrite = 0b1100,
+    /// Read, write and execute
+    ReadWriteExecute = 0b1110,
+}
+
+impl PiPermission {
+    /// Get the 4-bit field value for PIR_ELx
+    pub const fn as_bits(self) -> u8 {
+        self as u8
+    }
+
+    /// Create from a 4-bit PIR_ELx field, `None` for reserved encodings
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits & 0xF {
+            0b0000 => Some(Self::NoAccess),
+            0b0001 => Some(Self::ReadOverlay),
+            0b0010 => Some(Self::ExecuteOverlay),
+            0b0011 => Some(Self::ReadExecuteOverlay),
+            0b0101 => Some(Self::ReadWriteOverlay),
+            0b0110 => Some(Self::ReadWriteNoExecuteOverlay),
+            0b0111 => Some(Self::ReadWriteExecuteOverlay),
+            0b1000 => Some(Self::Read),
+            0b1001 => Some(Self::ReadGcs),
+            0b1010 => Some(Self::ReadExecute),
+            0b1100 => Some(Self::ReadWrite),
+            0b1110 => Some(Self::ReadWriteExecute),
+            _ => None,
+        }
+    }
+
+    /// Check if this permission allows read access
+    pub const fn allows_read(self) -> bool {
+        !matches!(self, Self::NoAccess | Self::ExecuteOverlay)
+    }
+
+    /// Check if this permission allows write access
+    pub const fn allows_write(self) -> bool {
+        matches!(
+            self,
+            Self::ReadWriteOverlay
+                | Self::ReadWriteNoExecuteOverlay
+                | Self::ReadWriteExecuteOverlay
+                | Self::ReadWrite
+                | Self::ReadWriteExecute
+        )
+    }
+
+    /// Check if this permission allows execution
+    pub const fn allows_execute(self) -> bool {
+        matches!(
+            self,
+            Self::ExecuteOverlay
+                | Self::ReadExecuteOverlay
+                | Self::ReadWriteExecuteOverlay
+                | Self::ReadExecute
+                | Self::ReadWriteExecute
+        )
+    }
+}
+
+/// 4-bit permission index stored in a stage 1 or stage 2 descriptor
+///
+/// The index is assembled from descriptor bits {54, 53, 51, 6}, which are {UXN, PXN, DBM, AP[1]}
+/// at stage 1 and {XN[1], XN[0], DBM, S2AP[0]} at stage 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PiIndex(u8);
+
+impl PiIndex {
+    /// Create a permission index, panics if `index` is not in `0..16`
+    pub const fn new(index: u8) -> Self {
+        assert!(index < 16, "PIIndex must be less than 16");
+        Self(index)
+    }
+
+    /// Get the raw index value
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+}
+
+/// Builder for the value of PIR_EL1, PIRE0_EL1 or the EL2/EL3 equivalents
+///
+/// Every index defaults to [`PiPermission::NoAccess`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pir(u64);
+
+impl Pir {
+    /// Create a PIR value with all indices set to no access
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw register value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Set the permission selected by `index`
+    pub const fn with(self, index: PiIndex, perm: PiPermission) -> Self {
+        let shift = index.value() as u64 * 4;
+        Self((self.0 & !(0xF << shift)) | ((perm.as_bits() as u64) << shift))
+    }
+
+    /// Get the permission selected by `index`, `None` if the field holds a reserved encoding
+    pub const fn get(&self, index: PiIndex) -> Option<PiPermission> {
+        PiPermission::from_bits((self.0 >> (index.value() as u64 * 4)) as u8)
+    }
+
+    /// Get the raw register value
+    pub const fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Stage 1 permission indirection configuration for the EL1&0 translation regime
+///
+/// `privileged` is programmed into PIR_EL1 and `unprivileged` into PIRE0_EL1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PermissionIndirection {
+    pub privileged: Pir,
+    pub unprivileged: Pir,
+}
+
+impl PermissionIndirection {
+    pub const fn new(privileged: Pir, unprivileged: Pir) -> Self {
+        Self {
+            privileged,
+            unprivileged,
+        }
+    }
+
+    /// Resolve the (privileged, unprivileged) permissions for a descriptor index
+    pub const fn resolve(&self, index: PiIndex) -> (Option<PiPermission>, Option<PiPermission>) {
+        (self.privileged.get(index), self.unprivileged.get(index))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl PermissionIndirection {
+    /// Check ID_AA64MMFR3_EL1.S1PIE for FEAT_S1PIE support
+    pub fn is_supported() -> bool {
+        crate::features::is_supported(crate::features::Feature::S1Pie)
+    }
+
+    /// Write PIR_EL1 and PIRE0_EL1 and set TCR2_EL1.PIE
+    ///
+    /// # Safety
+    ///
+    /// Switching the permission model changes how every live stage 1 descriptor is
+    /// interpreted. The caller must ensure the current tables are encoded for indirect
+    /// permissions and invalidate the TLB afterwards.
+    pub unsafe fn apply(&self) {
+        unsafe {
+            // PIR_EL1 / PIRE0_EL1
+            core::arch::asm!("msr S3_0_C10_C2_3, {}", in(reg) self.privileged.value(), options(nostack));
+            core::arch::asm!("msr S3_0_C10_C2_2, {}", in(reg) self.unprivileged.value(), options(nostack));
+            // TCR2_EL1.PIE
+            core::arch::asm!(
+                "mrs {tmp}, S3_0_C2_C0_3",
+                "orr {tmp}, {tmp}, #(1 << 1)",
+                "msr S3_0_C2_C0_3, {tmp}",
+                "isb",
+                tmp = out(reg) _,
+                options(nostack)
+            );
+        }
+    }
+}
+
+/// Permission encoding of a single 4-bit S2PIR_EL2 field
+///
+/// "MostlyReadOnly" (MRO) permissions allow writes only through the TopLevel0/TopLevel1
+/// (TL0/TL1) descriptor hints of the stage 1 walk, as used for protected page tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum S2PiPermission {
+    /// No access
+    NoAccess = 0b0000,
+    /// Mostly read-only
+    MostlyReadOnly = 0b0010,
+    /// Mostly read-only, writable through TopLevel1
+    MostlyReadOnlyTl1 = 0b0011,
+    /// Write-only
+    WriteOnly = 0b0100,
+    /// Mostly read-only, writable through TopLevel0
+    MostlyReadOnlyTl0 = 0b0110,
+    /// Mostly read-only, writable through TopLevel0 and TopLevel1
+    MostlyReadOnlyTl01 = 0b0111,
+    /// Read-only
+    ReadOnly = 0b1000,
+    /// Read-only, unprivileged execute
+    ReadOnlyUx = 0b1001,
+    /// Read-only, privileged execute
+    ReadOnlyPx = 0b1010,
+    /// Read-only, privileged and unprivileged execute
+    ReadOnlyPux = 0b1011,
+    /// Read and write
+    ReadWrite = 0b1100,
+    /// Read and write, unprivileged execute
+    ReadWriteUx = 0b1101,
+    /// Read and write, privileged execute
+    ReadWritePx = 0b1110,
+    /// Read and write, privileged and unprivileged execute
+    ReadWritePux = 0b1111,
+}
+
+impl S2PiPermission {
+    /// Get the 4-bit field value for S2PIR_EL2
+    pub const fn as_bits(self) -> u8 {
+        self as u8
+    }
+
+    /// Create from a 4-bit S2PIR_EL2 field, `None` for reserved encodings
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits & 0xF {
+            0b0000 => Some(Self::NoAccess),
+            0b0010 => Some(Self::MostlyReadOnly),
+            0b0011 => Some(Self::MostlyReadOnlyTl1),
+            0b0100 => Some(Self::WriteOnly),
+            0b0110 => Some(Self::MostlyReadOnlyTl0),
+            0b0111 => Some(Self::MostlyReadOnlyTl01),
+            0b1000 => Some(Self::ReadOnly),
+            0b1001 => Some(Self::ReadOnlyUx),
+            0b1010 => Some(Self::ReadOnlyPx),
+            0b1011 => Some(Self::ReadOnlyPux),
+            0b1100 => Some(Self::ReadWrite),
+            0b1101 => Some(Self::ReadWriteUx),
+            0b1110 => Some(Self::ReadWritePx),
+            0b1111 => Some(Self::ReadWritePux),
+            _ => None,
+        }
+    }
+
+    /// Check if this permission allows execution at EL0
+    pub const fn allows_unprivileged_execute(self) -> bool {
+        matches!(
+            self,
+            Self::ReadOnlyUx | Self::ReadOnlyPux | Self::ReadWriteUx | Self::ReadWritePux
+        )
+    }
+
+    /// Check if this permission allows execution at EL1
+    pub const fn allows_privileged_execute(self) -> bool {
+        matches!(
+            self,
+            Self::ReadOnlyPx | Self::ReadOnlyPux | Self::ReadWritePx | Self::ReadWritePux
+        )
+    }
+}
+
+/// Builder for the value of S2PIR_EL2
+///
+/// Every index defaults to [`S2PiPermission::NoAccess`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct S2Pir(u64);
+
+impl S2Pir {
+    /// Create an S2PIR value with all indices set to no access
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw register value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Set the permission selected by `index`
+    pub const fn with(self, index: PiIndex, perm: S2PiPermission) -> Self {
+        let shift = index.value() as u64 * 4;
+        Self((self.0 & !(0xF << shift)) | ((perm.as_bits() as u64) << shift))
+    }
+
+    /// Get the permission selected by `index`, `None` if the field holds a reserved encoding
+    pub const fn get(&self, index: PiIndex) -> Option<S2PiPermission> {
+        S2PiPermission::from_bits((self.0 >> (index.value() as u64 * 4)) as u8)
+    }
+
+    /// Get the raw register value
+    pub const fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl S2Pir {
+    /// Check ID_AA64MMFR3_EL1.S2PIE for FEAT_S2PIE support
+    pub fn is_supported() -> bool {
+        crate::features::is_supported(crate::features::Feature::S2Pie)
+    }
+
+    /// Write S2PIR_EL2 and set VTCR_EL2.S2PIE
+    ///
+    /// # Safety
+    ///
+    /// Must be called at EL2. Every live stage 2 descriptor must be encoded for indirect
+    /// permissions, and the caller must invalidate stage 2 TLB entries afterwards.
+    pub unsafe fn apply(&self) {
+        unsafe {
+            // S2PIR_EL2
+            core::arch::asm!("msr S3_4_C10_C2_5, {}", in(reg) self.0, options(nostack));
+            // VTCR_EL2.S2PIE
+            core::arch::asm!(
+                "mrs {tmp}, vtcr_el2",
+                "orr {tmp}, {tmp}, #(1 << 36)",
+                "msr vtcr_el2, {tmp}",
+                "isb",
+                tmp = out(reg) _,
+                options(nostack)
+            );
+        }
+    }
+}
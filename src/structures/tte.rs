@@ -3,6 +3,8 @@ use core::marker::PhantomData;
 /// This module defines the Translation Table Entry (TTE) structure used in AArch64 architecture.
 use tock_registers::{LocalRegisterCopy, register_bitfields};
 
+use super::{mapping::MappingRequest, pie::PiIndex};
+
 pub trait Granule: Clone + Copy {
     const M: u32;
     const SIZE: usize = 2usize.pow(Self::M);
@@ -112,6 +114,100 @@ pub enum Shareability {
     InnerShareable,
 }
 
+/// Physical address space selected by the NSE/NS descriptor bits (FEAT_RME)
+///
+/// In the EL3 translation regime bit[11] is NSE instead of nG, and {NSE, NS} select
+/// the output address space. Realm EL1&0/EL2 regimes use NS alone to pick Realm or Non-secure.
+#[cfg(feature = "rme")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PhysicalAddressSpace {
+    /// {NSE, NS} = 0b00
+    Secure = 0b00,
+    /// {NSE, NS} = 0b01
+    NonSecure = 0b01,
+    /// {NSE, NS} = 0b10
+    Root = 0b10,
+    /// {NSE, NS} = 0b11
+    Realm = 0b11,
+}
+
+#[cfg(feature = "rme")]
+impl PhysicalAddressSpace {
+    /// Get the {NSE, NS} bit pair
+    pub const fn as_bits(self) -> u8 {
+        self as u8
+    }
+
+    /// Create from the {NSE, NS} bit pair
+    pub const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::Secure,
+            0b01 => Self::NonSecure,
+            0b10 => Self::Root,
+            _ => Self::Realm,
+        }
+    }
+}
+
+/// Dirty state of a stage 1 descriptor as tracked by FEAT_HAFDBS
+///
+/// With DBM = 1 the hardware clears AP[2] on the first write, so AP[2] doubles as a
+/// "clean" bit: AP[2] = 1 means writable but not yet written, AP[2] = 0 means dirty.
+/// Based on ARM DDI 0487K.a "Hardware management of the dirty state"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DirtyState {
+    /// DBM = 0, AP[2] = 1: read-only, no dirty tracking
+    ReadOnly,
+    /// DBM = 0, AP[2] = 0: writable without hardware tracking, must be treated as dirty
+    Writable,
+    /// DBM = 1, AP[2] = 1: writable, not written since last cleaned
+    Clean,
+    /// DBM = 1, AP[2] = 0: written by hardware since last cleaned
+    Dirty,
+}
+
+impl DirtyState {
+    /// Check if the page content may differ from its backing store
+    pub const fn may_be_dirty(self) -> bool {
+        matches!(self, Self::Writable | Self::Dirty)
+    }
+}
+
+/// Hardware update support for the access flag and dirty state (ID_AA64MMFR1_EL1.HAFDBS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HwUpdateSupport {
+    /// Software must manage both the access flag and dirty state
+    None,
+    /// Hardware updates the access flag (TCR_ELx.HA)
+    AccessFlag,
+    /// Hardware updates the access flag and dirty state (TCR_ELx.HA and TCR_ELx.HD)
+    AccessFlagAndDirty,
+}
+
+impl HwUpdateSupport {
+    /// Read ID_AA64MMFR1_EL1.HAFDBS
+    #[cfg(target_arch = "aarch64")]
+    pub fn detect() -> Self {
+        use aarch64_cpu::registers::{ID_AA64MMFR1_EL1, Readable};
+
+        match ID_AA64MMFR1_EL1.read(ID_AA64MMFR1_EL1::HAFDBS) {
+            0 => Self::None,
+            1 => Self::AccessFlag,
+            _ => Self::AccessFlagAndDirty,
+        }
+    }
+
+    /// Check if hardware can set the access flag
+    pub const fn access_flag(self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    /// Check if hardware can track the dirty state through DBM
+    pub const fn dirty_state(self) -> bool {
+        matches!(self, Self::AccessFlagAndDirty)
+    }
+}
+
 register_bitfields![u64,
     /// Translation Table Entry for AArch64
     /// Based on ARMv8-A Architecture Reference Manual
@@ -162,6 +258,7 @@ register_bitfields![u64,
         ],
 
         /// Not global bit
+        /// In the EL3 regime with FEAT_RME this is the NSE bit instead
         NG OFFSET(11) NUMBITS(1) [
             Global = 0,
             NotGlobal = 1
@@ -238,6 +335,30 @@ impl<G: Granule, O: OA> TTE64<G, O> {
         tte
     }
 
+    /// Create a block or page entry at `block_addr` with the attributes of `req`
+    ///
+    /// `block_addr` is the output address of this particular entry, which lies inside
+    /// the physical range of `req` when a mapping spans several entries.
+    pub fn new_block_mapping(block_addr: u64, req: &MappingRequest) -> Self {
+        let mut tte = Self::new_block(block_addr);
+        tte.apply_mapping(req);
+        tte
+    }
+
+    /// Apply the attributes, permissions, shareability and global-ness of `req`
+    pub fn apply_mapping(&mut self, req: &MappingRequest) {
+        self.set_attr_index(req.attr_index);
+        self.set_access_permission(req.access);
+        self.set_shareability(req.shareability);
+        self.set_executable(req.executable);
+        self.set_privileged_executable(req.privileged_executable);
+        if req.global {
+            self.reg.modify(TTE64_REG::NG::Global);
+        } else {
+            self.set_not_global();
+        }
+    }
+
     /// Get the raw u64 value
     pub fn get(&self) -> u64 {
         self.reg.get()
@@ -469,11 +590,116 @@ impl<G: Granule, O: OA> TTE64<G, O> {
         self.reg.modify(TTE64_REG::NG::NotGlobal);
     }
 
+    /// Get the output physical address space from the NSE/NS bits (EL3 regime, FEAT_RME)
+    #[cfg(feature = "rme")]
+    pub fn physical_address_space(&self) -> PhysicalAddressSpace {
+        let bits = (self.reg.read(TTE64_REG::NG) << 1) | self.reg.read(TTE64_REG::NS);
+        PhysicalAddressSpace::from_bits(bits as u8)
+    }
+
+    /// Set the output physical address space through the NSE/NS bits (EL3 regime, FEAT_RME)
+    ///
+    /// This overwrites bit[11], which is nG in every other translation regime.
+    #[cfg(feature = "rme")]
+    pub fn set_physical_address_space(&mut self, pas: PhysicalAddressSpace) {
+        let bits = pas.as_bits() as u64;
+        self.reg
+            .modify(TTE64_REG::NG.val(bits >> 1) + TTE64_REG::NS.val(bits & 1));
+    }
+
+    /// Get the dirty state encoded by DBM and AP[2]
+    pub fn dirty_state(&self) -> DirtyState {
+        let read_only = self.reg.read(TTE64_REG::AP) & 0b10 != 0;
+        match (self.is_dirty_writable(), read_only) {
+            (false, true) => DirtyState::ReadOnly,
+            (false, false) => DirtyState::Writable,
+            (true, true) => DirtyState::Clean,
+            (true, false) => DirtyState::Dirty,
+        }
+    }
+
+    /// Check if the hardware has recorded a write through this entry (DBM = 1, AP[2] = 0)
+    pub fn is_hw_dirty(&self) -> bool {
+        self.dirty_state() == DirtyState::Dirty
+    }
+
+    /// Enable or disable hardware dirty state tracking by setting DBM
+    ///
+    /// Enabling tracking on a writable entry does not change its current state, call
+    /// [`make_clean`](Self::make_clean) to start tracking writes from now on.
+    pub fn set_dirty_writable(&mut self, val: bool) {
+        if val {
+            self.reg.modify(TTE64_REG::DBM::Writable);
+        } else {
+            self.reg.modify(TTE64_REG::DBM::ReadOnly);
+        }
+    }
+
+    /// Mark a hardware tracked entry as clean by setting AP[2], keeping DBM
+    ///
+    /// Returns the previous state. Entries without DBM are left unchanged.
+    ///
+    /// This only changes the local copy. When updating a live descriptor the caller must:
+    /// - write it back with a compare-and-swap against the value this copy was read from,
+    ///   since the hardware may concurrently clear AP[2] on a write
+    /// - invalidate the TLB entries for the VA (e.g. TLBI VAE1IS) followed by DSB ISH
+    ///   before relying on the clean state, because cached entries may still permit
+    ///   writes without updating the descriptor
+    ///
+    /// No break-before-make sequence is needed, as only the permission bits change.
+    pub fn make_clean(&mut self) -> DirtyState {
+        let prev = self.dirty_state();
+        if self.is_dirty_writable() {
+            let ap = self.reg.read(TTE64_REG::AP) | 0b10;
+            self.reg.modify(TTE64_REG::AP.val(ap));
+        }
+        prev
+    }
+
     /// Check if dirty bit modifier is set (ARMv8.1+)
     pub fn is_dirty_writable(&self) -> bool {
         self.reg.is_set(TTE64_REG::DBM)
     }
 
+    /// Get the permission index used when stage 1 permission indirection is enabled
+    ///
+    /// PIIndex[3:0] is assembled from {UXN, PXN, DBM, AP[1]}.
+    pub fn pi_index(&self) -> PiIndex {
+        let index = (self.reg.read(TTE64_REG::XN_UXN) << 3)
+            | (self.reg.read(TTE64_REG::PXN) << 2)
+            | (self.reg.read(TTE64_REG::DBM) << 1)
+            | (self.reg.read(TTE64_REG::AP) & 0b1);
+        PiIndex::new(index as u8)
+    }
+
+    /// Set the permission index used when stage 1 permission indirection is enabled
+    ///
+    /// This reuses the UXN, PXN, DBM and AP[1] bits, so it must not be mixed with the
+    /// direct permission setters on the same entry.
+    pub fn set_pi_index(&mut self, index: PiIndex) {
+        let index = index.value() as u64;
+        let ap = (self.reg.read(TTE64_REG::AP) & 0b10) | (index & 0b1);
+        self.reg.modify(
+            TTE64_REG::XN_UXN.val((index >> 3) & 1)
+                + TTE64_REG::PXN.val((index >> 2) & 1)
+                + TTE64_REG::DBM.val((index >> 1) & 1)
+                + TTE64_REG::AP.val(ap),
+        );
+    }
+
+    /// Get the permission index used when stage 2 permission indirection is enabled
+    ///
+    /// S2PIIndex[3:0] is assembled from {XN[1], XN[0], DBM, S2AP[0]}, the same descriptor
+    /// bits as the stage 1 index.
+    pub fn s2_pi_index(&self) -> PiIndex {
+        self.pi_index()
+    }
+
+    /// Set the permission index used when stage 2 permission indirection is enabled
+    pub fn set_s2_pi_index(&mut self, index: PiIndex) {
+        self.set_pi_index(index)
+    }
+
     /// Get the software reserved bits
     pub fn sw_reserved(&self) -> u64 {
         self.reg.read(TTE64_REG::SW_RESERVED)
@@ -528,6 +754,105 @@ pub mod block_sizes {
     }
 }
 
+/// A chunk of a range produced by [`split_range`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RangeChunk {
+    /// Virtual start address of the chunk
+    pub va: u64,
+    /// Physical start address of the chunk
+    pub pa: u64,
+    /// Size of the chunk, equal to the block or page size of `level`
+    pub size: usize,
+    /// Translation table level at which the chunk can be mapped
+    pub level: usize,
+}
+
+/// Iterator over the chunks of a range, see [`split_range`]
+#[derive(Clone)]
+pub struct SplitRange<G: Granule> {
+    va: u64,
+    pa: u64,
+    remaining: usize,
+    _marker: PhantomData<G>,
+}
+
+impl<G: Granule> SplitRange<G> {
+    /// Block and page sizes for the granule, largest first, with their level
+    ///
+    /// Level 0 (4KB) and level 1 (16KB/64KB) blocks require 52-bit output addresses
+    /// and are not used here.
+    fn block_sizes() -> &'static [(usize, usize)] {
+        use block_sizes::*;
+        match G::M {
+            12 => &[
+                (granule_4k::LEVEL1_BLOCK_SIZE, 1),
+                (granule_4k::LEVEL2_BLOCK_SIZE, 2),
+                (granule_4k::LEVEL3_PAGE_SIZE, 3),
+            ],
+            14 => &[
+                (granule_16k::LEVEL2_BLOCK_SIZE, 2),
+                (granule_16k::LEVEL3_PAGE_SIZE, 3),
+            ],
+            16 => &[
+                (granule_64k::LEVEL2_BLOCK_SIZE, 2),
+                (granule_64k::LEVEL3_PAGE_SIZE, 3),
+            ],
+            _ => panic!("Invalid granule size"),
+        }
+    }
+}
+
+impl<G: Granule> Iterator for SplitRange<G> {
+    type Item = RangeChunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let &(size, level) = Self::block_sizes()
+            .iter()
+            .find(|&&(size, _)| {
+                let mask = size as u64 - 1;
+                self.va & mask == 0 && self.pa & mask == 0 && self.remaining >= size
+            })
+            .expect("range must be aligned to the granule size");
+
+        let chunk = RangeChunk {
+            va: self.va,
+            pa: self.pa,
+            size,
+            level,
+        };
+        // The last chunk may end at the top of the address space
+        self.va = self.va.wrapping_add(size as u64);
+        self.pa = self.pa.wrapping_add(size as u64);
+        self.remaining -= size;
+        Some(chunk)
+    }
+}
+
+/// Split `[va, va + len)` mapped to `pa` into chunks using the largest block or page
+/// size that both addresses are aligned to and that fits in the remaining length
+///
+/// `va`, `pa` and `len` must be aligned to the granule size.
+pub fn split_range<G: Granule>(va: u64, pa: u64, len: usize) -> SplitRange<G> {
+    assert!(
+        va & G::MASK == 0 && pa & G::MASK == 0,
+        "Addresses must be aligned to granule size"
+    );
+    assert!(
+        len as u64 & G::MASK == 0,
+        "Length must be a multiple of granule size"
+    );
+    SplitRange {
+        va,
+        pa,
+        remaining: len,
+        _marker: PhantomData,
+    }
+}
+
 /// Helper functions for address calculations
 impl<G: Granule, O: OA> TTE64<G, O> {
     /// Calculate the index for a virtual address at a given level
@@ -568,8 +893,11 @@ impl<G: Granule, O: OA> TTE64<G, O> {
 }
 
 #[cfg(test)]
+#[allow(clippy::upper_case_acronyms)]
 mod tests {
     use super::*;
+    use crate::structures::mapping::MappingRequest;
+    use crate::structures::pie::{PiPermission, Pir, S2PiPermission, S2Pir};
 
     #[test]
     fn test_address_extraction_4k_48bit() {
@@ -699,4 +1027,163 @@ mod tests {
         assert_eq!(Granule16KB::MASK, 0x3FFF);
         assert_eq!(Granule64KB::MASK, 0xFFFF);
     }
+
+    #[test]
+    fn test_pi_index_roundtrip() {
+        type TTE = TTE4K48;
+
+        for i in 0..16 {
+            let mut tte = TTE::new_block(0x4000_0000);
+            tte.set_access_permission(AccessPermission::PrivilegedReadOnly);
+            tte.set_pi_index(PiIndex::new(i));
+            assert_eq!(tte.pi_index().value(), i);
+            // AP[2] is not part of the index and must be preserved
+            assert_eq!(tte.get() & (1 << 7), 1 << 7);
+            assert_eq!(tte.address_with_page_level(1), 0x4000_0000);
+        }
+    }
+
+    #[test]
+    fn test_pir_builder() {
+        let pir = Pir::new()
+            .with(PiIndex::new(0), PiPermission::ReadWrite)
+            .with(PiIndex::new(15), PiPermission::ReadExecute)
+            .with(PiIndex::new(0), PiPermission::Read);
+        assert_eq!(pir.value(), 0xA000_0000_0000_0008);
+        assert_eq!(pir.get(PiIndex::new(0)), Some(PiPermission::Read));
+        assert_eq!(pir.get(PiIndex::new(1)), Some(PiPermission::NoAccess));
+        assert_eq!(pir.get(PiIndex::new(15)), Some(PiPermission::ReadExecute));
+        assert_eq!(Pir::from_value(0xF).get(PiIndex::new(0)), None);
+    }
+
+    #[test]
+    fn test_s2pir_builder() {
+        let s2pir = S2Pir::new()
+            .with(PiIndex::new(1), S2PiPermission::ReadWritePux)
+            .with(PiIndex::new(2), S2PiPermission::MostlyReadOnly);
+        assert_eq!(s2pir.value(), 0x2F0);
+        assert_eq!(
+            s2pir.get(PiIndex::new(1)),
+            Some(S2PiPermission::ReadWritePux)
+        );
+        assert_eq!(S2Pir::from_value(0x1).get(PiIndex::new(0)), None);
+
+        let mut tte = TTE4K48::new_block(0x20_0000);
+        tte.set_s2_pi_index(PiIndex::new(0b1010));
+        assert_eq!(tte.get() & ((1 << 54) | (1 << 51)), (1 << 54) | (1 << 51));
+        assert_eq!(tte.s2_pi_index(), PiIndex::new(0b1010));
+    }
+
+    #[cfg(feature = "rme")]
+    #[test]
+    fn test_physical_address_space() {
+        let mut tte = TTE4K48::new_block(0x8000_0000);
+        assert_eq!(tte.physical_address_space(), PhysicalAddressSpace::Secure);
+
+        for pas in [
+            PhysicalAddressSpace::NonSecure,
+            PhysicalAddressSpace::Root,
+            PhysicalAddressSpace::Realm,
+            PhysicalAddressSpace::Secure,
+        ] {
+            tte.set_physical_address_space(pas);
+            assert_eq!(tte.physical_address_space(), pas);
+            assert_eq!(tte.address_with_page_level(1), 0x8000_0000);
+        }
+
+        tte.set_physical_address_space(PhysicalAddressSpace::Realm);
+        assert_eq!(tte.get() & ((1 << 11) | (1 << 5)), (1 << 11) | (1 << 5));
+    }
+
+    #[test]
+    fn test_block_from_mapping_request() {
+        let mut req = MappingRequest::new(0xffff_0000_4000_0000, 0x4000_0000, 0x20_0000);
+        req.attr_index = 2;
+        req.access = AccessPermission::ReadOnly;
+        req.executable = true;
+        req.global = false;
+        assert_eq!(req.translate(0xffff_0000_4000_1234), Some(0x4000_1234));
+        assert_eq!(req.translate(req.va_end()), None);
+        assert!(req.is_valid());
+
+        // The last 2MB of the address space
+        let top = MappingRequest::new(0xffff_ffff_ffe0_0000, 0x4000_0000, 0x20_0000);
+        assert!(top.is_valid());
+        assert_eq!(top.va_end(), u64::MAX);
+        assert_eq!(top.translate(u64::MAX), Some(0x401F_FFFF));
+        assert!(!MappingRequest::new(0xffff_ffff_fff0_0000, 0, 0x20_0000).is_valid());
+
+        let tte = TTE4K48::new_block_mapping(req.pa, &req);
+        assert!(tte.is_block());
+        assert_eq!(tte.address_with_page_level(2), 0x4000_0000);
+        assert_eq!(tte.attr_index(), 2);
+        assert_eq!(tte.access_permission(), AccessPermission::ReadOnly);
+        assert_eq!(tte.shareability(), Shareability::InnerShareable);
+        assert!(tte.is_executable());
+        assert!(!tte.is_privileged_executable());
+        assert!(!tte.is_global());
+    }
+
+    #[test]
+    fn test_split_range_4k() {
+        const K4: usize = 4 * 1024;
+        const M2: usize = 2 * 1024 * 1024;
+        const G1: usize = 1024 * 1024 * 1024;
+
+        // 4KB page up to the 2MB boundary, then 2MB blocks up to 1GB, one 1GB block, one page
+        let va = 0x3FE0_0000 - K4 as u64;
+        let len = K4 + M2 + G1 + K4;
+        let chunks: Vec<_> = split_range::<Granule4KB>(va, va, len).collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!((chunks[0].size, chunks[0].level), (K4, 3));
+        assert_eq!((chunks[1].size, chunks[1].level), (M2, 2));
+        assert_eq!(
+            (chunks[2].va, chunks[2].size, chunks[2].level),
+            (0x4000_0000, G1, 1)
+        );
+        assert_eq!((chunks[3].size, chunks[3].level), (K4, 3));
+        assert_eq!(chunks.iter().map(|c| c.size).sum::<usize>(), len);
+
+        // Misaligned VA and PA offsets prevent block mappings
+        let chunks: Vec<_> = split_range::<Granule4KB>(0x20_0000, 0x20_1000, M2).collect();
+        assert_eq!(chunks.len(), 512);
+        assert!(chunks.iter().all(|c| c.level == 3 && c.pa - c.va == 0x1000));
+
+        // The last chunk may end at the top of the address space
+        let top = u64::MAX - M2 as u64 + 1;
+        let chunks: Vec<_> = split_range::<Granule4KB>(top, top, M2).collect();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].va, chunks[0].level), (top, 2));
+    }
+
+    #[test]
+    fn test_split_range_64k() {
+        const M512: usize = 512 * 1024 * 1024;
+
+        let chunks: Vec<_> =
+            split_range::<Granule64KB>(0x2000_0000, 0x4000_0000, M512 + 0x10000).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].size, chunks[0].level), (M512, 2));
+        assert_eq!((chunks[1].pa, chunks[1].level), (0x6000_0000, 3));
+    }
+
+    #[test]
+    fn test_dirty_state() {
+        let mut tte = TTE4K48::new_block(0x1000);
+        tte.set_access_permission(AccessPermission::ReadWrite);
+        assert_eq!(tte.dirty_state(), DirtyState::Writable);
+        assert_eq!(tte.make_clean(), DirtyState::Writable);
+        assert_eq!(tte.dirty_state(), DirtyState::Writable);
+
+        tte.set_dirty_writable(true);
+        assert!(tte.is_hw_dirty());
+        assert_eq!(tte.make_clean(), DirtyState::Dirty);
+        assert_eq!(tte.dirty_state(), DirtyState::Clean);
+        // Cleaning keeps AP[1], so EL0 access is unchanged
+        assert_eq!(tte.access_permission(), AccessPermission::ReadOnly);
+
+        tte.set_dirty_writable(false);
+        assert_eq!(tte.dirty_state(), DirtyState::ReadOnly);
+        assert!(!tte.dirty_state().may_be_dirty());
+    }
 }
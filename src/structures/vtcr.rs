@@ -0,0 +1,423 @@
+//! VTCR_EL2, the stage 2 translation control register.
+//!
+//! The stage 2 starting level (SL0, SL2) has to match the IPA size (T0SZ): the starting
+//! level must resolve the IPA bits left over by the levels below it, using at most 16
+//! concatenated tables. A mismatch is not reported when the register is written, every
+//! stage 2 walk faults instead. [`VtcrEl2Builder::build`] checks the combination, and
+//! [`VtcrEl2Builder::new`] picks the starting level with the fewest levels.
+
+use core::marker::PhantomData;
+
+use super::tte::{Granule, Shareability};
+
+/// Size of the physical address space (VTCR_EL2.PS, ID_AA64MMFR0_EL1.PARange)
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PhysicalAddressSize {
+    Bits32 = 0b000,
+    Bits36 = 0b001,
+    Bits40 = 0b010,
+    Bits42 = 0b011,
+    Bits44 = 0b100,
+    Bits48 = 0b101,
+    Bits52 = 0b110,
+}
+
+impl PhysicalAddressSize {
+    /// Decode a PS or PARange value, `None` for reserved encodings
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b000 => Some(Self::Bits32),
+            0b001 => Some(Self::Bits36),
+            0b010 => Some(Self::Bits40),
+            0b011 => Some(Self::Bits42),
+            0b100 => Some(Self::Bits44),
+            0b101 => Some(Self::Bits48),
+            0b110 => Some(Self::Bits52),
+            _ => None,
+        }
+    }
+
+    /// Smallest size covering `bits` address bits, `None` above 52 bits
+    pub const fn covering(bits: u8) -> Option<Self> {
+        match bits {
+            0..=32 => Some(Self::Bits32),
+            33..=36 => Some(Self::Bits36),
+            37..=40 => Some(Self::Bits40),
+            41..=42 => Some(Self::Bits42),
+            43..=44 => Some(Self::Bits44),
+            45..=48 => Some(Self::Bits48),
+            49..=52 => Some(Self::Bits52),
+            _ => None,
+        }
+    }
+
+    /// Number of address bits
+    pub const fn bits(self) -> u8 {
+        match self {
+            Self::Bits32 => 32,
+            Self::Bits36 => 36,
+            Self::Bits40 => 40,
+            Self::Bits42 => 42,
+            Self::Bits44 => 44,
+            Self::Bits48 => 48,
+            Self::Bits52 => 52,
+        }
+    }
+}
+
+/// Cacheability of translation table walks (IRGN0, ORGN0)
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WalkCacheability {
+    NonCacheable = 0b00,
+    WriteBackWriteAllocate = 0b01,
+    WriteThrough = 0b10,
+    WriteBackNoWriteAllocate = 0b11,
+}
+
+/// Invalid VTCR_EL2 configurations rejected by [`VtcrEl2Builder::build`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtcrError {
+    /// The IPA size is outside 25 to 52 bits, or above 48 bits without 64KB pages or
+    /// LPA2
+    IpaSize,
+    /// The starting level does not exist for the granule, or cannot resolve the IPA size
+    /// with at most 16 concatenated tables
+    StartLevel,
+    /// The IPA size exceeds the physical address size (PS)
+    PhysicalAddressSize,
+}
+
+/// Builder for VTCR_EL2, generic over the stage 2 translation granule
+///
+/// Defaults to write-back inner shareable table walks, 8-bit VMIDs and the smallest
+/// physical address size covering the IPA size.
+#[derive(Clone, Copy)]
+pub struct VtcrEl2Builder<G: Granule> {
+    ipa_bits: u8,
+    start_level: i8,
+    ps: Option<PhysicalAddressSize>,
+    shareability: Shareability,
+    inner: WalkCacheability,
+    outer: WalkCacheability,
+    vmid16: bool,
+    hw_access_flag: bool,
+    hw_dirty_state: bool,
+    lpa2: bool,
+    _granule: PhantomData<G>,
+}
+
+impl<G: Granule> VtcrEl2Builder<G> {
+    const T0SZ_MASK: u64 = 0x3F;
+    const SL0_SHIFT: u64 = 6;
+    const IRGN0_SHIFT: u64 = 8;
+    const ORGN0_SHIFT: u64 = 10;
+    const SH0_SHIFT: u64 = 12;
+    const TG0_SHIFT: u64 = 14;
+    const PS_SHIFT: u64 = 16;
+    const VS: u64 = 1 << 19;
+    const HA: u64 = 1 << 21;
+    const HD: u64 = 1 << 22;
+    const RES1: u64 = 1 << 31;
+    const DS: u64 = 1 << 32;
+    const SL2: u64 = 1 << 33;
+
+    /// Bits resolved by one table level
+    const STRIDE: u32 = G::M - 3;
+
+    /// Stage 2 translation of `ipa_bits` wide IPAs, starting at the level with the fewest
+    /// levels to walk
+    ///
+    /// Starting levels that resolve more than one table's worth of bits use concatenated
+    /// tables, see [`root_tables`](Self::root_tables).
+    pub const fn new(ipa_bits: u8) -> Self {
+        let mut start_level = Self::max_start_level();
+        while start_level > -1
+            && Self::start_bits(ipa_bits, start_level) > Self::max_start_bits(start_level)
+        {
+            start_level -= 1;
+        }
+        Self {
+            ipa_bits,
+            start_level,
+            ps: None,
+            shareability: Shareability::InnerShareable,
+            inner: WalkCacheability::WriteBackWriteAllocate,
+            outer: WalkCacheability::WriteBackWriteAllocate,
+            vmid16: false,
+            hw_access_flag: false,
+            hw_dirty_state: false,
+            lpa2: false,
+            _granule: PhantomData,
+        }
+    }
+
+    /// Deepest level a stage 2 walk can start at without FEAT_TTST
+    const fn max_start_level() -> i8 {
+        if G::M == 12 { 2 } else { 3 }
+    }
+
+    /// IPA bits left for the starting level
+    const fn start_bits(ipa_bits: u8, level: i8) -> i32 {
+        ipa_bits as i32 - (G::M + (3 - level as i32) as u32 * Self::STRIDE) as i32
+    }
+
+    /// Bits the starting level can resolve, levels 1 to 3 allow up to 16 concatenated tables
+    const fn max_start_bits(level: i8) -> i32 {
+        if level >= 1 {
+            Self::STRIDE as i32 + 4
+        } else {
+            Self::STRIDE as i32
+        }
+    }
+
+    /// Start the walk at `level`, -1 to 3 (SL0, SL2)
+    ///
+    /// Level 3 with 4KB pages requires FEAT_TTST, level -1 with 4KB pages and level 0 with
+    /// 16KB pages require [`lpa2`](Self::lpa2).
+    pub const fn start_level(mut self, level: i8) -> Self {
+        self.start_level = level;
+        self
+    }
+
+    /// Size of the physical address space the stage 2 output addresses (PS)
+    pub const fn physical_address_size(mut self, ps: PhysicalAddressSize) -> Self {
+        self.ps = Some(ps);
+        self
+    }
+
+    /// Shareability of the table walks (SH0)
+    pub const fn shareability(mut self, shareability: Shareability) -> Self {
+        self.shareability = shareability;
+        self
+    }
+
+    /// Inner cacheability of the table walks (IRGN0)
+    pub const fn inner_cacheability(mut self, cacheability: WalkCacheability) -> Self {
+        self.inner = cacheability;
+        self
+    }
+
+    /// Outer cacheability of the table walks (ORGN0)
+    pub const fn outer_cacheability(mut self, cacheability: WalkCacheability) -> Self {
+        self.outer = cacheability;
+        self
+    }
+
+    /// Use 16-bit VMIDs (VS), see [`VmidAllocator`](crate::vmid::VmidAllocator)
+    pub const fn vmid16(mut self, enable: bool) -> Self {
+        self.vmid16 = enable;
+        self
+    }
+
+    /// Hardware updates of the access flag in stage 2 descriptors (HA)
+    pub const fn hw_access_flag(mut self, enable: bool) -> Self {
+        self.hw_access_flag = enable;
+        self
+    }
+
+    /// Hardware updates of the dirty state in stage 2 descriptors (HD)
+    pub const fn hw_dirty_state(mut self, enable: bool) -> Self {
+        self.hw_dirty_state = enable;
+        self
+    }
+
+    /// 52-bit addresses with 4KB and 16KB pages (DS), requires FEAT_LPA2
+    pub const fn lpa2(mut self, enable: bool) -> Self {
+        self.lpa2 = enable;
+        self
+    }
+
+    /// Number of concatenated tables at the starting level, the root table is this many
+    /// granules large and aligned to its size
+    pub const fn root_tables(&self) -> usize {
+        let bits = Self::start_bits(self.ipa_bits, self.start_level);
+        if bits > Self::STRIDE as i32 {
+            1 << (bits - Self::STRIDE as i32)
+        } else {
+            1
+        }
+    }
+
+    /// Size of the root table in bytes, including concatenated tables
+    ///
+    /// VTTBR_EL2.BADDR must be aligned to this size.
+    pub const fn root_table_size(&self) -> usize {
+        let bits = Self::start_bits(self.ipa_bits, self.start_level);
+        if bits < 3 { 64 } else { 8 << bits }
+    }
+
+    /// Width of the VMIDs in VTTBR_EL2, 16 with [`vmid16`](Self::vmid16), 8 otherwise
+    pub const fn vmid_bits(&self) -> u32 {
+        if self.vmid16 { 16 } else { 8 }
+    }
+
+    /// SL0 and SL2 of the starting level, `None` if the granule cannot start there
+    const fn start_level_bits(&self) -> Option<(u64, bool)> {
+        match (G::M, self.start_level) {
+            (12, -1) if self.lpa2 => Some((0b00, true)),
+            (12, 0..=2) => Some(((2 - self.start_level) as u64, false)),
+            (12, 3) => Some((0b11, false)),
+            (14, 0) if self.lpa2 => Some((0b11, false)),
+            (14, 1..=3) | (16, 1..=3) => Some(((3 - self.start_level) as u64, false)),
+            _ => None,
+        }
+    }
+
+    /// Check the configuration and return the VTCR_EL2 value
+    pub const fn build(&self) -> Result<u64, VtcrError> {
+        let max_ipa = if G::M == 16 || self.lpa2 { 52 } else { 48 };
+        if self.ipa_bits < 25 || self.ipa_bits > max_ipa {
+            return Err(VtcrError::IpaSize);
+        }
+        let Some((sl0, sl2)) = self.start_level_bits() else {
+            return Err(VtcrError::StartLevel);
+        };
+        let bits = Self::start_bits(self.ipa_bits, self.start_level);
+        if bits < 1 || bits > Self::max_start_bits(self.start_level) {
+            return Err(VtcrError::StartLevel);
+        }
+        let ps = match self.ps {
+            Some(ps) if ps.bits() < self.ipa_bits => {
+                return Err(VtcrError::PhysicalAddressSize);
+            }
+            Some(ps) => ps,
+            None => match PhysicalAddressSize::covering(self.ipa_bits) {
+                Some(ps) => ps,
+                None => return Err(VtcrError::IpaSize),
+            },
+        };
+
+        let tg0: u64 = match G::M {
+            12 => 0b00,
+            16 => 0b01,
+            _ => 0b10,
+        };
+        let sh0: u64 = match self.shareability {
+            Shareability::NonShareable => 0b00,
+            Shareability::OuterShareable => 0b10,
+            Shareability::InnerShareable => 0b11,
+        };
+        let mut value = Self::RES1
+            | ((64 - self.ipa_bits as u64) & Self::T0SZ_MASK)
+            | (sl0 << Self::SL0_SHIFT)
+            | ((self.inner as u64) << Self::IRGN0_SHIFT)
+            | ((self.outer as u64) << Self::ORGN0_SHIFT)
+            | (sh0 << Self::SH0_SHIFT)
+            | (tg0 << Self::TG0_SHIFT)
+            | ((ps as u64) << Self::PS_SHIFT);
+        if self.vmid16 {
+            value |= Self::VS;
+        }
+        if self.hw_access_flag {
+            value |= Self::HA;
+        }
+        if self.hw_dirty_state {
+            value |= Self::HD;
+        }
+        if self.lpa2 {
+            value |= Self::DS;
+        }
+        if sl2 {
+            value |= Self::SL2;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::{
+        asm::barrier::{SY, isb},
+        registers::{ID_AA64MMFR0_EL1, Readable, VTCR_EL2, Writeable},
+    };
+
+    use super::{PhysicalAddressSize, VtcrEl2Builder, VtcrError};
+    use crate::structures::tte::Granule;
+
+    impl PhysicalAddressSize {
+        /// Physical address size implemented by the core (ID_AA64MMFR0_EL1.PARange)
+        pub fn supported() -> Self {
+            Self::from_bits(ID_AA64MMFR0_EL1.read(ID_AA64MMFR0_EL1::PARange) as u8)
+                .unwrap_or(Self::Bits52)
+        }
+    }
+
+    impl<G: Granule> VtcrEl2Builder<G> {
+        /// Check the configuration and write it to VTCR_EL2
+        ///
+        /// Clears VTCR_EL2.S2PIE, apply [`S2Pir`](crate::structures::pie::S2Pir) afterwards.
+        ///
+        /// # Safety
+        ///
+        /// Must be called at EL2. The stage 2 tables in VTTBR_EL2 must match the new
+        /// configuration before the next stage 2 walk, and stage 2 TLB entries created
+        /// with the old configuration must be invalidated.
+        pub unsafe fn write(&self) -> Result<(), VtcrError> {
+            VTCR_EL2.set(self.build()?);
+            isb(SY);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::tte::{Granule4KB, Granule16KB, Granule64KB};
+
+    #[test]
+    fn test_start_level() {
+        // 40-bit IPA with 4KB pages: level 1 with 2 concatenated tables
+        let vtcr = VtcrEl2Builder::<Granule4KB>::new(40);
+        assert_eq!(vtcr.root_tables(), 2);
+        assert_eq!(vtcr.build(), Ok(0x8002_3558));
+
+        // 48-bit IPA with 4KB pages cannot concatenate at level 0
+        let vtcr = VtcrEl2Builder::<Granule4KB>::new(48);
+        assert_eq!(vtcr.root_tables(), 1);
+        assert_eq!(vtcr.build().unwrap() & 0xFF, 0x90);
+        assert_eq!(vtcr.start_level(1).build(), Err(VtcrError::StartLevel));
+
+        // 45-bit IPA with 64KB pages: level 2 with 8 concatenated tables
+        let vtcr = VtcrEl2Builder::<Granule64KB>::new(45);
+        assert_eq!(vtcr.root_tables(), 8);
+        assert_eq!(vtcr.build(), Ok(0x8005_7553));
+
+        // 16KB level 0 only with LPA2
+        let vtcr = VtcrEl2Builder::<Granule16KB>::new(48).start_level(0);
+        assert_eq!(vtcr.build(), Err(VtcrError::StartLevel));
+        assert!(vtcr.lpa2(true).build().is_ok());
+
+        // Level -1 for 52-bit IPAs with 4KB pages and LPA2
+        let vtcr = VtcrEl2Builder::<Granule4KB>::new(52).lpa2(true);
+        assert_eq!(vtcr.start_level, -1);
+        assert_eq!(vtcr.build().unwrap() & (0b11 << 32 | 0xFF), 0x3_0000_000c);
+    }
+
+    #[test]
+    fn test_validation() {
+        let vtcr = VtcrEl2Builder::<Granule4KB>::new(40);
+        assert_eq!(
+            vtcr.physical_address_size(PhysicalAddressSize::Bits36)
+                .build(),
+            Err(VtcrError::PhysicalAddressSize)
+        );
+        assert_eq!(
+            VtcrEl2Builder::<Granule4KB>::new(52).build(),
+            Err(VtcrError::IpaSize)
+        );
+        assert_eq!(
+            VtcrEl2Builder::<Granule4KB>::new(24).build(),
+            Err(VtcrError::IpaSize)
+        );
+        let value = vtcr
+            .vmid16(true)
+            .hw_access_flag(true)
+            .shareability(Shareability::OuterShareable)
+            .build()
+            .unwrap();
+        assert_eq!(value, 0x802a_2558);
+    }
+}
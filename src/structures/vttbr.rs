@@ -0,0 +1,185 @@
+//! VTTBR_EL2, the stage 2 translation table base register.
+//!
+//! The root table address (BADDR) must be aligned to the size of the root table, which
+//! depends on the VTCR_EL2 configuration, and 64 bytes at least. With 52-bit physical
+//! addresses the upper address bits [51:48] are held in BADDR[5:2]. A misaligned address
+//! is not rejected by the hardware, the walk uses the address with the low bits cleared.
+//! [`Vttbr::new`] checks the 64 byte minimum and [`Vttbr::with_vtcr`] the exact alignment.
+
+use super::{tte::Granule, vtcr::VtcrEl2Builder};
+
+/// Invalid VTTBR_EL2 values rejected by [`Vttbr::new`] and [`Vttbr::with_vtcr`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VttbrError {
+    /// The root table address is not aligned to the root table size
+    Alignment,
+    /// The root table address is above 52 bits
+    AddressSize,
+    /// The VMID does not fit the VMID width selected in VTCR_EL2.VS
+    VmidWidth,
+}
+
+/// VTTBR_EL2 value: stage 2 root table address, VMID and CnP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Vttbr(u64);
+
+impl Vttbr {
+    const CNP: u64 = 1 << 0;
+    const BADDR_MASK: u64 = 0x0000_FFFF_FFFF_FFC0;
+    const BADDR_HI_SHIFT: u64 = 2;
+    const VMID_SHIFT: u64 = 48;
+
+    /// Stage 2 translation of `vmid` with the root table at `root_pa`
+    ///
+    /// Only checks the 64 byte minimum alignment, use [`with_vtcr`](Self::with_vtcr) to
+    /// check against the actual root table size.
+    pub const fn new(root_pa: u64, vmid: u16) -> Result<Self, VttbrError> {
+        if root_pa & 0x3F != 0 {
+            return Err(VttbrError::Alignment);
+        }
+        if root_pa >> 52 != 0 {
+            return Err(VttbrError::AddressSize);
+        }
+        Ok(Self(
+            (root_pa & Self::BADDR_MASK)
+                | (((root_pa >> 48) & 0xF) << Self::BADDR_HI_SHIFT)
+                | ((vmid as u64) << Self::VMID_SHIFT),
+        ))
+    }
+
+    /// Stage 2 translation of `vmid` with the root table at `root_pa`, checked against
+    /// the root table size and VMID width of `vtcr`
+    pub const fn with_vtcr<G: Granule>(
+        vtcr: &VtcrEl2Builder<G>,
+        root_pa: u64,
+        vmid: u16,
+    ) -> Result<Self, VttbrError> {
+        if root_pa & (vtcr.root_table_size() as u64 - 1) != 0 {
+            return Err(VttbrError::Alignment);
+        }
+        if vmid as u32 >> vtcr.vmid_bits() != 0 {
+            return Err(VttbrError::VmidWidth);
+        }
+        Self::new(root_pa, vmid)
+    }
+
+    /// Create from a raw register value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Physical address of the root table
+    pub const fn root_pa(self) -> u64 {
+        (self.0 & Self::BADDR_MASK) | (((self.0 >> Self::BADDR_HI_SHIFT) & 0xF) << 48)
+    }
+
+    /// VMID tagging the TLB entries of this translation
+    pub const fn vmid(self) -> u16 {
+        (self.0 >> Self::VMID_SHIFT) as u16
+    }
+
+    /// Replace the VMID, e.g. after the previous one was reallocated
+    pub const fn with_vmid(self, vmid: u16) -> Self {
+        Self((self.0 & !(0xFFFF << Self::VMID_SHIFT)) | ((vmid as u64) << Self::VMID_SHIFT))
+    }
+
+    /// Share TLB entries between cores using the same VMID and root table (CnP),
+    /// requires FEAT_TTCNP
+    pub const fn common_not_private(self, enable: bool) -> Self {
+        if enable {
+            Self(self.0 | Self::CNP)
+        } else {
+            Self(self.0 & !Self::CNP)
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::{
+        asm::barrier::{SY, isb},
+        registers::{Readable, VTTBR_EL2, Writeable},
+    };
+
+    use super::Vttbr;
+
+    impl Vttbr {
+        /// Read the current VTTBR_EL2
+        pub fn read() -> Self {
+            Self(VTTBR_EL2.get())
+        }
+
+        /// Install this stage 2 translation for the next guest entry
+        ///
+        /// The ISB makes sure no stage 2 walk with the previous VMID or root table happens
+        /// after this returns. No TLB maintenance is needed as long as the VMID is current
+        /// in its [`VmidAllocator`](crate::vmid::VmidAllocator) generation:
+        ///
+        /// ```ignore
+        /// let vmid = VMIDS.lock().allocate(vm.vmid, &active_vmids_of_all_cores);
+        /// vm.vmid = Some(vmid);
+        /// unsafe { vm.vttbr.with_vmid(vmid.hw_id() as u16).switch() };
+        /// ```
+        ///
+        /// # Safety
+        ///
+        /// Must be called at EL2. The root table must hold valid stage 2 tables for the
+        /// current VTCR_EL2 configuration, and the VMID must not be in use by another
+        /// guest.
+        pub unsafe fn switch(self) {
+            VTTBR_EL2.set(self.0);
+            isb(SY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::tte::{Granule4KB, Granule64KB};
+
+    #[test]
+    fn test_vttbr_encoding() {
+        let vttbr = Vttbr::new(0x8_4000_0000, 5).unwrap();
+        assert_eq!(vttbr.value(), 0x0005_0008_4000_0000);
+        assert_eq!(vttbr.root_pa(), 0x8_4000_0000);
+        assert_eq!(vttbr.vmid(), 5);
+        assert_eq!(vttbr.with_vmid(0x1234).vmid(), 0x1234);
+        assert_eq!(vttbr.common_not_private(true).value() & 1, 1);
+
+        // PA[51:48] is held in BADDR[5:2]
+        let vttbr = Vttbr::new(0xA_0000_4000_0000, 0).unwrap();
+        assert_eq!(vttbr.value(), 0x4000_0028);
+        assert_eq!(vttbr.root_pa(), 0xA_0000_4000_0000);
+
+        assert_eq!(Vttbr::new(0x1020, 1), Err(VttbrError::Alignment));
+        assert_eq!(Vttbr::new(1 << 52, 1), Err(VttbrError::AddressSize));
+    }
+
+    #[test]
+    fn test_vttbr_with_vtcr() {
+        // 40-bit IPA with 4KB pages: two concatenated level 1 tables, 8KB aligned
+        let vtcr = VtcrEl2Builder::<Granule4KB>::new(40);
+        assert_eq!(vtcr.root_table_size(), 0x2000);
+        assert!(Vttbr::with_vtcr(&vtcr, 0x4000_2000, 1).is_ok());
+        assert_eq!(
+            Vttbr::with_vtcr(&vtcr, 0x4000_1000, 1),
+            Err(VttbrError::Alignment)
+        );
+        assert_eq!(
+            Vttbr::with_vtcr(&vtcr, 0x4000_2000, 0x100),
+            Err(VttbrError::VmidWidth)
+        );
+        assert!(Vttbr::with_vtcr(&vtcr.vmid16(true), 0x4000_2000, 0x100).is_ok());
+
+        // 36-bit IPA with 64KB pages: a level 2 table smaller than a granule
+        let vtcr = VtcrEl2Builder::<Granule64KB>::new(36);
+        assert_eq!(vtcr.root_table_size(), 0x400);
+        assert!(Vttbr::with_vtcr(&vtcr, 0x4000_0400, 1).is_ok());
+    }
+}
@@ -0,0 +1,52 @@
+//! Access to system registers by encoding.
+
+/// Read or write a system register by its `S<op0>_<op1>_C<n>_C<m>_<op2>` encoding
+///
+/// Intended for IMPLEMENTATION DEFINED registers and registers the assembler does not know
+/// by name. The register can be given as a name, encoded or not, or as the five encoding
+/// fields. The name may be built with `concat!`.
+/// Reads evaluate to a `u64`, writes must be wrapped in `unsafe`.
+///
+/// ```ignore
+/// // CPUECTLR_EL1 on Cortex-A cores
+/// let cpuectlr = sysreg!(read "S3_0_C15_C1_4");
+/// unsafe { sysreg!(write "S3_0_C15_C1_4", cpuectlr | (1 << 6)) };
+///
+/// let value = sysreg!(read 3, 1, 15, 2, 0);
+/// unsafe { sysreg!(write 3, 1, 15, 2, 0; value) };
+/// ```
+#[macro_export]
+macro_rules! sysreg {
+    (read $name:expr) => {{
+        let value: u64;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(concat!("mrs {}, ", $name), out(reg) value, options(nomem, nostack));
+        }
+        value
+    }};
+    (write $name:expr, $value:expr) => {{
+        let value: u64 = $value;
+        core::arch::asm!(concat!("msr ", $name, ", {}"), in(reg) value, options(nostack));
+    }};
+    (read $op0:literal, $op1:literal, $crn:literal, $crm:literal, $op2:literal) => {{
+        let value: u64;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(
+                concat!("mrs {}, S", $op0, "_", $op1, "_C", $crn, "_C", $crm, "_", $op2),
+                out(reg) value,
+                options(nomem, nostack)
+            );
+        }
+        value
+    }};
+    (write $op0:literal, $op1:literal, $crn:literal, $crm:literal, $op2:literal; $value:expr) => {{
+        let value: u64 = $value;
+        core::arch::asm!(
+            concat!("msr S", $op0, "_", $op1, "_C", $crn, "_C", $crm, "_", $op2, ", {}"),
+            in(reg) value,
+            options(nostack)
+        );
+    }};
+}
@@ -0,0 +1,181 @@
+//! Monotonic clock over the generic counter.
+//!
+//! An [`Instant`] is a reading of the virtual count CNTVCT_EL0, which runs at the
+//! CNTFRQ_EL0 frequency. Conversions between ticks and [`Duration`] go through 128-bit
+//! intermediates, so they do not overflow for any count the counter can reach.
+
+#[cfg(target_arch = "aarch64")]
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+pub use core::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Convert `ticks` of a counter running at `freq` Hz to a duration, rounding down
+pub const fn ticks_to_duration(ticks: u64, freq: u64) -> Duration {
+    if freq == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = ticks as u128 * NANOS_PER_SEC / freq as u128;
+    let secs = nanos / NANOS_PER_SEC;
+    if secs > u64::MAX as u128 {
+        return Duration::MAX;
+    }
+    Duration::new(secs as u64, (nanos % NANOS_PER_SEC) as u32)
+}
+
+/// Convert `duration` to ticks of a counter running at `freq` Hz, rounding up so a
+/// deadline is never reached early. Saturates at `u64::MAX`.
+pub const fn duration_to_ticks(duration: Duration, freq: u64) -> u64 {
+    let ticks = duration.as_nanos().saturating_mul(freq as u128);
+    let ticks = ticks.div_ceil(NANOS_PER_SEC);
+    if ticks > u64::MAX as u128 {
+        u64::MAX
+    } else {
+        ticks as u64
+    }
+}
+
+/// A reading of the virtual count, only meaningful when compared against another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    pub const fn ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Ticks from `earlier` to `self`, zero if `earlier` is later
+    pub const fn ticks_since(self, earlier: Instant) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// Instant `ticks` later, `None` on overflow
+    pub const fn checked_add_ticks(self, ticks: u64) -> Option<Self> {
+        match self.0.checked_add(ticks) {
+            Some(t) => Some(Self(t)),
+            None => None,
+        }
+    }
+
+    /// Instant `ticks` earlier, `None` on underflow
+    pub const fn checked_sub_ticks(self, ticks: u64) -> Option<Self> {
+        match self.0.checked_sub(ticks) {
+            Some(t) => Some(Self(t)),
+            None => None,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Instant {
+    /// Current virtual count, read after the preceding instructions have completed
+    #[inline]
+    pub fn now() -> Self {
+        Self(crate::timer::virt::counter_ordered())
+    }
+
+    /// Time from `earlier` to `self`, zero if `earlier` is later
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        ticks_to_duration(self.ticks_since(earlier), crate::timer::frequency())
+    }
+
+    /// Time since `self` was taken
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        self.checked_add_ticks(duration_to_ticks(duration, crate::timer::frequency()))
+    }
+
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        self.checked_sub_ticks(duration_to_ticks(duration, crate::timer::frequency()))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Panics on overflow
+    fn add(self, rhs: Duration) -> Instant {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// Panics on underflow
+    fn sub(self, rhs: Duration) -> Instant {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Saturates to zero like [`Instant::duration_since`]
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_conversion() {
+        // 62.5 MHz, a common CNTFRQ on QEMU
+        let freq = 62_500_000;
+        assert_eq!(ticks_to_duration(freq, freq), Duration::from_secs(1));
+        assert_eq!(ticks_to_duration(1, freq), Duration::from_nanos(16));
+        assert_eq!(duration_to_ticks(Duration::from_nanos(16), freq), 1);
+        // Rounds up, 1ns is a fraction of a tick
+        assert_eq!(duration_to_ticks(Duration::from_nanos(1), freq), 1);
+        assert_eq!(duration_to_ticks(Duration::ZERO, freq), 0);
+
+        // No overflow at the top of the counter range
+        let d = ticks_to_duration(u64::MAX, 1_000_000_000);
+        assert_eq!(d.as_nanos(), u64::MAX as u128);
+        assert_eq!(duration_to_ticks(Duration::MAX, freq), u64::MAX);
+        assert_eq!(ticks_to_duration(10, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_instant_ticks() {
+        let a = Instant::from_ticks(100);
+        let b = Instant::from_ticks(250);
+        assert!(a < b);
+        assert_eq!(b.ticks_since(a), 150);
+        assert_eq!(a.ticks_since(b), 0);
+        assert_eq!(a.checked_add_ticks(150), Some(b));
+        assert_eq!(b.checked_sub_ticks(150), Some(a));
+        assert_eq!(a.checked_sub_ticks(101), None);
+        assert_eq!(Instant::from_ticks(u64::MAX).checked_add_ticks(1), None);
+    }
+}
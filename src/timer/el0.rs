@@ -0,0 +1,139 @@
+//! EL0 access to the counters and timers and the event stream (CNTKCTL_EL1).
+//!
+//! Granting EL0 read access to the virtual counter is what lets user space keep time
+//! without a system call, as a vDSO does. The timers are better left to the kernel.
+//!
+//! With HCR_EL2.{E2H, TGE} set, EL0 is controlled by CNTHCTL_EL2 instead and CNTKCTL_EL1
+//! has no effect on it.
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{CNTKCTL_EL1, Readable, Writeable},
+};
+
+/// Builder and decoder for CNTKCTL_EL1 values
+///
+/// [`Cntkctl::new`] traps every EL0 access and has the event stream disabled.
+///
+/// ```ignore
+/// // User space timekeeping: counter reads only, plus a ~10kHz event stream
+/// Cntkctl::read()
+///     .virtual_counter(true)
+///     .event_stream(Some(Cntkctl::event_bit(frequency() / 10_000)))
+///     .write();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cntkctl(u64);
+
+impl Cntkctl {
+    const EL0PCTEN: u64 = 1 << 0;
+    const EL0VCTEN: u64 = 1 << 1;
+    const EVNTEN: u64 = 1 << 2;
+    const EVNTDIR: u64 = 1 << 3;
+    const EVNTI: u64 = 0b1111 << 4;
+    const EL0VTEN: u64 = 1 << 8;
+    const EL0PTEN: u64 = 1 << 9;
+
+    /// Nothing accessible from EL0, event stream disabled
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Create from a raw CNTKCTL_EL1 value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    const fn bit(self, mask: u64, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// EL0 may read CNTPCT_EL0 (EL0PCTEN)
+    pub const fn physical_counter(self, enable: bool) -> Self {
+        self.bit(Self::EL0PCTEN, enable)
+    }
+
+    /// EL0 may read CNTVCT_EL0 (EL0VCTEN)
+    pub const fn virtual_counter(self, enable: bool) -> Self {
+        self.bit(Self::EL0VCTEN, enable)
+    }
+
+    /// EL0 may access the CNTP_*_EL0 timer registers (EL0PTEN)
+    pub const fn physical_timer(self, enable: bool) -> Self {
+        self.bit(Self::EL0PTEN, enable)
+    }
+
+    /// EL0 may access the CNTV_*_EL0 timer registers (EL0VTEN)
+    pub const fn virtual_timer(self, enable: bool) -> Self {
+        self.bit(Self::EL0VTEN, enable)
+    }
+
+    /// Generate an event, waking WFE, each time bit `bit` (0-15) of the virtual count goes
+    /// from 0 to 1, i.e. every 2^(bit + 1) ticks. `None` disables the event stream.
+    pub const fn event_stream(self, bit: Option<u8>) -> Self {
+        match bit {
+            Some(bit) => {
+                assert!(bit <= 15, "invalid event stream bit");
+                Self((self.0 & !(Self::EVNTI | Self::EVNTDIR)) | Self::EVNTEN | ((bit as u64) << 4))
+            }
+            None => Self(self.0 & !Self::EVNTEN),
+        }
+    }
+
+    /// Counter bit whose event stream period is the longest one not above `period` ticks
+    pub const fn event_bit(period: u64) -> u8 {
+        // Period of bit n is 2^(n + 1)
+        let bit = match period {
+            0..=3 => 0,
+            period => period.ilog2() - 1,
+        };
+        if bit > 15 { 15 } else { bit as u8 }
+    }
+
+    pub const fn is_physical_counter(self) -> bool {
+        self.0 & Self::EL0PCTEN != 0
+    }
+
+    pub const fn is_virtual_counter(self) -> bool {
+        self.0 & Self::EL0VCTEN != 0
+    }
+
+    pub const fn is_physical_timer(self) -> bool {
+        self.0 & Self::EL0PTEN != 0
+    }
+
+    pub const fn is_virtual_timer(self) -> bool {
+        self.0 & Self::EL0VTEN != 0
+    }
+
+    /// Counter bit the event stream triggers on, `None` if it is disabled
+    pub const fn event_stream_bit(self) -> Option<u8> {
+        if self.0 & Self::EVNTEN != 0 {
+            Some(((self.0 & Self::EVNTI) >> 4) as u8)
+        } else {
+            None
+        }
+    }
+
+    /// Current CNTKCTL_EL1
+    #[inline]
+    pub fn read() -> Self {
+        Self(CNTKCTL_EL1.get())
+    }
+
+    /// Write to CNTKCTL_EL1, in effect when this returns
+    #[inline]
+    pub fn write(self) {
+        CNTKCTL_EL1.set(self.0);
+        isb(SY);
+    }
+}
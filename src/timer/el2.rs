@@ -0,0 +1,292 @@
+//! EL2 timers and guest access control (CNTHCTL_EL2, CNTVOFF_EL2).
+//!
+//! The hypervisor has its own physical timer ([`physical`], CNTHP_*_EL2) and, with FEAT_VHE,
+//! its own virtual timer ([`virt`], CNTHV_*_EL2), so the EL1 timers can be left to the
+//! guest. They follow the same API as the EL1 timers and must only be used at EL2 or EL3.
+//!
+//! The registers are accessed by encoding, the assembler only knows some of them by name
+//! and the virtual timer ones only with the `vh` target feature.
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{CNTHCTL_EL2, CNTVOFF_EL2, HCR_EL2, Readable, Writeable},
+};
+
+/// EL2 physical timer (CNTHP_CTL_EL2, CNTHP_TVAL_EL2, CNTHP_CVAL_EL2), comparing against
+/// the physical count. Its interrupt is PPI INTID 26.
+pub mod physical {
+    use aarch64_cpu::registers::{CNTPCT_EL0, Readable};
+
+    use crate::timer::regs::{self, TimerRegs};
+
+    /// EL2 physical timer registers
+    struct Regs;
+
+    impl TimerRegs for Regs {
+        fn count() -> u64 {
+            CNTPCT_EL0.get()
+        }
+
+        fn count_ss() -> u64 {
+            // CNTPCTSS_EL0
+            crate::sysreg!(read "S3_3_C14_C0_5")
+        }
+
+        fn ctl() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C2_1")
+        }
+
+        fn set_ctl(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C2_1", value) };
+        }
+
+        fn tval() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C2_0")
+        }
+
+        fn set_tval(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C2_0", value) };
+        }
+
+        fn cval() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C2_2")
+        }
+
+        fn set_cval(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C2_2", value) };
+        }
+    }
+
+    /// Fire `ticks` counter ticks from now
+    #[inline]
+    pub fn set_interval(ticks: u32) {
+        regs::set_interval::<Regs>(ticks);
+    }
+
+    /// Fire once the count reaches `absolute`
+    #[inline]
+    pub fn set_compare(absolute: u64) {
+        regs::set_compare::<Regs>(absolute);
+    }
+
+    /// Current compare value
+    #[inline]
+    pub fn compare() -> u64 {
+        Regs::cval()
+    }
+
+    /// Ticks until the timer fires, negative once it has fired
+    #[inline]
+    pub fn remaining() -> i32 {
+        regs::remaining::<Regs>()
+    }
+
+    /// Enable the timer
+    #[inline]
+    pub fn enable() {
+        regs::set_enabled::<Regs>(true);
+    }
+
+    /// Disable the timer, its interrupt is deasserted
+    #[inline]
+    pub fn disable() {
+        regs::set_enabled::<Regs>(false);
+    }
+
+    #[inline]
+    pub fn is_enabled() -> bool {
+        regs::is_enabled::<Regs>()
+    }
+
+    /// Check if the timer condition is met (ISTATUS), regardless of the interrupt mask
+    #[inline]
+    pub fn is_pending() -> bool {
+        regs::is_pending::<Regs>()
+    }
+
+    /// Mask or unmask the timer interrupt (IMASK)
+    #[inline]
+    pub fn set_interrupt_masked(masked: bool) {
+        regs::set_interrupt_masked::<Regs>(masked);
+    }
+
+    #[inline]
+    pub fn is_interrupt_masked() -> bool {
+        regs::is_interrupt_masked::<Regs>()
+    }
+}
+
+/// EL2 virtual timer (CNTHV_CTL_EL2, CNTHV_TVAL_EL2, CNTHV_CVAL_EL2), requires FEAT_VHE.
+/// Its interrupt is PPI INTID 28.
+pub mod virt {
+    use aarch64_cpu::registers::{CNTVCT_EL0, Readable};
+
+    use crate::timer::regs::{self, TimerRegs};
+
+    /// EL2 virtual timer registers
+    struct Regs;
+
+    impl TimerRegs for Regs {
+        fn count() -> u64 {
+            CNTVCT_EL0.get()
+        }
+
+        fn count_ss() -> u64 {
+            // CNTVCTSS_EL0
+            crate::sysreg!(read "S3_3_C14_C0_6")
+        }
+
+        fn ctl() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C3_1")
+        }
+
+        fn set_ctl(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C3_1", value) };
+        }
+
+        fn tval() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C3_0")
+        }
+
+        fn set_tval(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C3_0", value) };
+        }
+
+        fn cval() -> u64 {
+            crate::sysreg!(read "S3_4_C14_C3_2")
+        }
+
+        fn set_cval(value: u64) {
+            unsafe { crate::sysreg!(write "S3_4_C14_C3_2", value) };
+        }
+    }
+
+    /// Fire `ticks` counter ticks from now
+    #[inline]
+    pub fn set_interval(ticks: u32) {
+        regs::set_interval::<Regs>(ticks);
+    }
+
+    /// Fire once the count reaches `absolute`
+    #[inline]
+    pub fn set_compare(absolute: u64) {
+        regs::set_compare::<Regs>(absolute);
+    }
+
+    /// Current compare value
+    #[inline]
+    pub fn compare() -> u64 {
+        Regs::cval()
+    }
+
+    /// Ticks until the timer fires, negative once it has fired
+    #[inline]
+    pub fn remaining() -> i32 {
+        regs::remaining::<Regs>()
+    }
+
+    /// Enable the timer
+    #[inline]
+    pub fn enable() {
+        regs::set_enabled::<Regs>(true);
+    }
+
+    /// Disable the timer, its interrupt is deasserted
+    #[inline]
+    pub fn disable() {
+        regs::set_enabled::<Regs>(false);
+    }
+
+    #[inline]
+    pub fn is_enabled() -> bool {
+        regs::is_enabled::<Regs>()
+    }
+
+    /// Check if the timer condition is met (ISTATUS), regardless of the interrupt mask
+    #[inline]
+    pub fn is_pending() -> bool {
+        regs::is_pending::<Regs>()
+    }
+
+    /// Mask or unmask the timer interrupt (IMASK)
+    #[inline]
+    pub fn set_interrupt_masked(masked: bool) {
+        regs::set_interrupt_masked::<Regs>(masked);
+    }
+
+    #[inline]
+    pub fn is_interrupt_masked() -> bool {
+        regs::is_interrupt_masked::<Regs>()
+    }
+}
+
+const HCR_E2H: u64 = 1 << 34;
+
+/// Access of EL1 and EL0 to the physical counter and timer, controlled by CNTHCTL_EL2.
+///
+/// The virtual counter and timer are always accessible to EL1, they are what a guest is
+/// expected to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GuestTimerAccess {
+    /// CNTPCT_EL0 reads are not trapped (EL1PCTEN)
+    pub physical_counter: bool,
+    /// CNTP_*_EL0 accesses are not trapped (EL1PCEN, EL1PTEN with E2H set)
+    pub physical_timer: bool,
+}
+
+impl GuestTimerAccess {
+    /// Everything accessible
+    pub const ALL: Self = Self {
+        physical_counter: true,
+        physical_timer: true,
+    };
+    /// Everything trapped to EL2
+    pub const NONE: Self = Self {
+        physical_counter: false,
+        physical_timer: false,
+    };
+}
+
+/// Position of the counter and timer bits in CNTHCTL_EL2, they move up with HCR_EL2.E2H set
+fn access_shifts() -> (u32, u32) {
+    if HCR_EL2.get() & HCR_E2H != 0 {
+        (10, 11)
+    } else {
+        (0, 1)
+    }
+}
+
+/// Current guest access to the physical counter and timer
+pub fn guest_access() -> GuestTimerAccess {
+    let (counter, timer) = access_shifts();
+    let cnthctl = CNTHCTL_EL2.get();
+    GuestTimerAccess {
+        physical_counter: cnthctl & (1 << counter) != 0,
+        physical_timer: cnthctl & (1 << timer) != 0,
+    }
+}
+
+/// Grant or revoke guest access to the physical counter and timer.
+///
+/// Uses the CNTHCTL_EL2 layout selected by the current HCR_EL2.E2H, so E2H must not be
+/// changed afterwards. The other CNTHCTL_EL2 fields are preserved.
+pub fn set_guest_access(access: GuestTimerAccess) {
+    let (counter, timer) = access_shifts();
+    let mut cnthctl = CNTHCTL_EL2.get() & !((1 << counter) | (1 << timer));
+    if access.physical_counter {
+        cnthctl |= 1 << counter;
+    }
+    if access.physical_timer {
+        cnthctl |= 1 << timer;
+    }
+    CNTHCTL_EL2.set(cnthctl);
+    isb(SY);
+}
+
+/// Set the offset CNTVOFF_EL2 subtracted from the physical count to give the virtual count
+/// seen at EL1 and EL0, see [`super::virt::to_virtual`]
+#[inline]
+pub fn set_virtual_offset(offset: u64) {
+    CNTVOFF_EL2.set(offset);
+    isb(SY);
+}
@@ -0,0 +1,127 @@
+//! Generic Timer.
+//!
+//! Every core has an EL1 physical timer ([`physical`]) comparing against the physical count
+//! CNTPCT_EL0. A timer fires its interrupt (a PPI, e.g. INTID 30 for the EL1 physical timer)
+//! while it is enabled, not masked and the count has reached the compare value. The
+//! virtual timer ([`virt`]) does the same against the offset virtual count CNTVCT_EL0.
+//! A hypervisor has its own pair of timers and controls guest access in [`el2`], the kernel
+//! controls user space access in [`el0`].
+//!
+//! [`set_timeout_after`] and [`set_timeout_at`] arm the virtual timer for a one-shot
+//! deadline in [`crate::time`] units, the building block for a tickless scheduler.
+//! [`wait_for_event_with_timeout`] waits for a condition in WFE with a deadline.
+
+#[cfg(target_arch = "aarch64")]
+pub mod el0;
+#[cfg(target_arch = "aarch64")]
+pub mod el2;
+pub mod offset;
+#[cfg(target_arch = "aarch64")]
+pub mod physical;
+#[cfg(target_arch = "aarch64")]
+mod regs;
+#[cfg(target_arch = "aarch64")]
+pub mod virt;
+
+#[cfg(target_arch = "aarch64")]
+mod ops {
+    use aarch64_cpu::{
+        asm::wfe,
+        registers::{CNTFRQ_EL0, Readable},
+    };
+
+    use super::{el0::Cntkctl, virt};
+    use crate::time::{Duration, Instant};
+
+    /// Frequency of the system counter in Hz (CNTFRQ_EL0), as programmed by the firmware
+    #[inline]
+    pub fn frequency() -> u64 {
+        CNTFRQ_EL0.get()
+    }
+
+    /// Arm the virtual timer to fire once `duration` has passed
+    ///
+    /// A deadline beyond the range of the counter saturates to the largest compare value,
+    /// the timer then never fires.
+    pub fn set_timeout_after(duration: Duration) -> Timeout {
+        set_timeout_at(
+            Instant::now()
+                .checked_add(duration)
+                .unwrap_or(Instant::from_ticks(u64::MAX)),
+        )
+    }
+
+    /// Arm the virtual timer to fire at `deadline`, a deadline in the past fires
+    /// immediately.
+    ///
+    /// Programs CNTV_CVAL_EL0, unmasks the interrupt and enables the timer, replacing any
+    /// timeout that was pending.
+    pub fn set_timeout_at(deadline: Instant) -> Timeout {
+        virt::set_compare(deadline.ticks());
+        virt::set_interrupt_masked(false);
+        virt::enable();
+        Timeout { deadline }
+    }
+
+    /// A pending virtual timer timeout, see [`set_timeout_at`]
+    #[must_use = "dropping the handle leaves the timeout armed"]
+    #[derive(Debug)]
+    pub struct Timeout {
+        deadline: Instant,
+    }
+
+    impl Timeout {
+        pub fn deadline(&self) -> Instant {
+            self.deadline
+        }
+
+        /// Check if the deadline has passed
+        pub fn is_expired(&self) -> bool {
+            Instant::now() >= self.deadline
+        }
+
+        /// Disable the timer so the timeout no longer fires, its interrupt is deasserted.
+        ///
+        /// Does nothing if the timer has since been re-armed for another deadline.
+        pub fn cancel(self) {
+            if virt::compare() == self.deadline.ticks() {
+                virt::disable();
+            }
+        }
+    }
+
+    /// Event stream period used by [`wait_for_event_with_timeout`], in microseconds
+    const WAIT_EVENT_PERIOD_US: u64 = 100;
+
+    /// Wait in WFE until `condition` returns true, false if `deadline` passed first
+    ///
+    /// A low-power alternative to spinning for lock and mailbox waits. The condition is
+    /// checked on every wakeup, the event stream (CNTKCTL_EL1.EVNTI) is enabled for the
+    /// duration of the wait so the core wakes at least every ~100us to check the deadline
+    /// even without an SEV. The previous CNTKCTL_EL1 is restored before returning. Must run
+    /// at EL1, or at EL2 with HCR_EL2.E2H set.
+    pub fn wait_for_event_with_timeout(
+        deadline: Instant,
+        mut condition: impl FnMut() -> bool,
+    ) -> bool {
+        let saved = Cntkctl::read();
+        let period = frequency() * WAIT_EVENT_PERIOD_US / 1_000_000;
+        saved.event_stream(Some(Cntkctl::event_bit(period))).write();
+
+        let met = loop {
+            if condition() {
+                break true;
+            }
+            if Instant::now() >= deadline {
+                break false;
+            }
+            wfe();
+        };
+
+        saved.write();
+        met
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use ops::{Timeout, frequency, set_timeout_after, set_timeout_at, wait_for_event_with_timeout};
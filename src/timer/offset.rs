@@ -0,0 +1,26 @@
+//! Conversion between the physical count and the virtual count seen with an offset
+//! CNTVOFF_EL2.
+
+/// Convert a physical count to the virtual count seen with `offset`
+pub const fn to_virtual(physical: u64, offset: u64) -> u64 {
+    physical.wrapping_sub(offset)
+}
+
+/// Convert a virtual count seen with `offset` to the physical count
+pub const fn to_physical(virtual_count: u64, offset: u64) -> u64 {
+    virtual_count.wrapping_add(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_conversion() {
+        assert_eq!(to_virtual(1000, 400), 600);
+        assert_eq!(to_physical(600, 400), 1000);
+        // The counts wrap like the hardware subtraction does
+        assert_eq!(to_virtual(5, 10), u64::MAX - 4);
+        assert_eq!(to_physical(to_virtual(5, 10), 10), 5);
+    }
+}
@@ -0,0 +1,121 @@
+//! EL1 physical timer (CNTP_CTL_EL0, CNTP_TVAL_EL0, CNTP_CVAL_EL0).
+//!
+//! Writes are followed by an ISB, so the new configuration is in effect, and a condition
+//! that no longer holds is no longer signalled, by the time the function returns.
+
+use aarch64_cpu::registers::{
+    CNTP_CTL_EL0, CNTP_CVAL_EL0, CNTP_TVAL_EL0, CNTPCT_EL0, Readable, Writeable,
+};
+
+use super::regs::{self, TimerRegs};
+
+/// EL1 physical timer registers
+struct Physical;
+
+impl TimerRegs for Physical {
+    fn count() -> u64 {
+        CNTPCT_EL0.get()
+    }
+
+    fn count_ss() -> u64 {
+        // CNTPCTSS_EL0
+        crate::sysreg!(read "S3_3_C14_C0_5")
+    }
+
+    fn ctl() -> u64 {
+        CNTP_CTL_EL0.get()
+    }
+
+    fn set_ctl(value: u64) {
+        CNTP_CTL_EL0.set(value);
+    }
+
+    fn tval() -> u64 {
+        CNTP_TVAL_EL0.get()
+    }
+
+    fn set_tval(value: u64) {
+        CNTP_TVAL_EL0.set(value);
+    }
+
+    fn cval() -> u64 {
+        CNTP_CVAL_EL0.get()
+    }
+
+    fn set_cval(value: u64) {
+        CNTP_CVAL_EL0.set(value);
+    }
+}
+
+/// Current physical count (CNTPCT_EL0)
+#[inline]
+pub fn counter() -> u64 {
+    Physical::count()
+}
+
+/// Current physical count, not read before the preceding instructions have completed
+///
+/// A plain [`counter`] read may be speculated ahead of the code being timed. This issues an
+/// ISB first, or reads the self-synchronizing CNTPCTSS_EL0 when FEAT_ECV is implemented.
+#[inline]
+pub fn counter_ordered() -> u64 {
+    regs::counter_ordered::<Physical>()
+}
+
+/// Fire `ticks` counter ticks from now (CNTP_TVAL_EL0)
+#[inline]
+pub fn set_interval(ticks: u32) {
+    regs::set_interval::<Physical>(ticks);
+}
+
+/// Fire once the physical count reaches `absolute` (CNTP_CVAL_EL0)
+#[inline]
+pub fn set_compare(absolute: u64) {
+    regs::set_compare::<Physical>(absolute);
+}
+
+/// Current compare value
+#[inline]
+pub fn compare() -> u64 {
+    Physical::cval()
+}
+
+/// Ticks until the timer fires, negative once it has fired (CNTP_TVAL_EL0)
+#[inline]
+pub fn remaining() -> i32 {
+    regs::remaining::<Physical>()
+}
+
+/// Enable the timer
+#[inline]
+pub fn enable() {
+    regs::set_enabled::<Physical>(true);
+}
+
+/// Disable the timer, its interrupt is deasserted
+#[inline]
+pub fn disable() {
+    regs::set_enabled::<Physical>(false);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    regs::is_enabled::<Physical>()
+}
+
+/// Check if the timer condition is met (ISTATUS), regardless of the interrupt mask
+#[inline]
+pub fn is_pending() -> bool {
+    regs::is_pending::<Physical>()
+}
+
+/// Mask or unmask the timer interrupt (IMASK)
+#[inline]
+pub fn set_interrupt_masked(masked: bool) {
+    regs::set_interrupt_masked::<Physical>(masked);
+}
+
+#[inline]
+pub fn is_interrupt_masked() -> bool {
+    regs::is_interrupt_masked::<Physical>()
+}
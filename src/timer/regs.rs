@@ -0,0 +1,72 @@
+//! Register level operations shared by the EL1 physical and virtual timers and the EL2 timers.
+
+use aarch64_cpu::asm::barrier::{SY, isb};
+
+use crate::features::{Feature, is_supported};
+
+const CTL_ENABLE: u64 = 1 << 0;
+const CTL_IMASK: u64 = 1 << 1;
+const CTL_ISTATUS: u64 = 1 << 2;
+
+/// Control, timer value and compare value registers of a timer and the count it compares
+/// against
+pub(super) trait TimerRegs {
+    fn count() -> u64;
+    /// Self-synchronizing count, requires FEAT_ECV
+    fn count_ss() -> u64;
+    fn ctl() -> u64;
+    fn set_ctl(value: u64);
+    fn tval() -> u64;
+    fn set_tval(value: u64);
+    fn cval() -> u64;
+    fn set_cval(value: u64);
+}
+
+pub(super) fn counter_ordered<R: TimerRegs>() -> u64 {
+    if is_supported(Feature::Ecv) {
+        R::count_ss()
+    } else {
+        isb(SY);
+        R::count()
+    }
+}
+
+pub(super) fn set_interval<R: TimerRegs>(ticks: u32) {
+    R::set_tval(ticks as u64);
+    isb(SY);
+}
+
+pub(super) fn set_compare<R: TimerRegs>(absolute: u64) {
+    R::set_cval(absolute);
+    isb(SY);
+}
+
+pub(super) fn remaining<R: TimerRegs>() -> i32 {
+    R::tval() as i32
+}
+
+fn set_ctl_bit<R: TimerRegs>(mask: u64, set: bool) {
+    let ctl = R::ctl();
+    R::set_ctl(if set { ctl | mask } else { ctl & !mask });
+    isb(SY);
+}
+
+pub(super) fn set_enabled<R: TimerRegs>(enable: bool) {
+    set_ctl_bit::<R>(CTL_ENABLE, enable);
+}
+
+pub(super) fn is_enabled<R: TimerRegs>() -> bool {
+    R::ctl() & CTL_ENABLE != 0
+}
+
+pub(super) fn is_pending<R: TimerRegs>() -> bool {
+    R::ctl() & CTL_ISTATUS != 0
+}
+
+pub(super) fn set_interrupt_masked<R: TimerRegs>(masked: bool) {
+    set_ctl_bit::<R>(CTL_IMASK, masked);
+}
+
+pub(super) fn is_interrupt_masked<R: TimerRegs>() -> bool {
+    R::ctl() & CTL_IMASK != 0
+}
@@ -0,0 +1,136 @@
+//! Virtual timer (CNTV_CTL_EL0, CNTV_TVAL_EL0, CNTV_CVAL_EL0).
+//!
+//! The virtual timer compares against the virtual count CNTVCT_EL0, which is the physical
+//! count minus the offset CNTVOFF_EL2 set by the hypervisor. Guests and kernels that may run
+//! as guests use it, the offset hides the time the VM was not running.
+//!
+//! Writes are followed by an ISB, so the new configuration is in effect, and a condition
+//! that no longer holds is no longer signalled, by the time the function returns.
+
+use aarch64_cpu::registers::{
+    CNTV_CTL_EL0, CNTV_CVAL_EL0, CNTV_TVAL_EL0, CNTVCT_EL0, CNTVOFF_EL2, Readable, Writeable,
+};
+
+use super::regs::{self, TimerRegs};
+use crate::el::{ExceptionLevel, current_el};
+
+pub use super::offset::{to_physical, to_virtual};
+
+/// Virtual timer registers
+struct Virtual;
+
+impl TimerRegs for Virtual {
+    fn count() -> u64 {
+        CNTVCT_EL0.get()
+    }
+
+    fn count_ss() -> u64 {
+        // CNTVCTSS_EL0
+        crate::sysreg!(read "S3_3_C14_C0_6")
+    }
+
+    fn ctl() -> u64 {
+        CNTV_CTL_EL0.get()
+    }
+
+    fn set_ctl(value: u64) {
+        CNTV_CTL_EL0.set(value);
+    }
+
+    fn tval() -> u64 {
+        CNTV_TVAL_EL0.get()
+    }
+
+    fn set_tval(value: u64) {
+        CNTV_TVAL_EL0.set(value);
+    }
+
+    fn cval() -> u64 {
+        CNTV_CVAL_EL0.get()
+    }
+
+    fn set_cval(value: u64) {
+        CNTV_CVAL_EL0.set(value);
+    }
+}
+
+/// Current virtual count (CNTVCT_EL0)
+#[inline]
+pub fn counter() -> u64 {
+    Virtual::count()
+}
+
+/// Current virtual count, not read before the preceding instructions have completed
+///
+/// A plain [`counter`] read may be speculated ahead of the code being timed. This issues an
+/// ISB first, or reads the self-synchronizing CNTVCTSS_EL0 when FEAT_ECV is implemented.
+#[inline]
+pub fn counter_ordered() -> u64 {
+    regs::counter_ordered::<Virtual>()
+}
+
+/// Fire `ticks` counter ticks from now (CNTV_TVAL_EL0)
+#[inline]
+pub fn set_interval(ticks: u32) {
+    regs::set_interval::<Virtual>(ticks);
+}
+
+/// Fire once the virtual count reaches `absolute` (CNTV_CVAL_EL0)
+#[inline]
+pub fn set_compare(absolute: u64) {
+    regs::set_compare::<Virtual>(absolute);
+}
+
+/// Current compare value
+#[inline]
+pub fn compare() -> u64 {
+    Virtual::cval()
+}
+
+/// Ticks until the timer fires, negative once it has fired (CNTV_TVAL_EL0)
+#[inline]
+pub fn remaining() -> i32 {
+    regs::remaining::<Virtual>()
+}
+
+/// Enable the timer
+#[inline]
+pub fn enable() {
+    regs::set_enabled::<Virtual>(true);
+}
+
+/// Disable the timer, its interrupt is deasserted
+#[inline]
+pub fn disable() {
+    regs::set_enabled::<Virtual>(false);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    regs::is_enabled::<Virtual>()
+}
+
+/// Check if the timer condition is met (ISTATUS), regardless of the interrupt mask
+#[inline]
+pub fn is_pending() -> bool {
+    regs::is_pending::<Virtual>()
+}
+
+/// Mask or unmask the timer interrupt (IMASK)
+#[inline]
+pub fn set_interrupt_masked(masked: bool) {
+    regs::set_interrupt_masked::<Virtual>(masked);
+}
+
+#[inline]
+pub fn is_interrupt_masked() -> bool {
+    regs::is_interrupt_masked::<Virtual>()
+}
+
+/// Virtual counter offset CNTVOFF_EL2, `None` below EL2 where it is not accessible
+pub fn offset() -> Option<u64> {
+    match current_el() {
+        ExceptionLevel::EL2 | ExceptionLevel::EL3 => Some(CNTVOFF_EL2.get()),
+        _ => None,
+    }
+}
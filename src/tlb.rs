@@ -0,0 +1,351 @@
+use aarch64_cpu::asm::barrier::{ISH, ISHST, NSH, NSHST, OSH, OSHST, SY, dsb, isb};
+
+use crate::{
+    asm::tlb::{
+        ASIDE1, ASIDE1IS, ASIDE1OS, IPAS2E1, IPAS2E1IS, IPAS2E1OS, RIPAS2E1, RIPAS2E1IS,
+        RIPAS2E1OS, RVAE1, RVAE1IS, RVAE1OS, VAAE1, VAAE1IS, VAAE1OS, VAE1, VAE1IS, VAE1OS,
+        VMALLE1, VMALLE1IS, VMALLE1OS, VMALLS12E1, VMALLS12E1IS, VMALLS12E1OS, tlbi,
+    },
+    features::{Feature, is_supported},
+    structures::tte::Granule,
+};
+
+/// Above this many pages a per-page loop is replaced by invalidating the whole VMID.
+const MAX_TLBI_OPS: usize = 512;
+
+/// Check ID_AA64ISAR0_EL1.TLB for FEAT_TLBIOS (outer shareable TLBI operations)
+pub fn has_tlbi_os() -> bool {
+    is_supported(Feature::TlbiOs)
+}
+
+/// Check ID_AA64ISAR0_EL1.TLB for FEAT_TLBIRANGE
+pub fn has_tlbi_range() -> bool {
+    is_supported(Feature::TlbiRange)
+}
+
+/// The set of cores a TLB maintenance operation is broadcast to
+///
+/// Selects the local, `*IS` or `*OS` instruction variant and the matching DSB domain.
+/// Uniprocessor systems only need [`TlbScope::Local`], SMP systems sharing page tables
+/// across cores need at least [`TlbScope::InnerShareable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TlbScope {
+    /// Only the executing core
+    Local,
+    /// All cores in the inner shareable domain
+    #[default]
+    InnerShareable,
+    /// All cores in the outer shareable domain
+    ///
+    /// Requires FEAT_TLBIOS, see [`has_tlbi_os`].
+    OuterShareable,
+}
+
+impl TlbScope {
+    /// Barrier making page table updates visible to the table walkers of the scope
+    #[inline]
+    pub fn pre_barrier(self) {
+        match self {
+            TlbScope::Local => dsb(NSHST),
+            TlbScope::InnerShareable => dsb(ISHST),
+            TlbScope::OuterShareable => dsb(OSHST),
+        }
+    }
+
+    /// Barrier waiting for the completion of TLB maintenance in the scope
+    #[inline]
+    pub fn post_barrier(self) {
+        match self {
+            TlbScope::Local => dsb(NSH),
+            TlbScope::InnerShareable => dsb(ISH),
+            TlbScope::OuterShareable => dsb(OSH),
+        }
+    }
+
+    /// TLBI VAE1* for `va` tagged with `asid`
+    #[inline]
+    pub fn va(self, asid: usize, va: usize) {
+        match self {
+            TlbScope::Local => tlbi(VAE1::new(asid, va)),
+            TlbScope::InnerShareable => tlbi(VAE1IS::new(asid, va)),
+            TlbScope::OuterShareable => tlbi(VAE1OS::new(asid, va)),
+        }
+    }
+
+    /// TLBI VAAE1* for `va` in all ASIDs
+    #[inline]
+    pub fn va_all_asid(self, va: usize) {
+        match self {
+            TlbScope::Local => tlbi(VAAE1::new(va)),
+            TlbScope::InnerShareable => tlbi(VAAE1IS::new(va)),
+            TlbScope::OuterShareable => tlbi(VAAE1OS::new(va)),
+        }
+    }
+
+    /// TLBI RVAE1* for `[va, va + len)` tagged with `asid`, requires FEAT_TLBIRANGE
+    #[inline]
+    pub fn va_range<G: Granule>(self, asid: usize, va: usize, len: usize) {
+        match self {
+            TlbScope::Local => RVAE1::new::<G>(asid, va, len).for_each(tlbi),
+            TlbScope::InnerShareable => RVAE1IS::new::<G>(asid, va, len).for_each(tlbi),
+            TlbScope::OuterShareable => RVAE1OS::new::<G>(asid, va, len).for_each(tlbi),
+        }
+    }
+
+    /// TLBI ASIDE1* for the non-global entries of `asid`
+    #[inline]
+    pub fn asid(self, asid: usize) {
+        match self {
+            TlbScope::Local => tlbi(ASIDE1::new(asid)),
+            TlbScope::InnerShareable => tlbi(ASIDE1IS::new(asid)),
+            TlbScope::OuterShareable => tlbi(ASIDE1OS::new(asid)),
+        }
+    }
+
+    /// TLBI VMALLE1* for all stage 1 entries of the current VMID
+    #[inline]
+    pub fn all(self) {
+        match self {
+            TlbScope::Local => tlbi(VMALLE1),
+            TlbScope::InnerShareable => tlbi(VMALLE1IS),
+            TlbScope::OuterShareable => tlbi(VMALLE1OS),
+        }
+    }
+
+    /// TLBI IPAS2E1* for the stage 2 entries of `ipa` in the current VMID
+    #[inline]
+    pub fn ipa(self, ipa: usize) {
+        match self {
+            TlbScope::Local => tlbi(IPAS2E1::new(ipa)),
+            TlbScope::InnerShareable => tlbi(IPAS2E1IS::new(ipa)),
+            TlbScope::OuterShareable => tlbi(IPAS2E1OS::new(ipa)),
+        }
+    }
+
+    /// TLBI RIPAS2E1* for `[ipa, ipa + len)` in the current VMID, requires FEAT_TLBIRANGE
+    #[inline]
+    pub fn ipa_range<G: Granule>(self, ipa: usize, len: usize) {
+        match self {
+            TlbScope::Local => RIPAS2E1::new::<G>(ipa, len).for_each(tlbi),
+            TlbScope::InnerShareable => RIPAS2E1IS::new::<G>(ipa, len).for_each(tlbi),
+            TlbScope::OuterShareable => RIPAS2E1OS::new::<G>(ipa, len).for_each(tlbi),
+        }
+    }
+
+    /// TLBI VMALLS12E1* for all stage 1 and stage 2 entries of the current VMID
+    #[inline]
+    pub fn all_stage12(self) {
+        match self {
+            TlbScope::Local => tlbi(VMALLS12E1),
+            TlbScope::InnerShareable => tlbi(VMALLS12E1IS),
+            TlbScope::OuterShareable => tlbi(VMALLS12E1OS),
+        }
+    }
+}
+
+/// Invalidates the stage 1 EL1&0 TLB entries of `[va, va + len)` for `asid` on the
+/// cores in `scope`.
+///
+/// Uses range TLBI when FEAT_TLBIRANGE is implemented and a TLBI VAE1* per page otherwise.
+/// Global entries in the range are invalidated as well. Ranges of more than 512 pages
+/// without FEAT_TLBIRANGE invalidate all stage 1 entries (VMALLE1*) instead, as TLBI
+/// ASIDE1* would leave the global ones. The operations are bracketed by DSB *ST (so prior page table updates are visible to
+/// the table walkers) and DSB + ISB (so the invalidation has completed on return).
+pub fn flush_va_range<G: Granule>(scope: TlbScope, asid: usize, va: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+
+    scope.pre_barrier();
+    if has_tlbi_range() {
+        scope.va_range::<G>(asid, va, len);
+    } else {
+        let start = va & !(G::SIZE - 1);
+        let end = va.saturating_add(len);
+        if (end - start).div_ceil(G::SIZE) > MAX_TLBI_OPS {
+            // ASIDE1 would keep global entries the per-page and range paths invalidate
+            scope.all();
+        } else {
+            for page in (start..end).step_by(G::SIZE) {
+                scope.va(asid, page);
+            }
+        }
+    }
+    scope.post_barrier();
+    isb(SY);
+}
+
+/// Invalidates the stage 2 TLB entries of `[ipa, ipa + len)` for the current VMID on the
+/// cores in `scope`.
+///
+/// Must be called at EL2 with VTTBR_EL2 holding the VMID of the guest. Since combined
+/// stage 1+2 entries cannot be invalidated by IPA, all stage 1 entries of the VMID are
+/// invalidated with VMALLE1* after the stage 2 operations have completed.
+pub fn flush_ipa_range<G: Granule>(scope: TlbScope, ipa: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+
+    scope.pre_barrier();
+    if has_tlbi_range() {
+        scope.ipa_range::<G>(ipa, len);
+    } else {
+        let start = ipa & !(G::SIZE - 1);
+        let end = ipa.saturating_add(len);
+        if (end - start).div_ceil(G::SIZE) > MAX_TLBI_OPS {
+            scope.all_stage12();
+            scope.post_barrier();
+            isb(SY);
+            return;
+        }
+        for page in (start..end).step_by(G::SIZE) {
+            scope.ipa(page);
+        }
+    }
+    scope.post_barrier();
+    scope.all();
+    scope.post_barrier();
+    isb(SY);
+}
+
+/// A single EL1&0 TLB maintenance operation queued in a [`TlbFlush`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlbOp {
+    /// Entries for `va` tagged with `asid`, plus global entries for `va`
+    Va { asid: usize, va: usize },
+    /// Entries for `va` in all ASIDs
+    VaAllAsid { va: usize },
+    /// Non-global entries tagged with `asid`
+    Asid(usize),
+    /// All entries of the current VMID
+    All,
+}
+
+impl TlbOp {
+    fn issue(self, scope: TlbScope) {
+        match self {
+            TlbOp::Va { asid, va } => scope.va(asid, va),
+            TlbOp::VaAllAsid { va } => scope.va_all_asid(va),
+            TlbOp::Asid(asid) => scope.asid(asid),
+            TlbOp::All => scope.all(),
+        }
+    }
+}
+
+/// Batches TLB maintenance and issues it with the required barriers
+///
+/// Operations are queued with [`va`](Self::va), [`asid`](Self::asid) and friends, then
+/// issued on [`commit`](Self::commit) or when the builder is dropped as:
+///
+/// ```text
+/// dsb ishst    // page table updates visible to the walkers
+/// tlbi ...     // every queued operation
+/// dsb ish      // invalidation completed
+/// isb          // no stale translation in the pipeline
+/// ```
+///
+/// The barrier domains and TLBI variants follow the [`TlbScope`] of the builder.
+///
+/// At most `N` operations are stored, queuing more escalates to invalidating everything.
+///
+/// ```ignore
+/// let mut flush = TlbFlush::<8>::new();
+/// flush.va(asid, page_a).va(asid, page_b);
+/// flush.commit();
+/// ```
+pub struct TlbFlush<const N: usize = 16> {
+    scope: TlbScope,
+    ops: [TlbOp; N],
+    len: usize,
+    all: bool,
+}
+
+impl<const N: usize> TlbFlush<N> {
+    /// Create a builder broadcasting to the inner shareable domain
+    pub const fn new() -> Self {
+        Self::with_scope(TlbScope::InnerShareable)
+    }
+
+    /// Create a builder broadcasting to the cores in `scope`
+    pub const fn with_scope(scope: TlbScope) -> Self {
+        Self {
+            scope,
+            ops: [TlbOp::All; N],
+            len: 0,
+            all: false,
+        }
+    }
+
+    /// Queue an operation
+    pub fn push(&mut self, op: TlbOp) -> &mut Self {
+        if op == TlbOp::All || self.len == N {
+            self.all = true;
+        } else if !self.all {
+            self.ops[self.len] = op;
+            self.len += 1;
+        }
+        self
+    }
+
+    /// Queue invalidation of `va` for `asid`
+    pub fn va(&mut self, asid: usize, va: usize) -> &mut Self {
+        self.push(TlbOp::Va { asid, va })
+    }
+
+    /// Queue invalidation of `va` for all ASIDs
+    pub fn va_all_asid(&mut self, va: usize) -> &mut Self {
+        self.push(TlbOp::VaAllAsid { va })
+    }
+
+    /// Queue invalidation of all non-global entries of `asid`
+    pub fn asid(&mut self, asid: usize) -> &mut Self {
+        self.push(TlbOp::Asid(asid))
+    }
+
+    /// Queue invalidation of all entries
+    pub fn all(&mut self) -> &mut Self {
+        self.push(TlbOp::All)
+    }
+
+    /// Check if no operation is queued
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.len == 0
+    }
+
+    /// Issue the queued operations and wait for their completion
+    pub fn commit(mut self) {
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
+        self.scope.pre_barrier();
+        if self.all {
+            TlbOp::All.issue(self.scope);
+        } else {
+            for op in &self.ops[..self.len] {
+                op.issue(self.scope);
+            }
+        }
+        self.scope.post_barrier();
+        isb(SY);
+
+        self.len = 0;
+        self.all = false;
+    }
+}
+
+impl<const N: usize> Default for TlbFlush<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Drop for TlbFlush<N> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,390 @@
+//! EL2 trap configuration: CPTR_EL2 and MDCR_EL2.
+//!
+//! CPTR_EL2 has two layouts selected by HCR_EL2.E2H. Without E2H it holds single trap bits
+//! (TFP, TZ, TSM) next to RES1 bits, with E2H it matches CPACR_EL1 and uses 2-bit enable
+//! fields (FPEN, ZEN, SMEN) instead, and TTA moves from bit 20 to bit 28.
+//! [`CptrEl2Builder`] describes what is trapped and encodes it for either [`El2Layout`].
+//!
+//! MDCR_EL2 has a single layout, [`MdcrEl2Builder`] names its debug, PMU and trace traps.
+
+/// Layout of the EL2 registers that depend on HCR_EL2.E2H
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum El2Layout {
+    /// HCR_EL2.E2H = 0
+    NonVhe,
+    /// HCR_EL2.E2H = 1
+    Vhe,
+}
+
+impl El2Layout {
+    /// Layout for the given HCR_EL2.E2H
+    pub const fn from_e2h(e2h: bool) -> Self {
+        if e2h { Self::Vhe } else { Self::NonVhe }
+    }
+}
+
+/// Builder for CPTR_EL2, independent of the register layout
+///
+/// Starts with nothing trapped. The value is only encoded by [`build`](Self::build).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CptrEl2Builder(u8);
+
+impl CptrEl2Builder {
+    const FP: u8 = 1 << 0;
+    const SVE: u8 = 1 << 1;
+    const SME: u8 = 1 << 2;
+    const TRACE: u8 = 1 << 3;
+    const AMU: u8 = 1 << 4;
+    const CPACR: u8 = 1 << 5;
+
+    /// RES1 bits without E2H, excluding TSM
+    const NVHE_RES1: u64 = 0x22FF;
+    const NVHE_TZ: u64 = 1 << 8;
+    const NVHE_TFP: u64 = 1 << 10;
+    const NVHE_TSM: u64 = 1 << 12;
+    const NVHE_TTA: u64 = 1 << 20;
+    const VHE_ZEN_SHIFT: u64 = 16;
+    const VHE_FPEN_SHIFT: u64 = 20;
+    const VHE_SMEN_SHIFT: u64 = 24;
+    const VHE_TTA: u64 = 1 << 28;
+    const TAM: u64 = 1 << 30;
+    const TCPAC: u64 = 1 << 31;
+
+    /// Nothing trapped
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Decode a CPTR_EL2 value in `layout`
+    ///
+    /// With E2H, an enable field trapping only EL0 (0b01) counts as not trapped.
+    pub const fn from_value(value: u64, layout: El2Layout) -> Self {
+        let traps = match layout {
+            El2Layout::NonVhe => Self::new()
+                .trap_fp(value & Self::NVHE_TFP != 0)
+                .trap_sve(value & Self::NVHE_TZ != 0)
+                .trap_sme(value & Self::NVHE_TSM != 0)
+                .trap_trace(value & Self::NVHE_TTA != 0),
+            El2Layout::Vhe => Self::new()
+                .trap_fp((value >> Self::VHE_FPEN_SHIFT) & 0b11 == 0)
+                .trap_sve((value >> Self::VHE_ZEN_SHIFT) & 0b11 == 0)
+                .trap_sme((value >> Self::VHE_SMEN_SHIFT) & 0b11 == 0)
+                .trap_trace(value & Self::VHE_TTA != 0),
+        };
+        traps
+            .trap_amu(value & Self::TAM != 0)
+            .trap_cpacr(value & Self::TCPAC != 0)
+    }
+
+    const fn bit(self, mask: u8, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// Trap FP and Advanced SIMD instructions (TFP, FPEN)
+    pub const fn trap_fp(self, trap: bool) -> Self {
+        self.bit(Self::FP, trap)
+    }
+
+    /// Trap SVE instructions (TZ, ZEN)
+    ///
+    /// TZ is RES1 without FEAT_SVE, set this on cores without SVE when building for
+    /// [`El2Layout::NonVhe`].
+    pub const fn trap_sve(self, trap: bool) -> Self {
+        self.bit(Self::SVE, trap)
+    }
+
+    /// Trap SME instructions (TSM, SMEN)
+    ///
+    /// TSM is RES1 without FEAT_SME, set this on cores without SME when building for
+    /// [`El2Layout::NonVhe`].
+    pub const fn trap_sme(self, trap: bool) -> Self {
+        self.bit(Self::SME, trap)
+    }
+
+    /// Trap trace register accesses (TTA)
+    pub const fn trap_trace(self, trap: bool) -> Self {
+        self.bit(Self::TRACE, trap)
+    }
+
+    /// Trap activity monitor register accesses (TAM)
+    pub const fn trap_amu(self, trap: bool) -> Self {
+        self.bit(Self::AMU, trap)
+    }
+
+    /// Trap EL1 accesses to CPACR_EL1 (TCPAC)
+    pub const fn trap_cpacr(self, trap: bool) -> Self {
+        self.bit(Self::CPACR, trap)
+    }
+
+    /// Check if FP and Advanced SIMD instructions are trapped
+    pub const fn traps_fp(self) -> bool {
+        self.0 & Self::FP != 0
+    }
+
+    /// Check if SVE instructions are trapped
+    pub const fn traps_sve(self) -> bool {
+        self.0 & Self::SVE != 0
+    }
+
+    /// Check if SME instructions are trapped
+    pub const fn traps_sme(self) -> bool {
+        self.0 & Self::SME != 0
+    }
+
+    const fn has(self, mask: u8) -> bool {
+        self.0 & mask != 0
+    }
+
+    /// Encode the CPTR_EL2 value for `layout`
+    pub const fn build(self, layout: El2Layout) -> u64 {
+        let mut value = 0;
+        match layout {
+            El2Layout::NonVhe => {
+                value |= Self::NVHE_RES1;
+                if self.has(Self::FP) {
+                    value |= Self::NVHE_TFP;
+                }
+                if self.has(Self::SVE) {
+                    value |= Self::NVHE_TZ;
+                }
+                if self.has(Self::SME) {
+                    value |= Self::NVHE_TSM;
+                }
+                if self.has(Self::TRACE) {
+                    value |= Self::NVHE_TTA;
+                }
+            }
+            El2Layout::Vhe => {
+                if !self.has(Self::FP) {
+                    value |= 0b11 << Self::VHE_FPEN_SHIFT;
+                }
+                if !self.has(Self::SVE) {
+                    value |= 0b11 << Self::VHE_ZEN_SHIFT;
+                }
+                if !self.has(Self::SME) {
+                    value |= 0b11 << Self::VHE_SMEN_SHIFT;
+                }
+                if self.has(Self::TRACE) {
+                    value |= Self::VHE_TTA;
+                }
+            }
+        }
+        if self.has(Self::AMU) {
+            value |= Self::TAM;
+        }
+        if self.has(Self::CPACR) {
+            value |= Self::TCPAC;
+        }
+        value
+    }
+}
+
+/// Builder for MDCR_EL2
+///
+/// Starts with nothing trapped and an explicit HPMN, the number of event counters left to
+/// EL1 and EL0. HPMN = 0, reserving every counter for EL2, requires FEAT_HPMN0 and is
+/// CONSTRAINED UNPREDICTABLE otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MdcrEl2Builder(u64);
+
+impl MdcrEl2Builder {
+    const HPMN: u64 = 0x1F;
+    const TPMCR: u64 = 1 << 5;
+    const TPM: u64 = 1 << 6;
+    const HPME: u64 = 1 << 7;
+    const TDE: u64 = 1 << 8;
+    const TDA: u64 = 1 << 9;
+    const TDOSA: u64 = 1 << 10;
+    const TDRA: u64 = 1 << 11;
+    const TPMS: u64 = 1 << 14;
+    const HPMD: u64 = 1 << 17;
+    const TTRF: u64 = 1 << 19;
+    const TDCC: u64 = 1 << 27;
+
+    /// Nothing trapped, the first `hpmn` event counters accessible to EL1 and EL0
+    pub const fn new(hpmn: u8) -> Self {
+        Self(0).hpmn(hpmn)
+    }
+
+    /// Create from a raw register value
+    pub const fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    const fn bit(self, mask: u64, set: bool) -> Self {
+        if set {
+            Self(self.0 | mask)
+        } else {
+            Self(self.0 & !mask)
+        }
+    }
+
+    /// Give EL1 and EL0 the first `n` event counters (HPMN), the rest belong to EL2
+    pub const fn hpmn(self, n: u8) -> Self {
+        Self((self.0 & !Self::HPMN) | (n as u64 & Self::HPMN))
+    }
+
+    /// Number of event counters accessible to EL1 and EL0
+    pub const fn el1_counters(self) -> u8 {
+        (self.0 & Self::HPMN) as u8
+    }
+
+    /// Trap PMU register accesses (TPM), and PMCR_EL0 accesses in particular (TPMCR)
+    pub const fn trap_pmu(self, trap: bool) -> Self {
+        self.bit(Self::TPM | Self::TPMCR, trap)
+    }
+
+    /// Enable the event counters reserved for EL2 (HPME)
+    pub const fn enable_el2_counters(self, enable: bool) -> Self {
+        self.bit(Self::HPME, enable)
+    }
+
+    /// Do not count events at EL2 (HPMD)
+    pub const fn prohibit_el2_counting(self, prohibit: bool) -> Self {
+        self.bit(Self::HPMD, prohibit)
+    }
+
+    /// Route debug exceptions from EL1 and EL0 to EL2 (TDE), also traps the debug
+    /// registers
+    pub const fn route_debug(self, route: bool) -> Self {
+        self.bit(Self::TDE, route)
+    }
+
+    /// Trap debug register accesses (TDA), OS lock and power down registers (TDOSA) and
+    /// debug ROM address registers (TDRA)
+    pub const fn trap_debug(self, trap: bool) -> Self {
+        self.bit(Self::TDA | Self::TDOSA | Self::TDRA, trap)
+    }
+
+    /// Trap debug communications channel accesses (TDCC), requires FEAT_FGT
+    pub const fn trap_dcc(self, trap: bool) -> Self {
+        self.bit(Self::TDCC, trap)
+    }
+
+    /// Trap statistical profiling register accesses (TPMS), requires FEAT_SPE
+    pub const fn trap_spe(self, trap: bool) -> Self {
+        self.bit(Self::TPMS, trap)
+    }
+
+    /// Trap trace filter register accesses (TTRF), requires FEAT_TRF
+    pub const fn trap_trace_filter(self, trap: bool) -> Self {
+        self.bit(Self::TTRF, trap)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod access {
+    use aarch64_cpu::{
+        asm::barrier::{SY, isb},
+        registers::{CPTR_EL2, HCR_EL2, Readable, Writeable},
+    };
+
+    use super::{CptrEl2Builder, El2Layout, MdcrEl2Builder};
+    use crate::{
+        features::{Feature, is_supported},
+        pmu::counter_count,
+    };
+
+    impl El2Layout {
+        /// Layout selected by the current HCR_EL2.E2H
+        pub fn current() -> Self {
+            Self::from_e2h(HCR_EL2.read(HCR_EL2::E2H) != 0)
+        }
+    }
+
+    impl CptrEl2Builder {
+        /// Read CPTR_EL2 in the current layout
+        pub fn read() -> Self {
+            Self::from_value(CPTR_EL2.get(), El2Layout::current())
+        }
+
+        /// Write CPTR_EL2 in the current layout
+        ///
+        /// SVE is trapped on cores without FEAT_SVE and SME on cores without FEAT_SME, where
+        /// TZ and TSM are RES1.
+        ///
+        /// # Safety
+        ///
+        /// Must be called at EL2. Code at EL2 relying on an access that is now trapped
+        /// faults.
+        pub unsafe fn write(self) {
+            let traps = self
+                .trap_sve(self.traps_sve() || !is_supported(Feature::Sve))
+                .trap_sme(self.traps_sme() || !is_supported(Feature::Sme));
+            CPTR_EL2.set(traps.build(El2Layout::current()));
+            isb(SY);
+        }
+    }
+
+    impl MdcrEl2Builder {
+        /// Nothing trapped, every implemented event counter (PMCR_EL0.N) accessible to EL1
+        /// and EL0
+        pub fn all_counters_to_el1() -> Self {
+            Self::new(counter_count() as u8)
+        }
+
+        /// Read the current MDCR_EL2
+        pub fn read() -> Self {
+            // MDCR_EL2
+            Self(crate::sysreg!(read "S3_4_C1_C1_1"))
+        }
+
+        /// Write MDCR_EL2
+        ///
+        /// # Safety
+        ///
+        /// Must be called at EL2.
+        pub unsafe fn write(self) {
+            // MDCR_EL2
+            unsafe { crate::sysreg!(write "S3_4_C1_C1_1", self.0) };
+            isb(SY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cptr_layouts() {
+        let open = CptrEl2Builder::new();
+        assert_eq!(open.build(El2Layout::NonVhe), 0x22FF);
+        assert_eq!(open.build(El2Layout::Vhe), 0x0333_0000);
+
+        let traps = open
+            .trap_fp(true)
+            .trap_sve(true)
+            .trap_sme(true)
+            .trap_trace(true);
+        assert_eq!(traps.build(El2Layout::NonVhe), 0x0010_37FF);
+        assert_eq!(traps.build(El2Layout::Vhe), 0x1000_0000);
+
+        let traps = open.trap_sve(true).trap_cpacr(true);
+        for layout in [El2Layout::NonVhe, El2Layout::Vhe] {
+            assert_eq!(
+                CptrEl2Builder::from_value(traps.build(layout), layout),
+                traps
+            );
+        }
+    }
+
+    #[test]
+    fn test_mdcr() {
+        let mdcr = MdcrEl2Builder::new(6)
+            .trap_debug(true)
+            .enable_el2_counters(true);
+        assert_eq!(mdcr.value(), 0xE86);
+        assert_eq!(mdcr.el1_counters(), 6);
+        assert_eq!(mdcr.trap_pmu(true).trap_debug(false).value(), 0xE6);
+    }
+}
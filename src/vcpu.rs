@@ -0,0 +1,213 @@
+//! EL1 and EL0 system register context of a vCPU.
+//!
+//! A hypervisor running several guests on one core switches the guest visible EL1 and
+//! EL0 system registers with [`VcpuSysregs::save`] and [`VcpuSysregs::restore`]. Both move
+//! the whole set in one asm block, two registers per load or store pair.
+//!
+//! With HCR_EL2.E2H set, the EL1 register names used at EL2 access the EL2 registers,
+//! the guest's copies are reached through the `_EL12` aliases. Both routines check E2H
+//! and use the matching encodings.
+//!
+//! Not covered: the timer registers (see [`timer`](crate::timer)), debug and PMU state
+//! ([`DebugContext`](crate::debug::DebugContext), [`PmuContext`](crate::pmu::PmuContext)),
+//! FP/SIMD registers and the registers of optional extensions such as TCR2_EL1 or
+//! PIR_EL1.
+
+use core::arch::asm;
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{HCR_EL2, Readable},
+};
+
+/// Run `$batch` over the context registers, in the order of the [`VcpuSysregs`] fields
+macro_rules! with_el1_regs {
+    ($batch:ident, $base:expr, vhe) => {
+        $batch!($base;
+            // SCTLR_EL12, CPACR_EL12
+            "S3_5_C1_C0_0" "S3_5_C1_C0_2",
+            // TTBR0_EL12, TTBR1_EL12
+            "S3_5_C2_C0_0" "S3_5_C2_C0_1",
+            // TCR_EL12, MAIR_EL12
+            "S3_5_C2_C0_2" "S3_5_C10_C2_0",
+            // AMAIR_EL12, VBAR_EL12
+            "S3_5_C10_C3_0" "S3_5_C12_C0_0",
+            // CONTEXTIDR_EL12, CNTKCTL_EL12
+            "S3_5_C13_C0_1" "S3_5_C14_C1_0",
+            // ESR_EL12, FAR_EL12
+            "S3_5_C5_C2_0" "S3_5_C6_C0_0",
+            // AFSR0_EL12, AFSR1_EL12
+            "S3_5_C5_C1_0" "S3_5_C5_C1_1",
+            // SPSR_EL12, ELR_EL12
+            "S3_5_C4_C0_0" "S3_5_C4_C0_1",
+            "tpidr_el1" "tpidr_el0",
+            "tpidrro_el0" "par_el1",
+            "csselr_el1" "actlr_el1",
+            "sp_el0" "sp_el1"
+        )
+    };
+    ($batch:ident, $base:expr, nvhe) => {
+        $batch!($base;
+            "sctlr_el1" "cpacr_el1",
+            "ttbr0_el1" "ttbr1_el1",
+            "tcr_el1" "mair_el1",
+            "amair_el1" "vbar_el1",
+            "contextidr_el1" "cntkctl_el1",
+            "esr_el1" "far_el1",
+            "afsr0_el1" "afsr1_el1",
+            "spsr_el1" "elr_el1",
+            "tpidr_el1" "tpidr_el0",
+            "tpidrro_el0" "par_el1",
+            "csselr_el1" "actlr_el1",
+            "sp_el0" "sp_el1"
+        )
+    };
+}
+
+/// Store register pairs to consecutive 16 byte slots starting at `$base`
+macro_rules! save_batch {
+    ($base:expr; $($a:literal $b:literal),*) => {
+        unsafe {
+            asm!(
+                $(
+                    concat!("mrs {t0}, ", $a),
+                    concat!("mrs {t1}, ", $b),
+                    "stp {t0}, {t1}, [{base}], #16",
+                )*
+                base = inout(reg) $base => _,
+                t0 = out(reg) _,
+                t1 = out(reg) _,
+                options(nostack, preserves_flags)
+            )
+        }
+    };
+}
+
+/// Load register pairs from consecutive 16 byte slots starting at `$base`
+macro_rules! restore_batch {
+    ($base:expr; $($a:literal $b:literal),*) => {
+        unsafe {
+            asm!(
+                $(
+                    "ldp {t0}, {t1}, [{base}], #16",
+                    concat!("msr ", $a, ", {t0}"),
+                    concat!("msr ", $b, ", {t1}"),
+                )*
+                base = inout(reg) $base => _,
+                t0 = out(reg) _,
+                t1 = out(reg) _,
+                options(nostack, readonly, preserves_flags)
+            )
+        }
+    };
+}
+
+/// EL1 and EL0 system registers of a vCPU, switched with [`VcpuSysregs::save`] and
+/// [`VcpuSysregs::restore`]
+///
+/// The field order is the order of the batched accesses and must not change.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcpuSysregs {
+    pub sctlr_el1: u64,
+    pub cpacr_el1: u64,
+    pub ttbr0_el1: u64,
+    pub ttbr1_el1: u64,
+    pub tcr_el1: u64,
+    pub mair_el1: u64,
+    pub amair_el1: u64,
+    pub vbar_el1: u64,
+    pub contextidr_el1: u64,
+    pub cntkctl_el1: u64,
+    pub esr_el1: u64,
+    pub far_el1: u64,
+    pub afsr0_el1: u64,
+    pub afsr1_el1: u64,
+    pub spsr_el1: u64,
+    pub elr_el1: u64,
+    pub tpidr_el1: u64,
+    pub tpidr_el0: u64,
+    pub tpidrro_el0: u64,
+    pub par_el1: u64,
+    pub csselr_el1: u64,
+    pub actlr_el1: u64,
+    pub sp_el0: u64,
+    pub sp_el1: u64,
+}
+
+const _: () = assert!(size_of::<VcpuSysregs>() == 24 * 8);
+
+impl VcpuSysregs {
+    /// SCTLR_EL1 with only the RES1 bits set: MMU and caches off
+    const SCTLR_RES1: u64 = 0x30d0_0800;
+
+    /// State of a vCPU that has not run yet, with the MMU and caches off
+    ///
+    /// Every other register is zero. The guest's first instruction is given by the
+    /// ELR_EL2/SPSR_EL2 of the first entry, not by this context.
+    pub const fn new() -> Self {
+        Self {
+            sctlr_el1: Self::SCTLR_RES1,
+            cpacr_el1: 0,
+            ttbr0_el1: 0,
+            ttbr1_el1: 0,
+            tcr_el1: 0,
+            mair_el1: 0,
+            amair_el1: 0,
+            vbar_el1: 0,
+            contextidr_el1: 0,
+            cntkctl_el1: 0,
+            esr_el1: 0,
+            far_el1: 0,
+            afsr0_el1: 0,
+            afsr1_el1: 0,
+            spsr_el1: 0,
+            elr_el1: 0,
+            tpidr_el1: 0,
+            tpidr_el0: 0,
+            tpidrro_el0: 0,
+            par_el1: 0,
+            csselr_el1: 0,
+            actlr_el1: 0,
+            sp_el0: 0,
+            sp_el1: 0,
+        }
+    }
+
+    /// Save the guest registers of the executing core, must be called at EL2
+    pub fn save() -> Self {
+        let mut ctx = Self::new();
+        let base = &mut ctx as *mut Self as *mut u64;
+        if vhe() {
+            with_el1_regs!(save_batch, base, vhe);
+        } else {
+            with_el1_regs!(save_batch, base, nvhe);
+        }
+        ctx
+    }
+
+    /// Load this state into the guest registers of the executing core, must be called at
+    /// EL2
+    ///
+    /// The ISB at the end makes the registers take effect for the next guest entry.
+    pub fn restore(&self) {
+        let base = self as *const Self as *const u64;
+        if vhe() {
+            with_el1_regs!(restore_batch, base, vhe);
+        } else {
+            with_el1_regs!(restore_batch, base, nvhe);
+        }
+        isb(SY);
+    }
+}
+
+impl Default for VcpuSysregs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether EL1 register names are redirected to EL2 (HCR_EL2.E2H)
+fn vhe() -> bool {
+    HCR_EL2.read(HCR_EL2::E2H) != 0
+}
@@ -0,0 +1,108 @@
+//! Virtual interrupt injection through HCR_EL2.
+//!
+//! Without a GIC virtual CPU interface, a hypervisor signals interrupts to its guest with
+//! the HCR_EL2.VI, VF and VSE bits. A pending virtual IRQ or FIQ stays pending until the
+//! hypervisor clears it, typically once the guest acknowledged the interrupt at the
+//! emulated interrupt controller. A virtual SError is cleared by the hardware when the
+//! guest takes it.
+//!
+//! The virtual interrupts are only delivered to EL1 and EL0 while routed to EL2:
+//! HCR_EL2.IMO for IRQs, FMO for FIQs and AMO for SErrors.
+
+use aarch64_cpu::{
+    asm::barrier::{SY, isb},
+    registers::{HCR_EL2, Readable, Writeable},
+};
+
+use crate::{
+    features::{Feature, is_supported},
+    interrupts::without_interrupts,
+};
+
+/// Virtual exception signalled by HCR_EL2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VirtualInterrupt {
+    /// Virtual IRQ (VI)
+    Irq,
+    /// Virtual FIQ (VF)
+    Fiq,
+    /// Virtual SError (VSE)
+    SError,
+}
+
+impl VirtualInterrupt {
+    const fn hcr_bit(self) -> u64 {
+        match self {
+            Self::Fiq => 1 << 6,
+            Self::Irq => 1 << 7,
+            Self::SError => 1 << 8,
+        }
+    }
+}
+
+/// Make `irq` pending or not pending for the guest
+///
+/// The read-modify-write of HCR_EL2 runs with IRQs masked, so an EL2 interrupt
+/// handler injecting another virtual interrupt cannot lose its update. The ISB makes the
+/// new state visible before returning.
+pub fn set_pending(irq: VirtualInterrupt, pending: bool) {
+    without_interrupts(|| {
+        let hcr = HCR_EL2.get();
+        if pending {
+            HCR_EL2.set(hcr | irq.hcr_bit());
+        } else {
+            HCR_EL2.set(hcr & !irq.hcr_bit());
+        }
+        isb(SY);
+    });
+}
+
+/// Check if `irq` is pending for the guest
+pub fn is_pending(irq: VirtualInterrupt) -> bool {
+    HCR_EL2.get() & irq.hcr_bit() != 0
+}
+
+/// Signal a virtual IRQ to the guest (HCR_EL2.VI)
+pub fn inject_irq() {
+    set_pending(VirtualInterrupt::Irq, true);
+}
+
+/// Withdraw the virtual IRQ
+pub fn clear_irq() {
+    set_pending(VirtualInterrupt::Irq, false);
+}
+
+/// Signal a virtual FIQ to the guest (HCR_EL2.VF)
+pub fn inject_fiq() {
+    set_pending(VirtualInterrupt::Fiq, true);
+}
+
+/// Withdraw the virtual FIQ
+pub fn clear_fiq() {
+    set_pending(VirtualInterrupt::Fiq, false);
+}
+
+/// Signal a virtual SError to the guest (HCR_EL2.VSE)
+///
+/// The guest sees the syndrome held in VSESR_EL2 with FEAT_RAS, an IMPLEMENTATION DEFINED
+/// one otherwise, see [`inject_serror_with_syndrome`].
+pub fn inject_serror() {
+    set_pending(VirtualInterrupt::SError, true);
+}
+
+/// Signal a virtual SError with the ESR_EL1 syndrome bits `syndrome` (VSESR_EL2)
+///
+/// `syndrome` holds ESR_EL1.IDS and ISS[23:0]. Without FEAT_RAS the syndrome is ignored and
+/// this behaves like [`inject_serror`].
+pub fn inject_serror_with_syndrome(syndrome: u64) {
+    if is_supported(Feature::Ras) {
+        // VSESR_EL2
+        unsafe { crate::sysreg!(write "S3_4_C5_C2_3", syndrome) };
+    }
+    inject_serror();
+}
+
+/// Withdraw a virtual SError the guest has not taken yet
+pub fn clear_serror() {
+    set_pending(VirtualInterrupt::SError, false);
+}
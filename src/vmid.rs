@@ -0,0 +1,124 @@
+use aarch64_cpu::{
+    asm::barrier::{ISH, ISHST, SY, dsb, isb},
+    registers::*,
+};
+
+use crate::{
+    asm::tlb::{ALLE1IS, VMALLS12E1IS, tlbi},
+    structures::{
+        id_allocator::{GenerationalId, IdAllocator},
+        vttbr::Vttbr,
+    },
+};
+
+/// VMID allocator with generation based rollover
+///
+/// VMID 0 is never handed out and can be left for the host. When all VMIDs of a
+/// generation are in use, a new generation starts and all EL1&0 TLB entries of every
+/// VMID are invalidated with TLBI ALLE1IS before the new VMID is returned.
+///
+/// The allocator is not synchronized, SMP users must wrap it in a lock.
+#[derive(Clone)]
+pub struct VmidAllocator {
+    inner: IdAllocator,
+}
+
+impl VmidAllocator {
+    /// Create an allocator sized from ID_AA64MMFR1_EL1.VMIDBits
+    ///
+    /// 16-bit VMIDs additionally require VTCR_EL2.VS = 1.
+    pub fn new() -> Self {
+        Self::with_bits(vmid_bits())
+    }
+
+    /// Create an allocator for `bits` wide VMIDs (8 or 16)
+    pub const fn with_bits(bits: u32) -> Self {
+        Self {
+            inner: IdAllocator::new(bits),
+        }
+    }
+
+    /// Width of the VMIDs in bits
+    pub const fn bits(&self) -> u32 {
+        self.inner.bits()
+    }
+
+    /// Check if `vmid` is still valid without reallocation
+    pub const fn is_current(&self, vmid: GenerationalId) -> bool {
+        self.inner.is_current(vmid)
+    }
+
+    /// Return a VMID valid in the current generation, see [`IdAllocator::allocate`]
+    ///
+    /// Invalidates the EL1&0 TLB of all VMIDs on all cores in the inner shareable domain
+    /// on rollover.
+    pub fn allocate(
+        &mut self,
+        current: Option<GenerationalId>,
+        active: &[GenerationalId],
+    ) -> GenerationalId {
+        let alloc = self.inner.allocate(current, active);
+        if alloc.rolled_over {
+            dsb(ISHST);
+            tlbi(ALLE1IS);
+            dsb(ISH);
+            isb(SY);
+        }
+        alloc.id
+    }
+
+    /// Release a VMID and invalidate its TLB entries on all cores
+    pub fn free(&mut self, vmid: GenerationalId) {
+        if self.inner.is_current(vmid) {
+            flush_vmid(vmid.hw_id());
+        }
+        self.inner.free(vmid);
+    }
+}
+
+impl Default for VmidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read the supported VMID width from ID_AA64MMFR1_EL1.VMIDBits
+pub fn vmid_bits() -> u32 {
+    match ID_AA64MMFR1_EL1.read(ID_AA64MMFR1_EL1::VMIDBits) {
+        0b0010 => 16,
+        _ => 8,
+    }
+}
+
+/// Install a new stage 2 translation (VTTBR_EL2 value) for the next guest entry
+///
+/// The ISB makes sure no speculative stage 2 walk with the previous VMID happens after
+/// this returns. TLB maintenance is not needed when switching between live VMIDs.
+#[deprecated(note = "use `Vttbr::switch`, which states the requirements on the value")]
+pub fn switch_vttbr(vttbr: u64) {
+    unsafe { Vttbr::from_value(vttbr).switch() };
+}
+
+/// Invalidate all stage 1 and stage 2 EL1&0 TLB entries of `vmid` on all cores in the
+/// inner shareable domain
+///
+/// TLBI VMALLS12E1IS operates on the VMID held in VTTBR_EL2, so the VMID is installed
+/// temporarily and the previous VTTBR_EL2 value restored afterwards. Must be called at EL2
+/// with HCR_EL2.TGE = 0 and interrupts masked.
+pub fn flush_vmid(vmid: usize) {
+    let saved = VTTBR_EL2.get();
+    let switch = VTTBR_EL2.read(VTTBR_EL2::VMID) != vmid as u64;
+    if switch {
+        VTTBR_EL2.modify(VTTBR_EL2::VMID.val(vmid as u64));
+        isb(SY);
+    }
+
+    dsb(ISHST);
+    tlbi(VMALLS12E1IS);
+    dsb(ISH);
+
+    if switch {
+        VTTBR_EL2.set(saved);
+    }
+    isb(SY);
+}